@@ -0,0 +1,106 @@
+#include <assert.h>
+#include <errno.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <time.h>
+#include <unistd.h>
+
+static pthread_mutex_t held = PTHREAD_MUTEX_INITIALIZER;
+static volatile int handled = 0;
+static volatile int started = 0;
+
+static void handler(int sig)
+{
+    handled += sig;
+}
+
+static void *sleeper(void *arg)
+{
+    started = 1;
+    for (;;)
+        sleep(1);
+    return NULL;
+}
+
+static void *napper(void *arg)
+{
+    struct timespec ts = {10, 0};
+    started = 1;
+    // interrupted by the signal, after the handler is called
+    assert(nanosleep(&ts, &ts) != 0);
+    assert(handled == 2 * SIGUSR1);
+    assert(ts.tv_sec > 0);
+    return (void *)1;
+}
+
+static void *quick(void *arg)
+{
+    started = 1;
+    return NULL;
+}
+
+static void *unlocker(void *arg)
+{
+    return (void *)(long)pthread_mutex_unlock(&held);
+}
+
+static void wait_started(void)
+{
+    while (!started)
+        usleep(1000);
+    started = 0;
+    usleep(10000); // let it block
+}
+
+int main()
+{
+    pthread_t thread;
+    void *retval;
+
+    assert(signal(SIGUSR1, handler) == SIG_DFL);
+    assert(raise(SIGUSR1) == 0);
+    assert(handled == SIGUSR1);
+    assert(signal(SIGKILL, handler) == SIG_ERR);
+    puts("raise OK");
+
+    assert(pthread_create(&thread, NULL, napper, NULL) == 0);
+    wait_started();
+    assert(kill(thread, SIGUSR1) == 0);
+    assert(pthread_join(thread, &retval) == 0);
+    assert(retval == (void *)1);
+    puts("kill OK");
+
+    assert(pthread_create(&thread, NULL, sleeper, NULL) == 0);
+    wait_started();
+    assert(pthread_cancel(thread) == 0);
+    assert(pthread_join(thread, &retval) == 0);
+    assert(retval == PTHREAD_CANCELED);
+    puts("pthread_cancel OK");
+
+    assert(pthread_create(&thread, NULL, quick, NULL) == 0);
+    assert(pthread_detach(thread) == 0);
+    assert(pthread_detach(thread) == EINVAL);
+    assert(pthread_join(thread, NULL) == EINVAL);
+    wait_started();
+    // forgotten once it exited
+    assert(pthread_detach(thread) == ESRCH);
+    assert(pthread_create(&thread, NULL, quick, NULL) == 0);
+    wait_started();
+    assert(pthread_detach(thread) == 0);
+    assert(pthread_join(thread, NULL) == ESRCH);
+    puts("pthread_detach OK");
+
+    // only the owner unlocks a mutex
+    assert(pthread_mutex_lock(&held) == 0);
+    assert(pthread_create(&thread, NULL, unlocker, NULL) == 0);
+    assert(pthread_join(thread, &retval) == 0);
+    assert(retval == (void *)EPERM);
+    assert(pthread_mutex_unlock(&held) == 0);
+    assert(pthread_mutex_unlock(&held) == EPERM);
+    puts("pthread_mutex_unlock OK");
+
+    assert(signal(SIGUSR1, SIG_DFL) == handler);
+    puts("Pthread cancel tests run OK!");
+    return 0;
+}
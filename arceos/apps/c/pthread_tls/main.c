@@ -0,0 +1,78 @@
+#include <assert.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define NUM_THREADS 8
+
+__thread int counter = 100; // in .tdata
+__thread int buf[NUM_THREADS + 1]; // in .tbss
+
+static pthread_key_t key;
+static pthread_mutex_t lock = PTHREAD_MUTEX_INITIALIZER;
+static pthread_mutex_t start = PTHREAD_MUTEX_INITIALIZER;
+static int destructed = 0;
+
+static void destructor(void *value)
+{
+    pthread_mutex_lock(&lock);
+    destructed += *(int *)value;
+    pthread_mutex_unlock(&lock);
+    free(value);
+}
+
+static void check_locals(long i)
+{
+    assert(counter == 100 + i);
+    for (int j = 0; j <= NUM_THREADS; j++)
+        assert(buf[j] == (j == i ? i + 1 : 0));
+    assert(*(int *)pthread_getspecific(key) == i + 1);
+}
+
+static void init_locals(long i)
+{
+    assert(counter == 100);
+    for (int j = 0; j <= NUM_THREADS; j++)
+        assert(buf[j] == 0);
+    assert(pthread_getspecific(key) == NULL);
+
+    counter += i;
+    buf[i] = i + 1;
+    int *value = malloc(sizeof(int));
+    *value = i + 1;
+    assert(pthread_setspecific(key, value) == 0);
+}
+
+static void *thread_func(void *arg)
+{
+    long i = (long)arg;
+    init_locals(i);
+    // switch to the other threads
+    pthread_mutex_lock(&start);
+    pthread_mutex_unlock(&start);
+    check_locals(i);
+    printf("thread %ld checked its locals\n", i);
+    return NULL;
+}
+
+int main()
+{
+    pthread_t threads[NUM_THREADS];
+
+    assert(pthread_key_create(&key, destructor) == 0);
+    init_locals(NUM_THREADS);
+    pthread_mutex_lock(&start);
+    for (long i = 0; i < NUM_THREADS; i++)
+        assert(pthread_create(&threads[i], NULL, thread_func, (void *)i) == 0);
+    pthread_mutex_unlock(&start);
+    for (int i = 0; i < NUM_THREADS; i++)
+        assert(pthread_join(threads[i], NULL) == 0);
+    check_locals(NUM_THREADS);
+
+    // 1 + 2 + ... + NUM_THREADS, by the threads exited
+    printf("destructed = %d\n", destructed);
+    assert(destructed == NUM_THREADS * (NUM_THREADS + 1) / 2);
+    assert(pthread_key_delete(key) == 0);
+    puts("Pthread TLS tests run OK!");
+    return 0;
+}
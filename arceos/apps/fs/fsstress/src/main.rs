@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate libax;
+extern crate alloc;
+
+use alloc::{format, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use libax::fs;
+use libax::sync::WaitQueue;
+use libax::task;
+
+const NUM_READERS: usize = 8;
+const NUM_WRITERS: usize = 4;
+const NUM_ROUNDS: usize = 20;
+const SHARED_DIR: &str = "/stress";
+const SHARED_FILE: &str = "/stress/shared";
+const SHARED_LEN: usize = 6000;
+
+static FINISHED_TASKS: AtomicUsize = AtomicUsize::new(0);
+static MAIN_WQ: WaitQueue = WaitQueue::new();
+
+fn shared_content() -> Vec<u8> {
+    (0..SHARED_LEN).map(|i| (i % 251) as u8).collect()
+}
+
+fn finish() {
+    FINISHED_TASKS.fetch_add(1, Ordering::Release);
+    MAIN_WQ.notify_one(true);
+}
+
+/// Looks up, stats, lists and reads the shared directory and file, which
+/// only takes the inode locks shared.
+fn reader(id: usize) {
+    let expect = shared_content();
+    for round in 0..NUM_ROUNDS {
+        assert_eq!(fs::metadata(SHARED_FILE).unwrap().len(), SHARED_LEN as u64);
+        assert_eq!(fs::read(SHARED_FILE).unwrap(), expect);
+        let names = fs::read_dir(SHARED_DIR)
+            .unwrap()
+            .filter_map(|e| e.ok().map(|e| e.file_name()))
+            .collect::<Vec<_>>();
+        assert!(names.iter().any(|name| name == "shared"));
+        if round % 4 == 0 {
+            task::yield_now();
+        }
+    }
+    println!("reader {} finished", id);
+    finish();
+}
+
+/// Creates, rewrites and removes its own file in the shared directory, which
+/// takes the directory inode lock exclusively.
+fn writer(id: usize) {
+    let path = format!("{}/w{}", SHARED_DIR, id);
+    for round in 0..NUM_ROUNDS {
+        let content = format!("writer {} round {}", id, round);
+        fs::write(&path, &content).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        if round % 2 == 1 {
+            fs::remove_file(&path).unwrap();
+        }
+        task::yield_now();
+    }
+    println!("writer {} finished", id);
+    finish();
+}
+
+#[no_mangle]
+fn main() {
+    fs::create_dir(SHARED_DIR).ok();
+    fs::write(SHARED_FILE, shared_content()).unwrap();
+
+    for i in 0..NUM_READERS {
+        task::spawn(move || reader(i));
+    }
+    for i in 0..NUM_WRITERS {
+        task::spawn(move || writer(i));
+    }
+
+    MAIN_WQ.wait_until(|| FINISHED_TASKS.load(Ordering::Acquire) == NUM_READERS + NUM_WRITERS);
+
+    fs::remove_file(SHARED_FILE).unwrap();
+    assert_eq!(fs::read_dir(SHARED_DIR).unwrap().count(), 0);
+    fs::remove_dir(SHARED_DIR).unwrap();
+    println!("Fs stress tests run OK!");
+}
@@ -1,5 +1,7 @@
 use libax::fs::{self, File};
 use libax::io::{self, prelude::*};
+use libax::task::TaskState;
+use libax::time::Duration;
 use libax::{string::String, vec::Vec};
 
 macro_rules! print_err {
@@ -26,11 +28,19 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("cd", do_cd),
     ("echo", do_echo),
     ("exit", do_exit),
+    ("fstrim", do_fstrim),
     ("help", do_help),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    ("mount", do_mount),
+    ("ps", do_ps),
     ("pwd", do_pwd),
+    ("resize", do_resize),
     ("rm", do_rm),
+    ("snapshot", do_snapshot),
+    ("sync", do_sync),
+    ("top", do_top),
+    ("umount", do_umount),
     ("uname", do_uname),
 ];
 
@@ -214,6 +224,86 @@ fn do_pwd(_args: &str) {
     println!("{}", pwd);
 }
 
+fn do_sync(_args: &str) {
+    if let Err(e) = fs::sync() {
+        print_err!("sync", e);
+    }
+}
+
+fn do_mount(args: &str) {
+    if args.is_empty() {
+        for mp in fs::mounts() {
+            println!("{} on {} type {}", mp.source, mp.path, mp.fstype);
+        }
+        return;
+    }
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    match args[..] {
+        ["-t", fstype, source, path] => {
+            if let Err(e) = fs::mount(source, path, fstype) {
+                print_err!("mount", path, e);
+            }
+        }
+        _ => {
+            print_err!("mount", "usage: mount [-t TYPE SOURCE DIR]");
+            println!("supported types: {}", fs::fs_types().join(" "));
+        }
+    }
+}
+
+fn do_umount(args: &str) {
+    if args.is_empty() {
+        print_err!("umount", "missing operand");
+        return;
+    }
+    for path in args.split_whitespace() {
+        if let Err(e) = fs::umount(path) {
+            print_err!("umount", path, e);
+        }
+    }
+}
+
+fn do_snapshot(args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let res = match args[..] {
+        [] => fs::snapshots().map(|names| {
+            for name in names {
+                println!("{}", name);
+            }
+        }),
+        ["create", name] => fs::create_snapshot(name),
+        ["delete", name] => fs::delete_snapshot(name),
+        _ => {
+            print_err!("snapshot", "usage: snapshot [create NAME | delete NAME]");
+            return;
+        }
+    };
+    if let Err(e) = res {
+        print_err!("snapshot", e);
+    }
+}
+
+fn do_fstrim(_args: &str) {
+    match fs::fstrim() {
+        Ok(bytes) => println!("/: {} bytes trimmed", bytes),
+        Err(e) => {
+            print_err!("fstrim", e);
+        }
+    }
+}
+
+fn do_resize(args: &str) {
+    match args.trim().parse() {
+        Ok(blocks) => {
+            if let Err(e) = fs::resize_fs(blocks) {
+                print_err!("resize", e);
+            }
+        }
+        Err(_) => print_err!("resize", "usage: resize BLOCKS"),
+    }
+}
+
 fn do_uname(_args: &str) {
     let arch = option_env!("ARCH").unwrap_or("");
     let platform = option_env!("PLATFORM").unwrap_or("");
@@ -231,6 +321,85 @@ fn do_uname(_args: &str) {
     );
 }
 
+fn state_char(state: TaskState) -> char {
+    match state {
+        TaskState::Running => 'R',
+        TaskState::Ready => 'r',
+        TaskState::Blocked => 'S',
+        TaskState::Exited => 'Z',
+    }
+}
+
+fn do_ps(_args: &str) {
+    println!(
+        "{:>4} {:<16} {} {:>3} {:>8} {:>8} {:>6} {:>6} {:>10} {:>10}",
+        "ID", "NAME", "S", "CPU", "UTICKS", "KTICKS", "VCSW", "IVCSW", "RUN(ms)", "BLOCK(ms)"
+    );
+    for task in libax::task::tasks() {
+        let stats = &task.stats;
+        println!(
+            "{:>4} {:<16} {} {:>3} {:>8} {:>8} {:>6} {:>6} {:>10} {:>10}",
+            task.id.as_u64(),
+            task.name,
+            state_char(task.state),
+            task.cpu_id,
+            stats.user_ticks,
+            stats.kernel_ticks,
+            stats.voluntary_switches,
+            stats.involuntary_switches,
+            stats.run_time.as_millis(),
+            stats.blocked_time.as_millis(),
+        );
+    }
+}
+
+fn do_top(args: &str) {
+    let secs = if args.trim().is_empty() {
+        Ok(1)
+    } else {
+        args.trim().parse::<u64>()
+    };
+    let interval = match secs {
+        Ok(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => {
+            print_err!("top", "usage: top [SECONDS]");
+            return;
+        }
+    };
+
+    let before = libax::task::tasks().collect::<Vec<_>>();
+    libax::task::sleep(interval);
+    let mut usage = libax::task::tasks()
+        .map(|task| {
+            let last = before
+                .iter()
+                .find(|t| t.id == task.id)
+                .map_or(Duration::ZERO, |t| t.stats.run_time);
+            let delta = task.stats.run_time.saturating_sub(last);
+            (delta, task)
+        })
+        .collect::<Vec<_>>();
+    usage.sort_by(|a, b| b.0.cmp(&a.0));
+
+    println!(
+        "{:>4} {:<16} {} {:>3} {:>6} {:>10}",
+        "ID", "NAME", "S", "CPU", "%CPU", "RUN(ms)"
+    );
+    for (delta, task) in usage {
+        let percent = delta.as_nanos() * 1000 / interval.as_nanos();
+        println!(
+            "{:>4} {:<16} {} {:>3} {:>4}.{} {:>10}",
+            task.id.as_u64(),
+            task.name,
+            state_char(task.state),
+            task.cpu_id,
+            percent / 10,
+            percent % 10,
+            task.stats.run_time.as_millis(),
+        );
+    }
+}
+
 fn do_help(_args: &str) {
     println!("Available commands:");
     for (name, _) in CMD_TABLE {
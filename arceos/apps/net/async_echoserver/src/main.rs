@@ -0,0 +1,81 @@
+//! The echo server of `apps/net/echoserver`, serving all clients by futures
+//! on a single task instead of one task per client.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate libax;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use libax::future::Executor;
+use libax::io;
+use libax::net::{IpAddr, TcpListener, TcpStream};
+
+const LOCAL_IP: &str = "10.0.2.15";
+const LOCAL_PORT: u16 = 5555;
+
+fn reverse(buf: &[u8]) -> Vec<u8> {
+    let mut lines = buf
+        .split(|&b| b == b'\n')
+        .map(Vec::from)
+        .collect::<Vec<_>>();
+    for line in lines.iter_mut() {
+        line.reverse();
+    }
+    lines.join(&b'\n')
+}
+
+async fn echo_server(mut stream: TcpStream) -> io::Result {
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = stream.read_async(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        stream
+            .write_all_async(reverse(&buf[..n]).as_slice())
+            .await?;
+    }
+}
+
+async fn accept_loop(executor: Executor) -> io::Result {
+    let (addr, port) = (IpAddr::from_str(LOCAL_IP).unwrap(), LOCAL_PORT);
+    let mut listener = TcpListener::bind((addr, port).into())?;
+    println!("listen on: {}", listener.local_addr().unwrap());
+
+    let mut i = 0;
+    loop {
+        match listener.accept_async().await {
+            Ok((stream, addr)) => {
+                info!("new client {}: {}", i, addr);
+                executor.spawn(async move {
+                    match echo_server(stream).await {
+                        Err(e) => error!("client connection error: {:?}", e),
+                        Ok(()) => info!("client {} closed successfully", i),
+                    }
+                });
+            }
+            Err(e) => return Err(e),
+        }
+        i += 1;
+    }
+}
+
+#[no_mangle]
+fn main() {
+    println!("Hello, async echo server!");
+    let executor = Executor::new();
+    executor.spawn({
+        let executor = executor.clone();
+        async move {
+            accept_loop(executor)
+                .await
+                .expect("test echo server failed")
+        }
+    });
+    executor.run();
+}
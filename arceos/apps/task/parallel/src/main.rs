@@ -7,18 +7,15 @@ extern crate alloc;
 
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use core::time::Duration;
-use libax::sync::{Mutex, WaitQueue};
+use libax::sync::WaitQueue;
 use libax::{rand, task};
 
 const NUM_DATA: usize = 2_000_000;
 const NUM_TASKS: usize = 16;
 
-static FINISHED_TASKS: AtomicUsize = AtomicUsize::new(0);
-
 static MAIN_WQ: WaitQueue = WaitQueue::new();
-static RESULTS: Mutex<[u64; NUM_TASKS]> = Mutex::new([0; NUM_TASKS]); // TODO: task join
 
 fn barrier() {
     static BARRIER_WQ: WaitQueue = WaitQueue::new();
@@ -50,9 +47,16 @@ fn main() {
     let timeout = MAIN_WQ.wait_timeout(Duration::from_millis(500));
     assert!(timeout);
 
+    let results = Arc::new(
+        (0..NUM_TASKS)
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>(),
+    );
+    let mut tasks = Vec::with_capacity(NUM_TASKS);
     for i in 0..NUM_TASKS {
         let vec = vec.clone();
-        task::spawn(move || {
+        let results = results.clone();
+        tasks.push(task::spawn(move || {
             let left = i * (NUM_DATA / NUM_TASKS);
             let right = (left + (NUM_DATA / NUM_TASKS)).min(NUM_DATA);
             println!(
@@ -63,22 +67,22 @@ fn main() {
                 right
             );
 
-            RESULTS.lock()[i] = vec[left..right].iter().map(sqrt).sum();
+            let partial_sum: u64 = vec[left..right].iter().map(sqrt).sum();
+            results[i].store(partial_sum, Ordering::Relaxed);
 
             barrier();
 
             println!("part {}: {:?} finished", i, task::current().id());
-            let n = FINISHED_TASKS.fetch_add(1, Ordering::Relaxed);
-            if n == NUM_TASKS - 1 {
-                MAIN_WQ.notify_one(true);
-            }
-        });
+            task::exit(i as i32);
+        }));
     }
 
-    let timeout = MAIN_WQ.wait_timeout(Duration::from_millis(600));
-    println!("main task woken up! timeout={}", timeout);
+    for (i, t) in tasks.into_iter().enumerate() {
+        assert_eq!(t.join(), i as i32);
+    }
+    println!("main task joined all tasks!");
 
-    let actual = RESULTS.lock().iter().sum();
+    let actual: u64 = results.iter().map(|r| r.load(Ordering::Relaxed)).sum();
     println!("sum = {}", actual);
     assert_eq!(expect, actual);
 
@@ -0,0 +1,112 @@
+//! A low priority task holds the lock that a high priority task waits for,
+//! while medium priority tasks keep the CPU busy. The low priority task runs
+//! with the high priority until it releases the lock, so the high priority
+//! task isn't held back by the medium priority ones.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate libax;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use libax::sync::{Mutex, WaitQueue};
+use libax::task;
+
+const NUM_MEDIUM: usize = 3;
+const NUM_TASKS: usize = NUM_MEDIUM + 2;
+
+const LOW: usize = 0;
+const HIGH: usize = 1;
+
+const LOW_WORK: u64 = 20;
+const MEDIUM_WORK: u64 = 40;
+
+static LOCK: Mutex<u64> = Mutex::new(0);
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+static FINISHED_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+static MAIN_WQ: WaitQueue = WaitQueue::new();
+static LEAVE_TIME: Mutex<[u64; NUM_TASKS]> = Mutex::new([0; NUM_TASKS]);
+
+fn load(n: u64) -> u64 {
+    // time consuming is linear with n
+    let mut sum: u64 = n;
+    for i in 0..n {
+        sum += ((i ^ (i * 3)) ^ (i + n)) / (i + 1);
+    }
+    sum
+}
+
+fn work(times: u64) -> u64 {
+    (0..times).map(|_| load(1000000)).sum()
+}
+
+fn wait_locked() {
+    while !LOCKED.load(Ordering::Acquire) {
+        task::yield_now();
+    }
+}
+
+fn finish(i: usize, start_time: libax::time::Instant) {
+    LEAVE_TIME.lock()[i] = start_time.elapsed().as_millis() as u64;
+    let n = FINISHED_TASKS.fetch_add(1, Ordering::Relaxed);
+    if n == NUM_TASKS - 1 {
+        MAIN_WQ.notify_one(true);
+    }
+}
+
+#[no_mangle]
+fn main() {
+    let start_time = libax::time::Instant::now();
+
+    task::spawn(move || {
+        task::set_priority(19);
+        let mut value = LOCK.lock();
+        println!("low: {:?} holds the lock", task::current().id());
+        LOCKED.store(true, Ordering::Release);
+        *value += work(LOW_WORK);
+        drop(value);
+        finish(LOW, start_time);
+    });
+
+    task::spawn(move || {
+        task::set_priority(-20);
+        wait_locked();
+        println!("high: {:?} waits for the lock", task::current().id());
+        let value = LOCK.lock();
+        println!("high: {:?} got the lock", task::current().id());
+        drop(value);
+        finish(HIGH, start_time);
+    });
+
+    for i in 0..NUM_MEDIUM {
+        task::spawn(move || {
+            task::set_priority(0);
+            wait_locked();
+            work(MEDIUM_WORK);
+            finish(HIGH + 1 + i, start_time);
+        });
+    }
+
+    MAIN_WQ.wait();
+
+    let leave_time = LEAVE_TIME.lock();
+    println!("leave time:");
+    println!("low = {}ms", leave_time[LOW]);
+    println!("high = {}ms", leave_time[HIGH]);
+    for i in 0..NUM_MEDIUM {
+        println!("medium {} = {}ms", i, leave_time[HIGH + 1 + i]);
+    }
+
+    if cfg!(any(feature = "sched_cfs", feature = "sched_rt")) && option_env!("SMP") == Some("1") {
+        // without priority inheritance, the high priority task would leave
+        // after the medium priority ones
+        assert!(leave_time[HIGH + 1..]
+            .iter()
+            .all(|&t| leave_time[HIGH] < t));
+    }
+
+    println!("Priority inversion tests run OK!");
+}
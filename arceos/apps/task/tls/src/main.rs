@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+#[macro_use]
+extern crate libax;
+
+use core::cell::Cell;
+use libax::task;
+use libax::vec::Vec;
+
+const NUM_TASKS: usize = 10;
+
+// in `.tdata`
+#[thread_local]
+static COUNTER: Cell<u64> = Cell::new(0x1234_5678);
+
+// in `.tbss`
+#[thread_local]
+static BUF: Cell<[u8; NUM_TASKS + 1]> = Cell::new([0; NUM_TASKS + 1]);
+
+task::task_local! {
+    static ID: Cell<Option<usize>> = Cell::new(None);
+}
+
+fn init_locals(i: usize) {
+    assert_eq!(COUNTER.get(), 0x1234_5678);
+    assert!(BUF.get().iter().all(|&b| b == 0));
+    assert_eq!(ID.with(Cell::get), None);
+
+    COUNTER.set(COUNTER.get() + i as u64);
+    let mut buf = BUF.get();
+    buf[i] = i as u8 + 1;
+    BUF.set(buf);
+    ID.with(|id| id.set(Some(i)));
+}
+
+fn check_locals(i: usize) {
+    assert_eq!(COUNTER.get(), 0x1234_5678 + i as u64);
+    let buf = BUF.get();
+    for (j, &b) in buf.iter().enumerate() {
+        assert_eq!(b, if j == i { i as u8 + 1 } else { 0 });
+    }
+    assert_eq!(ID.with(Cell::get), Some(i));
+}
+
+#[no_mangle]
+fn main() {
+    init_locals(NUM_TASKS);
+    let tasks = (0..NUM_TASKS)
+        .map(|i| {
+            task::spawn(move || {
+                init_locals(i);
+                for _ in 0..5 {
+                    task::yield_now();
+                    check_locals(i);
+                }
+                println!("task {} checked its locals", i);
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in tasks {
+        t.join();
+    }
+    check_locals(NUM_TASKS);
+    println!("TLS tests run OK!");
+}
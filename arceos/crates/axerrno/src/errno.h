@@ -55,6 +55,7 @@
 #define ENOTEMPTY	39	/* Directory not empty */
 
 #define	ENOTCONN	107	/* Transport endpoint is not connected */
+#define	ETIMEDOUT	110	/* Connection timed out */
 #define	ECONNREFUSED	111	/* Connection refused */
 
 #endif
@@ -38,6 +38,8 @@ pub enum AxError {
     ConnectionRefused,
     /// A non-empty directory was specified where an empty directory was expected.
     DirectoryNotEmpty,
+    /// The operation was interrupted, e.g., by a signal sent to the task.
+    Interrupted,
     /// Data not valid for the operation were encountered.
     ///
     /// Unlike [`InvalidInput`], this typically means that the operation
@@ -199,6 +201,7 @@ impl From<AxError> for LinuxError {
             BadAddress | BadState => LinuxError::EFAULT,
             ConnectionRefused => LinuxError::ECONNREFUSED,
             DirectoryNotEmpty => LinuxError::ENOTEMPTY,
+            Interrupted => LinuxError::EINTR,
             InvalidInput | InvalidData => LinuxError::EINVAL,
             Io => LinuxError::EIO,
             IsADirectory => LinuxError::EISDIR,
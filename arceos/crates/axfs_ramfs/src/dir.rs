@@ -7,6 +7,7 @@ use axfs_vfs::{VfsError, VfsResult};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::xattr::Xattrs;
 
 /// The directory node in the RAM filesystem.
 ///
@@ -15,6 +16,7 @@ pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    xattrs: Xattrs,
 }
 
 impl DirNode {
@@ -23,6 +25,7 @@ impl DirNode {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
+            xattrs: Xattrs::new(),
         })
     }
 
@@ -165,6 +168,22 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn getxattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.xattrs.get(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.xattrs.set(name, value)
+    }
+
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        self.xattrs.list()
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult {
+        self.xattrs.remove(name)
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
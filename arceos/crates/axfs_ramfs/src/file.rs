@@ -1,18 +1,22 @@
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
 use spin::RwLock;
 
+use crate::xattr::Xattrs;
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
     content: RwLock<Vec<u8>>,
+    xattrs: Xattrs,
 }
 
 impl FileNode {
     pub(super) const fn new() -> Self {
         Self {
             content: RwLock::new(Vec::new()),
+            xattrs: Xattrs::new(),
         }
     }
 }
@@ -52,5 +56,21 @@ impl VfsNodeOps for FileNode {
         Ok(buf.len())
     }
 
+    fn getxattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.xattrs.get(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.xattrs.set(name, value)
+    }
+
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        self.xattrs.list()
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult {
+        self.xattrs.remove(name)
+    }
+
     impl_vfs_non_dir_default! {}
 }
@@ -8,6 +8,7 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod xattr;
 
 #[cfg(test)]
 mod tests;
@@ -88,6 +88,29 @@ fn test_get_parent(devfs: &RamFileSystem) -> VfsResult {
     Ok(())
 }
 
+fn test_xattr(devfs: &RamFileSystem) -> VfsResult {
+    let node = devfs.root_dir().lookup("f1")?;
+    assert_eq!(node.listxattr()?, Vec::<String>::new());
+    assert_eq!(node.getxattr("user.hash").err(), Some(VfsError::NotFound));
+    node.setxattr("user.hash", b"1234")?;
+    node.setxattr("user.label", b"")?;
+    assert_eq!(node.getxattr("user.hash")?, b"1234");
+    node.setxattr("user.hash", b"5678")?;
+    assert_eq!(node.getxattr("user.hash")?, b"5678");
+    assert_eq!(node.listxattr()?, ["user.hash", "user.label"]);
+    assert_eq!(node.setxattr("", b"x").err(), Some(VfsError::InvalidInput));
+
+    let foo = devfs.root_dir().lookup("foo")?;
+    foo.setxattr("user.label", b"dir")?;
+    assert_eq!(foo.getxattr("user.label")?, b"dir");
+    assert_eq!(node.getxattr("user.label")?, b"");
+
+    node.removexattr("user.hash")?;
+    assert_eq!(node.removexattr("user.hash").err(), Some(VfsError::NotFound));
+    assert_eq!(node.listxattr()?, ["user.label"]);
+    Ok(())
+}
+
 #[test]
 fn test_ramfs() {
     // .
@@ -117,6 +140,7 @@ fn test_ramfs() {
 
     test_ramfs_ops(&ramfs).unwrap();
     test_get_parent(&ramfs).unwrap();
+    test_xattr(&ramfs).unwrap();
 
     let root = ramfs.root_dir();
     assert_eq!(root.remove("f1"), Ok(()));
@@ -0,0 +1,34 @@
+use alloc::collections::BTreeMap;
+use alloc::{string::String, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsResult};
+use spin::RwLock;
+
+/// The extended attributes of a node, kept in memory.
+pub(crate) struct Xattrs(RwLock<BTreeMap<String, Vec<u8>>>);
+
+impl Xattrs {
+    pub(crate) const fn new() -> Self {
+        Self(RwLock::new(BTreeMap::new()))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.0.read().get(name).cloned().ok_or(VfsError::NotFound)
+    }
+
+    pub(crate) fn set(&self, name: &str, value: &[u8]) -> VfsResult {
+        if name.is_empty() {
+            return Err(VfsError::InvalidInput);
+        }
+        self.0.write().insert(name.into(), value.into());
+        Ok(())
+    }
+
+    pub(crate) fn list(&self) -> VfsResult<Vec<String>> {
+        Ok(self.0.read().keys().cloned().collect())
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> VfsResult {
+        self.0.write().remove(name).map(|_| ()).ok_or(VfsError::NotFound)
+    }
+}
@@ -12,6 +12,7 @@
 //! - [`umount()`](VfsOps::umount): Do something when the filesystem is unmounted.
 //! - [`format()`](VfsOps::format): Format the filesystem.
 //! - [`statfs()`](VfsOps::statfs): Get the attributes of the filesystem.
+//! - [`sync()`](VfsOps::sync): Write all pending data of the filesystem to disk.
 //! - [`root_dir()`](VfsOps::root_dir): Get root directory of the filesystem.
 //!
 //! The [`VfsNodeOps`] trait provides the following operations on a file or a
@@ -26,11 +27,17 @@
 //! | [`write_at()`](VfsNodeOps::write_at) | Write data to the file | file |
 //! | [`fsync()`](VfsNodeOps::fsync) | Synchronize the file data to disk | file |
 //! | [`truncate()`](VfsNodeOps::truncate) | Truncate the file | file |
+//! | [`cache_id()`](VfsNodeOps::cache_id) | Opt in to the page cache of the kernel | file |
 //! | [`parent()`](VfsNodeOps::parent) | Get the parent directory | directory |
 //! | [`lookup()`](VfsNodeOps::lookup) | Lookup the node with the given path | directory |
 //! | [`create()`](VfsNodeOps::create) | Create a new node with the given path | directory |
 //! | [`remove()`](VfsNodeOps::remove) | Remove the node with the given path | directory |
+//! | [`mknod()`](VfsNodeOps::mknod) | Create a device node with the given path | directory |
 //! | [`read_dir()`](VfsNodeOps::read_dir) | Read directory entries | directory |
+//! | [`getxattr()`](VfsNodeOps::getxattr) | Get an extended attribute | both |
+//! | [`setxattr()`](VfsNodeOps::setxattr) | Set an extended attribute | both |
+//! | [`listxattr()`](VfsNodeOps::listxattr) | List the extended attributes | both |
+//! | [`removexattr()`](VfsNodeOps::removexattr) | Remove an extended attribute | both |
 //!
 //! [inodes]: https://en.wikipedia.org/wiki/Inode
 
@@ -43,7 +50,7 @@ mod structs;
 
 pub mod path;
 
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
 
 pub use self::structs::{FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
@@ -79,6 +86,11 @@ pub trait VfsOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Write all pending data and metadata of the filesystem to disk.
+    fn sync(&self) -> VfsResult {
+        Ok(())
+    }
+
     /// Get the root directory of the filesystem.
     fn root_dir(&self) -> VfsNodeRef;
 }
@@ -122,6 +134,15 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(InvalidInput)
     }
 
+    /// Returns the ID of the file in the page cache of the kernel, or `None`
+    /// if its data must not be cached.
+    ///
+    /// The ID must be the same for every node of the same file, and unique
+    /// among all files that are cached.
+    fn cache_id(&self) -> Option<u64> {
+        None
+    }
+
     // directory operations:
 
     /// Get the parent directory of this directory.
@@ -150,11 +171,46 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Create a device node with the given `path` in the directory, whose
+    /// I/O is forwarded to the device numbered (`major`, `minor`).
+    fn mknod(&self, _path: &str, _major: u32, _minor: u32) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Read directory entries into `dirents`, starting from `start_idx`.
     fn read_dir(&self, _start_idx: usize, _dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
         ax_err!(Unsupported)
     }
 
+    // extended attributes:
+
+    /// Get the value of the extended attribute `name`.
+    ///
+    /// Return [`NotFound`](AxError::NotFound) if the node has no such
+    /// attribute.
+    fn getxattr(&self, _name: &str) -> VfsResult<Vec<u8>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Set the extended attribute `name` to `value`, creating it if it
+    /// doesn't exist.
+    fn setxattr(&self, _name: &str, _value: &[u8]) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Get the names of all extended attributes of the node.
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Remove the extended attribute `name`.
+    ///
+    /// Return [`NotFound`](AxError::NotFound) if the node has no such
+    /// attribute.
+    fn removexattr(&self, _name: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Convert `&self` to [`&dyn Any`][1] that can use
     /// [`Any::downcast_ref`][2].
     ///
@@ -50,6 +50,10 @@ macro_rules! impl_vfs_non_dir_default {
             $crate::__priv::ax_err!(NotADirectory)
         }
 
+        fn mknod(&self, _path: &str, _major: u32, _minor: u32) -> $crate::VfsResult {
+            $crate::__priv::ax_err!(NotADirectory)
+        }
+
         fn read_dir(
             &self,
             _start_idx: usize,
@@ -4,15 +4,20 @@ use axfs_vfs::{VfsError, VfsResult};
 use log::info;
 use xv6fs::disk_inode::InodeType;
 use xv6fs::file::VFile;
+use xv6fs::checksum::CORRUPTED;
 use alloc::sync::{Arc, Weak};
-use spin::RwLock;
+use alloc::vec::Vec;
+use spin::{Mutex, RwLock};
 use axerrno::AxError;
 
-use crate::file::FileNode;
+use crate::file::{xattr_error, FileNode};
 
 pub struct DirNode{
     pub dirnode: VFile,
     //parentnode: VFile,
+    /// Where the last `read_dir` stopped: the index of the next entry and
+    /// its byte offset in the directory, so that listing resumes from there.
+    cursor: Mutex<(usize, u32)>,
 }
 
 impl VfsNodeOps for DirNode{
@@ -21,23 +26,27 @@ impl VfsNodeOps for DirNode{
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        todo!();
+        // nodes don't track their parent, xv6fs paths are always looked up
+        // from the root
+        None
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
         let mut root:String=String::from("/")+path+&String::from("\0");
         let path=&root;
         info!("axfs xv6fs: lookup path is {}",path);
-        match VFile::vfile_lookup(path){
-            Some(vfile)=>{
+        // absolute paths stay in the tree of this directory, e.g. a snapshot
+        match self.dirnode.vfile_try_lookup_from(path){
+            Ok(vfile)=>{
                 info!("axfs xv6fs lookup: find path {}",path);
                 if(vfile.vfile_is_dir()){
-                    Ok(Arc::new(DirNode{dirnode:vfile}))
+                    Ok(Arc::new(DirNode::from_vfile(vfile)))
                 }else {
                     Ok(Arc::new(FileNode{filenode:vfile}))
                 }
             },
-            None =>{
+            Err(CORRUPTED)=>Err(AxError::InvalidData),
+            Err(_) =>{
                 info!("axfs xv6fs lookup: not find path {}",path);
                 Err(AxError::NotFound)
             },
@@ -45,30 +54,40 @@ impl VfsNodeOps for DirNode{
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
-        let dir_vec=self.dirnode.vfile_pass_dir().unwrap();
-        let mut dir=dir_vec.iter().skip(2);
-        for (i, ent) in dirents.iter_mut().enumerate() {
-            match i + start_idx {
-                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
-                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
-                _ => {
-                    if let Some((name, node_type)) = dir.next() {
-                        //*ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
-                        if node_type==&InodeType::Directory{
-                            *ent=VfsDirEntry::new(name, VfsNodeType::Dir);
-                        }else{
-                            *ent=VfsDirEntry::new(name, VfsNodeType::File);
-                        }
-                    } else {
-                        return Ok(i);
-                    }
-                }
+        let mut cursor=self.cursor.lock();
+        let (mut idx, mut offset)=*cursor;
+        if start_idx<idx{
+            (idx, offset)=(0, 0); // rewind
+        }
+        while idx<start_idx{
+            let (skipped, next)=self.dirnode.vfile_read_dir(offset, start_idx-idx, false)
+                .map_err(dir_error)?;
+            if skipped.is_empty(){
+                return Ok(0);
             }
+            idx+=skipped.len();
+            offset=next;
         }
-        Ok(dirents.len())   
+
+        let (entries, next)=self.dirnode.vfile_read_dir(offset, dirents.len(), true)
+            .map_err(dir_error)?;
+        for (ent, entry) in dirents.iter_mut().zip(entries.iter()){
+            let ty=match entry.itype{
+                Some(InodeType::Directory)=>VfsNodeType::Dir,
+                Some(InodeType::Device) if entry.dev.map_or(false, |dev| dev.is_block())=>{
+                    VfsNodeType::BlockDevice
+                }
+                Some(InodeType::Device)=>VfsNodeType::CharDevice,
+                _=>VfsNodeType::File,
+            };
+            *ent=VfsDirEntry::new(entry.name(), ty);
+        }
+        *cursor=(idx+entries.len(), next);
+        Ok(entries.len())
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.check_writeable()?;
         let mut root:String=String::from("/")+path+&String::from("\0");
         let path=&root;
         log::info!("axfs_xv6fs: create path is {}",path);
@@ -81,6 +100,7 @@ impl VfsNodeOps for DirNode{
     }
 
     fn remove(&self, path: &str) -> VfsResult {
+        self.check_writeable()?;
         let mut root:String=String::from("/")+path+&String::from("\0");
         let path=&root;
         log::info!("axfs_xv6fs: remove path is {}",path);
@@ -88,11 +108,56 @@ impl VfsNodeOps for DirNode{
         Ok(())
     }
 
+    fn mknod(&self, path: &str, major: u32, minor: u32) -> VfsResult {
+        self.check_writeable()?;
+        let root:String=String::from("/")+path+&String::from("\0");
+        let path=&root;
+        log::info!("axfs_xv6fs: mknod path is {}, dev is ({}, {})",path,major,minor);
+        if major>i16::MAX as u32||minor>i16::MAX as u32{
+            return Err(AxError::InvalidInput);
+        }
+        VFile::vfile_mknod(path, major as i16, minor as i16).map_err(|_| AxError::Io)?;
+        Ok(())
+    }
+
+    fn getxattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.dirnode.vfile_getxattr(name).map_err(xattr_error)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.dirnode.vfile_setxattr(name, value).map_err(xattr_error)
+    }
+
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        self.dirnode.vfile_listxattr().map_err(xattr_error)
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult {
+        self.dirnode.vfile_removexattr(name).map_err(xattr_error)
+    }
+
     impl_vfs_dir_default! {}
 }
 
 impl DirNode {
     pub fn new()->Self{
-        DirNode { dirnode: VFile::init() }
+        Self::from_vfile(VFile::init())
+    }
+
+    pub fn from_vfile(dirnode:VFile)->Self{
+        DirNode { dirnode, cursor: Mutex::new((0, 0)) }
     }
-}
\ No newline at end of file
+
+    /// Directories of a snapshot can't be changed.
+    fn check_writeable(&self)->VfsResult{
+        if self.dirnode.vfile_writeable(){
+            Ok(())
+        }else{
+            Err(AxError::PermissionDenied)
+        }
+    }
+}
+/// Error of a directory that can't be listed.
+fn dir_error(e: &'static str) -> AxError {
+    if e == CORRUPTED { AxError::InvalidData } else { AxError::NotADirectory }
+}
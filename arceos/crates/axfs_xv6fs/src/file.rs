@@ -1,6 +1,17 @@
-use axfs_vfs::{impl_vfs_non_dir_default,VfsNodeAttr,VfsNodeOps,VfsResult};
+use alloc::string::String;
+use alloc::vec::Vec;
+use axerrno::AxError;
+use axfs_vfs::{impl_vfs_non_dir_default,VfsNodeAttr,VfsNodeOps,VfsNodePerm,VfsNodeType,VfsResult};
 use log::info;
+use xv6fs::checksum::CORRUPTED;
 use xv6fs::file::VFile;
+use xv6fs::snapshot::READ_ONLY;
+use xv6fs::xattr::{BAD_NAME, NOT_SUPPORTED, NO_ATTR, NO_SPACE};
+
+/// The page cache ID of the inode `inum` of `dev`.
+pub fn cache_id(dev:u32,inum:u32)->u64{
+    ((dev as u64)<<32)|inum as u64
+}
 
 pub struct FileNode{
     pub filenode: VFile,
@@ -8,23 +19,80 @@ pub struct FileNode{
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        let size=self.filenode.get_size();
-        Ok(VfsNodeAttr::new_file(size as u64,0))//这里的filesize也有问题，这感觉还不太好办，，，
+        if self.filenode.vfile_is_dev(){
+            let ty=if self.filenode.vfile_dev().is_block(){
+                VfsNodeType::BlockDevice
+            }else{
+                VfsNodeType::CharDevice
+            };
+            return Ok(VfsNodeAttr::new(VfsNodePerm::default_file(), ty, 0, 0));
+        }
+        // the data of compressed files takes less blocks than their size
+        let stat=self.filenode.vfile_stat().map_err(|_| AxError::Io)?;
+        Ok(VfsNodeAttr::new_file(stat.size as u64,(stat.phys_size/512) as u64))
+    }
+
+    fn cache_id(&self) -> Option<u64> {
+        // files of a snapshot are read-only, writes must fail at once
+        if self.filenode.vfile_is_dev()||!self.filenode.vfile_writeable(){
+            return None;
+        }
+        let stat=self.filenode.vfile_stat().ok()?;
+        Some(cache_id(stat.dev, stat.inum))
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.filenode.vfile_fsync();
+        Ok(())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
+        if !self.filenode.vfile_writeable(){
+            return Err(AxError::PermissionDenied);
+        }
         self.filenode.vfile_truncate(size);
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         info!("begin read, offset is {}",offset);
-        VfsResult::Ok(self.filenode.vfile_read(buf.as_mut_ptr() as usize, offset as usize,buf.len()).unwrap())
+        self.filenode.vfile_read(buf.as_mut_ptr() as usize, offset as usize,buf.len()).map_err(|_| AxError::Io)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        VfsResult::Ok(self.filenode.vfile_write(offset as u32,buf.as_ptr() as usize,buf.len()).unwrap())
+        if !self.filenode.vfile_writeable(){
+            return Err(AxError::PermissionDenied);
+        }
+        self.filenode.vfile_write(offset as u32,buf.as_ptr() as usize,buf.len()).map_err(|_| AxError::Io)
+    }
+
+    fn getxattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.filenode.vfile_getxattr(name).map_err(xattr_error)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        self.filenode.vfile_setxattr(name, value).map_err(xattr_error)
+    }
+
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        self.filenode.vfile_listxattr().map_err(xattr_error)
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult {
+        self.filenode.vfile_removexattr(name).map_err(xattr_error)
     }
 
     impl_vfs_non_dir_default! {}
+}
+
+pub(crate) fn xattr_error(e: &'static str) -> AxError {
+    match e {
+        NO_ATTR => AxError::NotFound,
+        BAD_NAME => AxError::InvalidInput,
+        NO_SPACE => AxError::StorageFull,
+        NOT_SUPPORTED => AxError::Unsupported,
+        READ_ONLY => AxError::PermissionDenied,
+        CORRUPTED => AxError::InvalidData,
+        _ => AxError::Io,
+    }
 }
\ No newline at end of file
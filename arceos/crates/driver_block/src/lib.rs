@@ -1,6 +1,6 @@
 //! Common traits and types for block storage device drivers (i.e. disk).
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(doc_auto_cfg)]
 #![feature(const_trait_impl)]
 
@@ -33,4 +33,14 @@ pub trait BlockDriverOps: BaseDriverOps {
 
     /// Flushes the device to write all pending data to the storage.
     fn flush(&mut self) -> DevResult;
+
+    /// Tells the device that `count` blocks from the given block are no longer
+    /// used, so that it may release them. Their content is undefined until
+    /// they are written again.
+    ///
+    /// Returns [`DevError::Unsupported`] if the device can't discard blocks,
+    /// which callers may ignore.
+    fn discard(&mut self, _block_id: u64, _count: u64) -> DevResult {
+        Err(DevError::Unsupported)
+    }
 }
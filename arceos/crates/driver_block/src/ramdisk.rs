@@ -92,8 +92,43 @@ impl BlockDriverOps for RamDisk {
     fn flush(&mut self) -> DevResult {
         Ok(())
     }
+
+    /// Zeroes the blocks, the memory itself stays allocated.
+    fn discard(&mut self, block_id: u64, count: u64) -> DevResult {
+        let offset = block_id as usize * BLOCK_SIZE;
+        let len = count as usize * BLOCK_SIZE;
+        if offset + len > self.size {
+            return Err(DevError::Io);
+        }
+        self.data[offset..offset + len].fill(0);
+        Ok(())
+    }
 }
 
 const fn align_up(val: usize) -> usize {
     (val + BLOCK_SIZE - 1) & !(BLOCK_SIZE - 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discard() {
+        let mut disk = RamDisk::from(&[0xaa; 4 * BLOCK_SIZE]);
+        disk.discard(1, 2).unwrap();
+        let mut buf = [0; 4 * BLOCK_SIZE];
+        disk.read_block(0, &mut buf).unwrap();
+        assert!(buf[..BLOCK_SIZE].iter().all(|&b| b == 0xaa));
+        assert!(buf[BLOCK_SIZE..3 * BLOCK_SIZE].iter().all(|&b| b == 0));
+        assert!(buf[3 * BLOCK_SIZE..].iter().all(|&b| b == 0xaa));
+
+        // the blocks are usable again
+        disk.write_block(2, &[0x55; BLOCK_SIZE]).unwrap();
+        disk.read_block(2, &mut buf[..BLOCK_SIZE]).unwrap();
+        assert!(buf[..BLOCK_SIZE].iter().all(|&b| b == 0x55));
+
+        assert!(matches!(disk.discard(3, 2), Err(DevError::Io)));
+        assert!(disk.discard(4, 0).is_ok());
+    }
+}
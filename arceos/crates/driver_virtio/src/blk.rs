@@ -1,11 +1,79 @@
-use crate::as_dev_err;
+use crate::queue::VirtQueue;
+use core::ptr::{addr_of, read_volatile, NonNull};
 use driver_block::BlockDriverOps;
-use driver_common::{BaseDriverOps, DevResult, DeviceType};
-use virtio_drivers::{device::blk::VirtIOBlk as InnerDev, transport::Transport, Hal};
+use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::{transport::Transport, Hal};
+
+/// Size of a block, the unit of the device.
+const SECTOR_SIZE: usize = virtio_drivers::device::blk::SECTOR_SIZE;
+
+const QUEUE: u16 = 0;
+
+/// The device takes DISCARD requests.
+const VIRTIO_BLK_F_DISCARD: u64 = 1 << 13;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_DISCARD: u32 = 11;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// Segments of one DISCARD request at most.
+const MAX_SEGMENTS: usize = 16;
+
+/// The configuration space, up to the fields of DISCARD. Read in 32-bit
+/// words, which the legacy transports need.
+#[repr(C)]
+struct BlkConfig {
+    capacity_low: u32,
+    capacity_high: u32,
+    size_max: u32,
+    seg_max: u32,
+    geometry: u32,
+    blk_size: u32,
+    topology: [u32; 2],
+    writeback: u32,
+    max_discard_sectors: u32,
+    max_discard_seg: u32,
+    discard_sector_alignment: u32,
+}
+
+#[repr(C)]
+struct BlkReq {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct DiscardSegment {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+/// What the device takes in a DISCARD request.
+struct DiscardLimits {
+    max_sectors: u64,
+    max_segments: usize,
+    alignment: u64,
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
 
 /// The VirtIO block device driver.
+///
+/// `virtio-drivers` 0.4 neither negotiates `VIRTIO_BLK_F_DISCARD` nor sends
+/// DISCARD requests, so the requests go through our own queue.
 pub struct VirtIoBlkDev<H: Hal, T: Transport> {
-    inner: InnerDev<H, T>,
+    transport: T,
+    queue: VirtQueue<H>,
+    capacity: u64,
+    discard: Option<DiscardLimits>,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoBlkDev<H, T> {}
@@ -14,11 +82,80 @@ unsafe impl<H: Hal, T: Transport> Sync for VirtIoBlkDev<H, T> {}
 impl<H: Hal, T: Transport> VirtIoBlkDev<H, T> {
     /// Creates a new driver instance and initializes the device, or returns
     /// an error if any step fails.
-    pub fn try_new(transport: T) -> DevResult<Self> {
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        let mut features = 0;
+        transport.begin_init(|offered| {
+            // negotiate these flags only
+            features = offered & VIRTIO_BLK_F_DISCARD;
+            features
+        });
+
+        let config: NonNull<BlkConfig> = transport.config_space().map_err(crate::as_dev_err)?;
+        let config = config.as_ptr();
+        // Safe because config points to the configuration space of the device.
+        let (capacity, discard) = unsafe {
+            let capacity = read_volatile(addr_of!((*config).capacity_low)) as u64
+                | (read_volatile(addr_of!((*config).capacity_high)) as u64) << 32;
+            let discard = (features & VIRTIO_BLK_F_DISCARD != 0).then(|| {
+                let alignment =
+                    read_volatile(addr_of!((*config).discard_sector_alignment)).max(1) as u64;
+                let max_sectors = read_volatile(addr_of!((*config).max_discard_sectors)) as u64;
+                DiscardLimits {
+                    // segments after the first one start aligned too
+                    max_sectors: (max_sectors / alignment * alignment).max(alignment),
+                    max_segments: (read_volatile(addr_of!((*config).max_discard_seg)) as usize)
+                        .clamp(1, MAX_SEGMENTS),
+                    alignment,
+                }
+            });
+            (capacity, discard)
+        };
+        info!(
+            "found a block device of size {}KB, discard: {}",
+            capacity / 2,
+            discard.is_some()
+        );
+
+        let queue = VirtQueue::new(&mut transport, QUEUE)?;
+        transport.finish_init();
         Ok(Self {
-            inner: InnerDev::new(transport).map_err(as_dev_err)?,
+            transport,
+            queue,
+            capacity,
+            discard,
         })
     }
+
+    /// Sends a request whose header is followed by `data`, and the device
+    /// writes `output` if there is one.
+    fn request(&mut self, req: BlkReq, data: &[u8], output: Option<&mut [u8]>) -> DevResult {
+        let mut status = [0xffu8];
+        let inputs = [as_bytes(&req), data];
+        let inputs = if data.is_empty() { &inputs[..1] } else { &inputs[..] };
+        match output {
+            Some(buf) => self
+                .queue
+                .request(&mut self.transport, inputs, &mut [buf, &mut status[..]])?,
+            None => self
+                .queue
+                .request(&mut self.transport, inputs, &mut [&mut status[..]])?,
+        }
+        match status[0] {
+            VIRTIO_BLK_S_OK => Ok(()),
+            VIRTIO_BLK_S_UNSUPP => Err(DevError::Unsupported),
+            _ => Err(DevError::Io),
+        }
+    }
+
+    fn check_range(&self, block_id: u64, len: usize) -> DevResult {
+        if len == 0 || len % SECTOR_SIZE != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        if block_id + (len / SECTOR_SIZE) as u64 > self.capacity {
+            return Err(DevError::Io);
+        }
+        Ok(())
+    }
 }
 
 impl<H: Hal, T: Transport> const BaseDriverOps for VirtIoBlkDev<H, T> {
@@ -34,27 +171,70 @@ impl<H: Hal, T: Transport> const BaseDriverOps for VirtIoBlkDev<H, T> {
 impl<H: Hal, T: Transport> BlockDriverOps for VirtIoBlkDev<H, T> {
     #[inline]
     fn num_blocks(&self) -> u64 {
-        self.inner.capacity()
+        self.capacity
     }
 
     #[inline]
     fn block_size(&self) -> usize {
-        virtio_drivers::device::blk::SECTOR_SIZE
+        SECTOR_SIZE
     }
 
     fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
-        self.inner
-            .read_block(block_id as _, buf)
-            .map_err(as_dev_err)
+        self.check_range(block_id, buf.len())?;
+        let req = BlkReq {
+            type_: VIRTIO_BLK_T_IN,
+            reserved: 0,
+            sector: block_id,
+        };
+        self.request(req, &[], Some(buf))
     }
 
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
-        self.inner
-            .write_block(block_id as _, buf)
-            .map_err(as_dev_err)
+        self.check_range(block_id, buf.len())?;
+        let req = BlkReq {
+            type_: VIRTIO_BLK_T_OUT,
+            reserved: 0,
+            sector: block_id,
+        };
+        self.request(req, buf, None)
     }
 
     fn flush(&mut self) -> DevResult {
         Ok(())
     }
+
+    /// Sends DISCARD requests if the device takes them. The blocks at the
+    /// ends of the range that are not aligned as the device wants are kept.
+    fn discard(&mut self, block_id: u64, count: u64) -> DevResult {
+        let limits = self.discard.as_ref().ok_or(DevError::Unsupported)?;
+        if block_id + count > self.capacity {
+            return Err(DevError::Io);
+        }
+        let (max_sectors, max_segments, align) =
+            (limits.max_sectors, limits.max_segments, limits.alignment);
+        let mut sector = (block_id + align - 1) / align * align;
+        let end = (block_id + count) / align * align;
+        while sector < end {
+            let mut segments = [DiscardSegment::default(); MAX_SEGMENTS];
+            let mut n = 0;
+            while n < max_segments && sector < end {
+                let len = (end - sector).min(max_sectors);
+                segments[n] = DiscardSegment {
+                    sector,
+                    num_sectors: len as u32,
+                    flags: 0,
+                };
+                sector += len;
+                n += 1;
+            }
+            let req = BlkReq {
+                type_: VIRTIO_BLK_T_DISCARD,
+                reserved: 0,
+                sector: 0,
+            };
+            let data = as_bytes(&segments);
+            self.request(req, &data[..n * core::mem::size_of::<DiscardSegment>()], None)?;
+        }
+        Ok(())
+    }
 }
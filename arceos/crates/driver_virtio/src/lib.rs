@@ -23,6 +23,8 @@ mod blk;
 mod gpu;
 #[cfg(feature = "net")]
 mod net;
+#[cfg(feature = "block")]
+mod queue;
 
 #[cfg(feature = "block")]
 pub use self::blk::VirtIoBlkDev;
@@ -75,6 +77,23 @@ pub fn probe_mmio_device(
     }
 }
 
+/// Acknowledges the interrupt of the VirtIO MMIO device at the given base
+/// address, e.g., in its interrupt handler.
+///
+/// Returns `true` if the device had raised an interrupt, or `false` if no
+/// device is found.
+#[cfg(feature = "bus-mmio")]
+pub fn ack_mmio_interrupt(reg_base: *mut u8) -> bool {
+    use core::ptr::NonNull;
+    use transport::mmio::VirtIOHeader;
+
+    let header = NonNull::new(reg_base as *mut VirtIOHeader).unwrap();
+    match unsafe { MmioTransport::new(header) } {
+        Ok(mut transport) => transport.ack_interrupt(),
+        Err(_) => false,
+    }
+}
+
 const fn as_dev_type(t: transport::DeviceType) -> Option<DeviceType> {
     use transport::DeviceType::*;
     match t {
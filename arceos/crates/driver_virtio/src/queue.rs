@@ -0,0 +1,175 @@
+//! A split virtqueue that runs one request at a time.
+//!
+//! The queue of `virtio-drivers` 0.4 is private, so devices that send
+//! requests the crate doesn't know build on this one. The layout is the
+//! legacy one, which the modern transports accept as well: the descriptors
+//! and the available ring in the first page, the used ring in the second.
+
+use core::mem::size_of;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{fence, Ordering};
+
+use driver_common::{DevError, DevResult};
+use virtio_drivers::{transport::Transport, BufferDirection, Hal, PhysAddr, PAGE_SIZE};
+
+/// Number of descriptors, and of entries of both rings.
+pub const QUEUE_SIZE: usize = 16;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+const PAGES: usize = 2;
+
+/// A virtqueue of `QUEUE_SIZE` entries.
+pub struct VirtQueue<H: Hal> {
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    index: u16,
+    avail_idx: u16,
+    _hal: core::marker::PhantomData<H>,
+}
+
+impl<H: Hal> VirtQueue<H> {
+    /// Allocates the queue `index` and tells the device where it is.
+    pub fn new<T: Transport>(transport: &mut T, index: u16) -> DevResult<Self> {
+        if transport.queue_used(index) {
+            return Err(DevError::AlreadyExists);
+        }
+        if transport.max_queue_size() < QUEUE_SIZE as u32 {
+            return Err(DevError::InvalidParam);
+        }
+        let (paddr, vaddr) = H::dma_alloc(PAGES, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        // the device reads zeroed rings
+        unsafe { ptr::write_bytes(vaddr.as_ptr(), 0, PAGES * PAGE_SIZE) };
+        let avail = size_of::<Descriptor>() * QUEUE_SIZE;
+        transport.queue_set(
+            index,
+            QUEUE_SIZE as u32,
+            paddr,
+            paddr + avail,
+            paddr + PAGE_SIZE,
+        );
+        Ok(Self {
+            paddr,
+            vaddr,
+            index,
+            avail_idx: 0,
+            _hal: core::marker::PhantomData,
+        })
+    }
+
+    fn desc(&self) -> *mut Descriptor {
+        self.vaddr.as_ptr() as *mut Descriptor
+    }
+
+    fn avail(&self) -> *mut AvailRing {
+        unsafe { self.vaddr.as_ptr().add(size_of::<Descriptor>() * QUEUE_SIZE) as *mut AvailRing }
+    }
+
+    fn used(&self) -> *mut UsedRing {
+        unsafe { self.vaddr.as_ptr().add(PAGE_SIZE) as *mut UsedRing }
+    }
+
+    /// Passes the buffers to the device, `inputs` for it to read and then
+    /// `outputs` for it to write, and spins until it has used them.
+    pub fn request<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> DevResult {
+        let count = inputs.len() + outputs.len();
+        if count == 0 || count > QUEUE_SIZE {
+            return Err(DevError::InvalidParam);
+        }
+        let mut buffers = [None; QUEUE_SIZE];
+        let directed = inputs
+            .iter()
+            .map(|buf| (NonNull::from(*buf), BufferDirection::DriverToDevice))
+            .chain(
+                outputs
+                    .iter_mut()
+                    .map(|buf| (NonNull::from(&mut **buf), BufferDirection::DeviceToDriver)),
+            );
+        for (slot, buf) in buffers.iter_mut().zip(directed) {
+            *slot = Some(buf);
+        }
+        let mut shared = [0; QUEUE_SIZE];
+        // only one request is in flight, its chain starts at descriptor 0
+        for (i, &(buf, direction)) in buffers.iter().flatten().enumerate() {
+            shared[i] = unsafe { H::share(buf, direction) };
+            let mut flags = if i + 1 < count { DESC_F_NEXT } else { 0 };
+            if direction == BufferDirection::DeviceToDriver {
+                flags |= DESC_F_WRITE;
+            }
+            let desc = Descriptor {
+                addr: shared[i] as u64,
+                len: buf.len() as u32,
+                flags,
+                next: i as u16 + 1,
+            };
+            unsafe { ptr::write_volatile(self.desc().add(i), desc) };
+        }
+
+        let avail = self.avail();
+        let used = self.used();
+        unsafe {
+            let slot = self.avail_idx as usize % QUEUE_SIZE;
+            ptr::write_volatile(ptr::addr_of_mut!((*avail).ring[slot]), 0);
+            // the device sees the chain before the new index
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            ptr::write_volatile(ptr::addr_of_mut!((*avail).idx), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+        transport.notify(self.index);
+        while unsafe { ptr::read_volatile(ptr::addr_of!((*used).idx)) } != self.avail_idx {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+
+        for (i, &(buf, direction)) in buffers.iter().flatten().enumerate() {
+            unsafe { H::unshare(shared[i], buf, direction) };
+        }
+        Ok(())
+    }
+}
+
+impl<H: Hal> Drop for VirtQueue<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, PAGES) };
+    }
+}
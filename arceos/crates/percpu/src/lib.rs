@@ -3,8 +3,9 @@
 //! All per-CPU data is placed into several contiguous memory regions called
 //! **per-CPU data areas**, the number of which is the number of CPUs. Each CPU
 //! has its own per-CPU data area. The architecture-specific thread pointer
-//! register (e.g., `GS_BASE` on x86_64) is set to the base address of the area
-//! on initialization.
+//! register (`GS_BASE` on x86_64, `gp` on RISC-V, `TPIDR_EL1` on AArch64) is
+//! set to the base address of the area on initialization. The registers for
+//! thread-local storage (`FS_BASE`, `tp` and `TPIDR_EL0`) are left untouched.
 //!
 //! When accessing the per-CPU data on the current CPU, it first use the thread
 //! pointer register to obtain the corresponding per-CPU data area, and then add
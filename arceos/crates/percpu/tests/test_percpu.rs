@@ -93,6 +93,24 @@ fn test_percpu() {
     assert_eq!(U64.read_current(), 0xa2ce_a2ce_a2ce_a2ce);
     assert_eq!(USIZE.read_current(), 0xffff_0000);
 
+    #[cfg(not(feature = "sp-naive"))]
+    unsafe {
+        assert_eq!(USIZE.remote_ptr(0), USIZE.current_ptr());
+        assert_eq!(
+            STRUCT.remote_ptr(3) as usize,
+            percpu_area_base(3) + STRUCT.offset()
+        );
+        // the data of other CPUs is not touched
+        assert_eq!(*USIZE.remote_ref_raw(1), 0);
+        *(USIZE.remote_ptr(1) as *mut usize) = 0x1234;
+        assert_eq!(*USIZE.remote_ref_raw(1), 0x1234);
+        assert_eq!(USIZE.read_current(), 0xffff_0000);
+    }
+    #[cfg(feature = "sp-naive")]
+    unsafe {
+        assert_eq!(STRUCT.remote_ptr(0), STRUCT.current_ptr());
+    }
+
     STRUCT.with_current(|s| {
         println!("struct.foo value: {:#x}", s.foo);
         println!("struct.bar value: {}", s.bar);
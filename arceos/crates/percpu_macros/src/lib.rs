@@ -99,6 +99,7 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let offset = arch::gen_offset(inner_symbol_name);
     let current_ptr = arch::gen_current_ptr(inner_symbol_name, ty);
+    let remote_ptr = arch::gen_remote_ptr(inner_symbol_name, ty);
     quote! {
         #[cfg_attr(not(target_os = "macos"), link_section = ".percpu")] // unimplemented on macos
         #(#attrs)*
@@ -149,6 +150,28 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
                 &mut *(self.current_ptr() as *mut #ty)
             }
 
+            /// Returns the raw pointer of this per-CPU data on the given CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that the CPU ID is valid, and that the data
+            /// is not accessed by that CPU without synchronization.
+            #[inline]
+            pub unsafe fn remote_ptr(&self, cpu_id: usize) -> *const #ty {
+                #remote_ptr
+            }
+
+            /// Returns the reference of this per-CPU data on the given CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that the CPU ID is valid, and that the data
+            /// is not accessed by that CPU without synchronization.
+            #[inline]
+            pub unsafe fn remote_ref_raw(&self, cpu_id: usize) -> &#ty {
+                &*self.remote_ptr(cpu_id)
+            }
+
             /// Manipulate the per-CPU data on the current CPU in the given closure.
             /// Preemption will be disabled during the call.
             pub fn with_current<F, T>(&self, f: F) -> T
@@ -2,7 +2,7 @@ use alloc::{collections::BTreeMap, sync::Arc};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicIsize, Ordering};
 
-use crate::BaseScheduler;
+use crate::{BaseScheduler, SchedPolicy};
 
 /// task for CFS
 pub struct CFSTask<T> {
@@ -49,11 +49,15 @@ impl<T> CFSTask<T> {
         }
     }
 
-    fn get_id(&self) -> isize {
+    pub(crate) fn get_nice(&self) -> isize {
+        self.nice.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn get_id(&self) -> isize {
         self.id.load(Ordering::Acquire)
     }
 
-    fn get_vruntime(&self) -> isize {
+    pub(crate) fn get_vruntime(&self) -> isize {
         if self.nice.load(Ordering::Acquire) == 0 {
             self.init_vruntime.load(Ordering::Acquire) + self.delta.load(Ordering::Acquire)
         } else {
@@ -62,13 +66,13 @@ impl<T> CFSTask<T> {
         }
     }
 
-    fn set_vruntime(&self, v: isize) {
+    pub(crate) fn set_vruntime(&self, v: isize) {
         self.init_vruntime.store(v, Ordering::Release);
     }
 
     // Simple Implementation: no change in vruntime.
     // Only modifying priority of current process is supported currently.
-    fn set_priority(&self, nice: isize) {
+    pub(crate) fn set_priority(&self, nice: isize) {
         let current_init_vruntime = self.get_vruntime();
         self.init_vruntime
             .store(current_init_vruntime, Ordering::Release);
@@ -76,11 +80,11 @@ impl<T> CFSTask<T> {
         self.nice.store(nice, Ordering::Release);
     }
 
-    fn set_id(&self, id: isize) {
+    pub(crate) fn set_id(&self, id: isize) {
         self.id.store(id, Ordering::Release);
     }
 
-    fn task_tick(&self) {
+    pub(crate) fn task_tick(&self) {
         self.delta.fetch_add(1, Ordering::Release);
     }
 
@@ -164,6 +168,15 @@ impl<T> BaseScheduler for CFScheduler<T> {
         }
     }
 
+    fn steal_task<F>(&mut self, mut filter: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        // the largest vruntime runs last
+        let task = self.ready_queue.values().rev().find(|t| filter(t))?.clone();
+        self.remove_task(&task)
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
         let taskid = self.id_pool.fetch_add(1, Ordering::Release);
         prev.set_id(taskid);
@@ -185,4 +198,11 @@ impl<T> BaseScheduler for CFScheduler<T> {
             false
         }
     }
+
+    fn set_policy(&mut self, task: &Self::SchedItem, policy: SchedPolicy) -> bool {
+        match policy {
+            SchedPolicy::Normal(nice) => self.set_priority(task, nice),
+            _ => false,
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use alloc::sync::Arc;
+use core::mem::ManuallyDrop;
 use core::ops::Deref;
 
 use linked_list::{Adapter, Links, List};
 
-use crate::BaseScheduler;
+use crate::{BaseScheduler, SchedPolicy};
 
 /// A task wrapper for the [`FifoScheduler`].
 ///
@@ -88,6 +89,24 @@ impl<T> BaseScheduler for FifoScheduler<T> {
         self.ready_queue.pop_front()
     }
 
+    fn steal_task<F>(&mut self, mut filter: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        // the last match, the one closest to the back
+        let mut found = None;
+        let mut cursor = self.ready_queue.cursor_front();
+        while let Some(entry) = cursor.current() {
+            // the list owns the reference, it must not be dropped here
+            let task = ManuallyDrop::new(unsafe { Arc::from_raw(entry as *const FifoTask<T>) });
+            if filter(&task) {
+                found = Some(task);
+            }
+            cursor.move_next();
+        }
+        found.and_then(|task| unsafe { self.ready_queue.remove(&task) })
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
         self.ready_queue.push_back(prev);
     }
@@ -99,4 +118,8 @@ impl<T> BaseScheduler for FifoScheduler<T> {
     fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
         false
     }
+
+    fn set_policy(&mut self, _task: &Self::SchedItem, policy: SchedPolicy) -> bool {
+        policy == SchedPolicy::Normal(0)
+    }
 }
@@ -4,6 +4,8 @@
 //!
 //! - [`FifoScheduler`]: FIFO (First-In-First-Out) scheduler (cooperative).
 //! - [`RRScheduler`]: Round-robin scheduler (preemptive).
+//! - [`CFScheduler`]: Completely fair scheduler (preemptive).
+//! - [`RTScheduler`]: Deadline, real-time and normal task classes (preemptive).
 
 #![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
@@ -11,6 +13,7 @@
 mod cfs;
 mod fifo;
 mod round_robin;
+mod rt;
 
 #[cfg(test)]
 mod tests;
@@ -20,6 +23,7 @@ extern crate alloc;
 pub use cfs::{CFSTask, CFScheduler};
 pub use fifo::{FifoScheduler, FifoTask};
 pub use round_robin::{RRScheduler, RRTask};
+pub use rt::{DeadlineParams, RTScheduler, RTTask, SchedPolicy, RT_PRIO_LEVELS};
 
 /// The base scheduler trait that all schedulers should implement.
 ///
@@ -48,6 +52,13 @@ pub trait BaseScheduler {
     /// Returns [`None`] if there is not runnable task.
     fn pick_next_task(&mut self) -> Option<Self::SchedItem>;
 
+    /// Removes a task for which `filter` returns `true`, to run it on another
+    /// CPU. Tasks that are unlikely to run soon are tried first. Returns
+    /// [`None`] if there is no such task.
+    fn steal_task<F>(&mut self, filter: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool;
+
     /// Puts the previous task back to the scheduler. The previous task is
     /// usually placed at the end of the ready queue, making it less likely
     /// to be re-scheduled.
@@ -65,4 +76,9 @@ pub trait BaseScheduler {
 
     /// set priority for a task
     fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool;
+
+    /// Sets the scheduling policy of a task, which may be in the scheduler or
+    /// running. Returns `false` if the scheduler doesn't support the policy,
+    /// or can't admit the task with it.
+    fn set_policy(&mut self, task: &Self::SchedItem, policy: SchedPolicy) -> bool;
 }
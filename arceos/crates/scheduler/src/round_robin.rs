@@ -2,7 +2,7 @@ use alloc::{collections::VecDeque, sync::Arc};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicIsize, Ordering};
 
-use crate::BaseScheduler;
+use crate::{BaseScheduler, SchedPolicy};
 
 /// A task wrapper for the [`RRScheduler`].
 ///
@@ -92,6 +92,16 @@ impl<T, const S: usize> BaseScheduler for RRScheduler<T, S> {
         self.ready_queue.pop_front()
     }
 
+    fn steal_task<F>(&mut self, filter: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        self.ready_queue
+            .iter()
+            .rposition(filter)
+            .and_then(|idx| self.ready_queue.remove(idx))
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
         if prev.time_slice() > 0 && preempt {
             self.ready_queue.push_front(prev)
@@ -109,4 +119,8 @@ impl<T, const S: usize> BaseScheduler for RRScheduler<T, S> {
     fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
         false
     }
+
+    fn set_policy(&mut self, _task: &Self::SchedItem, policy: SchedPolicy) -> bool {
+        policy == SchedPolicy::Normal(0)
+    }
 }
@@ -0,0 +1,542 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicI64, AtomicIsize, AtomicU64, AtomicU8, Ordering};
+
+use crate::{BaseScheduler, CFSTask};
+
+/// Number of real-time priority levels, from 0 (lowest) to 99.
+pub const RT_PRIO_LEVELS: usize = 100;
+
+/// Time slice of [`SchedPolicy::RoundRobin`] tasks, in timer ticks.
+const RR_TIME_SLICE: isize = 5;
+
+/// Fixed-point unit of the CPU bandwidth, `1 << BW_SHIFT` is the whole CPU.
+const BW_SHIFT: u32 = 20;
+/// Bandwidth that deadline tasks may reserve together: 95%, so that the other
+/// tasks are not starved.
+const BW_LIMIT: u64 = (95 << BW_SHIFT) / 100;
+
+/// Bandwidth reserved by the deadline tasks of all schedulers. A task may
+/// wake up on another CPU than the one that admitted it, so the reservations
+/// are not kept per scheduler, and no CPU can be overcommitted.
+pub(crate) static DL_BANDWIDTH: AtomicU64 = AtomicU64::new(0);
+
+/// Parameters of a [`SchedPolicy::Deadline`] task, in timer ticks.
+///
+/// The task runs for `runtime` ticks in every `period`, and should be done
+/// `deadline` ticks after the period starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineParams {
+    /// CPU time reserved for the task in each period.
+    pub runtime: u64,
+    /// Relative deadline, from the start of the period.
+    pub deadline: u64,
+    /// Length of the period.
+    pub period: u64,
+}
+
+impl DeadlineParams {
+    fn is_valid(&self) -> bool {
+        0 < self.runtime && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+
+    fn bandwidth(&self) -> u64 {
+        (self.runtime << BW_SHIFT) / self.period
+    }
+}
+
+/// Scheduling policy of a task, see [`RTScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// A normal task, sharing the CPU with the other normal tasks according
+    /// to its nice value, from -20 to 19.
+    Normal(isize),
+    /// A real-time task with a fixed priority from 0 to 99, that runs until
+    /// it blocks or yields, or a task of higher priority is ready.
+    Fifo(u8),
+    /// Like [`SchedPolicy::Fifo`], but the tasks with the same priority take
+    /// turns every time slice.
+    RoundRobin(u8),
+    /// An earliest-deadline-first task.
+    Deadline(DeadlineParams),
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Normal = 0,
+    Fifo = 1,
+    RoundRobin = 2,
+    Deadline = 3,
+}
+
+impl From<u8> for Class {
+    fn from(class: u8) -> Self {
+        match class {
+            0 => Self::Normal,
+            1 => Self::Fifo,
+            2 => Self::RoundRobin,
+            3 => Self::Deadline,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// A task wrapper for the [`RTScheduler`].
+///
+/// It holds the scheduling policy of the task, and its state in the class
+/// of the policy. It's a normal task with nice value 0 when created.
+pub struct RTTask<T> {
+    cfs: CFSTask<T>,
+    class: AtomicU8,
+    rt_prio: AtomicU8,
+    time_slice: AtomicIsize,
+    dl_runtime: AtomicU64,
+    dl_deadline: AtomicU64,
+    dl_period: AtomicU64,
+    abs_deadline: AtomicU64,
+    runtime_left: AtomicI64,
+}
+
+impl<T> RTTask<T> {
+    /// Creates a new [`RTTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            cfs: CFSTask::new(inner),
+            class: AtomicU8::new(Class::Normal as u8),
+            rt_prio: AtomicU8::new(0),
+            time_slice: AtomicIsize::new(RR_TIME_SLICE),
+            dl_runtime: AtomicU64::new(0),
+            dl_deadline: AtomicU64::new(0),
+            dl_period: AtomicU64::new(0),
+            abs_deadline: AtomicU64::new(0),
+            runtime_left: AtomicI64::new(0),
+        }
+    }
+
+    /// Returns the scheduling policy of the task.
+    pub fn policy(&self) -> SchedPolicy {
+        match self.class() {
+            Class::Normal => SchedPolicy::Normal(self.cfs.get_nice()),
+            Class::Fifo => SchedPolicy::Fifo(self.rt_prio()),
+            Class::RoundRobin => SchedPolicy::RoundRobin(self.rt_prio()),
+            Class::Deadline => SchedPolicy::Deadline(self.dl_params()),
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        self.cfs.inner()
+    }
+
+    fn class(&self) -> Class {
+        self.class.load(Ordering::Acquire).into()
+    }
+
+    fn rt_prio(&self) -> u8 {
+        self.rt_prio.load(Ordering::Acquire)
+    }
+
+    fn dl_params(&self) -> DeadlineParams {
+        DeadlineParams {
+            runtime: self.dl_runtime.load(Ordering::Acquire),
+            deadline: self.dl_deadline.load(Ordering::Acquire),
+            period: self.dl_period.load(Ordering::Acquire),
+        }
+    }
+
+    fn bandwidth(&self) -> u64 {
+        match self.class() {
+            Class::Deadline => self.dl_params().bandwidth(),
+            _ => 0,
+        }
+    }
+
+    fn abs_deadline(&self) -> u64 {
+        self.abs_deadline.load(Ordering::Acquire)
+    }
+
+    /// Start of the period after the current one.
+    fn next_period(&self) -> u64 {
+        let params = self.dl_params();
+        self.abs_deadline() - params.deadline + params.period
+    }
+
+    fn runtime_left(&self) -> i64 {
+        self.runtime_left.load(Ordering::Acquire)
+    }
+
+    /// Starts a new period at `now`, with the whole runtime.
+    fn replenish(&self, now: u64) {
+        let params = self.dl_params();
+        self.abs_deadline
+            .store(now + params.deadline, Ordering::Release);
+        self.runtime_left
+            .store(params.runtime as i64, Ordering::Release);
+    }
+}
+
+impl<T> Deref for RTTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.cfs.inner()
+    }
+}
+
+/// A multi-class scheduler with deadline, real-time and normal tasks.
+///
+/// Each task has a [`SchedPolicy`], set with [`BaseScheduler::set_policy`].
+/// A ready deadline task always runs before the real-time tasks, which always
+/// run before the normal tasks:
+///
+/// - Deadline tasks are scheduled by earliest deadline first (EDF). A task
+///   is admitted only if the bandwidth (`runtime / period`) of all deadline
+///   tasks, of all schedulers, stays below 95%. A task that uses up its
+///   runtime is throttled: it doesn't run until its next period starts, and
+///   gets the runtime of that period then (constant bandwidth server).
+/// - Real-time tasks run by priority, with one FIFO queue for each of the
+///   [`RT_PRIO_LEVELS`] levels. Round-robin tasks are moved to the back of
+///   their queue when their time slice runs out.
+/// - Normal tasks are scheduled as in the [`CFScheduler`](crate::CFScheduler).
+///
+/// Time is counted in the ticks passed to [`BaseScheduler::task_tick`], so
+/// it stops while no task runs. Then, rather than leaving the CPU idle, the
+/// throttled task with the earliest period starts it early. Deadline tasks
+/// are never stolen by [`BaseScheduler::steal_task`].
+pub struct RTScheduler<T> {
+    dl_queue: BTreeMap<(u64, isize), Arc<RTTask<T>>>, // (deadline, taskid)
+    dl_throttled: BTreeMap<(u64, isize), Arc<RTTask<T>>>, // (next period, taskid)
+    rt_queues: [VecDeque<Arc<RTTask<T>>>; RT_PRIO_LEVELS],
+    rt_bitmap: u128, // bit `i` is set if `rt_queues[i]` is not empty
+    cfs_queue: BTreeMap<(isize, isize), Arc<RTTask<T>>>, // (vruntime, taskid)
+    min_vruntime: isize,
+    id_pool: isize,
+    clock: u64,
+}
+
+impl<T> RTScheduler<T> {
+    const EMPTY_QUEUE: VecDeque<Arc<RTTask<T>>> = VecDeque::new();
+
+    /// Creates a new empty [`RTScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            dl_queue: BTreeMap::new(),
+            dl_throttled: BTreeMap::new(),
+            rt_queues: [Self::EMPTY_QUEUE; RT_PRIO_LEVELS],
+            rt_bitmap: 0,
+            cfs_queue: BTreeMap::new(),
+            min_vruntime: 0,
+            id_pool: 0,
+            clock: 0,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time"
+    }
+
+    fn highest_rt_prio(&self) -> Option<usize> {
+        match self.rt_bitmap {
+            0 => None,
+            bitmap => Some(127 - bitmap.leading_zeros() as usize),
+        }
+    }
+
+    fn update_min_vruntime(&mut self) {
+        if let Some(((vruntime, _), _)) = self.cfs_queue.first_key_value() {
+            self.min_vruntime = self.min_vruntime.max(*vruntime);
+        }
+    }
+
+    /// Puts a task in the queue of its class, at the front of the real-time
+    /// queue if `front`. A deadline task without runtime left waits for its
+    /// next period.
+    fn enqueue(&mut self, task: Arc<RTTask<T>>, front: bool) {
+        let taskid = self.id_pool;
+        self.id_pool += 1;
+        task.cfs.set_id(taskid);
+        match task.class() {
+            Class::Deadline => {
+                if task.runtime_left() <= 0 {
+                    let next_period = task.next_period();
+                    if next_period > self.clock {
+                        self.dl_throttled.insert((next_period, taskid), task);
+                        return;
+                    }
+                    task.replenish(self.clock);
+                }
+                self.dl_queue.insert((task.abs_deadline(), taskid), task);
+            }
+            Class::Fifo | Class::RoundRobin => {
+                let prio = task.rt_prio() as usize;
+                if front {
+                    self.rt_queues[prio].push_front(task);
+                } else {
+                    self.rt_queues[prio].push_back(task);
+                }
+                self.rt_bitmap |= 1 << prio;
+            }
+            Class::Normal => {
+                self.cfs_queue
+                    .insert((task.cfs.get_vruntime(), taskid), task);
+                self.update_min_vruntime();
+            }
+        }
+    }
+
+    /// Makes the throttled tasks whose next period has started ready, or the
+    /// first one if `force`.
+    fn unthrottle(&mut self, force: bool) {
+        while let Some(entry) = self.dl_throttled.first_entry() {
+            let next_period = entry.key().0;
+            if next_period > self.clock && !force {
+                break;
+            }
+            let task = entry.remove();
+            task.replenish(next_period.min(self.clock));
+            self.enqueue(task, false);
+            if force {
+                break;
+            }
+        }
+    }
+
+    fn remove_rt(&mut self, prio: usize, idx: usize) -> Option<Arc<RTTask<T>>> {
+        let task = self.rt_queues[prio].remove(idx);
+        if self.rt_queues[prio].is_empty() {
+            self.rt_bitmap &= !(1 << prio);
+        }
+        task
+    }
+
+    /// Whether a task of a higher class or priority than `current` is ready.
+    fn has_higher(&self, current: &RTTask<T>) -> bool {
+        let dl_first = self.dl_queue.first_key_value().map(|((d, _), _)| *d);
+        match current.class() {
+            Class::Deadline => dl_first.map_or(false, |d| d < current.abs_deadline()),
+            Class::Fifo | Class::RoundRobin => {
+                dl_first.is_some()
+                    || self
+                        .highest_rt_prio()
+                        .map_or(false, |p| p > current.rt_prio() as usize)
+            }
+            Class::Normal => dl_first.is_some() || self.rt_bitmap != 0,
+        }
+    }
+}
+
+impl<T> BaseScheduler for RTScheduler<T> {
+    type SchedItem = Arc<RTTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        match task.class() {
+            Class::Deadline => {
+                if task.runtime_left() > 0 {
+                    // a new period if the last one is over
+                    if task.abs_deadline() <= self.clock {
+                        task.replenish(self.clock);
+                    }
+                } else if task.next_period() > self.clock + task.dl_params().period {
+                    // from a CPU whose clock is ahead, the current period
+                    // starts now on this one
+                    task.abs_deadline
+                        .store(self.clock + task.dl_params().deadline, Ordering::Release);
+                }
+            }
+            Class::RoundRobin => {
+                if task.time_slice.load(Ordering::Acquire) <= 0 {
+                    task.time_slice.store(RR_TIME_SLICE, Ordering::Release);
+                }
+            }
+            Class::Fifo => {}
+            Class::Normal => task.cfs.set_vruntime(self.min_vruntime),
+        }
+        self.enqueue(task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        match task.class() {
+            Class::Deadline => {
+                let taskid = task.cfs.get_id();
+                self.dl_queue
+                    .remove(&(task.abs_deadline(), taskid))
+                    .or_else(|| self.dl_throttled.remove(&(task.next_period(), taskid)))
+            }
+            Class::Fifo | Class::RoundRobin => {
+                let prio = task.rt_prio() as usize;
+                let idx = self.rt_queues[prio]
+                    .iter()
+                    .position(|t| Arc::ptr_eq(t, task))?;
+                self.remove_rt(prio, idx)
+            }
+            Class::Normal => {
+                let task = self
+                    .cfs_queue
+                    .remove(&(task.cfs.get_vruntime(), task.cfs.get_id()));
+                self.update_min_vruntime();
+                task
+            }
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        self.unthrottle(false);
+        if self.dl_queue.is_empty()
+            && self.rt_bitmap == 0
+            && self.cfs_queue.is_empty()
+        {
+            // the clock doesn't run while the CPU is idle
+            self.unthrottle(true);
+        }
+        if let Some((_, task)) = self.dl_queue.pop_first() {
+            return Some(task);
+        }
+        if let Some(prio) = self.highest_rt_prio() {
+            return self.remove_rt(prio, 0);
+        }
+        let (_, task) = self.cfs_queue.pop_first()?;
+        self.update_min_vruntime();
+        Some(task)
+    }
+
+    fn steal_task<F>(&mut self, mut filter: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        // normal tasks first, from the largest vruntime
+        if let Some(task) = self.cfs_queue.values().rev().find(|t| filter(t)) {
+            let task = task.clone();
+            return self.remove_task(&task);
+        }
+        // then real-time tasks, from the lowest priority
+        for prio in 0..RT_PRIO_LEVELS {
+            if self.rt_bitmap & (1 << prio) != 0 {
+                if let Some(idx) = self.rt_queues[prio].iter().rposition(&mut filter) {
+                    return self.remove_rt(prio, idx);
+                }
+            }
+        }
+        None
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        match prev.class() {
+            Class::RoundRobin => {
+                if prev.time_slice.load(Ordering::Acquire) > 0 && preempt {
+                    self.enqueue(prev, true);
+                } else {
+                    prev.time_slice.store(RR_TIME_SLICE, Ordering::Release);
+                    self.enqueue(prev, false);
+                }
+            }
+            // keeps its place if preempted by a higher priority
+            Class::Fifo => self.enqueue(prev, preempt),
+            Class::Deadline | Class::Normal => self.enqueue(prev, false),
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        self.clock += 1;
+        self.unthrottle(false);
+        match current.class() {
+            Class::Deadline => {
+                // runtime used up: throttled once it's put back
+                current.runtime_left.fetch_sub(1, Ordering::AcqRel) <= 1
+                    || self.has_higher(current)
+            }
+            Class::RoundRobin => {
+                let old_slice = current.time_slice.fetch_sub(1, Ordering::AcqRel);
+                let prio = current.rt_prio() as usize;
+                self.has_higher(current)
+                    || (old_slice <= 1 && !self.rt_queues[prio].is_empty())
+            }
+            Class::Fifo => self.has_higher(current),
+            Class::Normal => {
+                current.cfs.task_tick();
+                self.has_higher(current)
+                    || self
+                        .cfs_queue
+                        .first_key_value()
+                        .map_or(false, |((v, _), _)| current.cfs.get_vruntime() > *v)
+            }
+        }
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        let policy = match task.policy() {
+            SchedPolicy::Normal(_) => SchedPolicy::Normal(prio),
+            SchedPolicy::Fifo(_) | SchedPolicy::RoundRobin(_)
+                if !(0..RT_PRIO_LEVELS as isize).contains(&prio) =>
+            {
+                return false
+            }
+            SchedPolicy::Fifo(_) => SchedPolicy::Fifo(prio as u8),
+            SchedPolicy::RoundRobin(_) => SchedPolicy::RoundRobin(prio as u8),
+            SchedPolicy::Deadline(_) => return false,
+        };
+        self.set_policy(task, policy)
+    }
+
+    fn set_policy(&mut self, task: &Self::SchedItem, policy: SchedPolicy) -> bool {
+        let bandwidth = match policy {
+            SchedPolicy::Normal(nice) if !(-20..=19).contains(&nice) => return false,
+            SchedPolicy::Fifo(prio) | SchedPolicy::RoundRobin(prio)
+                if prio as usize >= RT_PRIO_LEVELS =>
+            {
+                return false
+            }
+            SchedPolicy::Deadline(params) if !params.is_valid() => return false,
+            SchedPolicy::Deadline(params) => params.bandwidth(),
+            _ => 0,
+        };
+        // admission control, the old bandwidth of the task is released
+        let old = task.bandwidth();
+        let admitted = DL_BANDWIDTH.fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+            total
+                .checked_sub(old)
+                .and_then(|total| total.checked_add(bandwidth))
+                .filter(|&total| total <= BW_LIMIT || bandwidth <= old)
+        });
+        if admitted.is_err() {
+            return false;
+        }
+
+        let was_normal = task.class() == Class::Normal;
+        let queued = self.remove_task(task);
+        match policy {
+            SchedPolicy::Normal(nice) => {
+                if !was_normal {
+                    task.cfs.set_vruntime(self.min_vruntime);
+                }
+                task.cfs.set_priority(nice);
+                task.class.store(Class::Normal as u8, Ordering::Release);
+            }
+            SchedPolicy::Fifo(prio) => {
+                task.rt_prio.store(prio, Ordering::Release);
+                task.class.store(Class::Fifo as u8, Ordering::Release);
+            }
+            SchedPolicy::RoundRobin(prio) => {
+                task.rt_prio.store(prio, Ordering::Release);
+                task.time_slice.store(RR_TIME_SLICE, Ordering::Release);
+                task.class
+                    .store(Class::RoundRobin as u8, Ordering::Release);
+            }
+            SchedPolicy::Deadline(params) => {
+                task.dl_runtime.store(params.runtime, Ordering::Release);
+                task.dl_deadline.store(params.deadline, Ordering::Release);
+                task.dl_period.store(params.period, Ordering::Release);
+                task.replenish(self.clock);
+                task.class.store(Class::Deadline as u8, Ordering::Release);
+            }
+        }
+        if let Some(task) = queued {
+            self.enqueue(task, false);
+        }
+        true
+    }
+}
@@ -28,6 +28,30 @@ macro_rules! def_test_sched {
                 assert_eq!(n, NUM_TASKS);
             }
 
+            #[test]
+            fn test_steal() {
+                const NUM_TASKS: usize = 10;
+
+                let mut scheduler = <$scheduler>::new();
+                for i in 0..NUM_TASKS {
+                    scheduler.add_task(Arc::new(<$task>::new(i)));
+                }
+
+                // the even tasks closest to the back first
+                for i in (0..NUM_TASKS).rev().filter(|i| i % 2 == 0) {
+                    let t = scheduler.steal_task(|t| *t.inner() % 2 == 0).unwrap();
+                    assert_eq!(*t.inner(), i);
+                }
+                assert!(scheduler.steal_task(|t| *t.inner() % 2 == 0).is_none());
+
+                for i in (0..NUM_TASKS).filter(|i| i % 2 == 1) {
+                    let next = scheduler.pick_next_task().unwrap();
+                    assert_eq!(*next.inner(), i);
+                }
+                assert!(scheduler.pick_next_task().is_none());
+                assert!(scheduler.steal_task(|_| true).is_none());
+            }
+
             #[test]
             fn bench_yield() {
                 const NUM_TASKS: usize = 1_000_000;
@@ -82,3 +106,230 @@ macro_rules! def_test_sched {
 def_test_sched!(fifo, FifoScheduler::<usize>, FifoTask::<usize>);
 def_test_sched!(rr, RRScheduler::<usize, 5>, RRTask::<usize, 5>);
 def_test_sched!(cfs, CFScheduler::<usize>, CFSTask::<usize>);
+def_test_sched!(rt, RTScheduler::<usize>, RTTask::<usize>);
+
+mod rt_classes {
+    use crate::*;
+    use alloc::sync::Arc;
+    use core::sync::atomic::Ordering;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Runs the tests that admit deadline tasks one at a time, they share the
+    /// bandwidth of all schedulers. All of it is free when a test starts.
+    fn serial() -> MutexGuard<'static, ()> {
+        static SERIAL: Mutex<()> = Mutex::new(());
+        let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        crate::rt::DL_BANDWIDTH.store(0, Ordering::Release);
+        guard
+    }
+
+    const fn dl(runtime: u64, deadline: u64, period: u64) -> SchedPolicy {
+        SchedPolicy::Deadline(DeadlineParams {
+            runtime,
+            deadline,
+            period,
+        })
+    }
+
+    fn new_task(
+        scheduler: &mut RTScheduler<usize>,
+        id: usize,
+        policy: SchedPolicy,
+    ) -> Arc<RTTask<usize>> {
+        let t = Arc::new(RTTask::new(id));
+        assert!(scheduler.set_policy(&t, policy));
+        assert_eq!(t.policy(), policy);
+        t
+    }
+
+    #[test]
+    fn test_class_order() {
+        let _serial = serial();
+        let mut scheduler = RTScheduler::new();
+        let policies = [
+            SchedPolicy::Normal(-20),
+            SchedPolicy::Fifo(10),
+            SchedPolicy::RoundRobin(50),
+            SchedPolicy::Fifo(99),
+            dl(1, 10, 10),
+            dl(1, 5, 5),
+        ];
+        for (i, policy) in policies.into_iter().enumerate() {
+            let t = new_task(&mut scheduler, i, policy);
+            scheduler.add_task(t);
+        }
+        for i in [5, 4, 3, 2, 1, 0] {
+            assert_eq!(*scheduler.pick_next_task().unwrap().inner(), i);
+        }
+        assert!(scheduler.pick_next_task().is_none());
+    }
+
+    #[test]
+    fn test_fifo_and_round_robin() {
+        let mut scheduler = RTScheduler::new();
+        for i in 0..2 {
+            let t = new_task(&mut scheduler, i, SchedPolicy::RoundRobin(5));
+            scheduler.add_task(t);
+        }
+
+        // round-robin tasks take turns when their time slice runs out
+        let curr = scheduler.pick_next_task().unwrap();
+        assert_eq!(*curr.inner(), 0);
+        for _ in 0..4 {
+            assert!(!scheduler.task_tick(&curr));
+        }
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, true);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+
+        // a FIFO task runs until a task of higher priority is ready
+        let curr = new_task(&mut scheduler, 2, SchedPolicy::Fifo(5));
+        let same = new_task(&mut scheduler, 3, SchedPolicy::Fifo(5));
+        scheduler.add_task(same);
+        for _ in 0..100 {
+            assert!(!scheduler.task_tick(&curr));
+        }
+        let higher = new_task(&mut scheduler, 4, SchedPolicy::RoundRobin(6));
+        scheduler.add_task(higher);
+        assert!(scheduler.task_tick(&curr));
+        // the preempted task keeps its place in the queue
+        scheduler.put_prev_task(curr, true);
+        for i in [4, 2, 3] {
+            assert_eq!(*scheduler.pick_next_task().unwrap().inner(), i);
+        }
+        assert!(scheduler.pick_next_task().is_none());
+    }
+
+    #[test]
+    fn test_edf() {
+        let _serial = serial();
+        let mut scheduler = RTScheduler::new();
+        let curr = new_task(&mut scheduler, 0, dl(2, 4, 8));
+        let other = new_task(&mut scheduler, 1, dl(1, 6, 8));
+        scheduler.add_task(other);
+
+        // deadline 4 before 6, until the runtime is used up
+        assert!(!scheduler.task_tick(&curr));
+        assert!(scheduler.task_tick(&curr));
+        assert_eq!(curr.policy(), dl(2, 4, 8));
+        scheduler.put_prev_task(curr, true);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+
+        // deadline tasks run before all others, and are never stolen
+        let rt = new_task(&mut scheduler, 2, SchedPolicy::Fifo(99));
+        scheduler.add_task(rt.clone());
+        assert!(!scheduler.task_tick(&rt));
+        let t = new_task(&mut scheduler, 3, dl(1, 2, 2));
+        scheduler.add_task(t);
+        assert!(scheduler.task_tick(&rt));
+        assert_eq!(*scheduler.steal_task(|_| true).unwrap().inner(), 2);
+        assert!(scheduler.steal_task(|_| true).is_none());
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 3);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let _serial = serial();
+        let mut scheduler = RTScheduler::new();
+        let curr = new_task(&mut scheduler, 0, dl(2, 5, 10));
+        let normal = new_task(&mut scheduler, 1, SchedPolicy::Normal(0));
+        scheduler.add_task(normal);
+
+        // runtime used up, throttled until the next period at tick 10
+        assert!(!scheduler.task_tick(&curr));
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr.clone(), true);
+        let normal = scheduler.pick_next_task().unwrap();
+        assert_eq!(*normal.inner(), 1);
+        for _ in 3..10 {
+            assert!(!scheduler.task_tick(&normal));
+        }
+        assert!(scheduler.task_tick(&normal));
+        scheduler.put_prev_task(normal, true);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+        assert_eq!(curr.policy(), dl(2, 5, 10));
+
+        // with nothing else to run, starts its next period early
+        assert!(!scheduler.task_tick(&curr));
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, false);
+        let normal = scheduler.pick_next_task().unwrap();
+        assert_eq!(*normal.inner(), 1);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+        assert!(scheduler.pick_next_task().is_none());
+    }
+
+    #[test]
+    fn test_admission() {
+        let _serial = serial();
+        let mut scheduler = RTScheduler::new();
+        let a = new_task(&mut scheduler, 0, dl(5, 10, 10));
+        let b = Arc::new(RTTask::new(1));
+        assert!(!scheduler.set_policy(&b, dl(5, 10, 10)));
+        assert_eq!(b.policy(), SchedPolicy::Normal(0));
+        assert!(scheduler.set_policy(&b, dl(4, 10, 10)));
+        // changing the parameters of an admitted task
+        assert!(!scheduler.set_policy(&a, dl(6, 10, 10)));
+        assert!(scheduler.set_policy(&a, dl(5, 5, 10)));
+        // leaving the class releases the bandwidth
+        assert!(scheduler.set_policy(&a, SchedPolicy::Normal(0)));
+        assert!(scheduler.set_policy(&b, dl(9, 10, 10)));
+
+        // invalid parameters
+        let t = Arc::new(RTTask::new(2));
+        assert!(!scheduler.set_policy(&t, dl(0, 10, 10)));
+        assert!(!scheduler.set_policy(&t, dl(2, 1, 10)));
+        assert!(!scheduler.set_policy(&t, dl(1, 11, 10)));
+        assert!(!scheduler.set_policy(&t, SchedPolicy::Fifo(RT_PRIO_LEVELS as u8)));
+        assert!(!scheduler.set_policy(&t, SchedPolicy::Normal(20)));
+        assert_eq!(t.policy(), SchedPolicy::Normal(0));
+    }
+
+    #[test]
+    fn test_bandwidth_of_all_cpus() {
+        let _serial = serial();
+        let mut cpu0 = RTScheduler::new();
+        let mut cpu1 = RTScheduler::new();
+        let t = new_task(&mut cpu0, 0, dl(9, 10, 10));
+        // the bandwidth is reserved on all CPUs
+        let other = Arc::new(RTTask::new(1));
+        assert!(!cpu1.set_policy(&other, dl(1, 10, 10)));
+
+        // woken up on the other CPU, where it releases the bandwidth
+        cpu1.add_task(t.clone());
+        assert!(Arc::ptr_eq(&cpu1.pick_next_task().unwrap(), &t));
+        assert!(cpu1.set_policy(&t, SchedPolicy::Normal(0)));
+        assert!(cpu0.set_policy(&other, dl(9, 10, 10)));
+        assert!(cpu1.set_policy(&t, dl(1, 40, 40)));
+    }
+
+    #[test]
+    fn test_set_priority() {
+        let _serial = serial();
+        let mut scheduler = RTScheduler::new();
+        let a = new_task(&mut scheduler, 0, SchedPolicy::Fifo(1));
+        let b = new_task(&mut scheduler, 1, SchedPolicy::Fifo(2));
+        let n = new_task(&mut scheduler, 2, SchedPolicy::Normal(0));
+        let d = new_task(&mut scheduler, 3, dl(1, 10, 10));
+        for t in [&a, &b, &n] {
+            scheduler.add_task(t.clone());
+        }
+
+        // moves queued tasks to their new level
+        assert!(scheduler.set_priority(&a, 3));
+        assert!(!scheduler.set_priority(&b, RT_PRIO_LEVELS as isize));
+        assert!(!scheduler.set_priority(&b, -1));
+        assert!(scheduler.set_priority(&n, -5));
+        assert!(!scheduler.set_priority(&d, 0));
+        assert_eq!(n.policy(), SchedPolicy::Normal(-5));
+
+        // a normal task becomes real-time while queued
+        assert!(scheduler.set_policy(&n, SchedPolicy::RoundRobin(4)));
+        for i in [2, 0, 1] {
+            assert_eq!(*scheduler.pick_next_task().unwrap().inner(), i);
+        }
+        assert!(scheduler.pick_next_task().is_none());
+    }
+}
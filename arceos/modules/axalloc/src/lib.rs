@@ -86,7 +86,18 @@ impl GlobalAllocator {
             } else {
                 let old_size = balloc.total_bytes();
                 let expand_size = old_size.max(size).next_power_of_two().max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                let heap_ptr = match self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE) {
+                    Ok(ptr) => ptr,
+                    Err(e) => {
+                        // the reclaimers free memory, so they run unlocked
+                        drop(balloc);
+                        if reclaim(expand_size / PAGE_SIZE) == 0 {
+                            return Err(e);
+                        }
+                        balloc = self.balloc.lock();
+                        continue;
+                    }
+                };
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
@@ -164,6 +175,47 @@ unsafe impl GlobalAlloc for GlobalAllocator {
     }
 }
 
+/// A function that releases memory under memory pressure, e.g. by dropping
+/// caches. It is given the number of pages wanted and returns the number of
+/// pages it released.
+///
+/// It runs in the context of a failed allocation, so it must neither block
+/// nor allocate memory.
+pub type Reclaimer = fn(usize) -> usize;
+
+const MAX_RECLAIMERS: usize = 4;
+
+static RECLAIMERS: SpinNoIrq<[Option<Reclaimer>; MAX_RECLAIMERS]> =
+    SpinNoIrq::new([None; MAX_RECLAIMERS]);
+
+/// Registers `reclaimer` to be called when the allocator runs out of memory.
+///
+/// Returns `false` if there are too many reclaimers.
+pub fn register_reclaimer(reclaimer: Reclaimer) -> bool {
+    let mut reclaimers = RECLAIMERS.lock();
+    match reclaimers.iter_mut().find(|r| r.is_none()) {
+        Some(slot) => {
+            *slot = Some(reclaimer);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Asks the reclaimers to release `nr_pages` pages, returns the number of
+/// pages they released.
+fn reclaim(nr_pages: usize) -> usize {
+    let reclaimers = *RECLAIMERS.lock();
+    let mut released = 0;
+    for reclaimer in reclaimers.iter().flatten() {
+        if released >= nr_pages {
+            break;
+        }
+        released += reclaimer(nr_pages - released);
+    }
+    released
+}
+
 #[cfg_attr(all(target_os = "none", not(test)), global_allocator)]
 static GLOBAL_ALLOCATOR: GlobalAllocator = GlobalAllocator::new();
 
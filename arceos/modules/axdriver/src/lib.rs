@@ -20,6 +20,8 @@
 //! - `bus-mmio`: if `virtio` is enabled, use MMIO bus for VirtIO devices. This
 //!    is enabled by default if `virtio` is enabled.
 //! - `bus-pci`: if `virtio` is enabled, use PCI bus for VirtIO devices.
+//! - `irq`: allow the upperlayer subsystems to handle the interrupts of the
+//!    devices, see [`register_net_irq_handler`].
 
 #![no_std]
 #![feature(doc_auto_cfg)]
@@ -32,6 +34,8 @@ mod virtio;
 
 use tuple_for_each::TupleForEach;
 
+#[cfg(all(feature = "irq", feature = "virtio-net"))]
+pub use self::virtio::register_net_irq_handler;
 #[cfg(feature = "virtio-blk")]
 pub use self::virtio::VirtIoBlockDev;
 #[cfg(feature = "virtio-gpu")]
@@ -33,6 +33,41 @@ cfg_if! {
     }
 }
 
+cfg_if! {
+    if #[cfg(all(feature = "irq", feature = "virtio-net"))] {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        use lazy_init::LazyInit;
+
+        /// The index in `VIRTIO_MMIO_REGIONS` of the probed network device.
+        static NET_SLOT: AtomicUsize = AtomicUsize::new(usize::MAX);
+        static NET_IRQ_HANDLER: LazyInit<fn()> = LazyInit::new();
+
+        fn net_irq_handler() {
+            let reg = axconfig::VIRTIO_MMIO_REGIONS[NET_SLOT.load(Ordering::Relaxed)];
+            if driver_virtio::ack_mmio_interrupt(phys_to_virt(reg.0.into()).as_mut_ptr()) {
+                NET_IRQ_HANDLER();
+            }
+        }
+
+        /// Registers a handler for the interrupts of the network device, which
+        /// are acknowledged before the handler is called.
+        ///
+        /// It returns `false` if the interrupts of the device are not supported
+        /// on this platform, or a handler is already registered.
+        pub fn register_net_irq_handler(handler: fn()) -> bool {
+            let slot = NET_SLOT.load(Ordering::Relaxed);
+            if axconfig::VIRTIO_MMIO_IRQ_BASE == 0
+                || slot == usize::MAX
+                || NET_IRQ_HANDLER.is_init()
+            {
+                return false;
+            }
+            NET_IRQ_HANDLER.init_by(handler);
+            axhal::irq::register_handler(axconfig::VIRTIO_MMIO_IRQ_BASE + slot, net_irq_handler)
+        }
+    }
+}
+
 cfg_if! {
     if #[cfg(feature = "virtio-gpu")] {
         /// Alias of [`driver_virtio::VirtIoGpuDev`].
@@ -82,7 +117,7 @@ impl AllDevices {
         F: FnOnce(VirtIoTransport) -> Option<D>,
     {
         // TODO: parse device tree
-        for reg in axconfig::VIRTIO_MMIO_REGIONS {
+        for (_slot, reg) in axconfig::VIRTIO_MMIO_REGIONS.iter().enumerate() {
             if let Some(transport) = driver_virtio::probe_mmio_device(
                 phys_to_virt(reg.0.into()).as_mut_ptr(),
                 reg.1,
@@ -94,6 +129,10 @@ impl AllDevices {
                     dev.device_type(),
                     dev.device_name()
                 );
+                #[cfg(all(feature = "irq", feature = "virtio-net"))]
+                if dev_type == DeviceType::Net {
+                    NET_SLOT.store(_slot, Ordering::Relaxed);
+                }
                 return Some(dev);
             }
         }
@@ -1,5 +1,6 @@
 use axio::{prelude::*, Result, SeekFrom};
 use core::fmt;
+use core::ops::Range;
 
 use crate::fops;
 
@@ -10,6 +11,12 @@ pub type FileType = fops::FileType;
 /// Representation of the various permissions on a file.
 pub type Permissions = fops::FilePerm;
 
+/// The kind of an advisory file lock.
+pub type LockKind = fops::LockKind;
+
+/// An advisory lock held on a range of a file.
+pub type FileLock = fops::FileLock;
+
 /// An object providing access to an open file on the filesystem.
 pub struct File {
     inner: fops::File,
@@ -152,10 +159,69 @@ impl File {
         self.inner.truncate(size)
     }
 
+    /// Attempts to sync all data and metadata of this file to disk.
+    pub fn sync_all(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
         self.inner.get_attr().map(Metadata)
     }
+
+    /// Acquires an exclusive advisory lock on the whole file, waiting until
+    /// no other opened file holds a lock on it.
+    ///
+    /// Locks are released by [`File::unlock`] or when the file is closed.
+    pub fn lock(&self) -> Result<()> {
+        self.inner.lock(LockKind::Exclusive, 0..u64::MAX, true)
+    }
+
+    /// Acquires a shared advisory lock on the whole file, waiting until no
+    /// other opened file holds an exclusive lock on it.
+    pub fn lock_shared(&self) -> Result<()> {
+        self.inner.lock(LockKind::Shared, 0..u64::MAX, true)
+    }
+
+    /// Like [`File::lock`], but fails with `Again` instead of waiting.
+    pub fn try_lock(&self) -> Result<()> {
+        self.inner.lock(LockKind::Exclusive, 0..u64::MAX, false)
+    }
+
+    /// Like [`File::lock_shared`], but fails with `Again` instead of waiting.
+    pub fn try_lock_shared(&self) -> Result<()> {
+        self.inner.lock(LockKind::Shared, 0..u64::MAX, false)
+    }
+
+    /// Releases all advisory locks this file holds.
+    pub fn unlock(&self) -> Result<()> {
+        self.inner.unlock(0..u64::MAX)
+    }
+
+    /// Acquires an advisory lock on the bytes `range` of the file, replacing
+    /// the locks this file holds there. If another opened file holds a
+    /// conflicting lock, waits for it, or fails with `Again` if `wait` is
+    /// false.
+    pub fn lock_range(&self, kind: LockKind, range: Range<u64>, wait: bool) -> Result<()> {
+        self.inner.lock(kind, range, wait)
+    }
+
+    /// Releases the advisory locks this file holds on the bytes `range`.
+    pub fn unlock_range(&self, range: Range<u64>) -> Result<()> {
+        self.inner.unlock(range)
+    }
+
+    /// Returns a lock held through another opened file that would keep this
+    /// file from locking the bytes `range`, if there is one.
+    pub fn lock_conflict(&self, kind: LockKind, range: Range<u64>) -> Option<FileLock> {
+        self.inner.lock_conflict(kind, range)
+    }
+
+    /// Returns a handle to the advisory locks of this file, to wait for a
+    /// lock without borrowing the file.
+    pub fn lock_handle(&self) -> fops::LockHandle {
+        self.inner.lock_handle()
+    }
 }
 
 impl Read for File {
@@ -4,9 +4,12 @@ mod dir;
 mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
-pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use self::file::{File, FileLock, FileType, LockKind, Metadata, OpenOptions, Permissions};
+pub use crate::fs::FsCreator;
+pub use crate::root::MountInfo;
 
 use alloc::{string::String, vec::Vec};
+use core::time::Duration;
 use axio::{self as io, prelude::*};
 
 /// Returns an iterator over the entries within a directory.
@@ -71,6 +74,131 @@ pub fn create_dir_all(path: &str) -> io::Result<()> {
     DirBuilder::new().recursive(true).create(path)
 }
 
+/// Creates a device file at the provided path, whose I/O is forwarded to the
+/// device registered as (`major`, `minor`) in [`devsw`](crate::devsw).
+pub fn mknod(path: &str, major: u32, minor: u32) -> io::Result<()> {
+    crate::root::mknod(None, path, major, minor)
+}
+
+/// Returns the value of the extended attribute `name` of the file or
+/// directory at `path`.
+pub fn getxattr(path: &str, name: &str) -> io::Result<Vec<u8>> {
+    crate::root::lookup(None, path)?.getxattr(name)
+}
+
+/// Sets the extended attribute `name` of the file or directory at `path` to
+/// `value`, creating the attribute if it doesn't exist.
+pub fn setxattr(path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+    crate::root::lookup(None, path)?.setxattr(name, value)
+}
+
+/// Returns the names of the extended attributes of the file or directory at
+/// `path`.
+pub fn listxattr(path: &str) -> io::Result<Vec<String>> {
+    crate::root::lookup(None, path)?.listxattr()
+}
+
+/// Removes the extended attribute `name` of the file or directory at `path`.
+pub fn removexattr(path: &str, name: &str) -> io::Result<()> {
+    crate::root::lookup(None, path)?.removexattr(name)
+}
+
+/// Writes all pending data and metadata of every mounted filesystem to disk.
+pub fn sync() -> io::Result<()> {
+    crate::root::sync()
+}
+
+/// Sets how the xv6 root filesystem writes back its log.
+///
+/// With `None`, every operation is committed to disk before it returns.
+/// With `Some(interval)`, operations are batched in the log and committed by a
+/// background task every `interval`, by [`sync`] and [`File::sync_all`], or
+/// once the log is full.
+pub fn set_write_back(interval: Option<Duration>) {
+    crate::fs::axxv6fs::set_write_back(interval)
+}
+
+/// Takes a read-only snapshot of the xv6 root filesystem, named `name`.
+///
+/// Later writes don't change the snapshot. It can be mounted with the
+/// filesystem type `xv6snap` and its name as the source, e.g.
+/// `mount(name, "/snap/name", "xv6snap")`.
+pub fn create_snapshot(name: &str) -> io::Result<()> {
+    crate::fs::axxv6fs::create_snapshot(name)
+}
+
+/// Deletes the snapshot `name` of the xv6 root filesystem, and frees the
+/// blocks only it still uses.
+///
+/// Fails with [`ResourceBusy`](io::Error::ResourceBusy) if it is mounted.
+pub fn delete_snapshot(name: &str) -> io::Result<()> {
+    crate::fs::axxv6fs::delete_snapshot(name)
+}
+
+/// Returns the names of the snapshots of the xv6 root filesystem.
+pub fn snapshots() -> io::Result<Vec<String>> {
+    crate::fs::axxv6fs::snapshots()
+}
+
+/// Grows or shrinks the xv6 root filesystem to `blocks` blocks of 512 bytes,
+/// e.g. after its disk was made larger.
+///
+/// Shrinking moves the data past the new end first. Nothing else should use
+/// the filesystem meanwhile. Fails with
+/// [`ResourceBusy`](io::Error::ResourceBusy) if it has snapshots, and with
+/// [`StorageFull`](io::Error::StorageFull) if the data doesn't fit.
+pub fn resize_fs(blocks: u64) -> io::Result<()> {
+    crate::fs::axxv6fs::resize(blocks)
+}
+
+/// Tells the disk under the xv6 root filesystem that its free blocks are
+/// unused, like `fstrim`, and returns how many bytes that covered.
+///
+/// Blocks freed later are discarded as their removal is committed; this also
+/// covers blocks freed before the filesystem was mounted. Disks that can't
+/// discard ignore it.
+pub fn fstrim() -> io::Result<u64> {
+    crate::fs::axxv6fs::trim()
+}
+
+/// Mounts a filesystem of the type `fstype` from `source` on `path`.
+///
+/// The mount point is created if it does not exist, and it may be inside
+/// another mounted filesystem. `fstype` must have been registered, see
+/// [`register_fs_type`] and [`fs_types`].
+pub fn mount(source: &str, path: &str, fstype: &str) -> io::Result<()> {
+    crate::root::mount(source, path, fstype)
+}
+
+/// Unmounts the filesystem mounted on `path`.
+///
+/// Fails with [`ResourceBusy`](io::Error::ResourceBusy) if another filesystem
+/// is mounted inside it, or if a file or directory in it is still open or is
+/// the current directory.
+pub fn umount(path: &str) -> io::Result<()> {
+    crate::root::umount(path)
+}
+
+/// Returns the mount table, starting with the root filesystem.
+pub fn mounts() -> Vec<MountInfo> {
+    crate::root::mounts()
+}
+
+/// Registers a filesystem type, so that it can be mounted by [`mount`].
+///
+/// `ramfs` and `devfs` are registered by default if their features are
+/// enabled, and so is the type of the root filesystem: `xv6fs` (with
+/// `xv6snap` for its snapshots) or `fatfs`, which mounts the root
+/// filesystem again from the source `"disk"`.
+pub fn register_fs_type(fstype: &'static str, creator: FsCreator) -> io::Result<()> {
+    crate::fs::register_fs_type(fstype, creator)
+}
+
+/// Returns the names of all registered filesystem types.
+pub fn fs_types() -> Vec<&'static str> {
+    crate::fs::fs_types()
+}
+
 /// Removes an empty directory.
 pub fn remove_dir(path: &str) -> io::Result<()> {
     crate::root::remove_dir(None, path)
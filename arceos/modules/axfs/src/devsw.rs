@@ -0,0 +1,100 @@
+//! Device switch table.
+//!
+//! Device files on disk (e.g. xv6fs device inodes) only store a
+//! (major, minor) device number. Their I/O is forwarded to the node that is
+//! registered under that number here.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use axsync::Mutex;
+
+/// Device number of the null device, nothing can be read and all writes are
+/// discarded.
+pub const NULL_DEV: (u32, u32) = (1, 3);
+/// Device number of the zero device, reads return zeros.
+pub const ZERO_DEV: (u32, u32) = (1, 5);
+/// Device number of the console.
+pub const CONSOLE_DEV: (u32, u32) = (5, 1);
+/// Device number of the disk that holds the root filesystem.
+pub const DISK_DEV: (u32, u32) = (8, 0);
+
+static DEVSW: Mutex<BTreeMap<(u32, u32), VfsNodeRef>> = Mutex::new(BTreeMap::new());
+
+/// Registers `node` as the device (`major`, `minor`).
+pub fn register(major: u32, minor: u32, node: VfsNodeRef) -> AxResult {
+    let mut devsw = DEVSW.lock();
+    if devsw.contains_key(&(major, minor)) {
+        return ax_err!(AlreadyExists, "device number already registered");
+    }
+    devsw.insert((major, minor), node);
+    Ok(())
+}
+
+/// Removes the device (`major`, `minor`) from the table.
+pub fn unregister(major: u32, minor: u32) -> AxResult {
+    DEVSW
+        .lock()
+        .remove(&(major, minor))
+        .map(|_| ())
+        .ok_or(AxError::NotFound)
+}
+
+/// Returns the node registered as the device (`major`, `minor`).
+pub fn get(major: u32, minor: u32) -> AxResult<VfsNodeRef> {
+    DEVSW
+        .lock()
+        .get(&(major, minor))
+        .cloned()
+        .ok_or(AxError::NotFound)
+}
+
+/// The console device, backed by [`axhal::console`].
+///
+/// Reads do not block, they return the characters that are already available.
+pub struct ConsoleDev;
+
+impl VfsNodeOps for ConsoleDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            match axhal::console::getchar() {
+                Some(c) => buf[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        Ok(read_len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// Registers the built-in devices.
+pub(crate) fn init() {
+    #[cfg(feature = "devfs")]
+    {
+        register(NULL_DEV.0, NULL_DEV.1, Arc::new(crate::fs::devfs::NullDev)).ok();
+        register(ZERO_DEV.0, ZERO_DEV.1, Arc::new(crate::fs::devfs::ZeroDev)).ok();
+    }
+    register(CONSOLE_DEV.0, CONSOLE_DEV.1, Arc::new(ConsoleDev)).ok();
+}
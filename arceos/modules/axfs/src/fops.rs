@@ -5,7 +5,13 @@ use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use capability::{Cap, WithCap};
 use core::fmt;
+use core::ops::Range;
 
+use crate::page_cache;
+use crate::root::MountUser;
+use alloc::{format, string::String};
+
+pub use crate::lock::{FileLock, LockHandle, LockKind};
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
@@ -23,8 +29,11 @@ pub type FilePerm = axfs_vfs::VfsNodePerm;
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
+    cache_id: Option<u64>,
     is_append: bool,
     offset: u64,
+    locks: LockHandle,
+    mount: Option<MountUser>,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +41,10 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    /// Absolute path of the directory, used to find the filesystem that
+    /// contains a path relative to it.
+    path: String,
+    _mount: Option<MountUser>,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -112,7 +125,12 @@ impl OpenOptions {
 }
 
 impl File {
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {//dir is none
+    fn _open_at(
+        dir: Option<&VfsNodeRef>,
+        abs_path: &str,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {//dir is none
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
@@ -151,26 +169,39 @@ impl File {
             return ax_err!(PermissionDenied);
         }
 
+        let mount = crate::root::mount_user(abs_path);
         node.open()?;
+        let cache_id = node.cache_id();
         if opts.truncate {
-            node.truncate(0)?;
+            match cache_id {
+                Some(id) => page_cache::truncate(id, &node, mount.as_ref(), 0)?,
+                None => node.truncate(0)?,
+            }
         }
+        let locks = LockHandle::new(&node, cache_id);
         Ok(Self {
             node: WithCap::new(node, access_cap),
+            cache_id,
             is_append: opts.append,
             offset: 0,
+            locks,
+            mount,
         })
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
     /// [`File`] object.
     pub fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_at(None, path, opts)
+        Self::_open_at(None, &crate::root::absolute_path(path)?, path, opts)
     }
 
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
-        self.node.access(Cap::WRITE)?.truncate(size)?;
+        let node = self.node.access(Cap::WRITE)?;
+        match self.cache_id {
+            Some(id) => page_cache::truncate(id, node, self.mount.as_ref(), size)?,
+            None => node.truncate(size)?,
+        }
         Ok(())
     }
 
@@ -180,7 +211,10 @@ impl File {
     /// After the read, the cursor will be advanced by the number of bytes read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::READ)?;
-        let read_len = node.read_at(self.offset, buf)?;
+        let read_len = match self.cache_id {
+            Some(id) => page_cache::read(id, node, self.mount.as_ref(), self.offset, buf)?,
+            None => node.read_at(self.offset, buf)?,
+        };
         debug!("read len is {}",read_len);
         self.offset += read_len as u64;
         Ok(read_len)
@@ -196,14 +230,21 @@ impl File {
         if self.is_append {
             self.offset = self.get_attr()?.size();
         };
-        let write_len = node.write_at(self.offset, buf)?;
+        let write_len = match self.cache_id {
+            Some(id) => page_cache::write(id, node, self.mount.as_ref(), self.offset, buf)?,
+            None => node.write_at(self.offset, buf)?,
+        };
         self.offset += write_len as u64;
         Ok(write_len)
     }
 
     /// Flushes the file, writes all buffered data to the underlying device.
     pub fn flush(&self) -> AxResult {
-        self.node.access(Cap::WRITE)?.fsync()?;
+        let node = self.node.access(Cap::WRITE)?;
+        if let Some(id) = self.cache_id {
+            page_cache::flush(id)?;
+        }
+        node.fsync()?;
         Ok(())
     }
 
@@ -223,12 +264,44 @@ impl File {
 
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
-        self.node.access(Cap::empty())?.get_attr()
+        let attr = self.node.access(Cap::empty())?.get_attr()?;
+        // the cache may hold data beyond the end of the file on the node
+        match self.cache_id.and_then(page_cache::size) {
+            Some(size) => Ok(FileAttr::new(attr.perm(), attr.file_type(), size, attr.blocks())),
+            None => Ok(attr),
+        }
+    }
+
+    /// Places an advisory lock of the given kind on the bytes `range` of the
+    /// file, see [`LockHandle::lock`].
+    pub fn lock(&self, kind: LockKind, range: Range<u64>, wait: bool) -> AxResult {
+        self.locks.lock(kind, range, wait)
+    }
+
+    /// Releases the advisory locks of this file on the bytes `range`.
+    pub fn unlock(&self, range: Range<u64>) -> AxResult {
+        self.locks.unlock(range)
+    }
+
+    /// Returns a lock held through another opened file that conflicts with
+    /// a lock of the given kind on the bytes `range`.
+    pub fn lock_conflict(&self, kind: LockKind, range: Range<u64>) -> Option<FileLock> {
+        self.locks.conflict(kind, range)
+    }
+
+    /// Returns a handle to the advisory locks of this file.
+    pub fn lock_handle(&self) -> LockHandle {
+        self.locks.clone()
     }
 }
 
 impl Directory {
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(
+        dir: Option<&VfsNodeRef>,
+        abs_path: String,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         //dir is None
         debug!("open dir: {}", path);
         if !opts.read {
@@ -248,10 +321,13 @@ impl Directory {
             return ax_err!(PermissionDenied);
         }
 
+        let mount = crate::root::mount_user(&abs_path);
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            path: abs_path,
+            _mount: mount,
         })
     }
 
@@ -263,22 +339,31 @@ impl Directory {
         }
     }
 
+    /// Returns the absolute form of the path relative to this directory.
+    fn absolute_path_at(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            axfs_vfs::path::canonicalize(path)
+        } else {
+            axfs_vfs::path::canonicalize(&format!("{}/{}", self.path, path))
+        }
+    }
+
     /// Opens a directory at the path relative to the current directory.
     /// Returns a [`Directory`] object.
     pub fn open_dir(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(None, path, opts)
+        Self::_open_dir_at(None, crate::root::absolute_path(path)?, path, opts)
     }
 
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        Self::_open_dir_at(self.access_at(path)?, self.absolute_path_at(path), path, opts)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        File::_open_at(self.access_at(path)?, &self.absolute_path_at(path), path, opts)
     }
 
     /// Creates an empty file at the path relative to this directory.
@@ -318,6 +403,7 @@ impl Directory {
 
 impl Drop for File {
     fn drop(&mut self) {
+        self.locks.release_all();
         unsafe { self.node.access_unchecked().release().ok() };
     }
 }
@@ -1,13 +1,22 @@
 use crate::sleeplock_shim::FsLockList;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use axfs_vfs::VfsOps;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use axerrno::{AxError, AxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsOps, VfsResult};
 use axfs_xv6fs::dir::DirNode;
-use driver_block::BlockDriverOps;
+use driver_block::{BlockDriverOps, DevError};
 use crate::BlockDevice as axdevice;
+use crate::devsw;
 
 use xv6fs::interface::{INTERFACE_MANAGER,InterfaceManager,FsInterface};
+use xv6fs::log::{LOG_MANAGER,WriteBackMode};
 use xv6fs::xv6fs::Xv6FS;
+use xv6fs::checksum::CORRUPTED;
+use xv6fs::snapshot;
+use xv6fs::resize;
 use xv6fs::BlockDevice;
 
 use lazy_init::LazyInit;
@@ -22,25 +31,252 @@ pub struct Xv6FileSystem{
 impl VfsOps for Xv6FileSystem {
     fn root_dir(&self) -> axfs_vfs::VfsNodeRef {
         let root_dir=self.inner.get_root_vfile();
-        Arc::new(DirNode{dirnode:root_dir})
+        Arc::new(DirNode::from_vfile(root_dir))
     }
+
+    fn sync(&self) -> VfsResult {
+        self.inner.sync();
+        Ok(())
+    }
+
+    /// Only the extra mounts of the disk are unmounted, the filesystem stays
+    /// mounted on `/`.
+    fn umount(&self) -> VfsResult {
+        self.inner.sync();
+        Ok(())
+    }
+}
+
+/// A snapshot of the xv6 root filesystem, mounted read-only.
+pub struct Xv6SnapshotFs{
+    root: Arc<DirNode>,
+}
+
+impl Xv6SnapshotFs{
+    /// The snapshot `name`, see [`create_snapshot`].
+    pub fn new(name: &str)->AxResult<Self>{
+        let root_dir=Xv6FS::new().get_snapshot_vfile(name).map_err(snapshot_error)?;
+        Ok(Self { root: Arc::new(DirNode::from_vfile(root_dir)) })
+    }
+}
+
+impl VfsOps for Xv6SnapshotFs {
+    fn root_dir(&self) -> axfs_vfs::VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn snapshot_error(e: &'static str) -> AxError {
+    match e {
+        snapshot::NOT_FOUND => AxError::NotFound,
+        snapshot::EXISTS => AxError::AlreadyExists,
+        snapshot::BUSY => AxError::ResourceBusy,
+        CORRUPTED => AxError::InvalidData,
+        snapshot::INVALID_NAME => AxError::InvalidInput,
+        snapshot::READ_ONLY => AxError::PermissionDenied,
+        _ => AxError::StorageFull,
+    }
+}
+
+/// Take a read-only snapshot of the root filesystem named `name`.
+pub fn create_snapshot(name: &str) -> AxResult {
+    // the snapshot holds what was written before, even if it is still cached
+    crate::page_cache::flush_all()?;
+    snapshot::create(name).map_err(snapshot_error)
+}
+
+/// Delete the snapshot `name`, which must not be mounted.
+pub fn delete_snapshot(name: &str) -> AxResult {
+    snapshot::delete(name).map_err(snapshot_error)
+}
+
+/// Names of all snapshots of the root filesystem.
+pub fn snapshots() -> AxResult<Vec<String>> {
+    snapshot::list().map_err(snapshot_error)
+}
+
+fn resize_error(e: &'static str) -> AxError {
+    match e {
+        resize::HAS_SNAPSHOTS => AxError::ResourceBusy,
+        resize::TOO_SMALL => AxError::InvalidInput,
+        CORRUPTED => AxError::InvalidData,
+        _ => AxError::StorageFull,
+    }
+}
+
+/// Grow or shrink the root filesystem to `blocks` blocks of its disk.
+pub fn resize(blocks: u64) -> AxResult {
+    if blocks > BLOCK_DEV.lock().num_blocks() || blocks > u32::MAX as u64 {
+        return Err(AxError::InvalidInput);
+    }
+    // the blocks of cached file data may move
+    crate::page_cache::flush_all()?;
+    Xv6FS::new().resize(blocks as u32).map_err(resize_error)
+}
+
+/// Discard the free blocks of the root filesystem on its disk,
+/// returns how many bytes were discarded.
+pub fn trim() -> AxResult<u64> {
+    match Xv6FS::new().trim(1) {
+        Ok(blocks) => Ok(blocks as u64 * xv6fs::fs_const::BSIZE as u64),
+        Err(CORRUPTED) => Err(AxError::InvalidData),
+        Err(_) => Err(AxError::Io),
+    }
+}
+
+/// Write-back interval in milliseconds, 0 means every op is committed at once.
+static WRITE_BACK_INTERVAL: AtomicU64 = AtomicU64::new(0);
+static WRITE_BACK_TASK: AtomicBool = AtomicBool::new(false);
+
+/// Switch between synchronous commits (`None`) and commits batched by a
+/// background task that wakes up every `interval`.
+///
+/// The task sleeps on the axtask timer, so axtask should have the `irq`
+/// feature enabled, otherwise the sleep is a busy wait.
+pub fn set_write_back(interval: Option<Duration>) {
+    match interval {
+        None => {
+            // the task exits when it wakes up next time
+            WRITE_BACK_INTERVAL.store(0, Ordering::Release);
+            LOG_MANAGER.set_mode(WriteBackMode::Sync);
+        }
+        Some(interval) => {
+            let ms = (interval.as_millis() as u64).max(1);
+            WRITE_BACK_INTERVAL.store(ms, Ordering::Release);
+            LOG_MANAGER.set_mode(WriteBackMode::Delayed);
+            if !WRITE_BACK_TASK.swap(true, Ordering::AcqRel) {
+                axtask::spawn(write_back_task);
+            }
+        }
+    }
+}
+
+fn write_back_task() {
+    info!("xv6fs write-back task started");
+    loop {
+        loop {
+            let ms = WRITE_BACK_INTERVAL.load(Ordering::Acquire);
+            if ms == 0 {
+                break;
+            }
+            axtask::sleep(Duration::from_millis(ms));
+            if LOG_MANAGER.pending() > 0 {
+                LOG_MANAGER.flush();
+            }
+        }
+        WRITE_BACK_TASK.store(false, Ordering::Release);
+        // write-back may be enabled again before the flag is cleared
+        if WRITE_BACK_INTERVAL.load(Ordering::Acquire) == 0
+            || WRITE_BACK_TASK.swap(true, Ordering::AcqRel)
+        {
+            break;
+        }
+    }
+    info!("xv6fs write-back task exited");
 }
 
 static BLOCK_DEV:LazyInit<Mutex<axdevice>>=LazyInit::new();
+/// Whether xv6fs is mounted on the disk, then only it writes to the disk.
+static DISK_MOUNTED: AtomicBool = AtomicBool::new(false);
+
 fn init_block_dev(blk_devs: axdevice){
     BLOCK_DEV.init_by(Mutex::new(blk_devs));
 }
 
 pub struct DiskOps;
 
+// xv6fs has no way to handle I/O errors, they are only logged
 impl BlockDevice for DiskOps {
     fn read_block(&self, _block_id: usize, _buf: &mut [u8]) {
-        let _=BLOCK_DEV.lock().read_block(_block_id as u64, _buf);
+        if let Err(e) = BLOCK_DEV.lock().read_block(_block_id as u64, _buf) {
+            error!("xv6fs: failed to read block {}: {:?}", _block_id, e);
+        }
     }
 
     fn write_block(&self, _block_id: usize, _buf: &[u8]) {
-        let _=BLOCK_DEV.lock().write_block(_block_id as u64, _buf);
+        if let Err(e) = BLOCK_DEV.lock().write_block(_block_id as u64, _buf) {
+            error!("xv6fs: failed to write block {}: {:?}", _block_id, e);
+        }
+    }
+
+    fn flush(&self) {
+        if let Err(e) = BLOCK_DEV.lock().flush() {
+            error!("xv6fs: failed to flush the disk: {:?}", e);
+        }
+    }
+
+    fn discard(&self, block_id: usize, count: usize) {
+        match BLOCK_DEV.lock().discard(block_id as u64, count as u64) {
+            // devices that can't discard simply keep the data
+            Ok(()) | Err(DevError::Unsupported) => {}
+            Err(e) => warn!("xv6fs: failed to discard {} blocks from {}: {:?}", count, block_id, e),
+        }
+    }
+}
+
+/// The disk under xv6fs as a block device node, registered as
+/// [`devsw::DISK_DEV`].
+///
+/// It can't be written while xv6fs is mounted on the disk, as the writes
+/// would bypass the log and the buffer cache of the filesystem.
+pub struct DiskDev;
+
+impl VfsNodeOps for DiskDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = BLOCK_DEV.lock().num_blocks() * xv6fs::fs_const::BSIZE as u64;
+        Ok(VfsNodeAttr::new(VfsNodePerm::default_file(), VfsNodeType::BlockDevice, size, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        const BSIZE: usize = xv6fs::fs_const::BSIZE;
+        let mut dev = BLOCK_DEV.lock();
+        let size = dev.num_blocks() as usize * BSIZE;
+        let mut pos = offset as usize;
+        let mut read_len = 0;
+        let mut data = [0u8; BSIZE];
+        while read_len < buf.len() && pos < size {
+            let start = pos % BSIZE;
+            let count = (buf.len() - read_len).min(BSIZE - start).min(size - pos);
+            dev.read_block((pos / BSIZE) as u64, &mut data)
+                .map_err(|_| AxError::Io)?;
+            buf[read_len..read_len + count].copy_from_slice(&data[start..start + count]);
+            pos += count;
+            read_len += count;
+        }
+        Ok(read_len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        const BSIZE: usize = xv6fs::fs_const::BSIZE;
+        if DISK_MOUNTED.load(Ordering::Acquire) {
+            return Err(AxError::ResourceBusy);
+        }
+        let mut dev = BLOCK_DEV.lock();
+        let size = dev.num_blocks() as usize * BSIZE;
+        let mut pos = offset as usize;
+        let mut write_len = 0;
+        let mut data = [0u8; BSIZE];
+        while write_len < buf.len() && pos < size {
+            let start = pos % BSIZE;
+            let count = (buf.len() - write_len).min(BSIZE - start).min(size - pos);
+            if count < BSIZE {
+                dev.read_block((pos / BSIZE) as u64, &mut data)
+                .map_err(|_| AxError::Io)?;
+            }
+            data[start..start + count].copy_from_slice(&buf[write_len..write_len + count]);
+            dev.write_block((pos / BSIZE) as u64, &data)
+                .map_err(|_| AxError::Io)?;
+            pos += count;
+            write_len += count;
+        }
+        Ok(write_len)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
     }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
 }
 
 impl Xv6FileSystem{
@@ -53,8 +289,13 @@ impl Xv6FileSystem{
         INTERFACE_MANAGER.init_by(interface);
         info!("init block device");
         init_block_dev(blk_dev);
+        devsw::register(devsw::DISK_DEV.0, devsw::DISK_DEV.1, Arc::new(DiskDev)).ok();
         info!("init xv6fs");
-        unsafe{xv6fs::init(Arc::new(DiskOps), 0);}
+        if let Err(e) = unsafe { xv6fs::init(Arc::new(DiskOps), 0) } {
+            panic!("xv6fs: failed to mount the root file system: {}", e);
+        }
+        // it's never unmounted
+        DISK_MOUNTED.store(true, Ordering::Release);
     }
 }
 
@@ -64,7 +305,7 @@ pub struct AxFsInterface{
 
 impl AxFsInterface {
     pub fn new()->Self{
-        Self{ fs_lock_list: RwLock::new(FsLockList { lock_list:Vec::new() }) }
+        Self{ fs_lock_list: RwLock::new(FsLockList::new()) }
     }
 }
 
@@ -76,16 +317,40 @@ impl FsInterface for AxFsInterface{
     fn new_sleep_lock(&self)->usize {
         self.fs_lock_list.write().new_lock()
     }
-    fn sleep_cur_proc(&self,index:usize) {
-        //info!("index is {}",index);
-        //info!("before, lock is {}",self.fs_lock_list.read().lock_list[index].flag.load(core::sync::atomic::Ordering::Acquire));
-        self.fs_lock_list.read().lock_list[index].sleep_cur_task();
-        //info!("after, lock is {}",self.fs_lock_list.read().lock_list[index].flag.load(core::sync::atomic::Ordering::Acquire));
+    fn sleep_lock(&self,index:usize) {
+        // don't hold the list while sleeping
+        let lock=self.fs_lock_list.read().get(index);
+        lock.lock();
+    }
+    fn sleep_lock_shared(&self,index:usize) {
+        let lock=self.fs_lock_list.read().get(index);
+        lock.lock_shared();
+    }
+    fn sleep_unlock(&self,index:usize) {
+        let lock=self.fs_lock_list.read().get(index);
+        lock.unlock();
+    }
+    fn sleep_unlock_shared(&self,index:usize) {
+        let lock=self.fs_lock_list.read().get(index);
+        lock.unlock_shared();
+    }
+    fn dev_read(&self,major:i16,minor:i16,offset:usize,buf:&mut [u8])->Result<usize,&'static str> {
+        let dev=devsw::get(major as u32, minor as u32).map_err(|_| "no such device")?;
+        dev.read_at(offset as u64, buf).map_err(|e| e.as_str())
+    }
+    fn dev_write(&self,major:i16,minor:i16,offset:usize,buf:&[u8])->Result<usize,&'static str> {
+        let dev=devsw::get(major as u32, minor as u32).map_err(|_| "no such device")?;
+        dev.write_at(offset as u64, buf).map_err(|e| e.as_str())
+    }
+    fn dev_is_block(&self,major:i16,minor:i16)->bool {
+        devsw::get(major as u32, minor as u32)
+            .and_then(|dev| dev.get_attr())
+            .map_or(false, |attr| attr.file_type()==VfsNodeType::BlockDevice)
     }
-    fn wake_up_next_proc(&self,index:usize) {
-        self.fs_lock_list.read().lock_list[index].wake_up_next_proc();
+    fn cur_task_id(&self)->usize {
+        axtask::current().id().as_u64() as usize
     }
-    fn get_flag(&self,index:usize)->bool {
-        self.fs_lock_list.read().lock_list[index].flag.load(core::sync::atomic::Ordering::Acquire)
+    fn inode_freed(&self,dev:u32,inum:u32) {
+        crate::page_cache::invalidate(axfs_xv6fs::file::cache_id(dev, inum));
     }
 }
\ No newline at end of file
@@ -4,12 +4,16 @@ use core::cell::UnsafeCell;
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
+use lazy_init::LazyInit;
 use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
 
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
 
+/// The FAT filesystem on the block device, if it is the root filesystem.
+pub static FAT_FS: LazyInit<Arc<FatFileSystem>> = LazyInit::new();
+
 pub struct FatFileSystem {
     inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
@@ -13,4 +13,102 @@ cfg_if::cfg_if! {
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
-pub use axfs_ramfs as ramfs;
\ No newline at end of file
+pub use axfs_ramfs as ramfs;
+
+use alloc::{sync::Arc, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::VfsOps;
+use axsync::Mutex;
+
+/// The name of the block device, which holds the root filesystem.
+pub(crate) const DISK: &str = "disk";
+
+/// Creates a filesystem on the device named by `source`.
+pub type FsCreator = fn(source: &str) -> AxResult<Arc<dyn VfsOps>>;
+
+static FS_TYPES: Mutex<Vec<(&'static str, FsCreator)>> = Mutex::new(Vec::new());
+
+/// Registers a filesystem type that can be mounted by the name `fstype`.
+pub(crate) fn register_fs_type(fstype: &'static str, creator: FsCreator) -> AxResult {
+    let mut fs_types = FS_TYPES.lock();
+    if fs_types.iter().any(|&(name, _)| name == fstype) {
+        return ax_err!(AlreadyExists, "filesystem type already registered");
+    }
+    fs_types.push((fstype, creator));
+    Ok(())
+}
+
+/// Returns the names of all registered filesystem types.
+pub(crate) fn fs_types() -> Vec<&'static str> {
+    FS_TYPES.lock().iter().map(|&(name, _)| name).collect()
+}
+
+/// Creates a filesystem of the type `fstype` on `source`.
+pub(crate) fn create(fstype: &str, source: &str) -> AxResult<Arc<dyn VfsOps>> {
+    let creator = FS_TYPES
+        .lock()
+        .iter()
+        .find(|&&(name, _)| name == fstype)
+        .map(|&(_, creator)| creator)
+        .ok_or(AxError::NotFound)?;
+    creator(source)
+}
+
+/// Returns an error unless `source` names the block device.
+#[cfg(any(feature = "xv6fs", feature = "fatfs"))]
+fn check_disk(source: &str) -> AxResult {
+    if source == DISK {
+        Ok(())
+    } else {
+        ax_err!(NotFound, "no such block device")
+    }
+}
+
+/// Registers the built-in filesystem types, whenever the root filesystem of
+/// the type `root_fstype` is set up.
+///
+/// The block device only holds the root filesystem, so just its type is
+/// registered among xv6fs and fatfs. Mounting it again shows the same files.
+#[cfg_attr(not(any(feature = "xv6fs", feature = "fatfs")), allow(unused_variables))]
+pub(crate) fn init_fs_types(root_fstype: &str) {
+    #[cfg(feature = "xv6fs")]
+    if root_fstype == "xv6fs" {
+        register_fs_type("xv6fs", |source| {
+            check_disk(source)?;
+            Ok(Arc::new(axxv6fs::Xv6FileSystem::new()))
+        })
+        .ok();
+        register_fs_type("xv6snap", |name| {
+            Ok(Arc::new(axxv6fs::Xv6SnapshotFs::new(name)?))
+        })
+        .ok();
+    }
+    #[cfg(feature = "fatfs")]
+    if root_fstype == "fatfs" {
+        register_fs_type("fatfs", |source| {
+            check_disk(source)?;
+            Ok(fatfs::FAT_FS.clone())
+        })
+        .ok();
+    }
+    #[cfg(feature = "ramfs")]
+    register_fs_type("ramfs", |_| Ok(Arc::new(ramfs::RamFileSystem::new()))).ok();
+    #[cfg(feature = "devfs")]
+    register_fs_type("devfs", |_| {
+        use crate::devsw;
+        let devfs = devfs::DeviceFileSystem::new();
+        let devices = [
+            ("null", devsw::NULL_DEV),
+            ("zero", devsw::ZERO_DEV),
+            ("console", devsw::CONSOLE_DEV),
+            ("disk", devsw::DISK_DEV),
+        ];
+        for (name, (major, minor)) in devices {
+            if let Ok(node) = devsw::get(major, minor) {
+                devfs.add(name, node);
+            }
+        }
+        Ok(Arc::new(devfs))
+    })
+    .ok();
+}
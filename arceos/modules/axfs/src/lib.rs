@@ -33,10 +33,13 @@ extern crate alloc;
 
 mod dev;
 mod fs;
+mod lock;
 mod root;
+pub mod page_cache;
 mod sleeplock_shim;
 
 pub mod api;
+pub mod devsw;
 pub mod fops;
 
 cfg_if::cfg_if! {
@@ -0,0 +1,348 @@
+//! Advisory file locks, as taken by `flock` and `fcntl(F_SETLK)`.
+//!
+//! A lock covers the bytes `start..end` of a file, a whole-file lock covers
+//! `0..u64::MAX`. There are two classes of locks, which never conflict with
+//! each other:
+//!
+//! - `flock` locks belong to an opened [`File`](crate::fops::File), and are
+//!   released when it is closed.
+//! - Record locks, as `fcntl` takes them, belong to an owner chosen by the
+//!   caller and to the file, not to an opened file: they are placed and
+//!   released through any opened file of the same file.
+//!
+//! The locks of one owner never conflict with each other. Between different
+//! owners, a shared lock conflicts with the overlapping exclusive locks, and
+//! an exclusive lock with all overlapping locks.
+//!
+//! Files are told apart by their cache ID, which is the same for every node of
+//! a file, or else by the address of their node, which can't be reused while
+//! the node is opened. Reads and writes don't check the locks.
+//!
+//! The table is a spin lock because waiters test it with the run queue held,
+//! see [`WaitQueue::wait_until`].
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axtask::WaitQueue;
+use core::cell::Cell;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spinlock::SpinNoIrq;
+
+/// The kind of an advisory lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Any number of files may hold a shared lock on the same bytes.
+    Shared,
+    /// No other file may hold a lock on the bytes.
+    Exclusive,
+}
+
+/// A lock held on a range of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLock {
+    /// The kind of the lock.
+    pub kind: LockKind,
+    /// The bytes it covers.
+    pub range: Range<u64>,
+    /// Who holds it: the owner of a record lock, or a number that tells the
+    /// opened file of a `flock` lock apart from the others.
+    pub owner: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NodeKey {
+    Cached(u64),
+    Node(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    /// An opened file, for `flock`.
+    File(u64),
+    /// The owner of record locks.
+    Record(u64),
+}
+
+impl Owner {
+    fn same_class(&self, other: &Owner) -> bool {
+        matches!(
+            (self, other),
+            (Owner::File(_), Owner::File(_)) | (Owner::Record(_), Owner::Record(_))
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Lock {
+    owner: Owner,
+    kind: LockKind,
+    start: u64,
+    end: u64,
+}
+
+impl Lock {
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.start < range.end && range.start < self.end
+    }
+}
+
+static LOCKS: SpinNoIrq<BTreeMap<NodeKey, Vec<Lock>>> = SpinNoIrq::new(BTreeMap::new());
+/// Tasks waiting for any lock, they are woken whenever a lock is released.
+static WAITERS: WaitQueue = WaitQueue::new();
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(1);
+
+/// The advisory locks of an opened file.
+///
+/// All clones of a handle take and release the same locks. Since the file may
+/// be used by other tasks while one waits for a lock, callers that share the
+/// file behind a mutex can take a handle and wait without holding the mutex.
+/// The `flock` locks are released when the file is closed, so a handle must
+/// not be used after that.
+#[derive(Debug, Clone)]
+pub struct LockHandle {
+    key: NodeKey,
+    owner: u64,
+}
+
+impl LockHandle {
+    pub(crate) fn new(node: &VfsNodeRef, cache_id: Option<u64>) -> Self {
+        let key = match cache_id {
+            Some(id) => NodeKey::Cached(id),
+            None => NodeKey::Node(Arc::as_ptr(node) as *const () as usize),
+        };
+        Self::with_key(key)
+    }
+
+    fn with_key(key: NodeKey) -> Self {
+        Self {
+            key,
+            owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Places a `flock` lock of the given kind on `range`, replacing the
+    /// locks this file holds there.
+    ///
+    /// If a conflicting lock is held through another file, waits until it is
+    /// released, or fails with [`Again`](axerrno::AxError::Again) if `wait`
+    /// is false.
+    pub fn lock(&self, kind: LockKind, range: Range<u64>, wait: bool) -> AxResult {
+        self.lock_as(Owner::File(self.owner), kind, range, wait)
+    }
+
+    /// Releases the `flock` locks this file holds on `range`.
+    pub fn unlock(&self, range: Range<u64>) -> AxResult {
+        self.unlock_as(Owner::File(self.owner), range)
+    }
+
+    /// Returns a `flock` lock held through another file that keeps this file
+    /// from placing a lock of the given kind on `range`, if there is one.
+    pub fn conflict(&self, kind: LockKind, range: Range<u64>) -> Option<FileLock> {
+        self.conflict_as(Owner::File(self.owner), kind, range)
+    }
+
+    /// Places a record lock of `owner` on `range` of the file, replacing the
+    /// record locks `owner` holds there, whichever opened file they were
+    /// placed through. Waits for the conflicting record locks of other owners
+    /// like [`LockHandle::lock`].
+    pub fn lock_record(&self, owner: u64, kind: LockKind, range: Range<u64>, wait: bool) -> AxResult {
+        self.lock_as(Owner::Record(owner), kind, range, wait)
+    }
+
+    /// Releases the record locks of `owner` on `range` of the file.
+    pub fn unlock_record(&self, owner: u64, range: Range<u64>) -> AxResult {
+        self.unlock_as(Owner::Record(owner), range)
+    }
+
+    /// Returns a record lock of another owner that keeps `owner` from placing
+    /// a lock of the given kind on `range`, if there is one.
+    pub fn record_conflict(&self, owner: u64, kind: LockKind, range: Range<u64>) -> Option<FileLock> {
+        self.conflict_as(Owner::Record(owner), kind, range)
+    }
+
+    /// Releases all `flock` locks of this file, when it is closed.
+    pub(crate) fn release_all(&self) {
+        self.unlock(0..u64::MAX).ok();
+    }
+
+    fn lock_as(&self, owner: Owner, kind: LockKind, range: Range<u64>, wait: bool) -> AxResult {
+        if range.is_empty() {
+            return ax_err!(InvalidInput);
+        }
+        let released = match self.try_lock(owner, kind, &range) {
+            Some(released) => released,
+            None if !wait => return ax_err!(Again),
+            None => {
+                // waking other waiters must not happen with the run queue held
+                let released = Cell::new(false);
+                WAITERS.wait_until(|| match self.try_lock(owner, kind, &range) {
+                    Some(r) => {
+                        released.set(r);
+                        true
+                    }
+                    None => false,
+                });
+                released.get()
+            }
+        };
+        if released {
+            WAITERS.notify_all(true);
+        }
+        Ok(())
+    }
+
+    fn unlock_as(&self, owner: Owner, range: Range<u64>) -> AxResult {
+        if range.is_empty() {
+            return ax_err!(InvalidInput);
+        }
+        let mut table = LOCKS.lock();
+        let released = match table.get_mut(&self.key) {
+            Some(locks) => cut(owner, locks, &range),
+            None => false,
+        };
+        if table.get(&self.key).map_or(false, |locks| locks.is_empty()) {
+            table.remove(&self.key);
+        }
+        drop(table);
+        if released {
+            WAITERS.notify_all(true);
+        }
+        Ok(())
+    }
+
+    fn conflict_as(&self, owner: Owner, kind: LockKind, range: Range<u64>) -> Option<FileLock> {
+        let table = LOCKS.lock();
+        let lock = find_conflict(owner, table.get(&self.key)?, kind, &range)?;
+        let owner = match lock.owner {
+            Owner::File(owner) | Owner::Record(owner) => owner,
+        };
+        Some(FileLock {
+            kind: lock.kind,
+            range: lock.start..lock.end,
+            owner,
+        })
+    }
+
+    /// Returns `None` if the lock conflicts, otherwise whether placing it
+    /// released a lock of `owner`, which happens when it changes kind.
+    fn try_lock(&self, owner: Owner, kind: LockKind, range: &Range<u64>) -> Option<bool> {
+        let mut table = LOCKS.lock();
+        let locks = table.entry(self.key).or_default();
+        if find_conflict(owner, locks, kind, range).is_some() {
+            return None;
+        }
+        let released = cut(owner, locks, range);
+        locks.push(Lock {
+            owner,
+            kind,
+            start: range.start,
+            end: range.end,
+        });
+        Some(released)
+    }
+}
+
+fn find_conflict<'a>(owner: Owner, locks: &'a [Lock], kind: LockKind, range: &Range<u64>) -> Option<&'a Lock> {
+    locks.iter().find(|l| {
+        l.owner != owner
+            && l.owner.same_class(&owner)
+            && l.overlaps(range)
+            && (kind == LockKind::Exclusive || l.kind == LockKind::Exclusive)
+    })
+}
+
+/// Removes the locks of `owner` from `range`, keeping the parts outside of
+/// it. Returns whether anything was removed.
+fn cut(owner: Owner, locks: &mut Vec<Lock>, range: &Range<u64>) -> bool {
+    let mut released = false;
+    for lock in core::mem::take(locks) {
+        if lock.owner != owner || !lock.overlaps(range) {
+            locks.push(lock);
+            continue;
+        }
+        released = true;
+        if lock.start < range.start {
+            locks.push(Lock { end: range.start, ..lock });
+        }
+        if range.end < lock.end {
+            locks.push(Lock { start: range.end, ..lock });
+        }
+    }
+    released
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axerrno::AxError;
+
+    const WHOLE: Range<u64> = 0..u64::MAX;
+
+    fn is_again(res: AxResult) -> bool {
+        matches!(res, Err(AxError::Again))
+    }
+
+    #[test]
+    fn test_flock_per_opened_file() {
+        let a = LockHandle::with_key(NodeKey::Cached(1));
+        let b = LockHandle::with_key(NodeKey::Cached(1));
+        a.lock(LockKind::Shared, WHOLE, false).unwrap();
+        b.lock(LockKind::Shared, WHOLE, false).unwrap();
+        assert!(is_again(a.lock(LockKind::Exclusive, WHOLE, false)));
+        b.unlock(WHOLE).unwrap();
+        // upgrading the own lock doesn't conflict
+        a.lock(LockKind::Exclusive, WHOLE, false).unwrap();
+        let held = b.conflict(LockKind::Shared, 10..20).unwrap();
+        assert_eq!((held.kind, held.range, held.owner), (LockKind::Exclusive, WHOLE, a.owner));
+        // clones share the locks, closing releases them
+        let clone = a.clone();
+        std::thread::spawn(move || clone.lock(LockKind::Exclusive, 0..10, false).unwrap())
+            .join()
+            .unwrap();
+        a.release_all();
+        assert_eq!(b.conflict(LockKind::Exclusive, WHOLE), None);
+        // another file isn't affected
+        let other = LockHandle::with_key(NodeKey::Cached(2));
+        b.lock(LockKind::Exclusive, WHOLE, false).unwrap();
+        other.lock(LockKind::Exclusive, WHOLE, false).unwrap();
+        b.release_all();
+        other.release_all();
+    }
+
+    #[test]
+    fn test_record_locks_per_owner() {
+        let (fd1, fd2) = (LockHandle::with_key(NodeKey::Cached(3)), LockHandle::with_key(NodeKey::Cached(3)));
+        fd1.lock_record(1, LockKind::Exclusive, 0..100, false).unwrap();
+        // the same owner through another opened file
+        fd2.lock_record(1, LockKind::Exclusive, 50..150, false).unwrap();
+        assert!(is_again(fd2.lock_record(2, LockKind::Shared, 120..130, false)));
+        let held = fd1.record_conflict(2, LockKind::Shared, 120..130).unwrap();
+        assert_eq!((held.kind, held.range, held.owner), (LockKind::Exclusive, 50..150, 1));
+        // released through any opened file, the rest of the range stays
+        fd2.unlock_record(1, 0..120).unwrap();
+        fd1.lock_record(2, LockKind::Shared, 0..120, false).unwrap();
+        assert!(is_again(fd1.lock_record(2, LockKind::Shared, 120..121, false)));
+        // closing an opened file leaves the record locks
+        fd1.release_all();
+        assert!(is_again(fd2.lock_record(1, LockKind::Exclusive, 10..20, false)));
+        fd1.unlock_record(1, WHOLE).unwrap();
+        fd1.unlock_record(2, WHOLE).unwrap();
+        assert_eq!(fd2.record_conflict(3, LockKind::Exclusive, WHOLE), None);
+    }
+
+    #[test]
+    fn test_classes_are_independent() {
+        let (a, b) = (LockHandle::with_key(NodeKey::Cached(4)), LockHandle::with_key(NodeKey::Cached(4)));
+        a.lock(LockKind::Exclusive, WHOLE, false).unwrap();
+        b.lock_record(7, LockKind::Exclusive, WHOLE, false).unwrap();
+        assert!(is_again(b.lock(LockKind::Shared, WHOLE, false)));
+        assert!(is_again(a.lock_record(8, LockKind::Shared, 0..1, false)));
+        a.release_all();
+        b.unlock_record(7, WHOLE).unwrap();
+        assert!(LOCKS.lock().get(&NodeKey::Cached(4)).is_none());
+    }
+}
@@ -0,0 +1,659 @@
+//! Page cache shared by all filesystems.
+//!
+//! File data is cached in pages of [`PAGE_SIZE`] bytes, keyed by the ID that
+//! a node returns from [`VfsNodeOps::cache_id`] and the page index. Nodes
+//! that return `None` there (the default) are not cached.
+//!
+//! Writes only update the cache and mark the pages dirty. Dirty pages are
+//! written back to the node by [`File::flush`](crate::fops::File::flush),
+//! [`sync`](crate::api::sync), the umount of their filesystem, or when pages
+//! are evicted because the
+//! cache is full or [`shrink`] is called. Under memory pressure the allocator
+//! calls [`reclaim`], which drops clean pages only.
+//!
+//! Each cached file has a lock of its own, so that the I/O of one file
+//! doesn't hold up the others. The lock of the file table is never held while
+//! waiting for the lock of a file.
+//!
+//! [`VfsNodeOps::cache_id`]: axfs_vfs::VfsNodeOps::cache_id
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, vec, vec::Vec};
+use axerrno::AxResult;
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::root::MountUser;
+
+/// Size of a cached page.
+pub const PAGE_SIZE: usize = 4096;
+/// Maximum number of pages kept in the cache.
+pub const MAX_PAGES: usize = 256;
+/// Number of pages read ahead when a file is accessed sequentially.
+pub const READ_AHEAD_PAGES: u64 = 4;
+/// Maximum number of files kept in the cache. Each cached file holds its node,
+/// which may pin an in-memory inode of the filesystem.
+pub const MAX_FILES: usize = 16;
+
+struct Page {
+    data: Box<[u8]>,
+    dirty: bool,
+    last_access: u64,
+}
+
+struct CachedFile {
+    node: VfsNodeRef,
+    /// Size of the file, including the data that is only in the cache.
+    size: u64,
+    /// Size of the file on the node, i.e. after the last write-back.
+    disk_size: u64,
+    pages: BTreeMap<u64, Page>,
+    /// Index of the page that a sequential read will access next.
+    next_page: u64,
+}
+
+/// A file in the table of the cache.
+struct FileEntry {
+    id: u64,
+    /// User token of the mounted filesystem the file is on, `None` for the
+    /// root filesystem. It doesn't keep the filesystem from being unmounted.
+    mount: Option<Weak<()>>,
+    file: Mutex<CachedFile>,
+    last_access: AtomicU64,
+    /// Set once the entry has left the table, the tasks still holding it
+    /// must look the file up again.
+    removed: AtomicBool,
+}
+
+static FILES: Mutex<BTreeMap<u64, Arc<FileEntry>>> = Mutex::new(BTreeMap::new());
+/// Number of pages of all cached files.
+static NR_PAGES: AtomicUsize = AtomicUsize::new(0);
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+fn tick() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+impl CachedFile {
+    /// Write all dirty pages back to the node, in the order of their offsets
+    /// so that a file is never written beyond its end.
+    fn flush(&mut self) -> AxResult {
+        for (&idx, page) in self.pages.iter_mut() {
+            if !page.dirty {
+                continue;
+            }
+            let start = idx * PAGE_SIZE as u64;
+            if start < self.size {
+                let len = (self.size - start).min(PAGE_SIZE as u64) as usize;
+                let mut written = 0;
+                while written < len {
+                    let n = self
+                        .node
+                        .write_at(start + written as u64, &page.data[written..len])?;
+                    if n == 0 {
+                        return axerrno::ax_err!(Io, "page cache write-back failed");
+                    }
+                    written += n;
+                }
+            }
+            page.dirty = false;
+        }
+        self.disk_size = self.size;
+        Ok(())
+    }
+
+    /// Evict the least recently used page, writing the file back if the page
+    /// is dirty. Returns `false` if the file has no pages.
+    fn evict_one(&mut self) -> AxResult<bool> {
+        let victim = self.pages.iter().min_by_key(|(_, p)| p.last_access);
+        let Some((&idx, page)) = victim else {
+            return Ok(false);
+        };
+        if page.dirty {
+            self.flush()?;
+        }
+        self.pages.remove(&idx);
+        NR_PAGES.fetch_sub(1, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Drop up to `max` clean pages, returns how many were dropped.
+    fn drop_clean(&mut self, max: usize) -> usize {
+        let mut dropped = 0;
+        self.pages.retain(|_, page| {
+            if dropped < max && !page.dirty {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+        NR_PAGES.fetch_sub(dropped, Ordering::Relaxed);
+        dropped
+    }
+
+    fn clear(&mut self) {
+        NR_PAGES.fetch_sub(self.pages.len(), Ordering::Relaxed);
+        self.pages.clear();
+    }
+
+    /// Returns the page `idx` of the file, reading it from the node if it is
+    /// not cached. The file is `id` in the table.
+    fn page(&mut self, id: u64, idx: u64) -> AxResult<&mut Page> {
+        let clock = tick();
+        let cached = self.pages.contains_key(&idx);
+        if !cached {
+            while NR_PAGES.load(Ordering::Relaxed) >= MAX_PAGES {
+                if !evict_other(id)? && !self.evict_one()? {
+                    break;
+                }
+            }
+            let mut data = vec![0u8; PAGE_SIZE].into_boxed_slice();
+            let start = idx * PAGE_SIZE as u64;
+            let end = self.disk_size.min(start + PAGE_SIZE as u64);
+            let mut read = 0;
+            while start + (read as u64) < end {
+                let len = (end - start) as usize;
+                let n = self.node.read_at(start + read as u64, &mut data[read..len])?;
+                if n == 0 {
+                    break;
+                }
+                read += n;
+            }
+            self.pages.insert(
+                idx,
+                Page {
+                    data,
+                    dirty: false,
+                    last_access: clock,
+                },
+            );
+            NR_PAGES.fetch_add(1, Ordering::Relaxed);
+        }
+        let page = self.pages.get_mut(&idx).unwrap();
+        page.last_access = clock;
+        Ok(page)
+    }
+
+    fn read(&mut self, id: u64, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let len = (self.size - offset).min(buf.len() as u64) as usize;
+        let first = offset / PAGE_SIZE as u64;
+        let last = (offset + len as u64 - 1) / PAGE_SIZE as u64;
+        let sequential = first == self.next_page || first + 1 == self.next_page;
+        self.next_page = last + 1;
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = pos as usize % PAGE_SIZE;
+            let n = (PAGE_SIZE - start).min(len - done);
+            let page = self.page(id, pos / PAGE_SIZE as u64)?;
+            buf[done..done + n].copy_from_slice(&page.data[start..start + n]);
+            done += n;
+        }
+
+        if sequential {
+            let end = ((self.size + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64)
+                .min(last + 1 + READ_AHEAD_PAGES);
+            for idx in last + 1..end {
+                self.page(id, idx)?;
+            }
+        }
+        Ok(len)
+    }
+
+    fn write(&mut self, id: u64, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        // a write beyond the end leaves a hole of zeros, which must reach the
+        // node as well
+        if offset > self.size {
+            let hole = self.size / PAGE_SIZE as u64..(offset + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64;
+            for idx in hole {
+                self.page(id, idx)?.dirty = true;
+            }
+        }
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let start = pos as usize % PAGE_SIZE;
+            let n = (PAGE_SIZE - start).min(buf.len() - done);
+            let page = self.page(id, pos / PAGE_SIZE as u64)?;
+            page.data[start..start + n].copy_from_slice(&buf[done..done + n]);
+            page.dirty = true;
+            done += n;
+        }
+        self.size = self.size.max(offset + buf.len() as u64);
+        Ok(buf.len())
+    }
+
+    fn truncate(&mut self, size: u64) -> AxResult {
+        self.flush()?;
+        let keep = (size + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64;
+        let dropped = self.pages.split_off(&keep).len();
+        NR_PAGES.fetch_sub(dropped, Ordering::Relaxed);
+        if size % PAGE_SIZE as u64 != 0 {
+            if let Some(page) = self.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+                page.data[size as usize % PAGE_SIZE..].fill(0);
+            }
+        }
+        self.node.truncate(size)?;
+        self.size = size;
+        self.disk_size = size;
+        Ok(())
+    }
+}
+
+impl Drop for CachedFile {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Returns the entry of the file `id`, adding it to the table if it isn't
+/// there. Evicts the least recently used file if the table is full.
+fn entry(id: u64, node: &VfsNodeRef, mount: Option<&MountUser>) -> AxResult<Arc<FileEntry>> {
+    loop {
+        let files = FILES.lock();
+        if let Some(entry) = files.get(&id) {
+            return Ok(entry.clone());
+        }
+        if files.len() < MAX_FILES {
+            break;
+        }
+        let victim = files
+            .values()
+            .min_by_key(|e| e.last_access.load(Ordering::Relaxed))
+            .unwrap()
+            .clone();
+        drop(files);
+        evict_file(&victim)?;
+    }
+    let size = node.get_attr()?.size();
+    let entry = Arc::new(FileEntry {
+        id,
+        mount: mount.map(Arc::downgrade),
+        file: Mutex::new(CachedFile {
+            node: node.clone(),
+            size,
+            disk_size: size,
+            pages: BTreeMap::new(),
+            next_page: 0,
+        }),
+        last_access: AtomicU64::new(tick()),
+        removed: AtomicBool::new(false),
+    });
+    // another task may have added it meanwhile
+    let entry = FILES.lock().entry(id).or_insert(entry).clone();
+    Ok(entry)
+}
+
+/// Takes `entry` out of the table, its lock must be held.
+fn remove(entry: &Arc<FileEntry>) {
+    entry.removed.store(true, Ordering::Release);
+    let mut files = FILES.lock();
+    let removed = match files.get(&entry.id) {
+        Some(e) if Arc::ptr_eq(e, entry) => files.remove(&entry.id),
+        _ => None,
+    };
+    drop(files);
+    // the node is dropped out of the lock of the table, as it may free an
+    // inode and get the file invalidated
+    drop(removed);
+}
+
+/// Writes the file of `entry` back and removes it from the cache. The file
+/// stays cached if the write-back fails, so that no data is lost.
+fn evict_file(entry: &Arc<FileEntry>) -> AxResult {
+    let mut file = entry.file.lock();
+    if entry.removed.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    file.flush()?;
+    file.clear();
+    remove(entry);
+    Ok(())
+}
+
+/// Evicts a page of another file than `id`, roughly the least recently used
+/// one. Files locked by other tasks are skipped. Returns `false` if there is
+/// no page to evict.
+fn evict_other(id: u64) -> AxResult<bool> {
+    let mut others: Vec<_> = FILES
+        .lock()
+        .values()
+        .filter(|e| e.id != id)
+        .cloned()
+        .collect();
+    others.sort_by_key(|e| e.last_access.load(Ordering::Relaxed));
+    for entry in others {
+        if let Some(mut file) = entry.file.try_lock() {
+            if !entry.removed.load(Ordering::Acquire) && file.evict_one()? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Runs `f` on the file `id` with its lock held.
+fn with_file<T>(
+    id: u64,
+    node: &VfsNodeRef,
+    mount: Option<&MountUser>,
+    mut f: impl FnMut(&mut CachedFile) -> AxResult<T>,
+) -> AxResult<T> {
+    loop {
+        let entry = entry(id, node, mount)?;
+        let mut file = entry.file.lock();
+        if entry.removed.load(Ordering::Acquire) {
+            continue;
+        }
+        entry.last_access.store(tick(), Ordering::Relaxed);
+        return f(&mut file);
+    }
+}
+
+/// Reads cached data of the file `id` at `offset`. `mount` is the user token
+/// of the filesystem the file is on, see [`clear_mount`].
+pub(crate) fn read(
+    id: u64,
+    node: &VfsNodeRef,
+    mount: Option<&MountUser>,
+    offset: u64,
+    buf: &mut [u8],
+) -> AxResult<usize> {
+    with_file(id, node, mount, |file| file.read(id, offset, buf))
+}
+
+/// Writes data of the file `id` at `offset` into the cache.
+pub(crate) fn write(
+    id: u64,
+    node: &VfsNodeRef,
+    mount: Option<&MountUser>,
+    offset: u64,
+    buf: &[u8],
+) -> AxResult<usize> {
+    with_file(id, node, mount, |file| file.write(id, offset, buf))
+}
+
+/// Truncates the file `id` both in the cache and on the node.
+pub(crate) fn truncate(
+    id: u64,
+    node: &VfsNodeRef,
+    mount: Option<&MountUser>,
+    size: u64,
+) -> AxResult {
+    with_file(id, node, mount, |file| file.truncate(size))
+}
+
+/// Returns the size of the file `id` if it is cached, which may be larger
+/// than the size on the node.
+pub(crate) fn size(id: u64) -> Option<u64> {
+    let entry = FILES.lock().get(&id)?.clone();
+    let size = entry.file.lock().size;
+    Some(size)
+}
+
+/// Writes back the dirty pages of the file `id`.
+pub(crate) fn flush(id: u64) -> AxResult {
+    let entry = FILES.lock().get(&id).cloned();
+    match entry {
+        Some(entry) => flush_entry(&entry),
+        None => Ok(()),
+    }
+}
+
+fn flush_entry(entry: &FileEntry) -> AxResult {
+    let mut file = entry.file.lock();
+    if entry.removed.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    file.flush()
+}
+
+/// Drops all pages of the file `id` without writing them back, e.g. when its
+/// inode is freed. It doesn't wait for the tasks using the file, which will
+/// find it gone.
+pub fn invalidate(id: u64) {
+    let entry = FILES.lock().remove(&id);
+    if let Some(entry) = entry {
+        entry.removed.store(true, Ordering::Release);
+    }
+}
+
+fn entries() -> Vec<Arc<FileEntry>> {
+    FILES.lock().values().cloned().collect()
+}
+
+/// Writes back the dirty pages of all files.
+pub fn flush_all() -> AxResult {
+    for entry in entries() {
+        flush_entry(&entry)?;
+    }
+    Ok(())
+}
+
+/// Writes back and drops all files. The files that fail to be written back
+/// stay cached.
+pub fn clear() -> AxResult {
+    let mut res = Ok(());
+    for entry in entries() {
+        if let Err(e) = evict_file(&entry) {
+            res = Err(e);
+        }
+    }
+    res
+}
+
+/// Writes back and drops the files of the filesystem mounted with the user
+/// token `mount`, before it is unmounted. Stops at the first file that fails
+/// to be written back, leaving it and the rest cached.
+pub(crate) fn clear_mount(mount: &MountUser) -> AxResult {
+    let on_mount = |e: &FileEntry| {
+        e.mount.as_ref().map_or(false, |m| m.as_ptr() == Arc::as_ptr(mount))
+    };
+    for entry in entries().into_iter().filter(|e| on_mount(e)) {
+        evict_file(&entry)?;
+    }
+    Ok(())
+}
+
+/// Evicts up to `nr_pages` pages to release memory, writing back dirty ones.
+/// Returns the number of pages evicted.
+pub fn shrink(nr_pages: usize) -> AxResult<usize> {
+    let mut evicted = 0;
+    let mut others = entries();
+    others.sort_by_key(|e| e.last_access.load(Ordering::Relaxed));
+    for entry in others {
+        if evicted == nr_pages {
+            break;
+        }
+        let mut file = entry.file.lock();
+        while evicted < nr_pages && file.evict_one()? {
+            evicted += 1;
+        }
+    }
+    Ok(evicted)
+}
+
+/// Drops up to `nr_pages` clean pages, for the allocator under memory
+/// pressure. It neither blocks nor allocates memory, and skips the files in
+/// use. Returns the number of pages dropped.
+pub fn reclaim(nr_pages: usize) -> usize {
+    let Some(files) = FILES.try_lock() else {
+        return 0;
+    };
+    let mut dropped = 0;
+    for entry in files.values() {
+        if dropped == nr_pages {
+            break;
+        }
+        if let Some(mut file) = entry.file.try_lock() {
+            dropped += file.drop_clean(nr_pages - dropped);
+        }
+    }
+    dropped
+}
+
+/// Number of pages in the cache.
+pub fn nr_pages() -> usize {
+    NR_PAGES.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::sync::Arc;
+    use axerrno::AxError;
+    use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+
+    /// A file in memory whose writes can be made to fail.
+    #[derive(Default)]
+    struct TestNode {
+        data: spin::Mutex<Vec<u8>>,
+        broken: AtomicBool,
+    }
+
+    impl VfsNodeOps for TestNode {
+        fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+            Ok(VfsNodeAttr::new_file(self.data.lock().len() as _, 0))
+        }
+
+        fn truncate(&self, size: u64) -> VfsResult {
+            self.data.lock().resize(size as _, 0);
+            Ok(())
+        }
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            let data = self.data.lock();
+            let start = data.len().min(offset as usize);
+            let end = data.len().min(offset as usize + buf.len());
+            buf[..end - start].copy_from_slice(&data[start..end]);
+            Ok(end - start)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+            if self.broken.load(Ordering::Relaxed) {
+                return Err(AxError::Io);
+            }
+            let mut data = self.data.lock();
+            let end = offset as usize + buf.len();
+            if end > data.len() {
+                data.resize(end, 0);
+            }
+            data[offset as usize..end].copy_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        impl_vfs_non_dir_default! {}
+    }
+
+    fn node(data: &[u8]) -> (Arc<TestNode>, VfsNodeRef) {
+        let node = Arc::new(TestNode::default());
+        node.data.lock().extend_from_slice(data);
+        (node.clone(), node)
+    }
+
+    /// The cache is shared, so the tests run one at a time on an empty one.
+    fn setup() -> std::sync::MutexGuard<'static, ()> {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        static SERIAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        INIT.call_once(axtask::init_scheduler);
+        clear().unwrap();
+        serial
+    }
+
+    #[test]
+    fn test_evict_with_error() {
+        let _serial = setup();
+        let (broken, broken_ref) = node(b"");
+        write(100, &broken_ref, None, 0, b"dirty").unwrap();
+        let others: Vec<_> = (0..MAX_FILES as u64 - 1).map(|_| node(b"").1).collect();
+        for (i, other) in others.iter().enumerate() {
+            write(101 + i as u64, other, None, 0, b"x").unwrap();
+        }
+
+        // the least recently used file can't be written back, so it stays
+        broken.broken.store(true, Ordering::Relaxed);
+        let (_, new_ref) = node(b"");
+        assert_eq!(write(200, &new_ref, None, 0, b"new"), Err(AxError::Io));
+        assert_eq!(size(100), Some(5));
+        assert!(broken.data.lock().is_empty());
+
+        broken.broken.store(false, Ordering::Relaxed);
+        write(200, &new_ref, None, 0, b"new").unwrap();
+        assert_eq!(size(100), None);
+        assert_eq!(&broken.data.lock()[..], b"dirty");
+    }
+
+    #[test]
+    fn test_invalidate_then_reuse_id() {
+        let _serial = setup();
+        let (old, old_ref) = node(b"");
+        write(300, &old_ref, None, 0, b"stale data").unwrap();
+        invalidate(300);
+        assert_eq!(nr_pages(), 0);
+
+        // the ID now names another file
+        let (_, new_ref) = node(b"new");
+        let mut buf = [0; 16];
+        assert_eq!(read(300, &new_ref, None, 0, &mut buf), Ok(3));
+        assert_eq!(&buf[..3], b"new");
+        flush_all().unwrap();
+        assert!(old.data.lock().is_empty());
+    }
+
+    #[test]
+    fn test_clear_writes_back() {
+        let _serial = setup();
+        let (file, file_ref) = node(b"");
+        write(400, &file_ref, None, PAGE_SIZE as u64, b"umount").unwrap();
+        assert_eq!(file.data.lock().len(), 0);
+        clear().unwrap();
+        assert_eq!(size(400), None);
+        assert_eq!(nr_pages(), 0);
+        let data = file.data.lock();
+        assert_eq!(data.len(), PAGE_SIZE + 6);
+        assert!(data[..PAGE_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(&data[PAGE_SIZE..], b"umount");
+    }
+
+    #[test]
+    fn test_reclaim_drops_clean_pages() {
+        let _serial = setup();
+        let (_, clean_ref) = node(&[1; PAGE_SIZE]);
+        let mut buf = [0; PAGE_SIZE];
+        read(500, &clean_ref, None, 0, &mut buf).unwrap();
+        let (dirty, dirty_ref) = node(b"");
+        write(501, &dirty_ref, None, 0, b"dirty").unwrap();
+        assert_eq!(nr_pages(), 2);
+
+        assert_eq!(reclaim(8), 1);
+        assert_eq!(nr_pages(), 1);
+        assert!(dirty.data.lock().is_empty());
+        flush(501).unwrap();
+        assert_eq!(&dirty.data.lock()[..], b"dirty");
+    }
+
+    #[test]
+    fn test_clear_mount_keeps_other_files() {
+        let _serial = setup();
+        let mount: MountUser = Arc::new(());
+        let (mounted, mounted_ref) = node(b"");
+        write(600, &mounted_ref, Some(&mount), 0, b"mounted").unwrap();
+        let (root, root_ref) = node(b"");
+        write(601, &root_ref, None, 0, b"root").unwrap();
+
+        clear_mount(&mount).unwrap();
+        assert_eq!(size(600), None);
+        assert_eq!(&mounted.data.lock()[..], b"mounted");
+        assert_eq!(size(601), Some(4));
+        assert!(root.data.lock().is_empty());
+    }
+}
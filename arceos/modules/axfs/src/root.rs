@@ -1,6 +1,4 @@
 //! Root directory of the filesystem
-//!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
 
 use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
@@ -14,61 +12,181 @@ use crate::{api::FileType, fs::{self}};
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
+/// Held by opened files and directories to keep the filesystem they are on
+/// from being unmounted.
+pub(crate) type MountUser = Arc<()>;
+
+/// An entry of the mount table.
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// The device or the name the filesystem was mounted from.
+    pub source: String,
+    /// The absolute path of the mount point.
+    pub path: String,
+    /// The name of the filesystem type.
+    pub fstype: String,
+}
+
 struct MountPoint {
-    path: &'static str,
+    path: String,
+    source: String,
+    fstype: String,
     fs: Arc<dyn VfsOps>,
+    users: MountUser,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    main_source: &'static str,
+    main_fstype: &'static str,
+    mounts: Mutex<Vec<MountPoint>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: String, source: String, fstype: String, fs: Arc<dyn VfsOps>) -> Self {
+        Self {
+            path,
+            source,
+            fstype,
+            fs,
+            users: Arc::new(()),
+        }
     }
-}
 
-impl Drop for MountPoint {
-    fn drop(&mut self) {
-        self.fs.umount().ok();
+    /// Returns the rest of `path` (without leading and trailing '/') inside
+    /// this mount point, or `None` if `path` is not under it.
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(&self.path[1..])?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None // e.g. "/tmpfoo" is not under "/tmp"
+        }
     }
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(
+        main_fs: Arc<dyn VfsOps>,
+        main_source: &'static str,
+        main_fstype: &'static str,
+    ) -> Self {
         Self {
             main_fs,
-            mounts: Vec::new(),
+            main_source,
+            main_fstype,
+            mounts: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` on the absolute and canonical `path`, which may be inside
+    /// another mounted filesystem.
+    pub fn mount(&self, path: &str, source: &str, fstype: &str, fs: Arc<dyn VfsOps>) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
+        if self.contains(path) {
             return ax_err!(InvalidInput, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        // create the mount point in the filesystem that contains it if it
+        // does not exist
+        let lookup = || self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path));
+        let mount_point = match lookup() {
+            Err(AxError::NotFound) => {
+                self.create(path, FileType::Dir)?;
+                lookup()?
+            }
+            res => res?,
+        };
+        if !mount_point.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        // checked again with the table locked until the insertion, so that
+        // two tasks can't mount on the same path
+        let mut mounts = self.mounts.lock();
+        if mounts.iter().any(|mp| mp.path == path) {
+            return ax_err!(InvalidInput, "mount point already exists");
+        }
+        fs.mount(path, mount_point)?;
+        mounts.push(MountPoint::new(path.into(), source.into(), fstype.into(), fs));
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Unmounts the filesystem mounted on the absolute and canonical `path`.
+    ///
+    /// Fails with [`AxError::ResourceBusy`] if another filesystem is mounted
+    /// inside it, or if it is still in use by opened files, directories or the
+    /// current directory. It stays mounted, returning the error, if its data
+    /// in the page cache fails to be written back.
+    pub fn umount(&self, path: &str) -> AxResult {
+        if path == "/" {
+            return ax_err!(ResourceBusy, "cannot unmount root filesystem");
+        }
+        let mut mounts = self.mounts.lock();
+        let idx = mounts
+            .iter()
+            .position(|mp| mp.path == path)
+            .ok_or(AxError::InvalidInput)?;
+        let mp = &mounts[idx];
+        let inside = |p: &str| mp.strip(p.trim_matches('/')).is_some();
+        if mounts.iter().any(|other| other.path != path && inside(&other.path)) {
+            return ax_err!(ResourceBusy, "another filesystem is mounted inside");
+        }
+        if Arc::strong_count(&mp.users) > 1 || inside(&CURRENT_DIR_PATH.lock()) {
+            return ax_err!(ResourceBusy);
+        }
+        // nothing can open a file on it while the table is locked, and the
+        // filesystem stays mounted if its cached files fail to be written back
+        crate::page_cache::clear_mount(&mp.users)?;
+        let mp = mounts.remove(idx);
+        drop(mounts);
+        mp.fs.umount()?;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> AxResult {
+        self.main_fs.sync()?;
+        let filesystems: Vec<_> = self.mounts.lock().iter().map(|mp| mp.fs.clone()).collect();
+        for fs in filesystems {
+            fs.sync()?;
+        }
+        Ok(())
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
+
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        let root = MountInfo {
+            source: self.main_source.into(),
+            path: "/".into(),
+            fstype: self.main_fstype.into(),
+        };
+        let mounts = self.mounts.lock();
+        let mounted = mounts.iter().map(|mp| MountInfo {
+            source: mp.source.clone(),
+            path: mp.path.clone(),
+            fstype: mp.fstype.clone(),
+        });
+        core::iter::once(root).chain(mounted).collect()
+    }
+
+    /// Returns the user token of the filesystem that contains `path`, or
+    /// `None` for the root filesystem.
+    fn mount_user(&self, path: &str) -> Option<MountUser> {
+        let path = path.trim_matches('/');
+        self.mounts
+            .lock()
+            .iter()
+            .filter(|mp| mp.strip(path).is_some())
+            .max_by_key(|mp| mp.path.len())
+            .map(|mp| mp.users.clone())
     }
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
@@ -81,23 +199,19 @@ impl RootDirectory {
             return self.lookup_mounted_fs(rest, f);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
         // Find the filesystem that has the longest mounted path match
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
-
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+        let matched = self
+            .mounts
+            .lock()
+            .iter()
+            .filter_map(|mp| mp.strip(path).map(|rest| (mp.fs.clone(), path.len() - rest.len())))
+            .max_by_key(|&(_, len)| len);
+
+        // call `f` without holding the lock, it may access the mount table
+        match matched {
+            Some((fs, len)) => f(fs, &path[len..]),
+            None => f(self.main_fs.clone(), path), // not matched any mount point
         }
     }
 }
@@ -132,21 +246,35 @@ impl VfsNodeOps for RootDirectory {
             }
         })
     }
+
+    fn mknod(&self, path: &str, major: u32, minor: u32) -> VfsResult {
+        self.lookup_mounted_fs(path, |fs, rest_path| {
+            if rest_path.is_empty() {
+                ax_err!(AlreadyExists)
+            } else {
+                fs.root_dir().mknod(rest_path, major, minor)
+            }
+        })
+    }
 }
 
 pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
+            const MAIN_FSTYPE: &str = "myfs";
         } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
+            const MAIN_FSTYPE: &str = "fatfs";
+            use fs::fatfs::FAT_FS;
             FAT_FS.init_by(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
             FAT_FS.init();
             let main_fs = FAT_FS.clone();
         }
     }
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    crate::devsw::init();
+    fs::init_fs_types(MAIN_FSTYPE);
+    let root_dir = RootDirectory::new(main_fs, fs::DISK, MAIN_FSTYPE);
 
     #[cfg(feature = "devfs")]
     {
@@ -160,7 +288,7 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
         foo_dir.add("bar", Arc::new(bar));
 
         root_dir
-            .mount("/dev", Arc::new(devfs))
+            .mount("/dev", "devfs", "devfs", Arc::new(devfs))
             .expect("failed to mount devfs at /dev");
     }
 
@@ -168,7 +296,7 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     {
         let ramfs = fs::ramfs::RamFileSystem::new();
         root_dir
-            .mount("/tmp", Arc::new(ramfs))
+            .mount("/tmp", "ramfs", "ramfs", Arc::new(ramfs))
             .expect("failed to mount ramfs at /tmp");
     }
 
@@ -180,7 +308,9 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
 pub(crate) fn init_rootfs_by_blk_dev(blk_dev:BlockDevice) {//?这一段内容也需要检查一下
     let mut xfs=fs::axxv6fs::Xv6FileSystem::new();
     xfs.init(blk_dev);
-    let root_dir=RootDirectory::new(Arc::new(xfs));
+    crate::devsw::init();
+    fs::init_fs_types("xv6fs");
+    let root_dir=RootDirectory::new(Arc::new(xfs), fs::DISK, "xv6fs");
     ROOT_DIR.init_by(Arc::new(root_dir));
     CURRENT_DIR.init_by(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
@@ -242,6 +372,19 @@ pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {//di
     }
 }
 
+pub(crate) fn mknod(dir: Option<&VfsNodeRef>, path: &str, major: u32, minor: u32) -> AxResult {
+    if path.is_empty() {
+        return ax_err!(NotFound);
+    } else if path.ends_with('/') {
+        return ax_err!(NotADirectory);
+    }
+    match lookup(dir, path) {
+        Ok(_) => ax_err!(AlreadyExists),
+        Err(AxError::NotFound) => parent_node_of(dir, path).mknod(path, major, minor),
+        Err(e) => Err(e),
+    }
+}
+
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     let node = lookup(dir, path)?;
     let attr = node.get_attr()?;
@@ -250,6 +393,9 @@ pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
+        if let Some(id) = node.cache_id() {
+            crate::page_cache::invalidate(id);
+        }
         parent_node_of(dir, path).remove(path)
     }
 }
@@ -283,6 +429,30 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {//di
     }
 }
 
+pub(crate) fn sync() -> AxResult {
+    crate::page_cache::flush_all()?;
+    ROOT_DIR.sync()
+}
+
+pub(crate) fn mount(source: &str, path: &str, fstype: &str) -> AxResult {
+    let path = absolute_path(path)?;
+    let fs = fs::create(fstype, source)?;
+    ROOT_DIR.mount(&path, source, fstype, fs)
+}
+
+pub(crate) fn umount(path: &str) -> AxResult {
+    ROOT_DIR.umount(&absolute_path(path)?)
+}
+
+pub(crate) fn mounts() -> Vec<MountInfo> {
+    ROOT_DIR.mounts()
+}
+
+/// Returns the user token of the filesystem that contains the absolute path.
+pub(crate) fn mount_user(abs_path: &str) -> Option<MountUser> {
+    ROOT_DIR.mount_user(abs_path)
+}
+
 pub(crate) fn current_dir() -> AxResult<String> {
     Ok(CURRENT_DIR_PATH.lock().clone())
 }
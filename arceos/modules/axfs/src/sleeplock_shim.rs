@@ -1,39 +1,142 @@
-use core::{sync::atomic::{AtomicBool,Ordering}};
+//! Sleep locks handed out to xv6fs through `FsInterface`.
+//!
+//! xv6fs only keeps the index of its locks, the locks themselves live here.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use axtask::WaitQueue;
+use axtask::{current, PiOwner, WaitQueue};
+
+/// The lock state when it is held exclusively, otherwise the state is the
+/// number of readers that hold it.
+const EXCLUSIVE: usize = usize::MAX;
 
-pub struct FsLockManager{
-    wq:WaitQueue,
-    pub flag:AtomicBool,
+/// A sleeping lock that is held either exclusively by one task, or shared by
+/// any number of readers.
+///
+/// New readers wait while a writer is waiting, so that a stream of readers
+/// can't starve writers. A task must not take a shared lock it already
+/// shares then, a waiting writer would deadlock with it.
+///
+/// A task holding the lock exclusively inherits the priority of the tasks
+/// waiting for it, readers don't.
+pub struct FsSleepLock {
+    wq: WaitQueue,
+    state: AtomicUsize,
+    /// Number of writers waiting for the lock.
+    writers: AtomicUsize,
+    owner_id: AtomicU64,
+    owner: PiOwner,
 }
 
-pub struct FsLockList{
-    pub lock_list:Vec<FsLockManager>,
+pub struct FsLockList {
+    lock_list: Vec<Arc<FsSleepLock>>,
 }
 
-impl FsLockManager {
-    pub fn sleep_cur_task(&self){
-        //info!("lock!,and flag is {}",self.flag.load(Ordering::Acquire));
-        self.wq.wait_until(||self.flag.load(Ordering::Acquire)==false);
-        //info!("lock!,and flag is {}",self.flag.load(Ordering::Acquire));
-        self.flag.store(true, Ordering::Release);
-        //info!("lock!,and flag is {}",self.flag.load(Ordering::Acquire));
+impl FsSleepLock {
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicUsize::new(0),
+            writers: AtomicUsize::new(0),
+            owner_id: AtomicU64::new(0),
+            owner: PiOwner::new(),
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(0, EXCLUSIVE, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let mut readers = self.state.load(Ordering::Relaxed);
+        while readers != EXCLUSIVE && self.writers.load(Ordering::Relaxed) == 0 {
+            match self.state.compare_exchange_weak(
+                readers,
+                readers + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(state) => readers = state,
+            }
+        }
+        false
+    }
+
+    fn state(&self) -> usize {
+        self.state.load(Ordering::Relaxed)
+    }
+
+    pub fn lock(&self) {
+        let current_id = current().id().as_u64();
+        if self.try_lock() {
+            self.owner_id.store(current_id, Ordering::Relaxed);
+            self.owner.set_current();
+            return;
+        }
+        self.writers.fetch_add(1, Ordering::Relaxed);
+        while !self.try_lock() {
+            assert_ne!(
+                self.owner_id.load(Ordering::Relaxed),
+                current_id,
+                "{} tried to acquire fs lock it already owns.",
+                current().id_name()
+            );
+            // the owner may have changed since the last try, and can't be
+            // boosted with the run queue locked
+            let _wait = self.owner.boost();
+            // the condition is checked with the run queue locked, so an
+            // unlock can't slip in between the check and going to sleep
+            self.wq.wait_until(|| self.state() == 0);
+        }
+        // the readers kept waiting are woken by the unlock
+        self.writers.fetch_sub(1, Ordering::Relaxed);
+        self.owner_id.store(current_id, Ordering::Relaxed);
+        self.owner.set_current();
     }
-    pub fn wake_up_next_proc(&self){
-        self.flag.store(false, Ordering::Release);
-        self.wq.notify_one(true);
+
+    pub fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            let _wait = self.owner.boost();
+            self.wq.wait_until(|| {
+                self.state() != EXCLUSIVE && self.writers.load(Ordering::Relaxed) == 0
+            });
+        }
     }
-    pub fn new()->Self{
-        Self { wq: WaitQueue::new(), flag: AtomicBool::new(false) }
+
+    pub fn unlock(&self) {
+        self.owner.clear();
+        self.owner_id.store(0, Ordering::Relaxed);
+        let state = self.state.swap(0, Ordering::Release);
+        assert_eq!(state, EXCLUSIVE, "fs lock is not locked exclusively");
+        self.wq.notify_all(true);
+    }
+
+    pub fn unlock_shared(&self) {
+        let readers = self.state.fetch_sub(1, Ordering::Release);
+        assert!(readers != 0 && readers != EXCLUSIVE, "fs lock is not locked shared");
+        if readers == 1 {
+            self.wq.notify_all(true);
+        }
     }
 }
 
 impl FsLockList {
-    pub fn new_lock(&mut self)->usize{
-        let index=self.lock_list.len();
-        let lock=FsLockManager::new();
-        self.lock_list.push(lock);
-        //info!("push!");
+    pub const fn new() -> Self {
+        Self { lock_list: Vec::new() }
+    }
+
+    pub fn new_lock(&mut self) -> usize {
+        let index = self.lock_list.len();
+        self.lock_list.push(Arc::new(FsSleepLock::new()));
         index
     }
+
+    /// Returns the lock `index`, which can be used after the list is unlocked.
+    pub fn get(&self, index: usize) -> Arc<FsSleepLock> {
+        self.lock_list[index].clone()
+    }
 }
@@ -252,6 +252,50 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+/// Mounts the disk again, and ramfs inside it.
+pub fn test_mount() -> Result<()> {
+    let root_fstype = fs::mounts()[0].fstype.clone();
+    assert!(fs::fs_types().contains(&root_fstype.as_str()));
+    assert_err!(fs::mount("disk", "/mnt-none", "nofs"), NotFound);
+    assert_err!(fs::mount("nodisk", "/mnt-none", &root_fstype), NotFound);
+
+    // the same files under both mount points
+    fs::write("/mnt.txt", "on the disk")?;
+    fs::mount("disk", "/mnt-disk", &root_fstype)?;
+    assert_eq!(fs::read_to_string("/mnt-disk/mnt.txt")?, "on the disk");
+    fs::write("/mnt-disk/mnt.txt", "through the mount")?;
+    assert_eq!(fs::read_to_string("/mnt.txt")?, "through the mount");
+    assert_err!(fs::mount("disk", "/mnt-disk", &root_fstype), InvalidInput);
+
+    // a filesystem mounted inside another mount
+    fs::mount("ramfs", "/mnt-disk/ram", "ramfs")?;
+    fs::write("/mnt-disk/ram/test.txt", "in ramfs")?;
+    assert_eq!(fs::read_dir("/ram")?.count(), 0);
+    let paths = fs::mounts().into_iter().map(|m| m.path).collect::<Vec<_>>();
+    assert!(paths.contains(&"/mnt-disk".into()));
+    assert!(paths.contains(&"/mnt-disk/ram".into()));
+    assert_err!(fs::umount("/mnt-disk"), ResourceBusy);
+
+    // a file open in it keeps it mounted
+    let file = File::open("/mnt-disk/ram/test.txt")?;
+    assert_err!(fs::umount("/mnt-disk/ram"), ResourceBusy);
+    drop(file);
+    fs::umount("/mnt-disk/ram")?;
+    assert_err!(fs::metadata("/mnt-disk/ram/test.txt"), NotFound);
+    assert_err!(fs::umount("/mnt-disk/ram"), InvalidInput);
+
+    fs::umount("/mnt-disk")?;
+    assert_err!(fs::metadata("/mnt-disk/mnt.txt"), NotFound);
+    assert_eq!(fs::read_to_string("/mnt.txt")?, "through the mount");
+    assert_err!(fs::umount("/"), ResourceBusy);
+
+    fs::remove_dir("/ram")?;
+    fs::remove_dir("/mnt-disk")?;
+    fs::remove_file("/mnt.txt")?;
+    println!("test_mount() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -0,0 +1,82 @@
+#![cfg(all(not(feature = "use-virtio-blk"), not(feature = "myfs"), feature = "xv6fs"))]
+
+#[allow(dead_code)]
+mod test_common;
+
+use axfs::api::{self as fs, File, FileType};
+use axio::{Read, Result};
+use driver_block::ramdisk::RamDisk;
+
+/// A fresh file system built by xv6mkfs.
+fn make_disk() -> RamDisk {
+    RamDisk::from(&xv6mkfs::mkfs(&xv6mkfs::Options::default()))
+}
+
+fn file_types(dir: &str) -> Result<Vec<(String, FileType)>> {
+    fs::read_dir(dir)?
+        .map(|e| e.map(|e| (e.file_name(), e.file_type())))
+        .collect()
+}
+
+fn test_devices() -> Result<()> {
+    fs::create_dir("/dev")?;
+    fs::mknod("/dev/disk", 8, 0)?;
+    fs::mknod("/dev/console", 5, 1)?;
+    fs::mknod("/dev/none", 42, 0)?;
+
+    // the type comes from the device the inode points to
+    assert_eq!(fs::metadata("/dev/disk")?.file_type(), FileType::BlockDevice);
+    assert_eq!(fs::metadata("/dev/console")?.file_type(), FileType::CharDevice);
+    assert_eq!(fs::metadata("/dev/none")?.file_type(), FileType::CharDevice);
+    let types = file_types("/dev")?;
+    assert!(types.contains(&("disk".into(), FileType::BlockDevice)));
+    assert!(types.contains(&("console".into(), FileType::CharDevice)));
+    assert!(types.contains(&("none".into(), FileType::CharDevice)));
+
+    // the super block through the disk device
+    let mut block = [0; 1024];
+    File::open("/dev/disk")?.read_exact(&mut block)?;
+    assert_eq!(block[512..516], 0x10203040u32.to_ne_bytes());
+    // only the mounted file system writes to the disk
+    assert!(fs::write("/dev/disk", [0; 1024]).is_err());
+    File::open("/dev/disk")?.read_exact(&mut block)?;
+    assert_eq!(block[512..516], 0x10203040u32.to_ne_bytes());
+
+    println!("test_devices() OK!");
+    Ok(())
+}
+
+fn test_page_cache_reuse() -> Result<()> {
+    // the data of the file stays in the page cache
+    fs::create_dir("/cached")?;
+    fs::write("/cached/file", "stale data")?;
+    assert_eq!(fs::read_to_string("/cached/file")?, "stale data");
+
+    // removing the directory frees the inodes of both, which come back in
+    // the same order
+    fs::remove_dir("/cached")?;
+    fs::create_dir("/reused-dir")?;
+    // no truncate, which would write the cached data back first
+    File::options().write(true).create(true).open("/reused")?;
+    assert_eq!(fs::metadata("/reused")?.len(), 0);
+    assert_eq!(fs::read_to_string("/reused")?, "");
+    fs::write("/reused", "fresh")?;
+    assert_eq!(fs::read_to_string("/reused")?, "fresh");
+    fs::remove_file("/reused")?;
+    fs::remove_dir("/reused-dir")?;
+
+    println!("test_page_cache_reuse() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_xv6fs() {
+    println!("Testing xv6fs with ramdisk ...");
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(make_disk());
+
+    test_devices().expect("test_devices() failed");
+    test_page_cache_reuse().expect("test_page_cache_reuse() failed");
+    test_common::test_mount().expect("test_mount() failed");
+}
@@ -40,7 +40,7 @@ impl FpState {
 ///
 /// - Callee-saved registers
 /// - Stack pointer register
-/// - Thread pointer register (for thread-local storage, currently unsupported)
+/// - Thread pointer register (for thread-local storage)
 /// - FP/SIMD registers
 ///
 /// On context switch, current task saves its context from CPU to memory,
@@ -73,11 +73,12 @@ impl TaskContext {
         unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
     }
 
-    /// Initializes the context for a new task, with the given entry point and
-    /// kernel stack.
-    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr) {
+    /// Initializes the context for a new task, with the given entry point,
+    /// kernel stack, and thread pointer of its TLS area.
+    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr, tls_area: VirtAddr) {
         self.sp = kstack_top.as_usize() as u64;
         self.lr = entry as u64;
+        self.tpidr_el0 = tls_area.as_usize() as u64;
     }
 
     /// Switches to another task.
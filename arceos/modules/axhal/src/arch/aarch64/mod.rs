@@ -3,7 +3,7 @@ pub(crate) mod trap;
 
 use core::arch::asm;
 
-use aarch64_cpu::registers::{DAIF, TTBR1_EL1, VBAR_EL1};
+use aarch64_cpu::registers::{DAIF, TPIDR_EL0, TTBR1_EL1, VBAR_EL1};
 use memory_addr::{PhysAddr, VirtAddr};
 use tock_registers::interfaces::{Readable, Writeable};
 
@@ -93,3 +93,23 @@ pub fn flush_icache_all() {
 pub fn set_exception_vector_base(vbar_el1: usize) {
     VBAR_EL1.set(vbar_el1 as _);
 }
+
+/// Reads the thread pointer of the current CPU (`TPIDR_EL0`).
+///
+/// It is used to implement TLS (Thread Local Storage).
+#[inline]
+pub fn read_thread_pointer() -> usize {
+    TPIDR_EL0.get() as usize
+}
+
+/// Writes the thread pointer of the current CPU (`TPIDR_EL0`).
+///
+/// It is used to implement TLS (Thread Local Storage).
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the CPU states.
+#[inline]
+pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
+    TPIDR_EL0.set(tpidr_el0 as _)
+}
@@ -80,6 +80,7 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &mut TrapFrame) {
-    crate::trap::handle_irq_extern(0)
+fn handle_irq_exception(tf: &mut TrapFrame) {
+    // SPSR_EL1.M[3:0] is 0 (EL0t) if it's taken from EL0
+    crate::trap::handle_irq_extern(0, tf.spsr & 0b1111 == 0)
 }
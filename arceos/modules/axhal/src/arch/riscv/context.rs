@@ -59,7 +59,7 @@ pub struct TrapFrame {
 ///
 /// - Callee-saved registers
 /// - Stack pointer register
-/// - Thread pointer register (for thread-local storage, currently unsupported)
+/// - Thread pointer register (for thread-local storage)
 /// - FP/SIMD registers
 ///
 /// On context switch, current task saves its context from CPU to memory,
@@ -84,6 +84,8 @@ pub struct TaskContext {
     pub s9: usize,
     pub s10: usize,
     pub s11: usize,
+
+    pub tp: usize, // thread pointer (x4)
     // TODO: FP states
 }
 
@@ -93,11 +95,12 @@ impl TaskContext {
         unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
     }
 
-    /// Initializes the context for a new task, with the given entry point and
-    /// kernel stack.
-    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr) {
+    /// Initializes the context for a new task, with the given entry point,
+    /// kernel stack, and thread pointer of its TLS area.
+    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr, tls_area: VirtAddr) {
         self.sp = kstack_top.as_usize();
         self.ra = entry;
+        self.tp = tls_area.as_usize();
     }
 
     /// Switches to another task.
@@ -105,10 +108,12 @@ impl TaskContext {
     /// It first saves the current task's context from CPU to this place, and then
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
-        unsafe {
-            // TODO: switch TLS
-            context_switch(self, next_ctx)
+        #[cfg(feature = "tls")]
+        {
+            self.tp = super::read_thread_pointer();
+            unsafe { super::write_thread_pointer(next_ctx.tp) };
         }
+        unsafe { context_switch(self, next_ctx) }
     }
 }
 
@@ -117,7 +117,7 @@ impl fmt::Debug for ExtendedState {
 ///
 /// - Callee-saved registers
 /// - Stack pointer register
-/// - Thread pointer register (for thread-local storage, currently unsupported)
+/// - Thread pointer register (for thread-local storage)
 /// - FP/SIMD registers
 ///
 /// On context switch, current task saves its context from CPU to memory,
@@ -136,6 +136,8 @@ pub struct TaskContext {
     pub kstack_top: VirtAddr,
     /// `RSP` after all callee-saved registers are pushed.
     pub rsp: u64,
+    /// Thread Local Storage (TLS), i.e., the `FS_BASE` register.
+    pub fs_base: usize,
     /// Extended states, i.e., FP/SIMD states.
     #[cfg(feature = "fp_simd")]
     pub ext_state: ExtendedState,
@@ -147,14 +149,15 @@ impl TaskContext {
         Self {
             kstack_top: VirtAddr::from(0),
             rsp: 0,
+            fs_base: 0,
             #[cfg(feature = "fp_simd")]
             ext_state: ExtendedState::default(),
         }
     }
 
-    /// Initializes the context for a new task, with the given entry point and
-    /// kernel stack.
-    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr) {
+    /// Initializes the context for a new task, with the given entry point,
+    /// kernel stack, and thread pointer of its TLS area.
+    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr, tls_area: VirtAddr) {
         unsafe {
             // x86_64 calling convention: the stack must be 16-byte aligned before
             // calling a function. That means when entering a new task (`ret` in `context_switch`
@@ -171,6 +174,7 @@ impl TaskContext {
             self.rsp = frame_ptr as u64;
         }
         self.kstack_top = kstack_top;
+        self.fs_base = tls_area.as_usize();
     }
 
     /// Switches to another task.
@@ -183,10 +187,12 @@ impl TaskContext {
             self.ext_state.save();
             next_ctx.ext_state.restore();
         }
-        unsafe {
-            // TODO: swtich tls
-            context_switch(&mut self.rsp, &next_ctx.rsp)
+        #[cfg(feature = "tls")]
+        {
+            self.fs_base = super::read_thread_pointer();
+            unsafe { super::write_thread_pointer(next_ctx.fs_base) };
         }
+        unsafe { context_switch(&mut self.rsp, &next_ctx.rsp) }
     }
 }
 
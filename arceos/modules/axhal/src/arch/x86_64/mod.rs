@@ -8,7 +8,7 @@ mod trap;
 use core::arch::asm;
 
 use memory_addr::{PhysAddr, VirtAddr};
-use x86::{controlregs, tlb};
+use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
 
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
@@ -88,3 +88,23 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
         unsafe { tlb::flush_all() }
     }
 }
+
+/// Reads the thread pointer of the current CPU (`FS_BASE`).
+///
+/// It is used to implement TLS (Thread Local Storage).
+#[inline]
+pub fn read_thread_pointer() -> usize {
+    unsafe { msr::rdmsr(msr::IA32_FS_BASE) as usize }
+}
+
+/// Writes the thread pointer of the current CPU (`FS_BASE`).
+///
+/// It is used to implement TLS (Thread Local Storage).
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the CPU states.
+#[inline]
+pub unsafe fn write_thread_pointer(fs_base: usize) {
+    msr::wrmsr(msr::IA32_FS_BASE, fs_base as u64)
+}
@@ -19,6 +19,7 @@
 //! - `fp_simd`: Enable floating-point and SIMD support.
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `tls`: Enable kernel space thread-local storage support.
 //! - `platform-pc-x86`: Specify for use on the corresponding platform.
 //! - `platform-qemu-virt-riscv`: Specify for use on the corresponding platform.
 //! - `platform-qemu-virt-aarch64`: Specify for use on the corresponding platform.
@@ -36,6 +37,9 @@
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "tls")]
+extern crate alloc;
+
 mod platform;
 
 pub mod arch;
@@ -50,6 +54,9 @@ pub mod irq;
 #[cfg(feature = "paging")]
 pub mod paging;
 
+#[cfg(feature = "tls")]
+pub mod tls;
+
 /// Console input and output.
 pub mod console {
     pub use super::platform::console::*;
@@ -0,0 +1,118 @@
+//! Thread Local Storage (TLS) support.
+//!
+//! The TLS template is formed by the `.tdata` and `.tbss` sections of the
+//! kernel image. Each task has its own copy of it in a [`TlsArea`], and the
+//! thread pointer register (see [`read_thread_pointer`]) points to the area of
+//! the running task. The layout follows the ELF TLS ABI of each architecture,
+//! and the TLS alignment must not exceed 16 bytes.
+//!
+//! ## TLS layout for x86_64
+//!
+//! ```text
+//! area base ->  +-------------------------+
+//!               | .tdata                  | \
+//!               + - - - - - - - - - - - - +  > static TLS block
+//!               | .tbss                   | /
+//! thread ptr -> +-------------------------+
+//!               | TCB (self pointer)      |
+//!               +-------------------------+
+//! ```
+//!
+//! ## TLS layout for AArch64 and RISC-V
+//!
+//! ```text
+//! thread ptr -> +-------------------------+
+//! (area base)   | TCB (16 bytes, AArch64) |
+//!               +-------------------------+
+//!               | .tdata                  | \
+//!               + - - - - - - - - - - - - +  > static TLS block
+//!               | .tbss                   | /
+//!               +-------------------------+
+//! ```
+//!
+//! [`read_thread_pointer`]: crate::arch::read_thread_pointer
+
+use alloc::alloc::{alloc_zeroed, dealloc};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use memory_addr::{align_up, VirtAddr};
+
+const TLS_ALIGN: usize = 0x10;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        const TCB_SIZE: usize = core::mem::size_of::<usize>(); // the self pointer
+        const GAP_ABOVE_TP: usize = 0;
+    } else if #[cfg(target_arch = "aarch64")] {
+        const TCB_SIZE: usize = 0;
+        const GAP_ABOVE_TP: usize = 16;
+    } else {
+        const TCB_SIZE: usize = 0;
+        const GAP_ABOVE_TP: usize = 0;
+    }
+}
+
+/// The TLS area of a task, initialized from the TLS template.
+pub struct TlsArea {
+    base: NonNull<u8>,
+    layout: Layout,
+}
+
+impl TlsArea {
+    /// Allocates a TLS area, and copies the TLS template into it.
+    pub fn alloc() -> Self {
+        // never empty, even if there are no TLS variables
+        let size = (static_tls_size() + TCB_SIZE + GAP_ABOVE_TP).max(TLS_ALIGN);
+        let layout = Layout::from_size_align(size, TLS_ALIGN).unwrap();
+        let base = NonNull::new(unsafe { alloc_zeroed(layout) }).expect("failed to allocate TLS");
+        let area = Self { base, layout };
+        unsafe {
+            // `.tbss` is left zeroed
+            core::ptr::copy_nonoverlapping(
+                _stdata as usize as *const u8,
+                area.static_tls_ptr(),
+                _etdata as usize - _stdata as usize,
+            );
+            if cfg!(target_arch = "x86_64") {
+                let tcb = area.tls_ptr().as_usize() as *mut usize;
+                tcb.write(tcb as usize);
+            }
+        }
+        area
+    }
+
+    /// Returns the value of the thread pointer register for the area.
+    pub fn tls_ptr(&self) -> VirtAddr {
+        let base = self.base.as_ptr() as usize;
+        if cfg!(target_arch = "x86_64") {
+            VirtAddr::from(base + static_tls_size())
+        } else {
+            VirtAddr::from(base)
+        }
+    }
+
+    fn static_tls_ptr(&self) -> *mut u8 {
+        if cfg!(target_arch = "x86_64") {
+            self.base.as_ptr()
+        } else {
+            unsafe { self.base.as_ptr().add(TCB_SIZE + GAP_ABOVE_TP) }
+        }
+    }
+}
+
+impl Drop for TlsArea {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.base.as_ptr(), self.layout) }
+    }
+}
+
+fn static_tls_size() -> usize {
+    align_up(_etbss as usize - _stdata as usize, TLS_ALIGN)
+}
+
+extern "C" {
+    fn _stdata();
+    fn _etdata();
+    fn _etbss();
+}
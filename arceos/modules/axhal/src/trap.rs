@@ -16,8 +16,21 @@ pub trait TrapHandler {
     // more e.g.: handle_page_fault();
 }
 
+/// Whether the IRQ being handled on this CPU interrupted the user mode.
+#[percpu::def_percpu]
+static IRQ_FROM_USER: bool = false;
+
 /// Call the external IRQ handler.
 #[allow(dead_code)]
-pub(crate) fn handle_irq_extern(irq_num: usize) {
+pub(crate) fn handle_irq_extern(irq_num: usize, from_user: bool) {
+    // Safety: IRQs are disabled in IRQ handlers.
+    unsafe { IRQ_FROM_USER.write_current_raw(from_user) };
     call_interface!(TrapHandler::handle_irq, irq_num);
 }
+
+/// Returns whether the IRQ being handled on the current CPU was raised in
+/// the user mode. It's only meaningful in IRQ handlers.
+pub fn irq_from_user() -> bool {
+    // Safety: IRQs are disabled in IRQ handlers.
+    unsafe { IRQ_FROM_USER.read_current_raw() }
+}
@@ -16,6 +16,12 @@
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `async`: Enable the `*_async` methods of the sockets, which return futures
+//!   woken up by a background task polling the interfaces, see
+//!   [`wake_poller`]. The futures can be run by [`axtask::future`].
+//! - `irq`: Wake up the task polling the interfaces from the interrupt handler
+//!   of the NIC, if its interrupts are supported on the platform. Only takes
+//!   effect with `async`.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -52,4 +58,20 @@ pub fn init_network(net_devs: NetDevices) {
     });
 
     net_impl::init(net_devs);
+    #[cfg(all(feature = "async", feature = "irq"))]
+    if axdriver::register_net_irq_handler(wake_poller) {
+        info!("  NIC interrupts enabled");
+    }
+}
+
+/// Wakes up the task polling the network interfaces for the async sockets,
+/// e.g., in the interrupt handler of the NIC, which calls it with the `irq`
+/// feature.
+///
+/// The interfaces are also polled at the deadlines requested by the network
+/// stack, and at least every few milliseconds in case the interrupts of the
+/// NIC are not supported on the platform.
+#[cfg(feature = "async")]
+pub fn wake_poller() {
+    net_impl::wake_poller();
 }
@@ -64,7 +64,7 @@ impl DnsSocket {
                     SOCKET_SET.poll_interfaces();
                     return Ok(n.to_vec());
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
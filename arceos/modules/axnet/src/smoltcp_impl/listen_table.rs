@@ -1,5 +1,7 @@
 use alloc::{boxed::Box, collections::VecDeque};
 use core::ops::DerefMut;
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use axerrno::{ax_err, AxError, AxResult};
 use axsync::Mutex;
@@ -13,12 +15,17 @@ const PORT_NUM: usize = 65536;
 
 struct ListenTableEntry {
     syn_queue: VecDeque<SocketHandle>,
+    /// Woken up when a socket in the SYN queue is established.
+    #[cfg(feature = "async")]
+    waker: Option<Waker>,
 }
 
 impl ListenTableEntry {
     pub fn new() -> Self {
         Self {
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            #[cfg(feature = "async")]
+            waker: None,
         }
     }
 }
@@ -127,6 +134,20 @@ impl ListenTable {
         }
     }
 
+    /// Registers the waker of an async `accept()` on the port, which is woken
+    /// up when a connection to the port is established.
+    #[cfg(feature = "async")]
+    pub fn register_accept_waker(&self, port: u16, waker: &Waker) {
+        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+            for &handle in &entry.syn_queue {
+                SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    socket.register_recv_waker(waker)
+                });
+            }
+            entry.waker = Some(waker.clone());
+        }
+    }
+
     pub fn incoming_tcp_packet(&self, src: SocketAddr, dst: SocketAddr) {
         if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
             if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
@@ -136,6 +157,10 @@ impl ListenTable {
             }
             let mut socket = SocketSetWrapper::new_tcp_socket();
             if socket.listen(dst).is_ok() {
+                #[cfg(feature = "async")]
+                if let Some(waker) = &entry.waker {
+                    socket.register_recv_waker(waker);
+                }
                 let handle = SOCKET_SET.add(socket);
                 debug!(
                     "socket {}: prepare for connection {} -> {}",
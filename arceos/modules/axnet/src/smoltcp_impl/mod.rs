@@ -3,11 +3,20 @@ mod listen_table;
 mod tcp;
 mod udp;
 
+#[cfg(feature = "async")]
+mod poller;
+
 use alloc::{collections::VecDeque, vec};
 use core::cell::RefCell;
 use core::ops::DerefMut;
+#[cfg(feature = "async")]
+use core::task::{Poll, Waker};
+#[cfg(feature = "async")]
+use core::time::Duration;
 
 use axdriver::NetDevices;
+#[cfg(feature = "async")]
+use axerrno::{AxError, AxResult};
 use axhal::time::{current_time_nanos, NANOS_PER_MICROS};
 use axsync::Mutex;
 use driver_net::{DevError, NetBuffer, NetDriverOps};
@@ -21,6 +30,8 @@ use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
 use self::listen_table::ListenTable;
 
 pub use self::dns::resolve_socket_addr;
+#[cfg(feature = "async")]
+pub use self::poller::wake as wake_poller;
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
 
@@ -111,6 +122,39 @@ impl<'a> SocketSetWrapper<'a> {
         ETH0.poll(&self.0);
     }
 
+    /// Returns how long to wait before the interfaces should be polled again,
+    /// or [`None`] if not until packets are received.
+    #[cfg(feature = "async")]
+    pub fn poll_delay(&self) -> Option<Duration> {
+        ETH0.poll_delay(&self.0)
+    }
+
+    /// Polls the interfaces, then tries an operation `f` on a socket. If it
+    /// returns [`AxError::Again`], registers `waker` to the socket by
+    /// `register` and returns [`Poll::Pending`].
+    #[cfg(feature = "async")]
+    pub fn poll_socket<T: AnySocket<'a>, R, F>(
+        &self,
+        handle: SocketHandle,
+        waker: &Waker,
+        register: fn(&mut T, &Waker),
+        f: F,
+    ) -> Poll<AxResult<R>>
+    where
+        F: FnOnce(&mut T) -> AxResult<R>,
+    {
+        self.poll_interfaces();
+        self.with_socket_mut::<T, _, _>(handle, |socket| match f(socket) {
+            Err(AxError::Again) => {
+                // checked and registered with the socket set locked, so that
+                // it can't be missed by the poller
+                register(socket, waker);
+                Poll::Pending
+            }
+            res => Poll::Ready(res),
+        })
+    }
+
     pub fn remove(&self, handle: SocketHandle) {
         self.0.lock().remove(handle);
         debug!("socket {}: destroyed", handle);
@@ -161,11 +205,18 @@ impl<D: NetDriverOps> InterfaceWrapper<D> {
             snoop_tcp_packet(buf).ok(); // preprocess TCP packets
         });
 
-        let timestamp =
-            Instant::from_micros_const((current_time_nanos() / NANOS_PER_MICROS) as i64);
         let mut iface = self.iface.lock();
         let mut sockets = sockets.lock();
-        iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        iface.poll(current_instant(), dev.deref_mut(), &mut sockets);
+    }
+
+    #[cfg(feature = "async")]
+    pub fn poll_delay(&self, sockets: &Mutex<SocketSet>) -> Option<Duration> {
+        let mut iface = self.iface.lock();
+        let sockets = sockets.lock();
+        iface
+            .poll_delay(current_instant(), &sockets)
+            .map(|delay| Duration::from_micros(delay.total_micros()))
     }
 }
 
@@ -260,6 +311,10 @@ impl<'a, D: NetDriverOps> TxToken for AxNetTxToken<'a, D> {
     }
 }
 
+fn current_instant() -> Instant {
+    Instant::from_micros_const((current_time_nanos() / NANOS_PER_MICROS) as i64)
+}
+
 fn snoop_tcp_packet(buf: &[u8]) -> Result<(), smoltcp::wire::Error> {
     use crate::SocketAddr;
     use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
@@ -297,4 +352,7 @@ pub(crate) fn init(net_devs: NetDevices) {
     }
     info!("  ip:       {}/{}", IP, IP_PREFIX);
     info!("  gateway:  {}", GATEWAY);
+
+    #[cfg(feature = "async")]
+    poller::start();
 }
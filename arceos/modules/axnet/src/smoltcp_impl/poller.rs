@@ -0,0 +1,35 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use super::SOCKET_SET;
+
+/// The longest time between two polls, as received packets may not be
+/// reported by interrupts.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static POLL_WQ: WaitQueue = WaitQueue::new();
+static POLL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Spawns the task polling the interfaces, which wakes up the async sockets.
+pub fn start() {
+    axtask::Builder::new()
+        .name("net-poller".into())
+        .kernel_service()
+        .spawn(|| loop {
+            SOCKET_SET.poll_interfaces();
+            let delay = SOCKET_SET
+                .poll_delay()
+                .map_or(MAX_POLL_INTERVAL, |delay| delay.min(MAX_POLL_INTERVAL));
+            if !delay.is_zero() {
+                POLL_WQ.wait_timeout_until(delay, || POLL_REQUESTED.swap(false, Ordering::AcqRel));
+            }
+        });
+}
+
+/// Makes the poller poll the interfaces as soon as possible.
+pub fn wake() {
+    POLL_REQUESTED.store(true, Ordering::Release);
+    POLL_WQ.notify_one(true);
+}
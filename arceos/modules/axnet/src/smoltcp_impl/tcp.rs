@@ -1,3 +1,8 @@
+#[cfg(feature = "async")]
+use core::future::poll_fn;
+#[cfg(feature = "async")]
+use core::task::Poll;
+
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axsync::Mutex;
 use smoltcp::iface::SocketHandle;
@@ -13,6 +18,9 @@ use crate::SocketAddr;
 /// - [`bind`], [`listen`], and [`accept`] are for TCP servers.
 /// - Other methods are for both TCP clients and servers.
 ///
+/// A blocking method returns [`Err(Interrupted)`](AxError::Interrupted) if a
+/// signal is handled while it's waiting, see `axtask::signal`.
+///
 /// [`connect`]: TcpSocket::connect
 /// [`bind`]: TcpSocket::bind
 /// [`listen`]: TcpSocket::listen
@@ -59,6 +67,46 @@ impl TcpSocket {
     ///
     /// The local port is generated automatically.
     pub fn connect(&mut self, addr: SocketAddr) -> AxResult {
+        let (handle, local_addr, peer_addr) = self.start_connect(addr)?;
+        loop {
+            SOCKET_SET.poll_interfaces();
+            match SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, connect_state) {
+                Ok(()) => {
+                    self.local_addr = local_addr;
+                    self.peer_addr = peer_addr;
+                    return Ok(());
+                }
+                Err(AxError::Again) => axtask::yield_interruptible()?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Connects to the given address and port, like [`connect`](Self::connect)
+    /// but without blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn connect_async(&mut self, addr: SocketAddr) -> AxResult {
+        let (handle, local_addr, peer_addr) = self.start_connect(addr)?;
+        poll_fn(|cx| {
+            SOCKET_SET.poll_socket(
+                handle,
+                cx.waker(),
+                tcp::Socket::register_recv_waker,
+                |socket| connect_state(socket),
+            )
+        })
+        .await?;
+        self.local_addr = local_addr;
+        self.peer_addr = peer_addr;
+        Ok(())
+    }
+
+    /// Starts connecting to `addr`, returns the socket handle and the
+    /// addresses of the connection.
+    fn start_connect(
+        &self,
+        addr: SocketAddr,
+    ) -> AxResult<(SocketHandle, Option<SocketAddr>, Option<SocketAddr>)> {
         let handle = if self.is_listening() {
             return ax_err!(AlreadyExists, "socket connect() failed: already connected");
         } else {
@@ -68,36 +116,19 @@ impl TcpSocket {
         // TODO: check host unreachable
         let local_port = get_ephemeral_port()?;
         let iface = &ETH0.iface;
-        let (local_addr, peer_addr) =
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                socket
-                    .connect(iface.lock().context(), addr, local_port)
-                    .or_else(|e| match e {
-                        ConnectError::InvalidState => {
-                            ax_err!(AlreadyExists, "socket connect() failed")
-                        }
-                        ConnectError::Unaddressable => {
-                            ax_err!(InvalidInput, "socket connect() failed")
-                        }
-                    })?;
-                Ok((socket.local_endpoint(), socket.remote_endpoint()))
-            })?;
-
-        loop {
-            SOCKET_SET.poll_interfaces();
-            let (state, may_recv) = SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-                (socket.state(), socket.may_recv())
-            });
-            if may_recv || state == State::Established {
-                self.local_addr = local_addr;
-                self.peer_addr = peer_addr;
-                return Ok(());
-            } else if state == State::SynSent {
-                axtask::yield_now();
-            } else {
-                return ax_err!(ConnectionRefused, "socket connect() failed");
-            }
-        }
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            socket
+                .connect(iface.lock().context(), addr, local_port)
+                .or_else(|e| match e {
+                    ConnectError::InvalidState => {
+                        ax_err!(AlreadyExists, "socket connect() failed")
+                    }
+                    ConnectError::Unaddressable => {
+                        ax_err!(InvalidInput, "socket connect() failed")
+                    }
+                })?;
+            Ok((handle, socket.local_endpoint(), socket.remote_endpoint()))
+        })
     }
 
     /// Binds an unbound socket to the given address and port.
@@ -152,32 +183,55 @@ impl TcpSocket {
     ///
     /// It's must be called after [`bind`](Self::bind) and [`listen`](Self::listen).
     pub fn accept(&mut self) -> AxResult<TcpSocket> {
-        if !self.is_listening() {
-            return ax_err!(InvalidInput, "socket accept() failed: not listen");
-        }
-
-        let local_port = self
-            .local_addr
-            .ok_or_else(|| ax_err_type!(InvalidInput, "socket accept() failed: no address bound"))?
-            .port;
-
+        let local_port = self.listening_port()?;
         loop {
             SOCKET_SET.poll_interfaces();
             match LISTEN_TABLE.accept(local_port) {
-                Ok((handle, peer_addr)) => {
-                    debug!("socket accepted a new connection {}", peer_addr.unwrap());
-                    return Ok(TcpSocket {
-                        handle: Some(handle),
-                        local_addr: self.local_addr,
-                        peer_addr,
-                    });
-                }
-                Err(AxError::Again) => axtask::yield_now(),
+                Ok((handle, peer_addr)) => return Ok(self.accepted(handle, peer_addr)),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
     }
 
+    /// Accepts a new connection, like [`accept`](Self::accept) but without
+    /// blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn accept_async(&mut self) -> AxResult<TcpSocket> {
+        let local_port = self.listening_port()?;
+        let (handle, peer_addr) = poll_fn(|cx| {
+            SOCKET_SET.poll_interfaces();
+            // registered before checking, so that a connection established
+            // in between wakes it up
+            LISTEN_TABLE.register_accept_waker(local_port, cx.waker());
+            match LISTEN_TABLE.accept(local_port) {
+                Err(AxError::Again) => Poll::Pending,
+                res => Poll::Ready(res),
+            }
+        })
+        .await?;
+        Ok(self.accepted(handle, peer_addr))
+    }
+
+    fn listening_port(&self) -> AxResult<u16> {
+        if !self.is_listening() {
+            return ax_err!(InvalidInput, "socket accept() failed: not listen");
+        }
+        let local_addr = self.local_addr.ok_or_else(|| {
+            ax_err_type!(InvalidInput, "socket accept() failed: no address bound")
+        })?;
+        Ok(local_addr.port)
+    }
+
+    fn accepted(&self, handle: SocketHandle, peer_addr: Option<SocketAddr>) -> TcpSocket {
+        debug!("socket accepted a new connection {}", peer_addr.unwrap());
+        TcpSocket {
+            handle: Some(handle),
+            local_addr: self.local_addr,
+            peer_addr,
+        }
+    }
+
     /// Close the connection.
     pub fn shutdown(&self) -> AxResult {
         if let Some(handle) = self.handle {
@@ -203,36 +257,39 @@ impl TcpSocket {
             .ok_or_else(|| ax_err_type!(NotConnected, "socket recv() failed"))?;
         loop {
             SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_open() {
-                    // not connected
-                    ax_err!(NotConnected, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.can_recv() {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    match socket.recv_slice(buf) {
-                        Ok(len) => Ok(len),
-                        Err(RecvError::Finished) => Ok(0),
-                        Err(_) => ax_err!(ConnectionRefused, "socket recv() failed"),
-                    }
-                } else {
-                    // no more data
-                    Err(AxError::Again)
-                }
-            }) {
+            match SOCKET_SET
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| try_recv(socket, buf))
+            {
                 Ok(n) => {
                     SOCKET_SET.poll_interfaces();
                     return Ok(n);
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
     }
 
+    /// Receives data from the socket, like [`recv`](Self::recv) but without
+    /// blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn recv_async(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let handle = self
+            .handle
+            .ok_or_else(|| ax_err_type!(NotConnected, "socket recv() failed"))?;
+        let n = poll_fn(|cx| {
+            SOCKET_SET.poll_socket(
+                handle,
+                cx.waker(),
+                tcp::Socket::register_recv_waker,
+                |socket| try_recv(socket, buf),
+            )
+        })
+        .await?;
+        SOCKET_SET.poll_interfaces();
+        Ok(n)
+    }
+
     /// Transmits data in the given buffer.
     pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
         let handle = self
@@ -240,31 +297,38 @@ impl TcpSocket {
             .ok_or_else(|| ax_err_type!(NotConnected, "socket send() failed"))?;
         loop {
             SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_open() || !socket.may_send() {
-                    // not connected
-                    ax_err!(NotConnected, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(ConnectionRefused, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::Again)
-                }
-            }) {
+            match SOCKET_SET
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| try_send(socket, buf))
+            {
                 Ok(n) => {
                     SOCKET_SET.poll_interfaces();
                     return Ok(n);
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
     }
+
+    /// Transmits data in the given buffer, like [`send`](Self::send) but
+    /// without blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn send_async(&self, buf: &[u8]) -> AxResult<usize> {
+        let handle = self
+            .handle
+            .ok_or_else(|| ax_err_type!(NotConnected, "socket send() failed"))?;
+        let n = poll_fn(|cx| {
+            SOCKET_SET.poll_socket(
+                handle,
+                cx.waker(),
+                tcp::Socket::register_send_waker,
+                |socket| try_send(socket, buf),
+            )
+        })
+        .await?;
+        SOCKET_SET.poll_interfaces();
+        Ok(n)
+    }
 }
 
 impl Drop for TcpSocket {
@@ -276,6 +340,57 @@ impl Drop for TcpSocket {
     }
 }
 
+/// Returns `Ok(())` if the connection is established, or
+/// [`Err(Again)`](AxError::Again) if it's still connecting.
+fn connect_state(socket: &tcp::Socket) -> AxResult {
+    let state = socket.state();
+    if socket.may_recv() || state == State::Established {
+        Ok(())
+    } else if state == State::SynSent {
+        Err(AxError::Again)
+    } else {
+        ax_err!(ConnectionRefused, "socket connect() failed")
+    }
+}
+
+fn try_recv(socket: &mut tcp::Socket, buf: &mut [u8]) -> AxResult<usize> {
+    if !socket.is_open() {
+        // not connected
+        ax_err!(NotConnected, "socket recv() failed")
+    } else if !socket.may_recv() {
+        // connection closed
+        Ok(0)
+    } else if socket.can_recv() {
+        // data available
+        // TODO: use socket.recv(|buf| {...})
+        match socket.recv_slice(buf) {
+            Ok(len) => Ok(len),
+            Err(RecvError::Finished) => Ok(0),
+            Err(_) => ax_err!(ConnectionRefused, "socket recv() failed"),
+        }
+    } else {
+        // no more data
+        Err(AxError::Again)
+    }
+}
+
+fn try_send(socket: &mut tcp::Socket, buf: &[u8]) -> AxResult<usize> {
+    if !socket.is_open() || !socket.may_send() {
+        // not connected
+        ax_err!(NotConnected, "socket send() failed")
+    } else if socket.can_send() {
+        // connected, and the tx buffer is not full
+        // TODO: use socket.send(|buf| {...})
+        let len = socket
+            .send_slice(buf)
+            .map_err(|_| ax_err_type!(ConnectionRefused, "socket send() failed"))?;
+        Ok(len)
+    } else {
+        // tx buffer is full
+        Err(AxError::Again)
+    }
+}
+
 fn get_ephemeral_port() -> AxResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
@@ -1,3 +1,6 @@
+#[cfg(feature = "async")]
+use core::future::poll_fn;
+
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 
 use smoltcp::iface::SocketHandle;
@@ -7,6 +10,9 @@ use super::{SocketSetWrapper, SOCKET_SET};
 use crate::SocketAddr;
 
 /// A UDP socket that provides POSIX-like APIs.
+///
+/// A blocking method returns [`Err(Interrupted)`](AxError::Interrupted) if a
+/// signal is handled while it's waiting, see `axtask::signal`.
 pub struct UdpSocket {
     handle: Option<SocketHandle>, // `None` if is listening
     local_addr: Option<SocketAddr>,
@@ -64,33 +70,38 @@ impl UdpSocket {
         loop {
             SOCKET_SET.poll_interfaces();
             match SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(handle, |socket| {
-                if !socket.is_open() {
-                    // not connected
-                    ax_err!(NotConnected, "socket send() failed")
-                } else if socket.can_send() {
-                    // TODO: size
-                    socket.send_slice(buf, addr).map_err(|e| match e {
-                        SendError::BufferFull => AxError::Again,
-                        SendError::Unaddressable => {
-                            ax_err_type!(ConnectionRefused, "socket send() failed")
-                        }
-                    })?;
-                    Ok(buf.len())
-                } else {
-                    // tx buffer is full
-                    Err(AxError::Again)
-                }
+                try_sendto(socket, buf, addr)
             }) {
                 Ok(n) => {
                     SOCKET_SET.poll_interfaces();
                     return Ok(n);
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
     }
 
+    /// Transmits data in the given buffer to the given address, like
+    /// [`sendto`](Self::sendto) but without blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn sendto_async(&self, buf: &[u8], addr: SocketAddr) -> AxResult<usize> {
+        let handle = self
+            .handle
+            .ok_or_else(|| ax_err_type!(InvalidInput, "socket bind() failed"))?;
+        let n = poll_fn(|cx| {
+            SOCKET_SET.poll_socket(
+                handle,
+                cx.waker(),
+                udp::Socket::register_send_waker,
+                |socket| try_sendto(socket, buf, addr),
+            )
+        })
+        .await?;
+        SOCKET_SET.poll_interfaces();
+        Ok(n)
+    }
+
     /// Receives data from the socket, stores it in the given buffer.
     pub fn recvfrom(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
         let handle = self
@@ -98,32 +109,39 @@ impl UdpSocket {
             .ok_or_else(|| ax_err_type!(InvalidInput, "socket recv() failed"))?;
         loop {
             SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(handle, |socket| {
-                if !socket.is_open() {
-                    // not connected
-                    ax_err!(NotConnected, "socket recv() failed")
-                } else if socket.can_recv() {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    match socket.recv_slice(buf) {
-                        Ok(x) => Ok(x),
-                        Err(_) => Err(AxError::Again),
-                    }
-                } else {
-                    // no more data
-                    Err(AxError::Again)
-                }
-            }) {
+            match SOCKET_SET
+                .with_socket_mut::<udp::Socket, _, _>(handle, |socket| try_recvfrom(socket, buf))
+            {
                 Ok(x) => {
                     SOCKET_SET.poll_interfaces();
                     return Ok(x);
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
     }
 
+    /// Receives data from the socket, like [`recvfrom`](Self::recvfrom) but
+    /// without blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn recvfrom_async(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        let handle = self
+            .handle
+            .ok_or_else(|| ax_err_type!(InvalidInput, "socket recv() failed"))?;
+        let x = poll_fn(|cx| {
+            SOCKET_SET.poll_socket(
+                handle,
+                cx.waker(),
+                udp::Socket::register_recv_waker,
+                |socket| try_recvfrom(socket, buf),
+            )
+        })
+        .await?;
+        SOCKET_SET.poll_interfaces();
+        Ok(x)
+    }
+
     /// Close the socket.
     pub fn shutdown(&self) -> AxResult {
         SOCKET_SET.poll_interfaces();
@@ -166,7 +184,7 @@ impl UdpSocket {
                     SOCKET_SET.poll_interfaces();
                     return Ok(x);
                 }
-                Err(AxError::Again) => axtask::yield_now(),
+                Err(AxError::Again) => axtask::yield_interruptible()?,
                 Err(e) => return Err(e),
             }
         }
@@ -181,3 +199,39 @@ impl Drop for UdpSocket {
         }
     }
 }
+
+fn try_sendto(socket: &mut udp::Socket, buf: &[u8], addr: SocketAddr) -> AxResult<usize> {
+    if !socket.is_open() {
+        // not connected
+        ax_err!(NotConnected, "socket send() failed")
+    } else if socket.can_send() {
+        // TODO: size
+        socket.send_slice(buf, addr).map_err(|e| match e {
+            SendError::BufferFull => AxError::Again,
+            SendError::Unaddressable => {
+                ax_err_type!(ConnectionRefused, "socket send() failed")
+            }
+        })?;
+        Ok(buf.len())
+    } else {
+        // tx buffer is full
+        Err(AxError::Again)
+    }
+}
+
+fn try_recvfrom(socket: &mut udp::Socket, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+    if !socket.is_open() {
+        // not connected
+        ax_err!(NotConnected, "socket recv() failed")
+    } else if socket.can_recv() {
+        // data available
+        // TODO: use socket.recv(|buf| {...})
+        match socket.recv_slice(buf) {
+            Ok(x) => Ok(x),
+            Err(_) => Err(AxError::Again),
+        }
+    } else {
+        // no more data
+        Err(AxError::Again)
+    }
+}
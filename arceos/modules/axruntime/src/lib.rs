@@ -159,7 +159,10 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         let all_devices = axdriver::init_drivers();
 
         #[cfg(feature = "fs")]
-        axfs::init_filesystems(all_devices.block.0);
+        {
+            axfs::init_filesystems(all_devices.block.0);
+            axalloc::register_reclaimer(axfs::page_cache::reclaim);
+        }
 
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
@@ -252,12 +255,15 @@ fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
 
     // Setup timer interrupt handler
+    #[cfg(not(feature = "tickless"))]
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
+    #[cfg(not(feature = "tickless"))]
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
+    #[cfg(not(feature = "tickless"))]
     fn update_timer() {
         let now_ns = axhal::time::current_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
@@ -270,6 +276,8 @@ fn init_interrupt() {
     }
 
     axhal::irq::register_handler(TIMER_IRQ_NUM, || {
+        // the timer is programmed by `axtask` in the tickless mode
+        #[cfg(not(feature = "tickless"))]
         update_timer();
         #[cfg(feature = "multitask")]
         axtask::on_timer_tick();
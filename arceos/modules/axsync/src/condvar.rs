@@ -0,0 +1,134 @@
+//! A sleeping condition variable.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use crate::MutexGuard;
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+#[cfg(feature = "irq")]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+#[cfg(feature = "irq")]
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable, similar to
+/// [`std::sync::Condvar`](https://doc.rust-lang.org/std/sync/struct.Condvar.html).
+///
+/// It's used with a [`Mutex`](crate::Mutex): a task waiting on it releases
+/// the mutex while it sleeps, and takes it again before it returns.
+pub struct Condvar {
+    wq: WaitQueue,
+    /// Incremented by each notification, so that a waiter can't miss one
+    /// sent after it released the mutex.
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks the current task until this condition variable receives a
+    /// notification.
+    ///
+    /// The mutex of `guard` is released while the task is blocked, and
+    /// locked again before this function returns.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = MutexGuard::mutex(&guard);
+        let seq = self.seq.load(Ordering::Acquire);
+        drop(guard);
+        self.wq
+            .wait_until(|| self.seq.load(Ordering::Acquire) != seq);
+        mutex.lock()
+    }
+
+    /// Blocks the current task while `condition` returns `true`, see
+    /// [`Condvar::wait`].
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration, see [`Condvar::wait`].
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult) {
+        let mutex = MutexGuard::mutex(&guard);
+        let seq = self.seq.load(Ordering::Acquire);
+        drop(guard);
+        let timeout = self
+            .wq
+            .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq);
+        (mutex.lock(), WaitTimeoutResult(timeout))
+    }
+
+    /// Waits on this condition variable while `condition` returns `true`,
+    /// timing out after the specified duration, see [`Condvar::wait`].
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = axhal::time::current_time() + dur;
+        while condition(&mut *guard) {
+            let now = axhal::time::current_time();
+            if now >= deadline {
+                return (guard, WaitTimeoutResult(true));
+            }
+            guard = self.wait_timeout(guard, deadline - now).0;
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    /// Wakes up one task blocked on this condition variable.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Wakes up all tasks blocked on this condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
@@ -2,26 +2,60 @@
 //!
 //! Currently supported primitives:
 //!
-//! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`Mutex`]: A mutual exclusion primitive, with priority inheritance.
+//! - [`RwLock`]: A readers-writer lock.
+//! - [`Condvar`]: A condition variable.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A barrier for a number of tasks.
+//! - [`Once`] and [`OnceCell`]: One-time initialization.
 //! - mod [`spin`](spinlock): spin-locks.
 //!
+//! All but the spin-locks block the current task while they wait, so they
+//! need the `multitask` feature.
+//!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
 //!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
 //!   feature is enabled by default.
+//! - `irq`: Interrupts are enabled, so the timed waits such as
+//!   [`Condvar::wait_timeout`] can be used.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
 pub use spinlock as spin;
 
+#[cfg(feature = "multitask")]
+mod barrier;
+#[cfg(feature = "multitask")]
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(feature = "multitask")]
+mod once;
+#[cfg(feature = "multitask")]
+mod rwlock;
+#[cfg(feature = "multitask")]
+mod semaphore;
+
+#[cfg(test)]
+mod tests;
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
-pub use self::mutex::{Mutex, MutexGuard};
+pub use self::{
+    barrier::{Barrier, BarrierWaitResult},
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+    once::{Once, OnceCell},
+    rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    semaphore::{Semaphore, SemaphoreGuard},
+};
+
+#[cfg(all(feature = "multitask", feature = "irq"))]
+#[doc(cfg(all(feature = "multitask", feature = "irq")))]
+pub use self::condvar::WaitTimeoutResult;
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
@@ -5,7 +5,7 @@ use core::fmt;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
 
-use axtask::{current, WaitQueue};
+use axtask::{current, PiOwner, WaitQueue};
 
 /// A mutual exclusion primitive useful for protecting shared data, similar to
 /// [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
@@ -13,9 +13,13 @@ use axtask::{current, WaitQueue};
 /// When the mutex is locked, the current task will block and be put into the
 /// wait queue. When the mutex is unlocked, all tasks waiting on the queue
 /// will be woken up.
+///
+/// The owner of the mutex inherits the priority of the tasks waiting for it,
+/// see [`PiOwner`].
 pub struct Mutex<T: ?Sized> {
     wq: WaitQueue,
     owner_id: AtomicU64,
+    owner: PiOwner,
     data: UnsafeCell<T>,
 }
 
@@ -38,6 +42,7 @@ impl<T> Mutex<T> {
         Self {
             wq: WaitQueue::new(),
             owner_id: AtomicU64::new(0),
+            owner: PiOwner::new(),
             data: UnsafeCell::new(data),
         }
     }
@@ -64,6 +69,13 @@ impl<T: ?Sized> Mutex<T> {
         self.owner_id.load(Ordering::Relaxed) != 0
     }
 
+    /// Returns `true` if the current task holds the lock.
+    #[inline(always)]
+    pub fn is_owned_by_current(&self) -> bool {
+        // only the current task stores its own ID
+        self.owner_id.load(Ordering::Relaxed) == current().id().as_u64()
+    }
+
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access
@@ -88,10 +100,12 @@ impl<T: ?Sized> Mutex<T> {
                         current().id_name()
                     );
                     // Wait until the lock looks unlocked before retrying
+                    let _wait = self.owner.boost();
                     self.wq.wait_until(|| !self.is_locked());
                 }
             }
         }
+        self.owner.set_current();
         MutexGuard {
             lock: self,
             data: unsafe { &mut *self.data.get() },
@@ -109,6 +123,7 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            self.owner.set_current();
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -118,6 +133,32 @@ impl<T: ?Sized> Mutex<T> {
         }
     }
 
+    /// Creates a guard for the [`Mutex`] without locking it, e.g. to pass a
+    /// lock taken by a C caller to [`Condvar::wait`](crate::Condvar::wait).
+    ///
+    /// # Safety
+    ///
+    /// The current task must hold the lock, and its other guards, if any,
+    /// must be forgotten with [`core::mem::forget`].
+    #[inline(always)]
+    pub unsafe fn make_guard_unchecked(&self) -> MutexGuard<T> {
+        MutexGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
+    /// Releases the lock held by the current task, whose guard has been
+    /// forgotten with [`core::mem::forget`].
+    ///
+    /// # Safety
+    ///
+    /// No guard of the lock must be used after this call.
+    #[inline(always)]
+    pub unsafe fn force_unlock(&self) {
+        drop(self.make_guard_unchecked());
+    }
+
     /// Returns a mutable reference to the underlying data.
     ///
     /// Since this call borrows the [`Mutex`] mutably, and a mutable reference is guaranteed to be exclusive in
@@ -149,6 +190,13 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
     }
 }
 
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// The [`Mutex`] the guard was created from.
+    pub(crate) fn mutex(guard: &Self) -> &'a Mutex<T> {
+        guard.lock
+    }
+}
+
 impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
     type Target = T;
     #[inline(always)]
@@ -175,6 +223,7 @@ impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'a, T> {
 impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
     /// The dropping of the [`MutexGuard`] will release the lock it was created from.
     fn drop(&mut self) {
+        self.lock.owner.clear();
         let owner_id = self.lock.owner_id.swap(0, Ordering::Release);
         assert_eq!(
             owner_id,
@@ -188,11 +237,9 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
 
 #[cfg(test)]
 mod tests {
+    use crate::tests::{INIT, SERIAL};
     use crate::Mutex;
     use axtask as task;
-    use std::sync::Once;
-
-    static INIT: Once = Once::new();
 
     fn may_interrupt() {
         // simulate interrupts
@@ -203,6 +250,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
+        let _lock = SERIAL.lock();
         INIT.call_once(axtask::init_scheduler);
 
         const NUM_TASKS: u32 = 10;
@@ -0,0 +1,250 @@
+//! A sleeping readers-writer lock.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axtask::{PiOwner, WaitQueue};
+
+/// The lock state when a writer holds it, otherwise the state is the number
+/// of readers that hold it.
+const WRITER: usize = usize::MAX;
+
+/// A readers-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// It allows any number of readers or at most one writer at any point in
+/// time. When the lock can't be taken, the current task blocks in a wait
+/// queue until it's released.
+///
+/// Waiting writers don't block new readers, so a task may take a read lock
+/// it already holds without deadlocking. The writer inherits the priority of
+/// the tasks waiting for the lock, see [`PiOwner`].
+pub struct RwLock<T: ?Sized> {
+    wq: WaitQueue,
+    state: AtomicUsize,
+    writer: PiOwner,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access, released when it's dropped.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *const T,
+}
+
+/// A guard that provides mutable data access, released when it's dropped.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicUsize::new(0),
+            writer: PiOwner::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    fn state(&self) -> usize {
+        self.state.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if a writer holds the lock. Like
+    /// [`Mutex::is_locked`](crate::Mutex::is_locked), it's only a heuristic.
+    #[inline(always)]
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.state() == WRITER
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            let _wait = self.writer.boost();
+            self.wq.wait_until(|| self.state() != WRITER);
+        }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with shared read access.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let mut readers = self.state();
+        while readers != WRITER {
+            match self.state.compare_exchange_weak(
+                readers,
+                readers + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(RwLockReadGuard {
+                        lock: self,
+                        data: self.data.get(),
+                    })
+                }
+                Err(state) => readers = state,
+            }
+        }
+        None
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the
+    /// current task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            let _wait = self.writer.boost();
+            self.wq.wait_until(|| self.state() == 0);
+        }
+    }
+
+    /// Attempts to lock this [`RwLock`] with exclusive write access.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.writer.set_current();
+            Some(RwLockWriteGuard {
+                lock: self,
+                data: self.data.get(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Releases a read lock whose guard has been forgotten with
+    /// [`core::mem::forget`].
+    ///
+    /// # Safety
+    ///
+    /// The current task must hold a read lock, whose guard must not be used
+    /// after this call.
+    pub unsafe fn force_read_unlock(&self) {
+        drop(RwLockReadGuard {
+            lock: self,
+            data: self.data.get(),
+        });
+    }
+
+    /// Releases the write lock whose guard has been forgotten with
+    /// [`core::mem::forget`].
+    ///
+    /// # Safety
+    ///
+    /// The current task must hold the write lock, whose guard must not be
+    /// used after this call.
+    pub unsafe fn force_write_unlock(&self) {
+        drop(RwLockWriteGuard {
+            lock: self,
+            data: self.data.get(),
+        });
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking
+    /// needs to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        let readers = self.lock.state.fetch_sub(1, Ordering::Release);
+        assert!(readers != 0 && readers != WRITER, "rwlock is not read locked");
+        if readers == 1 {
+            self.lock.wq.notify_all(true);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.writer.clear();
+        let state = self.lock.state.swap(0, Ordering::Release);
+        assert_eq!(state, WRITER, "rwlock is not write locked");
+        self.lock.wq.notify_all(true);
+    }
+}
@@ -0,0 +1,116 @@
+//! A sleeping counting semaphore.
+
+use core::sync::atomic::{AtomicIsize, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+/// A counting, blocking, semaphore.
+///
+/// Semaphores are a form of atomic counter where access is only granted if
+/// the counter is a positive value. Each acquisition will block the calling
+/// task until the counter is positive, and each release will increment the
+/// counter and wake up a task blocked on the semaphore if any.
+pub struct Semaphore {
+    wq: WaitQueue,
+    count: AtomicIsize,
+}
+
+/// An RAII guard which will release a resource acquired from a semaphore when
+/// dropped.
+pub struct SemaphoreGuard<'a> {
+    sem: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the initial count specified.
+    ///
+    /// The count specified can be thought of as a number of resources, and a
+    /// call to `acquire` or `access` will block until at least one resource
+    /// is available. It is valid to initialize a semaphore with a negative
+    /// count.
+    pub const fn new(count: isize) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            count: AtomicIsize::new(count),
+        }
+    }
+
+    /// Returns the current count, which may be out of date the instant it's
+    /// returned.
+    pub fn count(&self) -> isize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Acquires a resource of this semaphore, blocking the current task until
+    /// it can do so.
+    ///
+    /// This method will block until the internal count of the semaphore is at
+    /// least 1.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            self.wq.wait_until(|| self.count() > 0);
+        }
+    }
+
+    /// Acquires a resource of this semaphore if one is available, returns
+    /// `false` otherwise.
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count();
+        while count > 0 {
+            match self.count.compare_exchange_weak(
+                count,
+                count - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(c) => count = c,
+            }
+        }
+        false
+    }
+
+    /// Acquires a resource of this semaphore, blocking the current task until
+    /// it can do so or the given duration has elapsed. Returns `false` if it
+    /// timed out.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        let deadline = axhal::time::current_time() + dur;
+        while !self.try_acquire() {
+            let now = axhal::time::current_time();
+            if now >= deadline {
+                return false;
+            }
+            self.wq
+                .wait_timeout_until(deadline - now, || self.count() > 0);
+        }
+        true
+    }
+
+    /// Release a resource from this semaphore.
+    ///
+    /// This will increment the number of resources in this semaphore by 1 and
+    /// will notify any pending waiters in `acquire` or `access` if necessary.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Acquires a resource of this semaphore, returning an RAII guard to
+    /// release the semaphore when dropped.
+    ///
+    /// This function is semantically equivalent to an `acquire` followed by a
+    /// `release` when the guard returned is dropped.
+    pub fn access(&self) -> SemaphoreGuard<'_> {
+        self.acquire();
+        SemaphoreGuard { sem: self }
+    }
+}
+
+impl Drop for SemaphoreGuard<'_> {
+    fn drop(&mut self) {
+        self.sem.release();
+    }
+}
@@ -0,0 +1,183 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex as StdMutex, Once as StdOnce};
+
+use crate::{Barrier, Condvar, Mutex, OnceCell, RwLock, Semaphore};
+use axtask as task;
+
+pub(crate) static INIT: StdOnce = StdOnce::new();
+pub(crate) static SERIAL: StdMutex<()> = StdMutex::new(());
+
+fn may_interrupt() {
+    // simulate interrupts
+    if rand::random::<u32>() % 3 == 0 {
+        task::yield_now();
+    }
+}
+
+#[test]
+fn test_condvar() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_ITEMS: u32 = 1000;
+    static ITEM: Mutex<Option<u32>> = Mutex::new(None);
+    static CVAR: Condvar = Condvar::new();
+
+    let consumer = task::spawn(|| {
+        let mut sum = 0;
+        for _ in 0..NUM_ITEMS {
+            let mut item = CVAR.wait_while(ITEM.lock(), |item| item.is_none());
+            sum += item.take().unwrap();
+            CVAR.notify_all();
+        }
+        assert_eq!(sum, NUM_ITEMS * (NUM_ITEMS + 1) / 2);
+    });
+
+    for i in 1..=NUM_ITEMS {
+        let mut item = CVAR.wait_while(ITEM.lock(), |item| item.is_some());
+        *item = Some(i);
+        CVAR.notify_all();
+        drop(item);
+        may_interrupt();
+    }
+    assert_eq!(consumer.join(), 0);
+}
+
+#[test]
+fn test_rwlock() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 10;
+    const NUM_ITERS: usize = 1000;
+    static PAIR: RwLock<(usize, usize)> = RwLock::new((0, 0));
+
+    let handles = (0..NUM_TASKS)
+        .map(|i| {
+            task::spawn(move || {
+                for _ in 0..NUM_ITERS {
+                    if i % 2 == 0 {
+                        let mut pair = PAIR.write();
+                        pair.0 += 1;
+                        may_interrupt();
+                        pair.1 += 1;
+                    } else {
+                        let pair = PAIR.read();
+                        may_interrupt();
+                        assert_eq!(pair.0, pair.1);
+                        // a reader may take the lock again
+                        assert_eq!(PAIR.read().0, pair.0);
+                    }
+                    may_interrupt();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(*PAIR.read(), (NUM_TASKS / 2 * NUM_ITERS, NUM_TASKS / 2 * NUM_ITERS));
+    assert!(PAIR.try_write().is_some());
+}
+
+#[test]
+fn test_semaphore() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 10;
+    const NUM_RESOURCES: isize = 3;
+    static SEM: Semaphore = Semaphore::new(NUM_RESOURCES);
+    static IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+    let handles = (0..NUM_TASKS)
+        .map(|_| {
+            task::spawn(|| {
+                for _ in 0..100 {
+                    let _guard = SEM.access();
+                    let in_use = IN_USE.fetch_add(1, Ordering::SeqCst) + 1;
+                    assert!(in_use <= NUM_RESOURCES as usize);
+                    may_interrupt();
+                    IN_USE.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(SEM.count(), NUM_RESOURCES);
+    for _ in 0..NUM_RESOURCES {
+        assert!(SEM.try_acquire());
+    }
+    assert!(!SEM.try_acquire());
+    SEM.release();
+    assert_eq!(SEM.count(), 1);
+}
+
+#[test]
+fn test_barrier() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 10;
+    const NUM_ROUNDS: usize = 5;
+    static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+    static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+    static LEADERS: AtomicUsize = AtomicUsize::new(0);
+
+    let handles = (0..NUM_TASKS)
+        .map(|_| {
+            task::spawn(|| {
+                for round in 1..=NUM_ROUNDS {
+                    ARRIVED.fetch_add(1, Ordering::SeqCst);
+                    may_interrupt();
+                    if BARRIER.wait().is_leader() {
+                        LEADERS.fetch_add(1, Ordering::SeqCst);
+                    }
+                    assert!(ARRIVED.load(Ordering::SeqCst) >= round * NUM_TASKS);
+                    // nobody starts the next round before everyone leaves this one
+                    BARRIER.wait();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(LEADERS.load(Ordering::SeqCst), NUM_ROUNDS);
+}
+
+#[test]
+fn test_once() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 10;
+    static CELL: OnceCell<usize> = OnceCell::new();
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let handles = (0..NUM_TASKS)
+        .map(|i| {
+            task::spawn(move || {
+                let value = *CELL.get_or_init(|| {
+                    CALLS.fetch_add(1, Ordering::SeqCst);
+                    // let others wait for the initialization
+                    task::yield_now();
+                    i
+                });
+                assert_eq!(CELL.get(), Some(&value));
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(CELL.set(NUM_TASKS), Err(NUM_TASKS));
+
+    let mut cell = OnceCell::new();
+    assert_eq!(cell.get_mut(), None);
+    assert_eq!(cell.set(String::from("once")), Ok(()));
+    assert_eq!(cell.into_inner().as_deref(), Some("once"));
+}
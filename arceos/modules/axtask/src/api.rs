@@ -1,11 +1,27 @@
 //! Task APIs for multi-task configuration.
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+use crate::pi::NOT_NORMAL;
+use crate::run_queue::{current_run_queue, ALL_CPUS};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::pi::{PiOwner, PiWait};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::stats::{tasks, TaskInfo, TaskStats};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, JoinHandle, TaskId, TaskInner, TaskState};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task_local::LocalKey;
+#[cfg(feature = "tickless")]
+pub use crate::timers::{set_timer_slack, timer_slack};
+#[cfg(feature = "irq")]
+pub use crate::timers::{timer_stats, TimerStats};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
+#[doc(cfg(feature = "multitask"))]
+pub use scheduler::{DeadlineParams, SchedPolicy};
+
+use alloc::string::String;
+use axerrno::{AxError, AxResult};
 
 /// The reference type of a task.
 pub type AxTaskRef = alloc::sync::Arc<AxTask>;
@@ -21,6 +37,9 @@ cfg_if::cfg_if! {
     } else if #[cfg(feature = "sched_cfs")] {
         pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
         pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_rt")] {
+        pub(crate) type AxTask = scheduler::RTTask<TaskInner>;
+        pub(crate) type Scheduler = scheduler::RTScheduler<TaskInner>;
     }
 }
 
@@ -77,35 +96,204 @@ pub fn init_scheduler_secondary() {
 /// Handles periodic timer ticks for the task manager.
 ///
 /// For example, advance scheduler states, checks timed events, etc.
+///
+/// In the `tickless` mode, it's called on every timer interrupt, and programs
+/// the timer itself for the next tick or timed event, so the caller must not.
+/// Only the interrupts that are periodic ticks advance the scheduler states.
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
-    crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    if crate::timers::on_timer_irq() {
+        current()
+            .counters()
+            .tick(axhal::trap::irq_from_user());
+        current_run_queue().scheduler_timer_tick();
+    }
+}
+
+/// Task factory, which is used to configure the properties of a new task.
+///
+/// # Examples
+///
+/// ```
+/// axtask::init_scheduler();
+/// let handle = axtask::Builder::new()
+///     .name("worker".into())
+///     .stack_size(0x10000)
+///     .spawn(|| axtask::exit(7));
+/// assert_eq!(handle.join(), 7);
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    name: String,
+    stack_size: usize,
+    cpu_mask: u64,
+    service: bool,
+}
+
+impl Builder {
+    /// Creates a builder for a task with an empty name, a stack of
+    /// [`axconfig::TASK_STACK_SIZE`] bytes, that may run on all CPUs.
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            stack_size: axconfig::TASK_STACK_SIZE,
+            cpu_mask: ALL_CPUS,
+            service: false,
+        }
+    }
+
+    /// Names the task.
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Sets the size of the task stack in bytes, rounded up to 4K.
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    /// Sets the CPUs the task may run on, the bit `i` of `mask` is set for
+    /// the CPU `i`. The bits of the CPUs that don't exist are ignored, and
+    /// if none is left, the task may run on all CPUs.
+    pub fn cpu_affinity(mut self, mask: u64) -> Self {
+        self.cpu_mask = match mask & ALL_CPUS {
+            0 => ALL_CPUS,
+            mask => mask,
+        };
+        self
+    }
+
+    /// Marks the task as a kernel service, e.g., a device poller, which can't
+    /// be sent [signals](crate::signal).
+    pub fn kernel_service(mut self) -> Self {
+        self.service = true;
+        self
+    }
+
+    /// Spawns the task, and returns a [`JoinHandle`] for it.
+    pub fn spawn<F>(self, f: F) -> JoinHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let task = TaskInner::new(f, self.name, self.stack_size, self.service);
+        task.set_cpu_affinity(self.cpu_mask);
+        crate::run_queue::spawn_task(task.clone());
+        JoinHandle::new(task)
+    }
 }
 
-/// Spawns a new task.
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns a new task, and returns a [`JoinHandle`] for it.
 ///
 /// The task name is an empty string. The task stack size is
-/// [`axconfig::TASK_STACK_SIZE`].
-pub fn spawn<F>(f: F)
+/// [`axconfig::TASK_STACK_SIZE`]. Use [`Builder`] to change them.
+pub fn spawn<F>(f: F) -> JoinHandle
 where
     F: FnOnce() + Send + 'static,
 {
-    let task = TaskInner::new(f, "", axconfig::TASK_STACK_SIZE);
-    RUN_QUEUE.lock().add_task(task);
+    Builder::new().spawn(f)
 }
 
 /// set priority for current task.
 /// In CFS, priority is the nice value, ranging from -20 to 19.
+///
+/// The task keeps running with a higher priority lent by [`PiOwner`] until
+/// it releases the lock it was lent for.
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_priority(prio)
+    let curr = current();
+    let mut rq = current_run_queue();
+    if curr.base_prio() == NOT_NORMAL {
+        // a real-time priority
+        return rq.set_priority(prio);
+    }
+    let old = curr.base_prio();
+    curr.set_base_prio(prio);
+    if !rq.set_priority(curr.effective_prio()) {
+        curr.set_base_prio(old);
+        return false;
+    }
+    true
+}
+
+/// Sets the scheduling policy of the current task.
+///
+/// Only the `sched_rt` scheduler supports the real-time and deadline
+/// policies, the other ones accept [`SchedPolicy::Normal`] as
+/// [`set_priority`] does. Returns `false` if the policy is not supported or
+/// its parameters are invalid, or if the deadline task can't be admitted.
+///
+/// A deadline task is admitted by the CPU bandwidth left on the current CPU,
+/// so it's bound to that CPU.
+pub fn set_sched_policy(policy: SchedPolicy) -> bool {
+    let curr = current();
+    let mut rq = current_run_queue();
+    let old = curr.base_prio();
+    let policy = match policy {
+        SchedPolicy::Normal(nice) => {
+            curr.set_base_prio(nice);
+            SchedPolicy::Normal(curr.effective_prio())
+        }
+        _ => policy,
+    };
+    if !rq.set_policy(policy) {
+        curr.set_base_prio(old);
+        return false;
+    }
+    if !matches!(policy, SchedPolicy::Normal(_)) {
+        curr.set_base_prio(NOT_NORMAL);
+    }
+    if let SchedPolicy::Deadline(_) = policy {
+        // the task can't migrate until the run queue is released
+        curr.set_cpu_affinity(1 << axhal::cpu::this_cpu_id());
+    }
+    true
+}
+
+/// Sets the CPUs the current task may run on, the bit `i` of `mask` is set
+/// for the CPU `i`.
+///
+/// If the current CPU is not in `mask`, the task is moved to one that is
+/// before it returns. Returns `false` and does nothing if `mask` has no CPU
+/// that exists.
+pub fn set_cpu_affinity(mask: u64) -> bool {
+    let mask = mask & ALL_CPUS;
+    if mask == 0 {
+        return false;
+    }
+    current().set_cpu_affinity(mask);
+    if mask & (1 << axhal::cpu::this_cpu_id()) == 0 {
+        current_run_queue().yield_current();
+    }
+    true
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
+}
+
+/// Like [`yield_now`], but returns [`Err(Interrupted)`](AxError::Interrupted)
+/// if a signal that's not ignored is pending on the task, so that a blocking
+/// operation polling with it can be interrupted.
+///
+/// The signal is left pending, to be handled by
+/// [`signal::handle_pending`](crate::signal::handle_pending) once the caller
+/// holds no locks.
+pub fn yield_interruptible() -> AxResult {
+    current_run_queue().yield_current();
+    if crate::signal::interrupted() {
+        return Err(AxError::Interrupted);
+    }
+    Ok(())
 }
 
 /// Current task is going to sleep for the given duration.
@@ -120,23 +308,59 @@ pub fn sleep(dur: core::time::Duration) {
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    while axhal::time::current_time() < deadline {
+        current_run_queue().sleep_until(deadline);
+    }
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
 }
 
+/// Like [`sleep_until`], but a signal sent to the task wakes it up, and then it
+/// returns [`Err(Interrupted)`](AxError::Interrupted) if the signal is not
+/// ignored. The signal is left pending, as in [`yield_interruptible`].
+#[cfg(feature = "irq")]
+pub fn sleep_until_interruptible(deadline: axhal::time::TimeValue) -> AxResult {
+    while axhal::time::current_time() < deadline {
+        crate::signal::interruptible(|| current_run_queue().sleep_until(deadline));
+        let curr = current();
+        if curr.in_timer_list() {
+            // woken up by a signal
+            crate::timers::cancel_alarm(curr.as_task_ref());
+        }
+        if crate::signal::interrupted() {
+            return Err(AxError::Interrupted);
+        }
+    }
+    Ok(())
+}
+
 /// Exits the current task.
+///
+/// The exit code is returned to the tasks joining it, see
+/// [`JoinHandle::join`].
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    crate::run_queue::exit_current(exit_code)
 }
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`], and waits for
+/// IRQs unless tasks were queued on this CPU in the meantime. In the
+/// `tickless` mode, the CPU stops ticking while it waits for IRQs.
 pub fn run_idle() -> ! {
     loop {
         yield_now();
+        #[cfg(feature = "tickless")]
+        if !crate::timers::enter_idle() {
+            continue;
+        }
+        #[cfg(not(feature = "tickless"))]
+        if crate::run_queue::has_ready_tasks() {
+            continue;
+        }
         debug!("idle task: waiting for IRQs...");
         axhal::arch::wait_for_irqs();
+        #[cfg(feature = "tickless")]
+        crate::timers::exit_idle();
     }
 }
@@ -10,6 +10,13 @@ pub fn yield_now() {
     }
 }
 
+/// For single-task situation, it's the same as [`yield_now`], as there are no
+/// signals.
+pub fn yield_interruptible() -> axerrno::AxResult {
+    yield_now();
+    Ok(())
+}
+
 /// For single-task situation, we just busy wait for the given duration.
 pub fn sleep(dur: core::time::Duration) {
     axhal::time::busy_wait(dur);
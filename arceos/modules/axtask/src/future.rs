@@ -0,0 +1,269 @@
+//! Running futures on tasks.
+//!
+//! A future is polled by an ordinary task, which sleeps on a [`WaitQueue`]
+//! while the future is pending, and is woken up by the future's [`Waker`]:
+//!
+//! - [`block_on`] runs one future to completion on the current task.
+//! - [`Executor`] runs many futures concurrently on the task calling
+//!   [`Executor::run`].
+//! - [`sleep`] and [`sleep_until`] are futures woken up by the timer (require
+//!   the `irq` feature).
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, task::Wake};
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use spinlock::SpinNoIrq;
+
+use crate::WaitQueue;
+
+/// Wakes up the task blocked in [`block_on`].
+struct Signal {
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Signal {
+    fn wait(&self) {
+        self.wq
+            .wait_until(|| self.woken.swap(false, Ordering::Acquire));
+    }
+}
+
+impl Wake for Signal {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+/// Runs a future to completion on the current task, and returns its output.
+///
+/// The current task is blocked while the future is pending.
+///
+/// # Examples
+///
+/// ```
+/// axtask::init_scheduler();
+/// assert_eq!(axtask::future::block_on(async { 6 * 7 }), 42);
+/// ```
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let signal = Arc::new(Signal {
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let waker = Waker::from(signal.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        signal.wait();
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The futures of an [`Executor`] that are ready to be polled.
+struct ReadyQueue {
+    queue: SpinNoIrq<VecDeque<Arc<FutureTask>>>,
+    wq: WaitQueue,
+    /// The number of futures not completed yet.
+    pending: AtomicUsize,
+}
+
+/// A future spawned on an [`Executor`], which is its own waker.
+struct FutureTask {
+    future: SpinNoIrq<Option<BoxFuture>>,
+    queued: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for FutureTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.ready.queue.lock().push_back(self.clone());
+            self.ready.wq.notify_one(true);
+        }
+    }
+}
+
+/// An executor running many futures concurrently on one task.
+///
+/// Futures are added by [`spawn`](Executor::spawn), and polled by
+/// [`run`](Executor::run) when they're woken up. The executor is a cheap
+/// handle that can be cloned to spawn futures from other tasks or from the
+/// futures themselves.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use axtask::future::Executor;
+///
+/// static SUM: AtomicUsize = AtomicUsize::new(0);
+///
+/// axtask::init_scheduler();
+/// let executor = Executor::new();
+/// for i in 1..=10 {
+///     executor.spawn(async move {
+///         SUM.fetch_add(i, Ordering::Relaxed);
+///     });
+/// }
+/// executor.run();
+/// assert_eq!(SUM.load(Ordering::Relaxed), 55);
+/// ```
+#[derive(Clone)]
+pub struct Executor {
+    ready: Arc<ReadyQueue>,
+}
+
+impl Executor {
+    /// Creates an executor without futures.
+    pub fn new() -> Self {
+        Self {
+            ready: Arc::new(ReadyQueue {
+                queue: SpinNoIrq::new(VecDeque::new()),
+                wq: WaitQueue::new(),
+                pending: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Adds a future to the executor, it will be polled by
+    /// [`run`](Executor::run).
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.ready.pending.fetch_add(1, Ordering::AcqRel);
+        let task = Arc::new(FutureTask {
+            future: SpinNoIrq::new(Some(Box::pin(fut))),
+            queued: AtomicBool::new(false),
+            ready: self.ready.clone(),
+        });
+        task.wake_by_ref();
+    }
+
+    /// Polls the spawned futures until all of them are completed, including
+    /// those spawned while running.
+    ///
+    /// The current task is blocked when no future is ready. It must be the
+    /// only task running this executor.
+    pub fn run(&self) {
+        let ready = &self.ready;
+        loop {
+            let task = ready.queue.lock().pop_front();
+            let Some(task) = task else {
+                if ready.pending.load(Ordering::Acquire) == 0 {
+                    return;
+                }
+                ready.wq.wait_until(|| {
+                    !ready.queue.lock().is_empty() || ready.pending.load(Ordering::Acquire) == 0
+                });
+                continue;
+            };
+            // woken up again if it's woken while being polled
+            task.queued.store(false, Ordering::Release);
+            let mut fut = task.future.lock().take().unwrap();
+            let waker = Waker::from(task.clone());
+            let mut cx = Context::from_waker(&waker);
+            if fut.as_mut().poll(&mut cx).is_pending() {
+                *task.future.lock() = Some(fut);
+            } else {
+                // never queued again by the wakers left
+                task.queued.store(true, Ordering::Release);
+                ready.pending.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "irq")]
+pub use self::timer::{sleep, sleep_until, Sleep};
+
+#[cfg(feature = "irq")]
+mod timer {
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use core::time::Duration;
+
+    use axhal::time::{current_time, TimeValue};
+
+    use crate::timers::{cancel_alarm_waker, set_alarm_waker};
+
+    /// A future that completes at a deadline, returned by [`sleep`] and
+    /// [`sleep_until`].
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    pub struct Sleep {
+        deadline: TimeValue,
+        alarm: Option<u64>,
+    }
+
+    impl Sleep {
+        /// Returns the instant at which the future will complete.
+        pub fn deadline(&self) -> TimeValue {
+            self.deadline
+        }
+
+        fn cancel(&mut self) {
+            if let Some(id) = self.alarm.take() {
+                cancel_alarm_waker(id);
+            }
+        }
+    }
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let this = self.get_mut();
+            this.cancel();
+            if current_time() >= this.deadline {
+                return Poll::Ready(());
+            }
+            // set it again as the waker may be changed
+            this.alarm = Some(set_alarm_waker(this.deadline, cx.waker().clone()));
+            Poll::Pending
+        }
+    }
+
+    impl Drop for Sleep {
+        fn drop(&mut self) {
+            self.cancel();
+        }
+    }
+
+    /// Waits until the given duration has elapsed, without blocking the task
+    /// polling the future.
+    pub fn sleep(dur: Duration) -> Sleep {
+        sleep_until(current_time() + dur)
+    }
+
+    /// Waits until the given deadline, without blocking the task polling the
+    /// future.
+    pub fn sleep_until(deadline: TimeValue) -> Sleep {
+        Sleep {
+            deadline,
+            alarm: None,
+        }
+    }
+}
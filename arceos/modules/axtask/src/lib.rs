@@ -1,8 +1,8 @@
 //! [ArceOS](https://github.com/rcore-os/arceos) task management module.
 //!
 //! This module provides primitives for task management, including task
-//! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
-//! is configurable by cargo features.
+//! creation, scheduling, sleeping, termination, signals, etc. The scheduler
+//! algorithm is configurable by cargo features.
 //!
 //! # Cargo Features
 //!
@@ -11,15 +11,30 @@
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    [`WaitQueue::wait_timeout`], and [`future::sleep`].
+//! - `tickless`: Program the timer for the next tick or timed event instead
+//!   of ticking periodically, and stop ticking while the CPU is idle. Sleeps
+//!   then expire shortly after their deadlines, see [`set_timer_slack`],
+//!   rather than on the next tick. It also enables the `irq` and `multitask`
+//!   features.
 //! - `preempt`: Enable preemptive scheduling.
+//! - `tls`: Give each task its own copy of the `#[thread_local]` statics,
+//!   which must not be accessed before the scheduler is initialized on the
+//!   CPU. The statics declared by [`task_local!`] don't need this feature.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default.
 //! - `sched_rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_cfs`: Use the [Completely fair scheduler][3]. It also enables the
+//!   `multitask` and `preempt` features if it is enabled.
+//! - `sched_rt`: Use the [multi-class scheduler][4] with deadline, real-time
+//!   and normal tasks, see [`set_sched_policy`]. It also enables the
+//!   `multitask` and `preempt` features if it is enabled.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
+//! [3]: scheduler::CFScheduler
+//! [4]: scheduler::RTScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -30,15 +45,24 @@ cfg_if::cfg_if! {
         #[macro_use]
         extern crate log;
         extern crate alloc;
+        mod pi;
         mod run_queue;
+        mod stats;
         mod task;
         mod wait_queue;
 
         #[cfg(feature = "irq")]
         mod timers;
+
+        pub mod future;
+        pub mod signal;
     }
 }
 
+// not in `cfg_if!`, so that `task_local!` can be used by its path in the crate
+#[cfg(feature = "multitask")]
+mod task_local;
+
 #[cfg_attr(not(feature = "multitask"), path = "api_s.rs")]
 mod api;
 
@@ -47,4 +71,4 @@ mod tests;
 
 #[doc(cfg(feature = "multitask"))]
 pub use self::api::*;
-pub use self::api::{sleep, sleep_until, yield_now};
+pub use self::api::{sleep, sleep_until, yield_interruptible, yield_now};
@@ -0,0 +1,216 @@
+//! Priority inheritance for sleeping locks.
+//!
+//! Priorities are nice values here, the lower the higher. While a task waits
+//! for a lock, the owner of the lock runs with the waiter's priority if it's
+//! higher than its own, and so does the owner of the lock that this owner is
+//! waiting for, and so on. A task runs with the highest of the priority it
+//! set with [`set_priority`](crate::set_priority) and the ones lent to it,
+//! until it releases the locks they were lent for. When the lock passes to
+//! another owner, the tasks still waiting for it lend their priority to the
+//! new owner.
+//!
+//! Real-time and deadline tasks (see [`set_sched_policy`]) already run before
+//! all normal tasks. They lend the highest nice value, and are never boosted.
+//!
+//! Locking: an owner lock is locked before a run queue, which is locked
+//! before a task's [`PiState`]. The next owner in a chain is locked while the
+//! previous [`PiState`] is held, with a `try_lock`.
+//!
+//! [`set_sched_policy`]: crate::set_sched_policy
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spinlock::SpinNoIrq;
+
+use crate::run_queue::update_priority;
+use crate::{current, AxTaskRef, TaskInner};
+
+/// The highest priority of a normal task.
+const MIN_NICE: isize = -20;
+
+/// The base priority of a task that isn't a normal task.
+pub(crate) const NOT_NORMAL: isize = isize::MIN;
+
+/// The most owners boosted by one waiter.
+const MAX_CHAIN_LEN: usize = 8;
+
+/// The priority inheritance state of a task.
+pub(crate) struct PiState {
+    /// The priorities lent to the task, with the address of the lock they
+    /// were lent for.
+    lent: Vec<(usize, isize)>,
+    /// The lock the task is waiting for.
+    blocked_on: Option<*const PiOwner>,
+}
+
+impl PiState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            lent: Vec::new(),
+            blocked_on: None,
+        }
+    }
+
+    /// The highest priority lent to the task.
+    pub(crate) fn lent_prio(&self) -> Option<isize> {
+        self.lent.iter().map(|&(_, prio)| prio).min()
+    }
+}
+
+/// The owner of a sleeping lock, which inherits the priority of the tasks
+/// waiting for the lock.
+///
+/// The lock calls [`boost`](Self::boost) before a task waits for it and keeps
+/// the returned [`PiWait`] until the wait ends, however it ends,
+/// [`set_current`](Self::set_current) once a task acquires it exclusively,
+/// and [`clear`](Self::clear) before the owner releases it.
+pub struct PiOwner {
+    owner: SpinNoIrq<Owner>,
+}
+
+struct Owner {
+    task: Option<AxTaskRef>,
+    /// The tasks waiting for the lock, which lend their priority to the next
+    /// owner too.
+    waiters: Vec<AxTaskRef>,
+}
+
+impl PiOwner {
+    /// Creates a new [`PiOwner`] without owner.
+    pub const fn new() -> Self {
+        Self {
+            owner: SpinNoIrq::new(Owner {
+                task: None,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    fn key(&self) -> usize {
+        self as *const _ as usize
+    }
+
+    /// Records the current task as the owner of the lock, and lends it the
+    /// priorities of the tasks waiting for the lock, including those that
+    /// started waiting while it had no owner.
+    pub fn set_current(&self) {
+        let curr = current();
+        curr.pi_state().lock().blocked_on = None;
+        let key = self.key();
+        let mut owner = self.owner.lock();
+        let mut boosted = false;
+        for waiter in &owner.waiters {
+            boosted |= lend_prio(&curr, key, lent_prio(waiter));
+        }
+        if boosted {
+            update_priority(curr.as_task_ref());
+        }
+        owner.task = Some(curr.clone());
+    }
+
+    /// Lends the priority of the current task to the owner of the lock, and
+    /// to the owners of the locks it's waiting for, before the current task
+    /// waits for the lock. The task waits for it until the returned guard is
+    /// dropped.
+    pub fn boost(&self) -> PiWait<'_> {
+        let curr = current();
+        let prio = lent_prio(&curr);
+        curr.pi_state().lock().blocked_on = Some(self);
+
+        let mut key = self.key();
+        let mut owner = self.owner.lock();
+        owner.waiters.push(curr.clone());
+        for _ in 0..MAX_CHAIN_LEN {
+            let Some(task) = owner.task.as_ref() else {
+                break;
+            };
+            if !lend_prio(task, key, prio) {
+                break;
+            }
+            update_priority(task);
+
+            let pi = task.pi_state().lock();
+            let Some(next) = pi.blocked_on else {
+                break;
+            };
+            // SAFETY: the task is still waiting for the lock, which can't be
+            // dropped before it stops, and it can't stop without its
+            // `PiState`.
+            let next = unsafe { &*next };
+            // the chain may loop back to a lock we hold
+            let Some(next_owner) = next.owner.try_lock() else {
+                break;
+            };
+            drop(pi);
+            owner = next_owner;
+            key = next.key();
+        }
+        PiWait { lock: self }
+    }
+
+    /// Takes back the priorities lent to the current task for the lock, before
+    /// it releases the lock.
+    pub fn clear(&self) {
+        let key = self.key();
+        let mut owner = self.owner.lock();
+        if let Some(task) = owner.task.take() {
+            let mut pi = task.pi_state().lock();
+            let len = pi.lent.len();
+            pi.lent.retain(|&(k, _)| k != key);
+            let changed = pi.lent.len() != len;
+            drop(pi);
+            if changed {
+                update_priority(&task);
+            }
+        }
+    }
+}
+
+/// The current task waiting for a lock, returned by [`PiOwner::boost`].
+///
+/// Other tasks follow the lock a task waits for to boost its owner. The task
+/// stops waiting for it when this is dropped, which the borrow keeps from
+/// happening after the lock is dropped.
+#[must_use = "the task stops waiting for the lock when this is dropped"]
+pub struct PiWait<'a> {
+    lock: &'a PiOwner,
+}
+
+impl Drop for PiWait<'_> {
+    fn drop(&mut self) {
+        let curr = current();
+        curr.pi_state().lock().blocked_on = None;
+        let mut owner = self.lock.owner.lock();
+        if let Some(i) = owner.waiters.iter().position(|t| Arc::ptr_eq(t, curr.as_task_ref())) {
+            owner.waiters.swap_remove(i);
+        }
+    }
+}
+
+impl Default for PiOwner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The priority `task` lends to the owners of the locks it waits for.
+fn lent_prio(task: &TaskInner) -> isize {
+    match task.effective_prio() {
+        NOT_NORMAL => MIN_NICE,
+        prio => prio,
+    }
+}
+
+/// Lends `prio` to `task` for the lock `key`. Returns `false` if it doesn't
+/// raise the priority of the task.
+fn lend_prio(task: &TaskInner, key: usize, prio: isize) -> bool {
+    if prio >= task.effective_prio() {
+        return false;
+    }
+    let mut pi = task.pi_state().lock();
+    match pi.lent.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, lent)) => *lent = prio.min(*lent),
+        None => pi.lent.push((key, prio)),
+    }
+    true
+}
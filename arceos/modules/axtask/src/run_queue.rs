@@ -1,14 +1,51 @@
+//! Per-CPU run queues.
+//!
+//! Each CPU schedules the tasks of its own run queue. A task is added to the
+//! queue of the least loaded CPU when it's spawned, and to the queue of the
+//! CPU it last ran on when it's woken up, as long as its CPU affinity allows.
+//! A CPU that runs out of tasks steals one from the busiest other CPU. In the
+//! `tickless` mode, the idle CPUs that stopped ticking are avoided.
+//!
+//! Locking: the lock of the current CPU's queue is held across a context
+//! switch, and is released by the next task, which may run on another CPU
+//! than the one it was switched out on. So [`CurrentRunQueueRef`] always
+//! releases the lock of the CPU it's dropped on. Other CPUs' queues are only
+//! locked with nothing else held, or with [`SpinRaw::try_lock`], so that two
+//! CPUs never wait for each other. A wait queue is locked after the run
+//! queue, never before.
+
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use kernel_guard::NoPreemptIrqSave;
 use lazy_init::LazyInit;
-use scheduler::BaseScheduler;
-use spinlock::SpinNoIrq;
+use scheduler::{BaseScheduler, SchedPolicy};
+use spinlock::{SpinNoIrq, SpinRaw, SpinRawGuard};
 
+use crate::pi::NOT_NORMAL;
 use crate::task::{CurrentTask, TaskState};
+#[cfg(feature = "tickless")]
+use crate::timers::idle_cpus;
 use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
 
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// A mask with the bits of all CPUs set.
+pub(crate) const ALL_CPUS: u64 = if axconfig::SMP >= 64 {
+    u64::MAX
+} else {
+    (1 << axconfig::SMP) - 1
+};
+
+#[percpu::def_percpu]
+static RUN_QUEUE: LazyInit<SpinRaw<AxRunQueue>> = LazyInit::new();
+
+/// Number of ready tasks in the run queue, read by other CPUs without the lock.
+#[percpu::def_percpu]
+static NR_READY: AtomicUsize = AtomicUsize::new(0);
+
+/// CPUs whose run queue is initialized.
+static ONLINE_CPUS: AtomicU64 = AtomicU64::new(0);
 
 // TODO: per-CPU
 static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
@@ -18,22 +55,247 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task switched out by the last context switch on this CPU.
+#[percpu::def_percpu]
+static PREV_TASK: usize = 0;
+
+/// A running task that is no longer allowed on this CPU, and is moved to
+/// another one after the next context switch.
+#[percpu::def_percpu]
+static MIGRATING_TASK: Option<AxTaskRef> = None;
+
 pub(crate) struct AxRunQueue {
+    cpu_id: usize,
     scheduler: Scheduler,
 }
 
+/// The locked run queue of the current CPU.
+///
+/// It's dropped on the CPU it's created on, unless the task is switched out
+/// in between. Then, dropping it releases the queue of the CPU the task was
+/// resumed on, which is locked by the task that switched to it.
+pub(crate) struct CurrentRunQueueRef {
+    guard: ManuallyDrop<SpinRawGuard<'static, AxRunQueue>>,
+    _irq_guard: NoPreemptIrqSave, // released after the lock
+}
+
+/// Locks the run queue of the current CPU.
+pub(crate) fn current_run_queue() -> CurrentRunQueueRef {
+    let irq_guard = NoPreemptIrqSave::new();
+    let guard = unsafe { RUN_QUEUE.current_ref_raw() }.lock();
+    CurrentRunQueueRef {
+        guard: ManuallyDrop::new(guard),
+        _irq_guard: irq_guard,
+    }
+}
+
+impl Deref for CurrentRunQueueRef {
+    type Target = AxRunQueue;
+    fn deref(&self) -> &AxRunQueue {
+        &self.guard
+    }
+}
+
+impl DerefMut for CurrentRunQueueRef {
+    fn deref_mut(&mut self) -> &mut AxRunQueue {
+        &mut self.guard
+    }
+}
+
+impl Drop for CurrentRunQueueRef {
+    fn drop(&mut self) {
+        unsafe { RUN_QUEUE.current_ref_raw().force_unlock() };
+        finish_migration();
+    }
+}
+
+/// Locks the run queue of the given CPU, and calls `f` on it. Nothing else
+/// must be locked, but the owner of a lock, see [`crate::pi`].
+fn with_run_queue<F, T>(cpu_id: usize, f: F) -> T
+where
+    F: FnOnce(&mut AxRunQueue) -> T,
+{
+    let _guard = NoPreemptIrqSave::new();
+    let mut rq = unsafe { RUN_QUEUE.remote_ref_raw(cpu_id) }.lock();
+    f(&mut rq)
+}
+
+fn nr_ready(cpu_id: usize) -> &'static AtomicUsize {
+    unsafe { NR_READY.remote_ref_raw(cpu_id) }
+}
+
+/// Whether tasks are queued on this CPU, checked by its idle task before it
+/// waits for interrupts.
+pub(crate) fn has_ready_tasks() -> bool {
+    nr_ready(axhal::cpu::this_cpu_id()).load(Ordering::SeqCst) > 0
+}
+
+fn online_cpus() -> u64 {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+fn cpus_of(mask: u64) -> impl Iterator<Item = usize> {
+    (0..axconfig::SMP).filter(move |&i| mask & (1 << i) != 0)
+}
+
+/// No CPU stops ticking when it's idle without the `tickless` feature.
+#[cfg(not(feature = "tickless"))]
+fn idle_cpus() -> u64 {
+    0
+}
+
+/// The CPUs `task` may be queued on. The idle CPUs that stopped ticking are
+/// left out if possible, as they only find the task on their next timer
+/// interrupt.
+fn allowed_cpus(task: &TaskInner) -> u64 {
+    let allowed = task.cpu_affinity() & online_cpus();
+    match allowed & !idle_cpus() {
+        0 => allowed,
+        awake => awake,
+    }
+}
+
+/// The CPU to wake `task` up on: the one it last ran on if it's allowed
+/// there, or else the first allowed one.
+fn select_cpu(task: &TaskInner) -> usize {
+    let allowed = allowed_cpus(task);
+    let cpu_id = task.cpu_id();
+    if allowed & (1 << cpu_id) != 0 {
+        cpu_id
+    } else {
+        cpus_of(allowed).next().unwrap_or_else(axhal::cpu::this_cpu_id)
+    }
+}
+
+/// The allowed CPU with the fewest ready tasks, to spawn `task` on. The
+/// current CPU wins ties.
+fn select_cpu_for_new(task: &TaskInner) -> usize {
+    let this_cpu = axhal::cpu::this_cpu_id();
+    cpus_of(allowed_cpus(task))
+        .min_by_key(|&cpu_id| (nr_ready(cpu_id).load(Ordering::Relaxed), cpu_id != this_cpu))
+        .unwrap_or(this_cpu)
+}
+
+/// Adds a new task to a run queue.
+pub(crate) fn spawn_task(task: AxTaskRef) {
+    let cpu_id = select_cpu_for_new(&task);
+    task.set_cpu_id(cpu_id);
+    with_run_queue(cpu_id, |rq| rq.add_task(task));
+}
+
+/// Makes a blocked task ready. Returns `false` if it isn't blocked, or has
+/// been woken up by someone else.
+///
+/// [`wake_task`] must be called after it returns `true`, which can be done
+/// without the lock that serializes the wakeup, e.g. of a wait queue.
+pub(crate) fn try_wake(task: &TaskInner) -> bool {
+    task.transition_state(TaskState::Blocked, TaskState::Ready)
+}
+
+/// Adds a task made ready by [`try_wake`] to a run queue. Nothing must be
+/// locked.
+///
+/// If `resched` is true, the current task will be preempted when the
+/// preemption is enabled and the task is woken up on the current CPU.
+pub(crate) fn wake_task(task: AxTaskRef, resched: bool) {
+    debug!("task unblock: {}", task.id_name());
+    // it may still be switching out on its last CPU
+    while task.on_cpu() {
+        core::hint::spin_loop();
+    }
+    task.counters()
+        .wake_up(axhal::time::current_time_nanos());
+    let cpu_id = select_cpu(&task);
+    task.set_cpu_id(cpu_id);
+    with_run_queue(cpu_id, |rq| rq.add_ready(task));
+    if resched && cpu_id == axhal::cpu::this_cpu_id() {
+        #[cfg(feature = "preempt")]
+        crate::current().set_preempt_pending(true);
+    }
+}
+
+/// Unblocks a task, see [`try_wake`] and [`wake_task`].
+#[cfg(feature = "irq")]
+pub(crate) fn unblock_task(task: AxTaskRef, resched: bool) {
+    if try_wake(&task) {
+        wake_task(task, resched);
+    }
+}
+
+/// Makes the scheduler of a task use the priority it runs with, after a
+/// priority was lent to it or taken back.
+pub(crate) fn update_priority(task: &AxTaskRef) {
+    // the task may be moved to another CPU in the meantime, which then uses
+    // its new priority
+    with_run_queue(task.cpu_id(), |rq| {
+        let prio = task.effective_prio();
+        if prio != NOT_NORMAL {
+            rq.scheduler.set_priority(task, prio);
+        }
+    });
+}
+
+/// Moves the task set aside by [`AxRunQueue::resched_inner`] to an allowed
+/// CPU, once it's switched out and the run queue is released.
+fn finish_migration() {
+    let task = unsafe { MIGRATING_TASK.current_ref_mut_raw() }.take();
+    if let Some(task) = task {
+        wake_task(task, false);
+    }
+}
+
+/// Called by the task that has just been switched to.
+fn finish_switch() {
+    let prev = unsafe { PREV_TASK.read_current_raw() } as *const TaskInner;
+    // after this, the previous task may run on another CPU or be dropped
+    unsafe { (*prev).set_on_cpu(false) };
+}
+
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc", axconfig::TASK_STACK_SIZE);
-        let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+    fn new(cpu_id: usize) -> SpinRaw<Self> {
+        SpinRaw::new(Self {
+            cpu_id,
+            scheduler: Scheduler::new(),
+        })
     }
 
     pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
+        debug!("task spawn: {} on CPU {}", task.id_name(), self.cpu_id);
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        self.add_ready(task);
+    }
+
+    fn add_ready(&mut self, task: AxTaskRef) {
+        self.scheduler.add_task(task); // TODO: priority
+        nr_ready(self.cpu_id).fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool) {
+        self.scheduler.put_prev_task(prev, preempt);
+        nr_ready(self.cpu_id).fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        let task = self.scheduler.pick_next_task()?;
+        nr_ready(self.cpu_id).fetch_sub(1, Ordering::Relaxed);
+        Some(task)
+    }
+
+    /// Takes a ready task allowed on this CPU from the busiest other CPU.
+    fn steal_task(&mut self) -> Option<AxTaskRef> {
+        let this_cpu = self.cpu_id;
+        let victim = cpus_of(online_cpus() & !(1 << this_cpu))
+            .max_by_key(|&cpu_id| nr_ready(cpu_id).load(Ordering::Relaxed))
+            .filter(|&cpu_id| nr_ready(cpu_id).load(Ordering::Relaxed) > 0)?;
+        // waiting for it could deadlock with a CPU stealing from this one
+        let mut rq = unsafe { RUN_QUEUE.remote_ref_raw(victim) }.try_lock()?;
+        let task = rq.scheduler.steal_task(|t| {
+            t.cpu_affinity() & (1 << this_cpu) != 0 && !t.on_cpu() && !t.is_idle()
+        })?;
+        nr_ready(victim).fetch_sub(1, Ordering::Relaxed);
+        debug!("task steal: {} from CPU {}", task.id_name(), victim);
+        task.set_cpu_id(this_cpu);
+        Some(task)
     }
 
     #[cfg(feature = "irq")]
@@ -57,13 +319,18 @@ impl AxRunQueue {
             .set_priority(crate::current().as_task_ref(), prio)
     }
 
+    pub fn set_policy(&mut self, policy: SchedPolicy) -> bool {
+        self.scheduler
+            .set_policy(crate::current().as_task_ref(), policy)
+    }
+
     #[cfg(feature = "preempt")]
     pub fn resched(&mut self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
         // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
+        // have locked it with both IRQs and preemption
         // disabled. So we need to set `current_disable_count` to 1 in
         // `can_preempt()` to obtain the preemption permission before
         //  locking the run queue.
@@ -81,20 +348,12 @@ impl AxRunQueue {
         }
     }
 
-    pub fn exit_current(&mut self, exit_code: i32) -> ! {
-        let curr = crate::current();
-        debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
-        assert!(curr.is_running());
-        assert!(!curr.is_idle());
-        if curr.is_init() {
-            EXITED_TASKS.lock().clear();
-            axhal::misc::terminate();
-        } else {
-            curr.set_state(TaskState::Exited);
-            EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
-            self.resched_inner(false);
-        }
+    /// Switches out the current task for good. It must have been marked as
+    /// exited, with preemption disabled since then.
+    pub fn exit_current(&mut self) -> ! {
+        // release the CPU bandwidth reserved for it
+        self.set_policy(SchedPolicy::Normal(0));
+        self.resched_inner(false);
         unreachable!("task exited!");
     }
 
@@ -113,19 +372,10 @@ impl AxRunQueue {
 
         curr.set_state(TaskState::Blocked);
         wait_queue_push(curr.clone());
-        self.resched_inner(false);
-    }
-
-    pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
-        debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
-            if resched {
-                #[cfg(feature = "preempt")]
-                crate::current().set_preempt_pending(true);
-            }
+        if crate::signal::cancel_block(&curr) {
+            return;
         }
+        self.resched_inner(false);
     }
 
     #[cfg(feature = "irq")]
@@ -137,8 +387,12 @@ impl AxRunQueue {
 
         let now = axhal::time::current_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            // blocked before the alarm is set, so that it can't be missed
             curr.set_state(TaskState::Blocked);
+            if crate::signal::cancel_block(&curr) {
+                return;
+            }
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
             self.resched_inner(false);
         }
     }
@@ -151,18 +405,24 @@ impl AxRunQueue {
         let prev = crate::current();
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
-            if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+            if prev.cpu_affinity() & (1 << self.cpu_id) == 0 {
+                // moved to an allowed CPU once it's switched out
+                unsafe { *MIGRATING_TASK.current_ref_mut_raw() = Some(prev.clone()) };
+            } else if !prev.is_idle() {
+                self.put_prev_task(prev.clone(), preempt);
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
-        self.switch_to(prev, next);
+        let next = self
+            .pick_next_task()
+            .or_else(|| self.steal_task())
+            .unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
+        self.switch_to(prev, next, preempt);
     }
 
-    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
+    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef, preempt: bool) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
@@ -175,6 +435,10 @@ impl AxRunQueue {
             return;
         }
 
+        let now = axhal::time::current_time_nanos();
+        prev_task.counters().switch_out(now, preempt);
+        next_task.counters().switch_in(now);
+
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
             let next_ctx_ptr = next_task.ctx_mut_ptr();
@@ -184,8 +448,11 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            next_task.set_on_cpu(true);
+            PREV_TASK.write_current_raw(&*prev_task as *const TaskInner as usize);
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
+            finish_switch();
         }
     }
 }
@@ -193,36 +460,86 @@ impl AxRunQueue {
 fn gc_entry() {
     loop {
         // Drop all exited tasks and recycle resources.
-        while !EXITED_TASKS.lock().is_empty() {
+        let n = EXITED_TASKS.lock().len();
+        for _ in 0..n {
             // Do not do the slow drops in the critical section.
             let task = EXITED_TASKS.lock().pop_front();
             if let Some(task) = task {
-                // wait for other threads to release the reference.
-                while Arc::strong_count(&task) > 1 {
-                    core::hint::spin_loop();
+                if Arc::strong_count(&task) == 1 && !task.on_cpu() {
+                    drop(task);
+                } else {
+                    // Still referenced, e.g. by a `JoinHandle`, or still
+                    // switching out. Try again after the next exit.
+                    EXITED_TASKS.lock().push_back(task);
                 }
-                drop(task);
             }
         }
         WAIT_FOR_EXIT.wait();
     }
 }
 
+/// Marks the current task as exited, with preemption disabled.
+pub(crate) fn exit_current(exit_code: i32) -> ! {
+    let curr = crate::current();
+    debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
+    assert!(curr.is_running());
+    assert!(!curr.is_idle());
+    if curr.is_init() {
+        EXITED_TASKS.lock().clear();
+        axhal::misc::terminate();
+    }
+    // the destructors run on the task itself, and may block
+    curr.locals().clear();
+    // it must not be switched out before it's done
+    let _guard = ManuallyDrop::new(kernel_guard::NoPreempt::new());
+    curr.notify_exit(exit_code);
+    EXITED_TASKS.lock().push_back(curr.clone());
+    WAIT_FOR_EXIT.notify_one(false);
+    current_run_queue().exit_current()
+}
+
 pub(crate) fn init() {
     const IDLE_TASK_STACK_SIZE: usize = 4096;
-    let idle_task = TaskInner::new(|| crate::run_idle(), "idle", IDLE_TASK_STACK_SIZE);
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let idle_task = TaskInner::new(
+        || crate::run_idle(),
+        "idle".into(),
+        IDLE_TASK_STACK_SIZE,
+        true,
+    );
+    idle_task.set_cpu_id(cpu_id);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
 
-    let main_task = TaskInner::new_init("main");
+    let main_task = TaskInner::new_init("main".into());
     main_task.set_state(TaskState::Running);
+    main_task.set_cpu_id(cpu_id);
+    main_task.set_on_cpu(true);
 
-    RUN_QUEUE.init_by(AxRunQueue::new());
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id)));
+    ONLINE_CPUS.fetch_or(1 << cpu_id, Ordering::Release);
     unsafe { CurrentTask::init_current(main_task) }
+
+    let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE, true);
+    spawn_task(gc_task);
 }
 
 pub(crate) fn init_secondary() {
-    let idle_task = TaskInner::new_init("idle");
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let idle_task = TaskInner::new_init("idle".into());
     idle_task.set_state(TaskState::Running);
+    idle_task.set_cpu_id(cpu_id);
+    idle_task.set_on_cpu(true);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
+
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id)));
+    ONLINE_CPUS.fetch_or(1 << cpu_id, Ordering::Release);
     unsafe { CurrentTask::init_current(idle_task) }
 }
+
+/// Called by a new task: finishes the context switch to it.
+pub(crate) fn finish_new_task() {
+    finish_switch();
+    // release the lock that was implicitly held across the reschedule
+    unsafe { RUN_QUEUE.current_ref_raw().force_unlock() };
+    finish_migration();
+}
@@ -0,0 +1,279 @@
+//! Signals, to interrupt and terminate tasks.
+//!
+//! A signal sent by [`kill`] is marked as pending in the task, and interrupts
+//! it only where it can be interrupted: in
+//! [`yield_interruptible`](crate::yield_interruptible),
+//! [`sleep_until_interruptible`](crate::sleep_until_interruptible), or an
+//! interruptible wait such as [`WaitQueue::wait_until_interruptible`]. If the
+//! task is blocked in an interruptible sleep or wait, it's woken up, and the
+//! sleep or wait returns [`AxError::Interrupted`]. Elsewhere, e.g., in
+//! [`yield_now`](crate::yield_now) or the wait of a mutex, the signal is not
+//! noticed.
+//!
+//! The signal stays pending until the task calls [`handle_pending`], as the
+//! action of a signal may exit the task, which doesn't unwind its stack. So the
+//! interrupted operation returns the error up to a point where the task holds
+//! no locks, and handles the signal there, e.g., the C bindings once `sleep`
+//! returns, or `pthread_testcancel`.
+//!
+//! Signals are not handled when a task is preempted either, as it may be in
+//! the middle of anything then. So a task that never reaches one of the
+//! points above, e.g., a loop that doesn't block, can't be stopped by a
+//! signal.
+//!
+//! Kernel service tasks, such as the idle tasks, can't be sent signals, see
+//! [`Builder::kernel_service`](crate::Builder::kernel_service).
+//!
+//! The action of a signal is the same for all tasks, and is set by
+//! [`set_action`]. By default, a task exits with the code `128 + sig` when it
+//! handles a signal, unless the signal is ignored by default, such as
+//! [`SIGCHLD`].
+//!
+//! [`WaitQueue::wait_until_interruptible`]: crate::WaitQueue::wait_until_interruptible
+
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+use axerrno::{AxError, AxResult};
+use spinlock::SpinNoIrq;
+
+use crate::run_queue::{try_wake, wake_task};
+use crate::stats::find_task;
+use crate::task::{TaskInner, TaskState};
+
+/// The number of signals, the valid signal numbers are `1..NSIG`.
+pub const NSIG: usize = 64;
+
+/// Hangup.
+pub const SIGHUP: usize = 1;
+/// Interrupt, e.g., by Ctrl-C.
+pub const SIGINT: usize = 2;
+/// Quit.
+pub const SIGQUIT: usize = 3;
+/// Abort.
+pub const SIGABRT: usize = 6;
+/// Kill, whose action can't be changed.
+pub const SIGKILL: usize = 9;
+/// User-defined signal 1.
+pub const SIGUSR1: usize = 10;
+/// User-defined signal 2.
+pub const SIGUSR2: usize = 12;
+/// Broken pipe.
+pub const SIGPIPE: usize = 13;
+/// Alarm clock.
+pub const SIGALRM: usize = 14;
+/// Termination.
+pub const SIGTERM: usize = 15;
+/// Child stopped or exited, ignored by default.
+pub const SIGCHLD: usize = 17;
+/// Continue, ignored by default.
+pub const SIGCONT: usize = 18;
+/// Urgent data on a socket, ignored by default.
+pub const SIGURG: usize = 23;
+/// Window size changed, ignored by default.
+pub const SIGWINCH: usize = 28;
+/// Thread cancellation, e.g., by `pthread_cancel`.
+pub const SIGCANCEL: usize = 32;
+
+/// What a task does when it handles a signal.
+#[derive(Debug, Clone, Copy)]
+pub enum SigAction {
+    /// Exits with the code `128 + sig`, or ignores the signal if it's ignored
+    /// by default.
+    Default,
+    /// Ignores the signal.
+    Ignore,
+    /// Calls the handler with the signal number.
+    Handler(fn(usize)),
+}
+
+static ACTIONS: SpinNoIrq<[SigAction; NSIG]> = SpinNoIrq::new([SigAction::Default; NSIG]);
+
+fn check_signal(sig: usize) -> AxResult {
+    if sig == 0 || sig >= NSIG {
+        return Err(AxError::InvalidInput);
+    }
+    Ok(())
+}
+
+fn is_ignored(sig: usize, action: SigAction) -> bool {
+    match action {
+        SigAction::Default => matches!(sig, SIGCHLD | SIGCONT | SIGURG | SIGWINCH),
+        SigAction::Ignore => true,
+        SigAction::Handler(_) => false,
+    }
+}
+
+/// Sets the action of a signal for all tasks, and returns the old one.
+///
+/// Returns [`Err(InvalidInput)`](AxError::InvalidInput) if the signal number
+/// is invalid, or is [`SIGKILL`].
+pub fn set_action(sig: usize, action: SigAction) -> AxResult<SigAction> {
+    check_signal(sig)?;
+    if sig == SIGKILL {
+        return Err(AxError::InvalidInput);
+    }
+    Ok(core::mem::replace(&mut ACTIONS.lock()[sig], action))
+}
+
+/// Sends a signal to the task of the given ID.
+///
+/// The signal is discarded if it's ignored. Returns
+/// [`Err(NotFound)`](AxError::NotFound) if there's no such task,
+/// [`Err(InvalidInput)`](AxError::InvalidInput) if the signal number is
+/// invalid, or [`Err(PermissionDenied)`](AxError::PermissionDenied) for a
+/// kernel service task.
+///
+/// # Examples
+///
+/// ```
+/// use axtask::signal::{self, SIGTERM};
+///
+/// axtask::init_scheduler();
+/// let handle = axtask::spawn(|| loop {
+///     if axtask::yield_interruptible().is_err() {
+///         signal::handle_pending();
+///     }
+/// });
+/// signal::kill(handle.id().as_u64(), SIGTERM).unwrap();
+/// assert_eq!(handle.join(), 128 + SIGTERM as i32);
+/// ```
+pub fn kill(task_id: u64, sig: usize) -> AxResult {
+    check_signal(sig)?;
+    let task = find_task(task_id).ok_or(AxError::NotFound)?;
+    if task.is_service() {
+        return Err(AxError::PermissionDenied);
+    }
+    if is_ignored(sig, ACTIONS.lock()[sig]) {
+        return Ok(());
+    }
+    debug!("task kill: {}, sig={}", task.id_name(), sig);
+    if TaskSignals::send(&task, sig) {
+        wake_task(task, true);
+    }
+    Ok(())
+}
+
+/// Sends a signal to the current task, and handles it before returning.
+///
+/// Like [`handle_pending`], it must not be called with any lock held.
+pub fn raise(sig: usize) -> AxResult {
+    kill(crate::current().id().as_u64(), sig)?;
+    handle_pending();
+    Ok(())
+}
+
+/// Handles the signals pending on the current task, and returns whether one
+/// of them was not ignored.
+///
+/// It's called once an interruptible operation returns
+/// [`AxError::Interrupted`], or by a task that doesn't block, to handle the
+/// signals sent to it. It must not be called with any lock held, as the task
+/// exits without unwinding if a signal's action is the default one, or if the
+/// handler exits it.
+pub fn handle_pending() -> bool {
+    let curr = crate::current();
+    let mut handled = false;
+    while let Some(sig) = curr.signals().take_one() {
+        let action = ACTIONS.lock()[sig];
+        if is_ignored(sig, action) {
+            continue;
+        }
+        debug!("task handle signal: {}, sig={}", curr.id_name(), sig);
+        handled = true;
+        match action {
+            SigAction::Handler(handler) => handler(sig),
+            _ => crate::exit(128 + sig as i32),
+        }
+    }
+    handled
+}
+
+/// Returns whether a signal that's not ignored is pending on the current task,
+/// and discards the ignored ones, whose action was changed after they were
+/// sent. The signals are left pending for [`handle_pending`].
+pub(crate) fn interrupted() -> bool {
+    let curr = crate::current();
+    let signals = curr.signals();
+    let actions = ACTIONS.lock();
+    let mut pending = signals.pending.load(Ordering::Acquire);
+    while pending != 0 {
+        let sig = pending.trailing_zeros() as usize;
+        if is_ignored(sig, actions[sig]) {
+            signals.pending.fetch_and(!(1 << sig), Ordering::AcqRel);
+        }
+        pending &= !(1 << sig);
+    }
+    signals.has_pending()
+}
+
+/// Runs `f`, in which the current task can be woken up by a signal when it's
+/// blocked. `f` must block it only once, or in a loop that ends when a signal
+/// is pending.
+pub(crate) fn interruptible<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let curr = crate::current();
+    *curr.signals().interruptible.lock() = true;
+    let ret = f();
+    *curr.signals().interruptible.lock() = false;
+    ret
+}
+
+/// Called by the current task right after it's marked as blocked. Returns
+/// `true` if it's running again, as it's interruptible and a signal sent
+/// before could not wake it up.
+pub(crate) fn cancel_block(curr: &TaskInner) -> bool {
+    if !*curr.signals().interruptible.lock() {
+        return false;
+    }
+    // pairs with the fence in `TaskSignals::send`: either the signal is seen
+    // here, or the blocked state is seen there
+    fence(Ordering::SeqCst);
+    curr.signals().has_pending() && curr.transition_state(TaskState::Blocked, TaskState::Running)
+}
+
+/// The signals of a task.
+pub(crate) struct TaskSignals {
+    /// The bit `sig` is set if the signal is pending.
+    pending: AtomicU64,
+    /// Whether the task can be woken up by a signal, see [`interruptible`].
+    interruptible: SpinNoIrq<bool>,
+}
+
+impl TaskSignals {
+    pub const fn new() -> Self {
+        Self {
+            pending: AtomicU64::new(0),
+            interruptible: SpinNoIrq::new(false),
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire) != 0
+    }
+
+    /// Marks a signal as pending in `task`, and makes the task ready if it's
+    /// blocked and interruptible. [`wake_task`] must be called if it
+    /// returns `true`.
+    fn send(task: &TaskInner, sig: usize) -> bool {
+        let signals = task.signals();
+        signals.pending.fetch_or(1 << sig, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        // not woken up once it's no longer interruptible, whatever it's
+        // blocked on then
+        let interruptible = signals.interruptible.lock();
+        *interruptible && try_wake(task)
+    }
+
+    /// Takes the pending signal with the lowest number.
+    fn take_one(&self) -> Option<usize> {
+        let pending = self.pending.load(Ordering::Acquire);
+        if pending == 0 {
+            return None;
+        }
+        let sig = pending.trailing_zeros() as usize;
+        self.pending.fetch_and(!(1 << sig), Ordering::AcqRel);
+        Some(sig)
+    }
+}
@@ -0,0 +1,182 @@
+//! CPU accounting of tasks.
+//!
+//! Every task counts the timer ticks it's interrupted by, its context
+//! switches, and the time it spends running and blocked. The counters are
+//! updated by the run queue when it switches tasks, and read with
+//! [`tasks`], which iterates over all the tasks alive.
+
+use alloc::{collections::BTreeMap, string::String, sync::Weak, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::current_time_nanos;
+use spinlock::SpinNoIrq;
+
+use crate::task::TaskState;
+use crate::{AxTask, AxTaskRef, TaskId};
+
+/// All the tasks alive, by ID.
+static TASKS: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// The CPU accounting of a task, see [`TaskInfo`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskStats {
+    /// Timer ticks that interrupted the task in the user mode.
+    pub user_ticks: u64,
+    /// Timer ticks that interrupted the task in the kernel mode.
+    pub kernel_ticks: u64,
+    /// Times the task gave up the CPU, by blocking, yielding or exiting.
+    pub voluntary_switches: u64,
+    /// Times the task was preempted.
+    pub involuntary_switches: u64,
+    /// Time spent running on a CPU.
+    pub run_time: Duration,
+    /// Time spent blocked, e.g., sleeping or waiting on a wait queue.
+    pub blocked_time: Duration,
+}
+
+/// A snapshot of a task, returned by [`tasks`].
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    /// The ID of the task.
+    pub id: TaskId,
+    /// The name of the task.
+    pub name: String,
+    /// The state of the task.
+    pub state: TaskState,
+    /// The CPU the task runs on, or last ran on.
+    pub cpu_id: usize,
+    /// The CPU accounting of the task.
+    pub stats: TaskStats,
+}
+
+/// The counters of [`TaskStats`] kept in a task.
+pub(crate) struct TaskCounters {
+    user_ticks: AtomicU64,
+    kernel_ticks: AtomicU64,
+    voluntary_switches: AtomicU64,
+    involuntary_switches: AtomicU64,
+    run_nanos: AtomicU64,
+    blocked_nanos: AtomicU64,
+    /// When the task was last switched in, or blocked.
+    since: AtomicU64,
+}
+
+impl TaskCounters {
+    pub const fn new() -> Self {
+        Self {
+            user_ticks: AtomicU64::new(0),
+            kernel_ticks: AtomicU64::new(0),
+            voluntary_switches: AtomicU64::new(0),
+            involuntary_switches: AtomicU64::new(0),
+            run_nanos: AtomicU64::new(0),
+            blocked_nanos: AtomicU64::new(0),
+            since: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a timer tick that interrupted the task.
+    #[cfg(feature = "irq")]
+    pub fn tick(&self, from_user: bool) {
+        if from_user {
+            self.user_ticks.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.kernel_ticks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The task starts running at `now`.
+    pub fn switch_in(&self, now: u64) {
+        self.since.store(now, Ordering::Relaxed);
+    }
+
+    /// The task stops running at `now`, it's preempted if `preempt`.
+    pub fn switch_out(&self, now: u64, preempt: bool) {
+        // also the time it's blocked since, if it is
+        let since = self.since.swap(now, Ordering::Relaxed);
+        self.run_nanos
+            .fetch_add(now.saturating_sub(since), Ordering::Relaxed);
+        if preempt {
+            self.involuntary_switches.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The task blocked since the last [`switch_out`](Self::switch_out) is
+    /// woken up at `now`.
+    pub fn wake_up(&self, now: u64) {
+        let since = self.since.load(Ordering::Relaxed);
+        self.blocked_nanos
+            .fetch_add(now.saturating_sub(since), Ordering::Relaxed);
+    }
+
+    /// Reads the counters at `now`, the run time includes the time since the
+    /// task was switched in if it's `running`.
+    fn read(&self, now: u64, running: bool) -> TaskStats {
+        let mut run_nanos = self.run_nanos.load(Ordering::Relaxed);
+        if running {
+            run_nanos += now.saturating_sub(self.since.load(Ordering::Relaxed));
+        }
+        TaskStats {
+            user_ticks: self.user_ticks.load(Ordering::Relaxed),
+            kernel_ticks: self.kernel_ticks.load(Ordering::Relaxed),
+            voluntary_switches: self.voluntary_switches.load(Ordering::Relaxed),
+            involuntary_switches: self.involuntary_switches.load(Ordering::Relaxed),
+            run_time: Duration::from_nanos(run_nanos),
+            blocked_time: Duration::from_nanos(self.blocked_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Adds a new task to the list of [`tasks`].
+pub(crate) fn register_task(task: &AxTaskRef) {
+    TASKS
+        .lock()
+        .insert(task.id().as_u64(), AxTaskRef::downgrade(task));
+}
+
+/// Removes a task being dropped from the list of [`tasks`].
+pub(crate) fn unregister_task(id: TaskId) {
+    TASKS.lock().remove(&id.as_u64());
+}
+
+/// Finds the task alive of the given ID.
+pub(crate) fn find_task(id: u64) -> Option<AxTaskRef> {
+    TASKS.lock().get(&id).and_then(Weak::upgrade)
+}
+
+/// Returns the snapshots of all the tasks alive, including the exited ones
+/// not recycled yet, ordered by ID.
+///
+/// # Examples
+///
+/// ```
+/// axtask::init_scheduler();
+/// let handle = axtask::spawn(|| axtask::yield_now());
+/// assert!(axtask::tasks().any(|t| t.id == handle.id()));
+/// assert_eq!(handle.join(), 0);
+/// ```
+pub fn tasks() -> impl Iterator<Item = TaskInfo> {
+    let tasks = TASKS
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>();
+    let now = current_time_nanos();
+    // taken now, not to keep the exited tasks from being recycled
+    tasks
+        .into_iter()
+        .map(|task| {
+            let state = task.state();
+            TaskInfo {
+                id: task.id(),
+                name: String::from(task.name()),
+                state,
+                cpu_id: task.cpu_id(),
+                stats: task.counters().read(now, state == TaskState::Running),
+            }
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
@@ -1,15 +1,22 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicIsize, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 use axhal::arch::TaskContext;
+#[cfg(feature = "tls")]
+use axhal::tls::TlsArea;
 use memory_addr::{align_up_4k, VirtAddr};
+use spinlock::SpinNoIrq;
 
-use crate::{AxTask, AxTaskRef};
+use crate::pi::{PiState, NOT_NORMAL};
+use crate::run_queue::ALL_CPUS;
+use crate::signal::TaskSignals;
+use crate::stats::{register_task, unregister_task, TaskCounters};
+use crate::task_local::TaskLocals;
+use crate::{AxTask, AxTaskRef, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -18,27 +25,55 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// Running on a CPU.
     Running = 1,
+    /// Waiting in a run queue to run.
     Ready = 2,
+    /// Waiting for something to happen, e.g., a timer or a notification.
     Blocked = 3,
+    /// Exited, but not recycled yet.
     Exited = 4,
 }
 
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
-    name: &'static str,
+    name: String,
     is_idle: bool,
     is_init: bool,
+    /// Whether it's a kernel service task, e.g., the idle task, which can't
+    /// be sent signals.
+    is_service: bool,
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
+    exit_code: AtomicI32,
+    wait_for_exit: WaitQueue,
 
     in_wait_queue: AtomicBool,
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// Whether the task is running, or still being switched out.
+    on_cpu: AtomicBool,
+    /// The CPU the task runs on, or last ran on.
+    cpu_id: AtomicUsize,
+    /// The CPUs the task may run on, one bit per CPU.
+    cpu_mask: AtomicU64,
+
+    /// The nice value set by `set_priority`, or `NOT_NORMAL`.
+    base_prio: AtomicIsize,
+    /// The priorities lent by priority inheritance.
+    pi: SpinNoIrq<PiState>,
+
+    /// The CPU accounting.
+    counters: TaskCounters,
+    /// The values of the task-local statics.
+    locals: TaskLocals,
+    /// The pending signals.
+    signals: TaskSignals,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
@@ -46,6 +81,9 @@ pub struct TaskInner {
 
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
+
+    #[cfg(feature = "tls")]
+    tls: TlsArea,
 }
 
 impl TaskId {
@@ -83,62 +121,99 @@ impl TaskInner {
     }
 
     /// Gets the name of the task.
-    pub const fn name(&self) -> &str {
-        self.name
+    pub fn name(&self) -> &str {
+        self.name.as_str()
     }
 
     /// Get a combined string of the task ID and name.
     pub fn id_name(&self) -> alloc::string::String {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
     }
+
+    /// Gets the ID of the CPU the task runs on, or last ran on.
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    /// Gets the CPU affinity of the task, the bit `i` is set if it may run
+    /// on the CPU `i`.
+    pub fn cpu_affinity(&self) -> u64 {
+        self.cpu_mask.load(Ordering::Acquire)
+    }
 }
 
 // private methods
 impl TaskInner {
-    const fn new_common(id: TaskId, name: &'static str) -> Self {
+    fn new_common(id: TaskId, name: String) -> Self {
         Self {
             id,
             name,
             is_idle: false,
             is_init: false,
+            is_service: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            exit_code: AtomicI32::new(0),
+            wait_for_exit: WaitQueue::new(),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            on_cpu: AtomicBool::new(false),
+            cpu_id: AtomicUsize::new(0),
+            cpu_mask: AtomicU64::new(ALL_CPUS),
+            base_prio: AtomicIsize::new(0),
+            pi: SpinNoIrq::new(PiState::new()),
+            counters: TaskCounters::new(),
+            locals: TaskLocals::new(),
+            signals: TaskSignals::new(),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
+            #[cfg(feature = "tls")]
+            tls: TlsArea::alloc(),
         }
     }
 
-    pub(crate) fn new<F>(entry: F, name: &'static str, stack_size: usize) -> AxTaskRef
+    pub(crate) fn new<F>(entry: F, name: String, stack_size: usize, is_service: bool) -> AxTaskRef
     where
         F: FnOnce() + Send + 'static,
     {
+        let is_idle = name == "idle";
         let mut t = Self::new_common(TaskId::new(), name);
         debug!("new task: {}", t.id_name());
         let kstack = TaskStack::alloc(align_up_4k(stack_size));
         t.entry = Some(Box::into_raw(Box::new(entry)));
-        t.ctx.get_mut().init(task_entry as usize, kstack.top());
+        #[cfg(feature = "tls")]
+        let tls = t.tls.tls_ptr();
+        #[cfg(not(feature = "tls"))]
+        let tls = VirtAddr::from(0);
+        t.ctx.get_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
-        if name == "idle" {
-            t.is_idle = true;
-        }
-        Arc::new(AxTask::new(t))
+        t.is_idle = is_idle;
+        t.is_service = is_service || is_idle;
+        let task = Arc::new(AxTask::new(t));
+        register_task(&task);
+        task
     }
 
-    pub(crate) fn new_init(name: &'static str) -> AxTaskRef {
+    pub(crate) fn new_init(name: String) -> AxTaskRef {
         // init_task does not change PC and SP, so `entry` and `kstack` fields are not used.
+        let is_idle = name == "idle";
         let mut t = Self::new_common(TaskId::new(), name);
         t.is_init = true;
-        if name == "idle" {
-            t.is_idle = true;
-        }
-        Arc::new(AxTask::new(t))
+        t.is_idle = is_idle;
+        t.is_service = is_idle;
+        // it's already running on the current CPU
+        #[cfg(feature = "tls")]
+        unsafe {
+            axhal::arch::write_thread_pointer(t.tls.tls_ptr().as_usize())
+        };
+        let task = Arc::new(AxTask::new(t));
+        register_task(&task);
+        task
     }
 
     #[inline]
@@ -151,6 +226,29 @@ impl TaskInner {
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Changes the state from `current` to `new`, returns `false` if it
+    /// isn't `current`.
+    #[inline]
+    pub(crate) fn transition_state(&self, current: TaskState, new: TaskState) -> bool {
+        self.state
+            .compare_exchange(current as u8, new as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    #[inline]
+    pub(crate) fn is_exited(&self) -> bool {
+        matches!(self.state(), TaskState::Exited)
+    }
+
+    /// Records the exit code, sets the state to exited and wakes up the tasks
+    /// joining this one. The code is stored first, so that whoever sees the
+    /// task exited reads it.
+    pub(crate) fn notify_exit(&self, exit_code: i32) {
+        self.exit_code.store(exit_code, Ordering::Release);
+        self.set_state(TaskState::Exited);
+        self.wait_for_exit.notify_all(false);
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -161,11 +259,6 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
-    #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
-    }
-
     #[inline]
     pub(crate) const fn is_init(&self) -> bool {
         self.is_init
@@ -176,6 +269,11 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    pub(crate) const fn is_service(&self) -> bool {
+        self.is_service
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -198,6 +296,66 @@ impl TaskInner {
         self.in_timer_list.store(in_timer_list, Ordering::Release);
     }
 
+    #[inline]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_affinity(&self, mask: u64) {
+        self.cpu_mask.store(mask, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn base_prio(&self) -> isize {
+        self.base_prio.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_base_prio(&self, prio: isize) {
+        self.base_prio.store(prio, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn pi_state(&self) -> &SpinNoIrq<PiState> {
+        &self.pi
+    }
+
+    #[inline]
+    pub(crate) fn counters(&self) -> &TaskCounters {
+        &self.counters
+    }
+
+    #[inline]
+    pub(crate) fn locals(&self) -> &TaskLocals {
+        &self.locals
+    }
+
+    #[inline]
+    pub(crate) fn signals(&self) -> &TaskSignals {
+        &self.signals
+    }
+
+    /// The priority the task runs with: the highest of its own and the ones
+    /// lent to it. `NOT_NORMAL` for a real-time or deadline task.
+    pub(crate) fn effective_prio(&self) -> isize {
+        let base = self.base_prio();
+        if base == NOT_NORMAL {
+            return NOT_NORMAL;
+        }
+        self.pi.lock().lent_prio().map_or(base, |prio| prio.min(base))
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn set_preempt_pending(&self, pending: bool) {
@@ -229,7 +387,7 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let mut rq = crate::run_queue::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.resched();
             }
@@ -255,6 +413,7 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        unregister_task(self.id);
     }
 }
 
@@ -283,6 +442,68 @@ impl Drop for TaskStack {
     }
 }
 
+/// An owned permission to join on a task (block on its termination).
+///
+/// It is returned by [`spawn`](crate::spawn) and
+/// [`Builder::spawn`](crate::Builder::spawn). Dropping the handle detaches
+/// the task, which keeps running and is recycled once it exits.
+pub struct JoinHandle {
+    task: AxTaskRef,
+}
+
+impl JoinHandle {
+    pub(crate) const fn new(task: AxTaskRef) -> Self {
+        Self { task }
+    }
+
+    /// Gets the ID of the task.
+    pub fn id(&self) -> TaskId {
+        self.task.id()
+    }
+
+    /// Gets the name of the task.
+    pub fn name(&self) -> &str {
+        self.task.name()
+    }
+
+    /// Whether the task has exited.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_exited()
+    }
+
+    /// Waits for the task to exit, and returns its exit code.
+    pub fn join(self) -> i32 {
+        self.task.wait_for_exit.wait_until(|| self.task.is_exited());
+        self.task.exit_code.load(Ordering::Acquire)
+    }
+
+    /// Waits for the task to exit for at most the given duration.
+    ///
+    /// Returns the exit code of the task, or [`None`] on timeout, in which
+    /// case the task can still be joined later.
+    #[cfg(feature = "irq")]
+    pub fn join_timeout(&self, dur: core::time::Duration) -> Option<i32> {
+        if !self.task.is_exited() {
+            self.task
+                .wait_for_exit
+                .wait_timeout_until(dur, || self.task.is_exited());
+        }
+        if self.task.is_exited() {
+            Some(self.task.exit_code.load(Ordering::Acquire))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Debug for JoinHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("task", &self.task.id_name())
+            .finish()
+    }
+}
+
 use core::mem::ManuallyDrop;
 
 /// A wrapper of [`AxTaskRef`] as the current task.
@@ -335,8 +556,7 @@ impl Deref for CurrentTask {
 }
 
 extern "C" fn task_entry() -> ! {
-    // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    crate::run_queue::finish_new_task();
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
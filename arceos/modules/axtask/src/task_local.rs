@@ -0,0 +1,112 @@
+//! Task-local storage.
+//!
+//! Each task keeps the values of the task-local statics it accessed in its
+//! [`TaskLocals`], keyed by the address of the statics. A value is initialized
+//! on the first access in the task, and dropped when the task exits.
+
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::any::Any;
+use core::fmt;
+
+use spinlock::SpinNoIrq;
+
+/// Declares task-local statics, each of which is a [`LocalKey`].
+///
+/// Each task has its own value of the static, initialized by the given
+/// expression when the task accesses it the first time, and dropped when the
+/// task exits.
+///
+/// # Examples
+///
+/// ```
+/// use core::cell::Cell;
+///
+/// axtask::task_local! {
+///     static COUNTER: Cell<u32> = Cell::new(0);
+/// }
+///
+/// axtask::init_scheduler();
+/// COUNTER.with(|c| c.set(c.get() + 1));
+/// axtask::spawn(|| COUNTER.with(|c| assert_eq!(c.get(), 0))).join();
+/// COUNTER.with(|c| assert_eq!(c.get(), 1));
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::LocalKey<$t> = {
+            fn __init() -> $t {
+                $init
+            }
+            $crate::LocalKey::new(__init)
+        };
+        $crate::task_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $crate::task_local!($(#[$attr])* $vis static $name: $t = $init;);
+    };
+}
+
+/// A key to the task-local value, declared by [`task_local!`].
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self { init }
+    }
+
+    /// Calls `f` with a reference to the value of the current task, which is
+    /// initialized first if it's not yet.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let curr = crate::current();
+        let value = curr
+            .locals()
+            .get_or_init(self as *const Self as usize, || Box::new((self.init)()));
+        // the value lives until the task exits
+        f(unsafe { &*value }.downcast_ref().unwrap())
+    }
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+/// The task-local values of a task, by the address of their [`LocalKey`].
+pub(crate) struct TaskLocals(SpinNoIrq<BTreeMap<usize, Box<dyn Any>>>);
+
+impl TaskLocals {
+    pub const fn new() -> Self {
+        Self(SpinNoIrq::new(BTreeMap::new()))
+    }
+
+    /// Returns the value of `key`, which is inserted by `init` if absent.
+    fn get_or_init(&self, key: usize, init: impl FnOnce() -> Box<dyn Any>) -> *const dyn Any {
+        if let Some(value) = self.0.lock().get(&key) {
+            return &**value;
+        }
+        // not under the lock, as `init` may access other task-local values
+        let value = init();
+        &**self.0.lock().entry(key).or_insert(value)
+    }
+
+    /// Drops all the values, including those initialized by the destructors
+    /// of the others. It's called by the task itself when it exits.
+    pub fn clear(&self) {
+        loop {
+            let values = core::mem::take(&mut *self.0.lock());
+            if values.is_empty() {
+                break;
+            }
+            drop(values);
+        }
+    }
+}
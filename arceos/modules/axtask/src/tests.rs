@@ -1,7 +1,10 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Mutex, Once};
 
-use crate::{self as axtask, current, WaitQueue};
+use crate::{
+    self as axtask, current, Builder, DeadlineParams, PiOwner, SchedPolicy, TaskState,
+    WaitQueue,
+};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -99,3 +102,526 @@ fn test_wait_queue() {
     assert_eq!(COUNTER.load(Ordering::Relaxed), 0);
     assert!(!current().in_wait_queue());
 }
+
+#[test]
+fn test_join() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_TASKS: usize = 10;
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+    let handles: Vec<_> = (0..NUM_TASKS)
+        .map(|i| {
+            Builder::new()
+                .name(format!("worker-{}", i))
+                .stack_size(0x4000)
+                .spawn(move || {
+                    assert_eq!(current().name(), format!("worker-{}", i));
+                    STARTED.fetch_add(1, Ordering::Relaxed);
+                    axtask::yield_now();
+                    if i % 2 == 1 {
+                        axtask::exit(i as i32);
+                    }
+                })
+        })
+        .collect();
+    assert!(handles.iter().all(|h| !h.is_finished()));
+
+    // join in reverse order, the tasks exit in spawn order
+    for (i, handle) in handles.into_iter().enumerate().rev() {
+        assert_eq!(handle.name(), format!("worker-{}", i));
+        let code = handle.join();
+        assert_eq!(code, if i % 2 == 1 { i as i32 } else { 0 });
+    }
+    assert_eq!(STARTED.load(Ordering::Relaxed), NUM_TASKS);
+
+    // joining a task that has already exited returns at once
+    let handle = axtask::spawn(|| axtask::exit(-1));
+    while !handle.is_finished() {
+        axtask::yield_now();
+    }
+    assert_eq!(handle.join(), -1);
+}
+
+#[test]
+fn test_cpu_affinity() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // there is a single CPU in tests
+    assert_eq!(current().cpu_id(), 0);
+    assert_eq!(current().cpu_affinity(), 1);
+    assert!(!axtask::set_cpu_affinity(0));
+    assert!(!axtask::set_cpu_affinity(0b10));
+    assert!(axtask::set_cpu_affinity(u64::MAX));
+    assert_eq!(current().cpu_affinity(), 1);
+
+    let handle = Builder::new().cpu_affinity(0b11).spawn(|| {
+        assert_eq!(current().cpu_affinity(), 1);
+        axtask::yield_now();
+        axtask::exit(current().cpu_id() as i32);
+    });
+    assert_eq!(handle.join(), 0);
+
+    // no CPU that exists, may run anywhere
+    let handle = Builder::new()
+        .cpu_affinity(0b10)
+        .spawn(|| assert_eq!(current().cpu_affinity(), 1));
+    assert_eq!(handle.join(), 0);
+}
+
+#[test]
+fn test_sched_policy() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // only normal tasks without the `sched_rt` scheduler
+    let handle = axtask::spawn(|| {
+        let rt = cfg!(feature = "sched_rt");
+        assert!(axtask::set_sched_policy(SchedPolicy::Normal(0)));
+        assert_eq!(axtask::set_sched_policy(SchedPolicy::Fifo(10)), rt);
+        let params = DeadlineParams {
+            runtime: 1,
+            deadline: 10,
+            period: 10,
+        };
+        assert_eq!(axtask::set_sched_policy(SchedPolicy::Deadline(params)), rt);
+        assert!(!axtask::set_sched_policy(SchedPolicy::Normal(20)));
+    });
+    assert_eq!(handle.join(), 0);
+}
+
+#[test]
+fn test_priority_inheritance() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static LOCK_A: PiOwner = PiOwner::new();
+    static LOCK_B: PiOwner = PiOwner::new();
+    static WQ: WaitQueue = WaitQueue::new();
+    static STAGE: AtomicUsize = AtomicUsize::new(0);
+
+    // the main task holds B, `owner` holds A and waits for B, `waiter` waits for A
+    LOCK_B.set_current();
+    let owner = axtask::spawn(|| {
+        LOCK_A.set_current();
+        let wait = LOCK_B.boost();
+        STAGE.store(1, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 3);
+        drop(wait);
+        LOCK_B.set_current();
+        LOCK_B.clear();
+        assert_eq!(current().effective_prio(), -10);
+        LOCK_A.clear();
+        assert_eq!(current().effective_prio(), 0);
+    });
+    while STAGE.load(Ordering::Acquire) < 1 {
+        axtask::yield_now();
+    }
+    assert_eq!(current().effective_prio(), 0);
+
+    let waiter = axtask::spawn(|| {
+        current().set_base_prio(-10);
+        let wait = LOCK_A.boost();
+        STAGE.store(2, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 3);
+        drop(wait);
+        LOCK_A.set_current();
+        LOCK_A.clear();
+    });
+    while STAGE.load(Ordering::Acquire) < 2 {
+        axtask::yield_now();
+    }
+    assert_eq!(current().effective_prio(), -10);
+
+    LOCK_B.clear();
+    assert_eq!(current().effective_prio(), 0);
+    STAGE.store(3, Ordering::Release);
+    WQ.notify_all(false);
+    assert_eq!(owner.join(), 0);
+    assert_eq!(waiter.join(), 0);
+}
+
+#[test]
+fn test_priority_inheritance_after_handoff() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static LOCK: PiOwner = PiOwner::new();
+    static WQ: WaitQueue = WaitQueue::new();
+    static STAGE: AtomicUsize = AtomicUsize::new(0);
+
+    // the main task holds the lock, `mid` and then `high` wait for it, and
+    // it passes to `mid` first while `high` keeps waiting
+    LOCK.set_current();
+    let mid = axtask::spawn(|| {
+        current().set_base_prio(-5);
+        let wait = LOCK.boost();
+        STAGE.store(1, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 3);
+        drop(wait);
+        LOCK.set_current();
+        assert_eq!(current().effective_prio(), -10);
+        LOCK.clear();
+        assert_eq!(current().effective_prio(), -5);
+        STAGE.store(4, Ordering::Release);
+        WQ.notify_all(false);
+    });
+    while STAGE.load(Ordering::Acquire) < 1 {
+        axtask::yield_now();
+    }
+    assert_eq!(current().effective_prio(), -5);
+
+    let high = axtask::spawn(|| {
+        current().set_base_prio(-10);
+        let wait = LOCK.boost();
+        STAGE.store(2, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 4);
+        drop(wait);
+        LOCK.set_current();
+        LOCK.clear();
+    });
+    while STAGE.load(Ordering::Acquire) < 2 {
+        axtask::yield_now();
+    }
+    assert_eq!(current().effective_prio(), -10);
+
+    LOCK.clear();
+    assert_eq!(current().effective_prio(), 0);
+    STAGE.store(3, Ordering::Release);
+    WQ.notify_all(false);
+    assert_eq!(mid.join(), 0);
+    assert_eq!(high.join(), 0);
+
+    // a task that starts waiting before the next owner records itself
+    let late = axtask::spawn(|| {
+        current().set_base_prio(-10);
+        let wait = LOCK.boost();
+        STAGE.store(5, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 6);
+        drop(wait);
+    });
+    while STAGE.load(Ordering::Acquire) < 5 {
+        axtask::yield_now();
+    }
+    LOCK.set_current();
+    assert_eq!(current().effective_prio(), -10);
+    LOCK.clear();
+    assert_eq!(current().effective_prio(), 0);
+    STAGE.store(6, Ordering::Release);
+    WQ.notify_all(false);
+    assert_eq!(late.join(), 0);
+}
+
+#[test]
+fn test_priority_inheritance_after_shared_wait() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static LOCK_W: PiOwner = PiOwner::new();
+    static LOCK_C: PiOwner = PiOwner::new();
+    static WQ: WaitQueue = WaitQueue::new();
+    static STAGE: AtomicUsize = AtomicUsize::new(0);
+
+    // the main task holds W, `reader` holds C and waits for W, then gets
+    // shared access without becoming its owner
+    LOCK_W.set_current();
+    let reader = axtask::spawn(|| {
+        LOCK_C.set_current();
+        let wait = LOCK_W.boost();
+        STAGE.store(1, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 2);
+        drop(wait);
+        STAGE.store(3, Ordering::Release);
+        WQ.wait_until(|| STAGE.load(Ordering::Acquire) == 4);
+        assert_eq!(current().effective_prio(), -10);
+        LOCK_C.clear();
+        assert_eq!(current().effective_prio(), 0);
+    });
+    while STAGE.load(Ordering::Acquire) < 1 {
+        axtask::yield_now();
+    }
+    STAGE.store(2, Ordering::Release);
+    WQ.notify_all(false);
+    while STAGE.load(Ordering::Acquire) < 3 {
+        axtask::yield_now();
+    }
+
+    // the chain from C stops at `reader`, which no longer waits for W
+    let waiter = axtask::spawn(|| {
+        current().set_base_prio(-10);
+        drop(LOCK_C.boost());
+        STAGE.store(4, Ordering::Release);
+    });
+    assert_eq!(waiter.join(), 0);
+    assert_eq!(current().effective_prio(), 0);
+    WQ.notify_all(false);
+    assert_eq!(reader.join(), 0);
+    LOCK_W.clear();
+}
+
+#[test]
+fn test_future() {
+    use crate::future::{block_on, Executor};
+    use core::future::poll_fn;
+    use core::task::{Poll, Waker};
+    use std::sync::Arc;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // A one-shot event set by a task and awaited by futures.
+    #[derive(Default)]
+    struct Event {
+        set: Mutex<bool>,
+        wakers: Mutex<Vec<Waker>>,
+    }
+
+    impl Event {
+        fn set(&self) {
+            *self.set.lock().unwrap() = true;
+            self.wakers.lock().unwrap().drain(..).for_each(Waker::wake);
+        }
+
+        async fn wait(&self) {
+            poll_fn(|cx| {
+                let mut wakers = self.wakers.lock().unwrap();
+                if *self.set.lock().unwrap() {
+                    Poll::Ready(())
+                } else {
+                    wakers.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    const NUM_FUTURES: usize = 10;
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+
+    let event = Arc::new(Event::default());
+    let executor = Executor::new();
+    for i in 0..NUM_FUTURES {
+        let (event, spawner) = (event.clone(), executor.clone());
+        executor.spawn(async move {
+            event.wait().await;
+            // spawned by a running future
+            spawner.spawn(async move {
+                DONE.fetch_add(i, Ordering::Relaxed);
+            });
+        });
+    }
+    let setter = event.clone();
+    axtask::spawn(move || {
+        axtask::yield_now();
+        setter.set();
+    });
+    executor.run();
+    assert_eq!(
+        DONE.load(Ordering::Relaxed),
+        NUM_FUTURES * (NUM_FUTURES - 1) / 2
+    );
+
+    let event = Arc::new(Event::default());
+    let setter = event.clone();
+    axtask::spawn(move || setter.set());
+    assert_eq!(
+        block_on(async {
+            event.wait().await;
+            42
+        }),
+        42
+    );
+}
+
+#[test]
+fn test_task_stats() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_YIELDS: u64 = 10;
+    static WQ: WaitQueue = WaitQueue::new();
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+    let handle = Builder::new().name("stats".into()).spawn(|| {
+        for _ in 0..NUM_YIELDS {
+            axtask::yield_now();
+        }
+        WQ.wait_until(|| WOKEN.load(Ordering::Acquire) == 1);
+    });
+    let info = || axtask::tasks().find(|t| t.id == handle.id()).unwrap();
+    while info().state != TaskState::Blocked {
+        axtask::yield_now();
+    }
+    let info = info();
+    assert_eq!(info.name, "stats");
+    assert!(info.stats.voluntary_switches > NUM_YIELDS);
+    assert_eq!(info.stats.involuntary_switches, 0); // FIFO scheduler
+
+    WOKEN.store(1, Ordering::Release);
+    WQ.notify_one(false);
+    let id = handle.id();
+    assert_eq!(handle.join(), 0);
+    // recycled by the GC task once it's not referenced
+    while axtask::tasks().any(|t| t.id == id) {
+        axtask::yield_now();
+    }
+    let ids = axtask::tasks().map(|t| t.id.as_u64()).collect::<Vec<_>>();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert!(ids.contains(&current().id().as_u64()));
+}
+
+#[test]
+fn test_task_local() {
+    use core::cell::Cell;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Guard(usize);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(self.0, Ordering::Relaxed);
+            // initialized again while being dropped, and dropped later
+            if self.0 == 1 {
+                LATE.with(|_| {});
+            }
+        }
+    }
+
+    axtask::task_local! {
+        static ID: Cell<u64> = Cell::new(current().id().as_u64());
+        static GUARD: Guard = Guard(1);
+        static LATE: Guard = Guard(100);
+    }
+
+    let handles = (0..4)
+        .map(|_| {
+            axtask::spawn(|| {
+                let id = current().id().as_u64();
+                assert_eq!(ID.with(Cell::get), id);
+                axtask::yield_now();
+                ID.with(|v| v.set(v.get() + 1));
+                assert_eq!(ID.with(Cell::get), id + 1);
+                GUARD.with(|g| assert_eq!(g.0, 1));
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ID.with(Cell::get), current().id().as_u64());
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 404);
+}
+
+#[test]
+fn test_signal() {
+    use crate::signal::{self, SigAction, SIGKILL, SIGTERM, SIGUSR1};
+    use axerrno::AxError;
+    use core::sync::atomic::AtomicBool;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static READY: AtomicBool = AtomicBool::new(false);
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+    assert!(signal::set_action(SIGKILL, SigAction::Ignore).is_err());
+    assert_eq!(signal::kill(u64::MAX, SIGTERM), Err(AxError::NotFound));
+    signal::set_action(
+        SIGUSR1,
+        SigAction::Handler(|sig| {
+            HANDLED.fetch_add(sig, Ordering::Relaxed);
+        }),
+    )
+    .unwrap();
+
+    // an interruptible wait returns with the signal pending, the handler is
+    // called once the task handles it
+    let waiter = axtask::spawn(|| {
+        assert_eq!(
+            WQ.wait_until_interruptible(|| READY.load(Ordering::Acquire)),
+            Err(AxError::Interrupted)
+        );
+        assert_eq!(HANDLED.load(Ordering::Relaxed), 0);
+        assert!(signal::handle_pending());
+        assert_eq!(HANDLED.load(Ordering::Relaxed), SIGUSR1);
+    });
+    axtask::yield_now(); // let it block
+    signal::kill(waiter.id().as_u64(), SIGUSR1).unwrap();
+    assert_eq!(waiter.join(), 0);
+
+    // other waits and `yield_now` don't notice the signal, the next
+    // interruptible point returns without exiting the task, which exits once
+    // it handles the signal
+    let waiter = axtask::spawn(|| {
+        WQ.wait_until(|| READY.load(Ordering::Acquire));
+        axtask::yield_now();
+        assert_eq!(axtask::yield_interruptible(), Err(AxError::Interrupted));
+        signal::handle_pending();
+        unreachable!();
+    });
+    axtask::yield_now();
+    signal::kill(waiter.id().as_u64(), SIGTERM).unwrap();
+    axtask::yield_now();
+    assert!(!waiter.is_finished());
+    READY.store(true, Ordering::Release);
+    WQ.notify_one(true);
+    assert_eq!(waiter.join(), 128 + SIGTERM as i32);
+
+    // kernel service tasks can't be sent signals
+    let service = axtask::Builder::new()
+        .kernel_service()
+        .spawn(|| WQ.wait_until(|| READY.load(Ordering::Acquire)));
+    assert_eq!(
+        signal::kill(service.id().as_u64(), SIGTERM),
+        Err(AxError::PermissionDenied)
+    );
+    service.join();
+
+    signal::set_action(SIGUSR1, SigAction::Default).unwrap();
+}
+
+#[test]
+#[cfg(feature = "irq")]
+fn test_timer_stats() {
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+
+    let before = axtask::timer_stats();
+    let waiters = [(); 2].map(|_| axtask::spawn(|| assert!(WQ.wait_timeout(Duration::ZERO))));
+    axtask::yield_now(); // let them wait
+    axtask::on_timer_tick(); // as the timer interrupt would
+
+    // both alarms expire in the same interrupt
+    for waiter in waiters {
+        assert_eq!(waiter.join(), 0);
+    }
+    let stats = axtask::timer_stats();
+    assert_eq!(stats.interrupts - before.interrupts, 1);
+    assert_eq!(stats.alarms - before.alarms, 2);
+    assert_eq!(stats.coalesced - before.coalesced, 1);
+}
+
+#[test]
+#[cfg(feature = "tickless")]
+fn test_enter_idle_with_ready_tasks() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // a task queued on this CPU keeps it ticking and awake
+    let task = axtask::spawn(|| {});
+    assert!(!crate::timers::enter_idle());
+    assert_eq!(crate::timers::idle_cpus(), 0);
+    assert_eq!(task.join(), 0);
+}
@@ -1,41 +1,162 @@
+//! Timers of the tasks, i.e., the alarms to wake up sleeping tasks and
+//! futures, which are checked on timer interrupts.
+//!
+//! With periodic ticks, a timer interrupt is a tick, so an alarm expires at
+//! the first tick after its deadline. In the `tickless` mode, each CPU
+//! programs its timer for the next tick or the next alarm, whichever comes
+//! first, and stops ticking while it's idle. An alarm then expires at most
+//! [`timer_slack`] after its deadline, along with all the other alarms due
+//! by then (coalescing).
+
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::Waker;
+use core::time::Duration;
+
 use axhal::time::current_time;
 use lazy_init::LazyInit;
 use spinlock::SpinNoIrq;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use crate::{AxTaskRef, RUN_QUEUE};
+use crate::run_queue::unblock_task;
+use crate::AxTaskRef;
 
 // TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<AlarmEvent>>> = LazyInit::new();
 
-struct TaskWakeupEvent(AxTaskRef);
+enum AlarmEvent {
+    /// Unblocks a sleeping task.
+    TaskWakeup(AxTaskRef),
+    /// Wakes a future, identified by the ID returned by [`set_alarm_waker`].
+    Waker(u64, Waker),
+}
 
-impl TimerEvent for TaskWakeupEvent {
+impl TimerEvent for AlarmEvent {
     fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+        match self {
+            Self::TaskWakeup(task) => {
+                task.set_in_timer_list(false);
+                unblock_task(task, true);
+            }
+            Self::Waker(_, waker) => waker.wake(),
+        }
+    }
+}
+
+/// Statistics on the timer interrupts and the alarms, see [`timer_stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimerStats {
+    /// Timer interrupts handled on all CPUs.
+    pub interrupts: u64,
+    /// Timer interrupts that were periodic ticks. All of them are, unless the
+    /// `tickless` feature is enabled.
+    pub ticks: u64,
+    /// Alarms expired, i.e., the sleeping tasks and futures woken up.
+    pub alarms: u64,
+    /// Alarms expired in the same interrupt as an earlier one.
+    pub coalesced: u64,
+    /// Times a CPU stopped ticking as it was idle.
+    pub idle_entries: u64,
+    /// Total delay of the alarms, from their deadlines to when they expired.
+    pub total_latency: Duration,
+    /// Longest delay of an alarm.
+    pub max_latency: Duration,
+}
+
+struct TimerCounters {
+    interrupts: AtomicU64,
+    ticks: AtomicU64,
+    alarms: AtomicU64,
+    coalesced: AtomicU64,
+    idle_entries: AtomicU64,
+    total_latency_nanos: AtomicU64,
+    max_latency_nanos: AtomicU64,
+}
+
+static COUNTERS: TimerCounters = TimerCounters {
+    interrupts: AtomicU64::new(0),
+    ticks: AtomicU64::new(0),
+    alarms: AtomicU64::new(0),
+    coalesced: AtomicU64::new(0),
+    idle_entries: AtomicU64::new(0),
+    total_latency_nanos: AtomicU64::new(0),
+    max_latency_nanos: AtomicU64::new(0),
+};
+
+/// Returns the statistics on the timer interrupts and the alarms since boot.
+///
+/// # Examples
+///
+/// ```
+/// let stats = axtask::timer_stats();
+/// let avg_latency = stats.total_latency / stats.alarms.max(1) as u32;
+/// println!("{} alarms, {:?} late on average", stats.alarms, avg_latency);
+/// ```
+pub fn timer_stats() -> TimerStats {
+    TimerStats {
+        interrupts: COUNTERS.interrupts.load(Ordering::Relaxed),
+        ticks: COUNTERS.ticks.load(Ordering::Relaxed),
+        alarms: COUNTERS.alarms.load(Ordering::Relaxed),
+        coalesced: COUNTERS.coalesced.load(Ordering::Relaxed),
+        idle_entries: COUNTERS.idle_entries.load(Ordering::Relaxed),
+        total_latency: Duration::from_nanos(COUNTERS.total_latency_nanos.load(Ordering::Relaxed)),
+        max_latency: Duration::from_nanos(COUNTERS.max_latency_nanos.load(Ordering::Relaxed)),
     }
 }
 
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
     let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    timers.set(deadline, AlarmEvent::TaskWakeup(task));
+    drop(timers);
+    #[cfg(feature = "tickless")]
+    tickless::program_alarm(deadline);
 }
 
 pub fn cancel_alarm(task: &AxTaskRef) {
     let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    timers.cancel(|e| matches!(e, AlarmEvent::TaskWakeup(t) if Arc::ptr_eq(t, task)));
 }
 
-pub fn check_events() {
+/// Wakes `waker` at `deadline`, returns the ID to cancel it.
+pub fn set_alarm_waker(deadline: TimeValue, waker: Waker) -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    TIMER_LIST
+        .lock()
+        .set(deadline, AlarmEvent::Waker(id, waker));
+    #[cfg(feature = "tickless")]
+    tickless::program_alarm(deadline);
+    id
+}
+
+pub fn cancel_alarm_waker(id: u64) {
+    TIMER_LIST
+        .lock()
+        .cancel(|e| matches!(e, AlarmEvent::Waker(i, _) if *i == id));
+}
+
+/// Expires the alarms due by now, all but the first of which are counted as
+/// coalesced.
+fn check_events() {
+    let mut expired = 0;
     loop {
         let now = current_time();
         let event = TIMER_LIST.lock().expire_one(now);
-        if let Some((_deadline, event)) = event {
+        if let Some((deadline, event)) = event {
+            let latency = now.saturating_sub(deadline).as_nanos() as u64;
+            COUNTERS.alarms.fetch_add(1, Ordering::Relaxed);
+            if expired > 0 {
+                COUNTERS.coalesced.fetch_add(1, Ordering::Relaxed);
+            }
+            COUNTERS
+                .total_latency_nanos
+                .fetch_add(latency, Ordering::Relaxed);
+            COUNTERS
+                .max_latency_nanos
+                .fetch_max(latency, Ordering::Relaxed);
+            expired += 1;
             event.callback(now);
         } else {
             break;
@@ -43,6 +164,170 @@ pub fn check_events() {
     }
 }
 
+/// Handles a timer interrupt on this CPU, and returns whether it's a
+/// periodic tick.
+pub fn on_timer_irq() -> bool {
+    COUNTERS.interrupts.fetch_add(1, Ordering::Relaxed);
+    #[cfg(not(feature = "tickless"))]
+    {
+        check_events();
+        COUNTERS.ticks.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+    #[cfg(feature = "tickless")]
+    tickless::on_timer_irq()
+}
+
 pub fn init() {
     TIMER_LIST.init_by(SpinNoIrq::new(TimerList::new()));
 }
+
+#[cfg(feature = "tickless")]
+pub use self::tickless::{enter_idle, exit_idle, idle_cpus, set_timer_slack, timer_slack};
+
+#[cfg(feature = "tickless")]
+mod tickless {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    use axhal::cpu::this_cpu_id;
+    use axhal::time::{current_time_nanos, NANOS_PER_MICROS, NANOS_PER_MILLIS, NANOS_PER_SEC};
+    use kernel_guard::NoPreemptIrqSave;
+    use timer_list::TimeValue;
+
+    use super::{check_events, COUNTERS, TIMER_LIST};
+
+    /// The interval of the periodic ticks.
+    const TICK_INTERVAL_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+    /// The longest time a CPU stays idle without a timer interrupt. As there
+    /// are no IPIs, it's also how late an idle CPU can find the tasks queued
+    /// on it by other CPUs.
+    const MAX_IDLE_NANOS: u64 = 100 * NANOS_PER_MILLIS;
+
+    static TIMER_SLACK_NANOS: AtomicU64 = AtomicU64::new(50 * NANOS_PER_MICROS);
+
+    /// The CPUs that stopped ticking as they are idle.
+    static IDLE_CPUS: AtomicU64 = AtomicU64::new(0);
+
+    /// The time of the next tick of this CPU.
+    #[percpu::def_percpu]
+    static NEXT_TICK: u64 = 0;
+
+    /// The deadline the timer of this CPU is programmed with.
+    #[percpu::def_percpu]
+    static PROGRAMMED_DEADLINE: u64 = 0;
+
+    /// Sets how late an alarm may expire after its deadline, so that it can
+    /// expire in the same timer interrupt as the later ones. It's 50
+    /// microseconds by default.
+    pub fn set_timer_slack(slack: Duration) {
+        let nanos = slack.as_nanos().min(MAX_IDLE_NANOS as u128) as u64;
+        TIMER_SLACK_NANOS.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Returns how late an alarm may expire after its deadline, see
+    /// [`set_timer_slack`].
+    pub fn timer_slack() -> Duration {
+        Duration::from_nanos(TIMER_SLACK_NANOS.load(Ordering::Relaxed))
+    }
+
+    /// Returns the mask of the CPUs that stopped ticking as they are idle.
+    pub fn idle_cpus() -> u64 {
+        IDLE_CPUS.load(Ordering::Acquire)
+    }
+
+    fn is_idle(cpu_id: usize) -> bool {
+        idle_cpus() & (1 << cpu_id) != 0
+    }
+
+    /// Programs the timer of this CPU for its next tick, unless it's idle, or
+    /// the first alarm plus the slack, whichever comes first.
+    ///
+    /// IRQs and preemption must be disabled.
+    fn program_timer(now: u64) {
+        let mut deadline = now + MAX_IDLE_NANOS;
+        if !is_idle(this_cpu_id()) {
+            deadline = deadline.min(unsafe { NEXT_TICK.read_current_raw() });
+        }
+        if let Some(alarm) = TIMER_LIST.lock().next_deadline() {
+            let slack = TIMER_SLACK_NANOS.load(Ordering::Relaxed);
+            deadline = deadline.min(alarm.as_nanos() as u64 + slack);
+        }
+        unsafe { PROGRAMMED_DEADLINE.write_current_raw(deadline) };
+        axhal::time::set_oneshot_timer(deadline);
+    }
+
+    /// Reprograms the timer of this CPU if a new alarm at `deadline` must
+    /// expire before the programmed deadline, so that it doesn't wait for
+    /// the next tick.
+    pub(super) fn program_alarm(deadline: TimeValue) {
+        let _guard = NoPreemptIrqSave::new();
+        let deadline = deadline.as_nanos() as u64 + TIMER_SLACK_NANOS.load(Ordering::Relaxed);
+        if deadline < unsafe { PROGRAMMED_DEADLINE.read_current_raw() } {
+            unsafe { PROGRAMMED_DEADLINE.write_current_raw(deadline) };
+            axhal::time::set_oneshot_timer(deadline);
+        }
+    }
+
+    /// Restarts the ticks of this CPU if it stopped ticking as it was idle,
+    /// and returns whether it did.
+    fn wake_from_idle(now: u64) -> bool {
+        let bit = 1 << this_cpu_id();
+        if IDLE_CPUS.fetch_and(!bit, Ordering::AcqRel) & bit == 0 {
+            return false;
+        }
+        unsafe { NEXT_TICK.write_current_raw(now + TICK_INTERVAL_NANOS) };
+        true
+    }
+
+    pub(super) fn on_timer_irq() -> bool {
+        // awake before the alarms expire, so the tasks woken up can be queued
+        // on this CPU
+        wake_from_idle(current_time_nanos());
+        check_events();
+        let now = current_time_nanos();
+        let next_tick = unsafe { NEXT_TICK.read_current_raw() };
+        let tick = now >= next_tick;
+        if tick {
+            COUNTERS.ticks.fetch_add(1, Ordering::Relaxed);
+            // stays on the same period, unless a tick is missed
+            let next_tick = if next_tick + TICK_INTERVAL_NANOS > now {
+                next_tick + TICK_INTERVAL_NANOS
+            } else {
+                now + TICK_INTERVAL_NANOS
+            };
+            unsafe { NEXT_TICK.write_current_raw(next_tick) };
+        }
+        program_timer(now);
+        tick
+    }
+
+    /// Stops the ticks of this CPU, which is about to wait for interrupts as
+    /// it's idle. Returns `false` and keeps it ticking if tasks were queued
+    /// on it in the meantime, so it must not wait.
+    pub fn enter_idle() -> bool {
+        let _guard = NoPreemptIrqSave::new();
+        let bit = 1 << this_cpu_id();
+        // marked idle before the check, so that the other CPUs avoid it from
+        // now on, and the tasks they queued on it before are found here
+        IDLE_CPUS.fetch_or(bit, Ordering::SeqCst);
+        if crate::run_queue::has_ready_tasks() {
+            IDLE_CPUS.fetch_and(!bit, Ordering::AcqRel);
+            return false;
+        }
+        COUNTERS.idle_entries.fetch_add(1, Ordering::Relaxed);
+        program_timer(current_time_nanos());
+        true
+    }
+
+    /// Restarts the ticks of this CPU once it's woken up from idle, unless a
+    /// timer interrupt did.
+    pub fn exit_idle() {
+        let _guard = NoPreemptIrqSave::new();
+        let now = current_time_nanos();
+        if wake_from_idle(now) {
+            program_timer(now);
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use axerrno::{AxError, AxResult};
+use core::cell::Cell;
 use spinlock::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::run_queue::{current_run_queue, try_wake, wake_task};
+use crate::{AxTaskRef, CurrentTask};
 
 /// A queue to store sleeping tasks.
 ///
@@ -27,7 +30,7 @@ use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinRaw<VecDeque<AxTaskRef>>, // waiters already disabled IRQs when lock the run queue
 }
 
 impl WaitQueue {
@@ -45,14 +48,21 @@ impl WaitQueue {
         }
     }
 
+    /// Locks the queue without the run queue locked.
+    fn with_queue<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut VecDeque<AxTaskRef>) -> T,
+    {
+        let _guard = kernel_guard::IrqSave::new();
+        f(&mut self.queue.lock())
+    }
+
     fn cancel_events(&self, curr: CurrentTask) {
         // A task can be wake up only one events (timer or `notify()`), remove
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
-            let _guard = kernel_guard::IrqSave::new();
-            self.queue.lock().retain(|t| !curr.ptr_eq(t));
+            self.with_queue(|wq| wq.retain(|t| !curr.ptr_eq(t)));
             curr.set_in_wait_queue(false);
         }
         #[cfg(feature = "irq")]
@@ -65,7 +75,7 @@ impl WaitQueue {
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
     pub fn wait(&self) {
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
@@ -77,23 +87,52 @@ impl WaitQueue {
     ///
     /// Note that even other tasks notify this task, it will not wake up until
     /// the condition becomes true.
+    ///
+    /// The condition is tested with the wait queue locked, so that a
+    /// notification can't be missed. It must not notify this wait queue.
     pub fn wait_until<F>(&self, condition: F)
     where
         F: Fn() -> bool,
     {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
+            let mut wq = self.queue.lock();
             if condition() {
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
+                drop(wq); // unlocked once the task is blocked, not after it's woken up
             });
         }
         self.cancel_events(crate::current());
     }
 
+    /// Like [`WaitQueue::wait_until`], but returns
+    /// [`Err(Interrupted)`](AxError::Interrupted) if a signal is sent to the
+    /// task before the condition becomes true. The signal is left pending, to
+    /// be handled by [`signal::handle_pending`] once the caller holds no locks.
+    ///
+    /// [`signal::handle_pending`]: crate::signal::handle_pending
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> AxResult
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let met = Cell::new(false);
+        crate::signal::interruptible(|| {
+            self.wait_until(|| {
+                met.set(condition());
+                met.get() || curr.signals().has_pending()
+            })
+        });
+        if !met.get() {
+            return Err(AxError::Interrupted);
+        }
+        Ok(())
+    }
+
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
     #[cfg(feature = "irq")]
@@ -105,11 +144,12 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
-            self.queue.lock().push_back(task)
+            self.queue.lock().push_back(task.clone());
+            // armed once the task is blocked, so that the wakeup can't be missed
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
         self.cancel_events(curr);
@@ -120,7 +160,8 @@ impl WaitQueue {
     /// `condition` becomes true, or the given duration has elapsed.
     ///
     /// Note that even other tasks notify this task, it will not wake up until
-    /// the above conditions are met.
+    /// the above conditions are met. The condition is tested as in
+    /// [`WaitQueue::wait_until`].
     #[cfg(feature = "irq")]
     pub fn wait_timeout_until<F>(&self, dur: core::time::Duration, condition: F) -> bool
     where
@@ -133,34 +174,100 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
         let mut timeout = true;
         while axhal::time::current_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
+            let mut wq = self.queue.lock();
             if condition() {
                 timeout = false;
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task.clone());
+                drop(wq);
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
             });
         }
         self.cancel_events(curr);
         timeout
     }
 
+    /// Like [`WaitQueue::wait_timeout_until`], but returns
+    /// [`Err(Interrupted)`](AxError::Interrupted) if a signal is sent to the
+    /// task before the condition becomes true or the duration has elapsed.
+    /// The signal is left pending, as in
+    /// [`WaitQueue::wait_until_interruptible`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use axerrno::AxError;
+    /// use axtask::{signal, WaitQueue};
+    /// use core::time::Duration;
+    ///
+    /// static WQ: WaitQueue = WaitQueue::new();
+    ///
+    /// axtask::init_scheduler();
+    /// signal::set_action(signal::SIGUSR1, signal::SigAction::Ignore).unwrap();
+    /// signal::set_action(signal::SIGUSR2, signal::SigAction::Handler(|_| {})).unwrap();
+    /// let handle = axtask::spawn(|| {
+    ///     let res = WQ.wait_timeout_until_interruptible(Duration::from_secs(10), || false);
+    ///     assert_eq!(res, Err(AxError::Interrupted));
+    /// });
+    /// axtask::yield_now(); // let it block
+    /// signal::kill(handle.id().as_u64(), signal::SIGUSR1).unwrap(); // ignored
+    /// signal::kill(handle.id().as_u64(), signal::SIGUSR2).unwrap();
+    /// assert_eq!(handle.join(), 0);
+    /// ```
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_interruptible<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> AxResult<bool>
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        let met = Cell::new(false);
+        let timeout = crate::signal::interruptible(|| {
+            self.wait_timeout_until(dur, || {
+                met.set(condition());
+                met.get() || curr.signals().has_pending()
+            })
+        });
+        if !timeout && !met.get() {
+            return Err(AxError::Interrupted);
+        }
+        Ok(timeout)
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        if !self.queue.lock().is_empty() {
-            self.notify_one_locked(resched, &mut rq)
-        } else {
-            false
+        let task = self.with_queue(|wq| {
+            while let Some(task) = wq.pop_front() {
+                task.set_in_wait_queue(false);
+                // skip the tasks that have been woken up by their timer
+                if try_wake(&task) {
+                    return Some(task);
+                }
+            }
+            None
+        });
+        match task {
+            // the task is added to a run queue without the wait queue locked.
+            Some(task) => {
+                wake_task(task, resched);
+                true
+            }
+            None => false,
         }
     }
 
@@ -169,15 +276,15 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
-        loop {
-            let mut rq = RUN_QUEUE.lock();
-            if let Some(task) = self.queue.lock().pop_front() {
-                task.set_in_wait_queue(false);
-                rq.unblock_task(task, resched);
-            } else {
-                break;
+        while let Some((task, woken)) = self.with_queue(|wq| {
+            let task = wq.pop_front()?;
+            task.set_in_wait_queue(false);
+            let woken = try_wake(&task);
+            Some((task, woken))
+        }) {
+            if woken {
+                wake_task(task, resched);
             }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
         }
     }
 
@@ -186,24 +293,15 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        let mut wq = self.queue.lock();
-        if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
+        let woken = self.with_queue(|wq| {
+            let index = wq.iter().position(|t| Arc::ptr_eq(t, task))?;
+            let task = wq.remove(index).unwrap();
             task.set_in_wait_queue(false);
-            rq.unblock_task(wq.remove(index).unwrap(), resched);
-            true
-        } else {
-            false
-        }
-    }
-
-    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &mut AxRunQueue) -> bool {
-        if let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
-            true
-        } else {
-            false
+            Some(try_wake(&task))
+        });
+        if woken == Some(true) {
+            wake_task(task.clone(), resched);
         }
+        woken.is_some()
     }
 }
@@ -113,10 +113,15 @@ if [ -z "$1" ]; then
         "apps/task/parallel"
         "apps/task/sleep"
         "apps/task/priority"
+        "apps/task/priority_inversion"
+        "apps/task/tls"
+        "apps/fs/fsstress"
         "apps/net/httpclient"
         "apps/c/helloworld"
         "apps/c/memtest"
         "apps/c/sqlite3"
+        "apps/c/pthread_tls"
+        "apps/c/pthread_cancel"
     )
 else
     test_list="$@"
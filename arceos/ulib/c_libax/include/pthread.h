@@ -0,0 +1,93 @@
+#ifndef __PTHREAD_H__
+#define __PTHREAD_H__
+
+#include <stddef.h>
+#include <time.h>
+
+typedef unsigned long pthread_t;
+typedef unsigned int pthread_key_t;
+
+typedef struct {
+    size_t stacksize;
+} pthread_attr_t;
+
+// The objects are allocated when they are first used, so that zero is a valid
+// initializer.
+typedef struct {
+    void *__obj;
+} pthread_mutex_t;
+
+typedef struct {
+    void *__obj;
+} pthread_cond_t;
+
+typedef struct {
+    void *__obj;
+} pthread_rwlock_t;
+
+// Attributes are not supported, only the defaults are used.
+typedef struct {
+    int __unused;
+} pthread_mutexattr_t;
+
+typedef struct {
+    int __unused;
+} pthread_condattr_t;
+
+typedef struct {
+    int __unused;
+} pthread_rwlockattr_t;
+
+#define PTHREAD_MUTEX_INITIALIZER  { 0 }
+#define PTHREAD_COND_INITIALIZER   { 0 }
+#define PTHREAD_RWLOCK_INITIALIZER { 0 }
+
+#define PTHREAD_CANCELED ((void *)-1)
+
+int pthread_attr_init(pthread_attr_t *attr);
+int pthread_attr_destroy(pthread_attr_t *attr);
+int pthread_attr_setstacksize(pthread_attr_t *attr, size_t stacksize);
+int pthread_attr_getstacksize(const pthread_attr_t *attr, size_t *stacksize);
+
+#ifdef AX_CONFIG_MULTITASK
+int pthread_create(pthread_t *thread, const pthread_attr_t *attr, void *(*start_routine)(void *),
+                   void *arg);
+int pthread_join(pthread_t thread, void **retval);
+int pthread_detach(pthread_t thread);
+_Noreturn void pthread_exit(void *retval);
+pthread_t pthread_self(void);
+int pthread_cancel(pthread_t thread);
+void pthread_testcancel(void);
+
+int pthread_key_create(pthread_key_t *key, void (*destructor)(void *));
+int pthread_key_delete(pthread_key_t key);
+void *pthread_getspecific(pthread_key_t key);
+int pthread_setspecific(pthread_key_t key, const void *value);
+
+int pthread_mutex_init(pthread_mutex_t *mutex, const pthread_mutexattr_t *attr);
+int pthread_mutex_destroy(pthread_mutex_t *mutex);
+int pthread_mutex_lock(pthread_mutex_t *mutex);
+int pthread_mutex_trylock(pthread_mutex_t *mutex);
+int pthread_mutex_unlock(pthread_mutex_t *mutex);
+
+int pthread_cond_init(pthread_cond_t *cond, const pthread_condattr_t *attr);
+int pthread_cond_destroy(pthread_cond_t *cond);
+int pthread_cond_wait(pthread_cond_t *cond, pthread_mutex_t *mutex);
+int pthread_cond_signal(pthread_cond_t *cond);
+int pthread_cond_broadcast(pthread_cond_t *cond);
+
+int pthread_rwlock_init(pthread_rwlock_t *rwlock, const pthread_rwlockattr_t *attr);
+int pthread_rwlock_destroy(pthread_rwlock_t *rwlock);
+int pthread_rwlock_rdlock(pthread_rwlock_t *rwlock);
+int pthread_rwlock_tryrdlock(pthread_rwlock_t *rwlock);
+int pthread_rwlock_wrlock(pthread_rwlock_t *rwlock);
+int pthread_rwlock_trywrlock(pthread_rwlock_t *rwlock);
+int pthread_rwlock_unlock(pthread_rwlock_t *rwlock);
+
+#ifdef AX_CONFIG_IRQ
+int pthread_cond_timedwait(pthread_cond_t *cond, pthread_mutex_t *mutex,
+                           const struct timespec *abstime);
+#endif
+#endif
+
+#endif // __PTHREAD_H__
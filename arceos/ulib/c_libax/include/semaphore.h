@@ -0,0 +1,24 @@
+#ifndef __SEMAPHORE_H__
+#define __SEMAPHORE_H__
+
+#include <time.h>
+
+// The semaphore is allocated by `sem_init`.
+typedef struct {
+    void *__obj;
+} sem_t;
+
+#ifdef AX_CONFIG_MULTITASK
+int sem_init(sem_t *sem, int pshared, unsigned int value);
+int sem_destroy(sem_t *sem);
+int sem_wait(sem_t *sem);
+int sem_trywait(sem_t *sem);
+int sem_post(sem_t *sem);
+int sem_getvalue(sem_t *sem, int *sval);
+
+#ifdef AX_CONFIG_IRQ
+int sem_timedwait(sem_t *sem, const struct timespec *abstime);
+#endif
+#endif
+
+#endif // __SEMAPHORE_H__
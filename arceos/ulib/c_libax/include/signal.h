@@ -0,0 +1,35 @@
+#ifndef __SIGNAL_H__
+#define __SIGNAL_H__
+
+#include <sys/types.h>
+
+typedef void (*sighandler_t)(int);
+
+#define SIG_DFL ((sighandler_t)0)
+#define SIG_IGN ((sighandler_t)1)
+#define SIG_ERR ((sighandler_t)-1)
+
+#define SIGHUP   1
+#define SIGINT   2
+#define SIGQUIT  3
+#define SIGABRT  6
+#define SIGKILL  9
+#define SIGUSR1  10
+#define SIGUSR2  12
+#define SIGPIPE  13
+#define SIGALRM  14
+#define SIGTERM  15
+#define SIGCHLD  17
+#define SIGCONT  18
+#define SIGURG   23
+#define SIGWINCH 28
+
+#define NSIG 64
+
+#ifdef AX_CONFIG_MULTITASK
+sighandler_t signal(int sig, sighandler_t handler);
+int raise(int sig);
+int kill(pid_t pid, int sig);
+#endif
+
+#endif // __SIGNAL_H__
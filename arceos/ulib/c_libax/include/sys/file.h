@@ -0,0 +1,11 @@
+#ifndef __SYS_FILE_H__
+#define __SYS_FILE_H__
+
+#define LOCK_SH 1
+#define LOCK_EX 2
+#define LOCK_NB 4
+#define LOCK_UN 8
+
+int flock(int fd, int operation);
+
+#endif
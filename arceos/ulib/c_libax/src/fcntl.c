@@ -1,10 +1,20 @@
 #include <fcntl.h>
 #include <libax.h>
 #include <stdio.h>
+#include <sys/file.h>
 
-// TODO:
 int fcntl(int fd, int cmd, ... /* arg */)
 {
+#ifdef AX_CONFIG_FS
+    if (cmd == F_GETLK || cmd == F_SETLK || cmd == F_SETLKW) {
+        va_list ap;
+        va_start(ap, cmd);
+        struct flock *lock = va_arg(ap, struct flock *);
+        va_end(ap);
+        return ax_fcntl_lock(fd, cmd, lock);
+    }
+#endif
+    // TODO: other commands
     unimplemented("fd: %d cmd: %d", fd, cmd);
     return 0;
 }
@@ -22,3 +32,12 @@ int open(const char *filename, int flags, ...)
 
     return ax_open(filename, flags, mode);
 }
+
+#ifdef AX_CONFIG_FS
+
+int flock(int fd, int operation)
+{
+    return ax_flock(fd, operation);
+}
+
+#endif
@@ -0,0 +1,183 @@
+#include <errno.h>
+#include <libax.h>
+#include <pthread.h>
+
+int pthread_attr_init(pthread_attr_t *attr)
+{
+    // 0 is the default stack size
+    attr->stacksize = 0;
+    return 0;
+}
+
+int pthread_attr_destroy(pthread_attr_t *attr)
+{
+    return 0;
+}
+
+int pthread_attr_setstacksize(pthread_attr_t *attr, size_t stacksize)
+{
+    if (stacksize == 0)
+        return EINVAL;
+    attr->stacksize = stacksize;
+    return 0;
+}
+
+int pthread_attr_getstacksize(const pthread_attr_t *attr, size_t *stacksize)
+{
+    *stacksize = attr->stacksize;
+    return 0;
+}
+
+#ifdef AX_CONFIG_MULTITASK
+
+int pthread_create(pthread_t *thread, const pthread_attr_t *attr, void *(*start_routine)(void *),
+                   void *arg)
+{
+    size_t stacksize = attr ? attr->stacksize : 0;
+    return -ax_pthread_create(thread, stacksize, start_routine, arg);
+}
+
+int pthread_join(pthread_t thread, void **retval)
+{
+    return -ax_pthread_join(thread, retval);
+}
+
+int pthread_detach(pthread_t thread)
+{
+    return -ax_pthread_detach(thread);
+}
+
+_Noreturn void pthread_exit(void *retval)
+{
+    ax_pthread_exit(retval);
+}
+
+pthread_t pthread_self(void)
+{
+    return ax_pthread_self();
+}
+
+int pthread_cancel(pthread_t thread)
+{
+    return -ax_pthread_cancel(thread);
+}
+
+void pthread_testcancel(void)
+{
+    ax_pthread_testcancel();
+}
+
+int pthread_key_create(pthread_key_t *key, void (*destructor)(void *))
+{
+    return -ax_pthread_key_create(key, destructor);
+}
+
+int pthread_key_delete(pthread_key_t key)
+{
+    return -ax_pthread_key_delete(key);
+}
+
+void *pthread_getspecific(pthread_key_t key)
+{
+    return ax_pthread_getspecific(key);
+}
+
+int pthread_setspecific(pthread_key_t key, const void *value)
+{
+    return -ax_pthread_setspecific(key, value);
+}
+
+int pthread_mutex_init(pthread_mutex_t *mutex, const pthread_mutexattr_t *attr)
+{
+    return -ax_pthread_mutex_init(mutex);
+}
+
+int pthread_mutex_destroy(pthread_mutex_t *mutex)
+{
+    return -ax_pthread_mutex_destroy(mutex);
+}
+
+int pthread_mutex_lock(pthread_mutex_t *mutex)
+{
+    return -ax_pthread_mutex_lock(mutex);
+}
+
+int pthread_mutex_trylock(pthread_mutex_t *mutex)
+{
+    return -ax_pthread_mutex_trylock(mutex);
+}
+
+int pthread_mutex_unlock(pthread_mutex_t *mutex)
+{
+    return -ax_pthread_mutex_unlock(mutex);
+}
+
+int pthread_cond_init(pthread_cond_t *cond, const pthread_condattr_t *attr)
+{
+    return -ax_pthread_cond_init(cond);
+}
+
+int pthread_cond_destroy(pthread_cond_t *cond)
+{
+    return -ax_pthread_cond_destroy(cond);
+}
+
+int pthread_cond_wait(pthread_cond_t *cond, pthread_mutex_t *mutex)
+{
+    return -ax_pthread_cond_wait(cond, mutex);
+}
+
+#ifdef AX_CONFIG_IRQ
+int pthread_cond_timedwait(pthread_cond_t *cond, pthread_mutex_t *mutex,
+                           const struct timespec *abstime)
+{
+    return -ax_pthread_cond_timedwait(cond, mutex, abstime);
+}
+#endif
+
+int pthread_cond_signal(pthread_cond_t *cond)
+{
+    return -ax_pthread_cond_signal(cond);
+}
+
+int pthread_cond_broadcast(pthread_cond_t *cond)
+{
+    return -ax_pthread_cond_broadcast(cond);
+}
+
+int pthread_rwlock_init(pthread_rwlock_t *rwlock, const pthread_rwlockattr_t *attr)
+{
+    return -ax_pthread_rwlock_init(rwlock);
+}
+
+int pthread_rwlock_destroy(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_destroy(rwlock);
+}
+
+int pthread_rwlock_rdlock(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_rdlock(rwlock);
+}
+
+int pthread_rwlock_tryrdlock(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_tryrdlock(rwlock);
+}
+
+int pthread_rwlock_wrlock(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_wrlock(rwlock);
+}
+
+int pthread_rwlock_trywrlock(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_trywrlock(rwlock);
+}
+
+int pthread_rwlock_unlock(pthread_rwlock_t *rwlock)
+{
+    return -ax_pthread_rwlock_unlock(rwlock);
+}
+
+#endif
@@ -0,0 +1,45 @@
+#include <libax.h>
+#include <semaphore.h>
+
+#ifdef AX_CONFIG_MULTITASK
+
+// TODO: set errno, the negative error code is returned for now
+
+int sem_init(sem_t *sem, int pshared, unsigned int value)
+{
+    return ax_sem_init(sem, value);
+}
+
+int sem_destroy(sem_t *sem)
+{
+    return ax_sem_destroy(sem);
+}
+
+int sem_wait(sem_t *sem)
+{
+    return ax_sem_wait(sem);
+}
+
+int sem_trywait(sem_t *sem)
+{
+    return ax_sem_trywait(sem);
+}
+
+#ifdef AX_CONFIG_IRQ
+int sem_timedwait(sem_t *sem, const struct timespec *abstime)
+{
+    return ax_sem_timedwait(sem, abstime);
+}
+#endif
+
+int sem_post(sem_t *sem)
+{
+    return ax_sem_post(sem);
+}
+
+int sem_getvalue(sem_t *sem, int *sval)
+{
+    return ax_sem_getvalue(sem, sval);
+}
+
+#endif
@@ -0,0 +1,26 @@
+#include <libax.h>
+#include <signal.h>
+
+#ifdef AX_CONFIG_MULTITASK
+
+// TODO: set errno, the negative error code is returned for now
+
+sighandler_t signal(int sig, sighandler_t handler)
+{
+    sighandler_t old;
+    if (ax_signal(sig, handler, &old) < 0)
+        return SIG_ERR;
+    return old;
+}
+
+int raise(int sig)
+{
+    return ax_raise(sig);
+}
+
+int kill(pid_t pid, int sig)
+{
+    return ax_kill(pid, sig);
+}
+
+#endif
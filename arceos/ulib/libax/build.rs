@@ -13,6 +13,8 @@ fn main() {
                 "sys/stat.h".into(),
                 "stdio.h".into(),
                 "time.h".into(),
+                "pthread.h".into(),
+                "semaphore.h".into(),
             ],
             header: Some("/* Generated by cbindgen and build.rs, DO NOT edit! */".into()),
             ..Default::default()
@@ -22,6 +24,11 @@ fn main() {
             .rename
             .insert("stat".into(), "struct stat".into());
 
+        config
+            .export
+            .rename
+            .insert("flock".into(), "struct flock".into());
+
         config
             .export
             .rename
@@ -37,9 +44,11 @@ fn main() {
 
         let include_dir = crate_dir.join("../c_libax/include");
         let allow_types = [
-            "stat", "size_t", "ssize_t", "off_t", "mode_t", "O_*", "FILE",
+            "stat", "size_t", "ssize_t", "off_t", "mode_t", "O_*", "FILE", "flock",
+            "pthread_t", "pthread_key_t", "pthread_mutex_t", "pthread_cond_t", "pthread_rwlock_t",
+            "sem_t",
         ];
-        let allow_vars = ["O_.*"];
+        let allow_vars = ["O_.*", "F_.*", "LOCK_.*"];
 
         let mut builder = bindgen::Builder::default()
             .header(in_file)
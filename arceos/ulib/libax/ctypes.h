@@ -1,4 +1,7 @@
 #include <fcntl.h>
+#include <pthread.h>
+#include <semaphore.h>
 #include <stddef.h>
 #include <sys/stat.h>
 #include <stdio.h>
+#include <sys/file.h>
@@ -1,10 +1,11 @@
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use core::ffi::{c_char, c_int, c_void};
+use core::ops::Range;
 
 use super::{ctypes, utils::char_ptr_to_str};
 use crate::debug;
-use crate::fs::{File, OpenOptions};
+use crate::fs::{File, LockKind, OpenOptions};
 use crate::io::{self, prelude::*, SeekFrom};
 use crate::sync::Mutex;
 
@@ -87,11 +88,14 @@ pub unsafe extern "C" fn ax_close(fd: c_int) -> c_int {
         return 0; // stdin, stdout, stderr
     }
     ax_call_body!(ax_close, {
-        FD_TABLE
+        let file = FD_TABLE
             .lock()
             .get_mut(fd as usize)
             .and_then(|file| file.take())
             .ok_or(LinuxError::EBADF)?;
+        // closing any descriptor of a file drops the record locks on it
+        let locks = file.lock().lock_handle();
+        locks.unlock_record(lock_owner(), 0..u64::MAX).ok();
         Ok(0)
     })
 }
@@ -224,6 +228,117 @@ pub unsafe extern "C" fn ax_fstat(fd: c_int, buf: *mut ctypes::stat) -> ctypes::
     })
 }
 
+/// The owner of the record locks of the current task, the ID `getpid`
+/// returns.
+fn lock_owner() -> u64 {
+    #[cfg(feature = "multitask")]
+    {
+        crate::task::current().id().as_u64()
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        0
+    }
+}
+
+/// Convert the range of a `struct flock` to the bytes it covers.
+fn flock_range(file: &mut File, lock: &ctypes::flock) -> LinuxResult<Range<u64>> {
+    let base = match lock.l_whence {
+        0 => 0,
+        1 => file.seek(SeekFrom::Current(0))? as i64,
+        2 => file.metadata()?.len() as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = base.checked_add(lock.l_start).ok_or(LinuxError::EINVAL)?;
+    // a length of 0 covers the bytes to the end of the file and beyond it
+    let (start, end) = match lock.l_len {
+        0 => (start, i64::MAX),
+        len if len > 0 => (start, start.checked_add(len).ok_or(LinuxError::EINVAL)?),
+        len => (start + len, start),
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(start as u64..if end == i64::MAX { u64::MAX } else { end as u64 })
+}
+
+/// Place, remove or test an advisory lock on a range of the file indicated by
+/// `fd`, for `fcntl` with `F_SETLK`, `F_SETLKW` or `F_GETLK`.
+///
+/// The locks belong to the calling task and the file, any descriptor of the
+/// file releases them, and closing one does. They are apart from the locks
+/// of `flock`. `l_pid` of a conflicting lock is the ID of its task.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_fcntl_lock(fd: c_int, cmd: c_int, lock: *mut ctypes::flock) -> c_int {
+    debug!("ax_fcntl_lock <= {} {} {:#x}", fd, cmd, lock as usize);
+    ax_call_body!(ax_fcntl_lock, {
+        if lock.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let lock = unsafe { &mut *lock };
+        let kind = match lock.l_type as u32 {
+            ctypes::F_RDLCK => Some(LockKind::Shared),
+            ctypes::F_WRLCK => Some(LockKind::Exclusive),
+            ctypes::F_UNLCK => None,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        // keep the file open, but don't hold it while waiting
+        let file = get_file_by_fd(fd)?;
+        let (range, locks) = {
+            let mut file = file.lock();
+            (flock_range(&mut file, lock)?, file.lock_handle())
+        };
+        let owner = lock_owner();
+        match (cmd as u32, kind) {
+            (ctypes::F_GETLK, Some(kind)) => match locks.record_conflict(owner, kind, range) {
+                Some(other) => {
+                    lock.l_type = match other.kind {
+                        LockKind::Shared => ctypes::F_RDLCK,
+                        LockKind::Exclusive => ctypes::F_WRLCK,
+                    } as _;
+                    lock.l_whence = 0;
+                    lock.l_start = other.range.start as _;
+                    lock.l_len = match other.range.end {
+                        u64::MAX => 0,
+                        end => (end - other.range.start) as _,
+                    };
+                    lock.l_pid = other.owner as _;
+                }
+                None => lock.l_type = ctypes::F_UNLCK as _,
+            },
+            (ctypes::F_SETLK | ctypes::F_SETLKW, Some(kind)) => {
+                locks.lock_record(owner, kind, range, cmd as u32 == ctypes::F_SETLKW)?
+            }
+            (ctypes::F_SETLK | ctypes::F_SETLKW, None) => locks.unlock_record(owner, range)?,
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
+/// Place or remove an advisory lock on the whole file indicated by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_flock(fd: c_int, operation: c_int) -> c_int {
+    debug!("ax_flock <= {} {:#x}", fd, operation);
+    ax_call_body!(ax_flock, {
+        let file = get_file_by_fd(fd)?;
+        let locks = file.lock().lock_handle();
+        let op = operation as u32;
+        let wait = op & ctypes::LOCK_NB == 0;
+        match op & !ctypes::LOCK_NB {
+            ctypes::LOCK_SH => locks.lock(LockKind::Shared, 0..u64::MAX, wait)?,
+            ctypes::LOCK_EX => locks.lock(LockKind::Exclusive, 0..u64::MAX, wait)?,
+            ctypes::LOCK_UN => locks.unlock(0..u64::MAX)?,
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
 /// Get the path of the current directory.
 #[no_mangle]
 pub unsafe extern "C" fn ax_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
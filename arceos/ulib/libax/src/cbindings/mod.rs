@@ -10,6 +10,10 @@ mod fs;
 #[cfg(feature = "alloc")]
 mod malloc;
 
+#[cfg(feature = "multitask")]
+mod signal;
+#[cfg(feature = "multitask")]
+mod sync;
 mod thread;
 mod time;
 
@@ -55,11 +59,32 @@ pub use self::malloc::{ax_free, ax_malloc};
 
 #[cfg(feature = "fs")]
 pub use self::fs::{
-    ax_close, ax_fstat, ax_getcwd, ax_lseek, ax_lstat, ax_open, ax_read, ax_stat, ax_write,
+    ax_close, ax_fcntl_lock, ax_flock, ax_fstat, ax_getcwd, ax_lseek, ax_lstat, ax_open, ax_read,
+    ax_stat, ax_write,
+};
+
+#[cfg(feature = "multitask")]
+pub use self::thread::{
+    ax_getpid, ax_pthread_cancel, ax_pthread_create, ax_pthread_detach, ax_pthread_exit,
+    ax_pthread_getspecific, ax_pthread_join, ax_pthread_key_create, ax_pthread_key_delete,
+    ax_pthread_self, ax_pthread_setspecific, ax_pthread_testcancel,
 };
 
 #[cfg(feature = "multitask")]
-pub use self::thread::ax_getpid;
+pub use self::signal::{ax_kill, ax_raise, ax_signal};
+
+#[cfg(feature = "multitask")]
+pub use self::sync::{
+    ax_pthread_cond_broadcast, ax_pthread_cond_destroy, ax_pthread_cond_init,
+    ax_pthread_cond_signal, ax_pthread_cond_wait, ax_pthread_mutex_destroy,
+    ax_pthread_mutex_init, ax_pthread_mutex_lock, ax_pthread_mutex_trylock,
+    ax_pthread_mutex_unlock, ax_pthread_rwlock_destroy, ax_pthread_rwlock_init,
+    ax_pthread_rwlock_rdlock, ax_pthread_rwlock_tryrdlock, ax_pthread_rwlock_trywrlock,
+    ax_pthread_rwlock_unlock, ax_pthread_rwlock_wrlock, ax_sem_destroy, ax_sem_getvalue,
+    ax_sem_init, ax_sem_post, ax_sem_trywait, ax_sem_wait,
+};
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub use self::sync::{ax_pthread_cond_timedwait, ax_sem_timedwait};
 
 pub use self::thread::ax_exit;
 pub use self::time::{ax_clock_gettime, ax_nanosleep};
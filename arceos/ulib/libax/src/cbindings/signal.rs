@@ -0,0 +1,71 @@
+use axerrno::{AxError, LinuxError, LinuxResult};
+use core::ffi::c_int;
+
+use crate::sync::Mutex;
+use crate::task::signal::{self, SigAction, NSIG};
+
+/// A signal handler, or `SIG_DFL` (0), or `SIG_IGN` (1).
+type SigHandler = Option<unsafe extern "C" fn(c_int)>;
+
+const SIG_IGN: usize = 1;
+
+/// The handlers set by `ax_signal`, by signal number.
+static HANDLERS: Mutex<[SigHandler; NSIG]> = Mutex::new([None; NSIG]);
+
+fn call_handler(sig: usize) {
+    let handler = HANDLERS.lock()[sig];
+    if let Some(handler) = handler {
+        unsafe { handler(sig as c_int) };
+    }
+}
+
+fn to_linux_error(e: AxError) -> LinuxError {
+    match e {
+        AxError::NotFound => LinuxError::ESRCH,
+        AxError::PermissionDenied => LinuxError::EPERM,
+        e => e.into(),
+    }
+}
+
+/// Set the handler of a signal, and store the old one to `old` if it is not
+/// NULL.
+#[no_mangle]
+pub unsafe extern "C" fn ax_signal(sig: c_int, handler: SigHandler, old: *mut SigHandler) -> c_int {
+    ax_call_body!(ax_signal, {
+        let action = match handler {
+            None => SigAction::Default,
+            Some(h) if h as usize == SIG_IGN => SigAction::Ignore,
+            Some(_) => SigAction::Handler(call_handler),
+        };
+        let mut handlers = HANDLERS.lock();
+        signal::set_action(sig as usize, action)?;
+        let old_handler = core::mem::replace(&mut handlers[sig as usize], handler);
+        if !old.is_null() {
+            unsafe { *old = old_handler };
+        }
+        Ok(0)
+    })
+}
+
+/// Send a signal to the current thread, it is handled before returning.
+#[no_mangle]
+pub unsafe extern "C" fn ax_raise(sig: c_int) -> c_int {
+    ax_call_body!(ax_raise, {
+        signal::raise(sig as usize).map_err(to_linux_error)?;
+        Ok(0)
+    })
+}
+
+/// Send a signal to the thread of the given ID.
+#[no_mangle]
+pub unsafe extern "C" fn ax_kill(pid: c_int, sig: c_int) -> c_int {
+    ax_call_body!(ax_kill, {
+        signal::kill(pid as u64, sig as usize).map_err(to_linux_error)?;
+        Ok(0)
+    })
+}
+
+/// Send `SIGCANCEL` to a thread, see `ax_pthread_cancel`.
+pub(super) fn cancel(thread: u64) -> LinuxResult {
+    signal::kill(thread, signal::SIGCANCEL).map_err(to_linux_error)
+}
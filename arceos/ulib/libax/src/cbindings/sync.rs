@@ -0,0 +1,381 @@
+//! Mutexes, condition variables, readers-writer locks and semaphores for the
+//! pthread and semaphore C APIs.
+//!
+//! A C object only holds a pointer to the Rust one, which is allocated when
+//! it's first used, so that the static initializers, all zeros, work. The
+//! guard of a lock is forgotten while the C caller holds it.
+
+use alloc::boxed::Box;
+use axerrno::{LinuxError, LinuxResult};
+use core::ffi::{c_int, c_uint};
+use core::mem::forget;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use super::ctypes;
+use crate::sync::{Condvar, Mutex, RwLock, Semaphore};
+
+type PthreadMutex = Mutex<()>;
+type PthreadRwLock = RwLock<()>;
+
+/// The pointer to the Rust object in the C object `obj`.
+unsafe fn slot<'a, C, T>(obj: *mut C) -> LinuxResult<&'a AtomicPtr<T>> {
+    if obj.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    // the C types only hold the pointer
+    Ok(unsafe { &*(obj as *const AtomicPtr<T>) })
+}
+
+/// Returns the Rust object of `obj`, which is allocated if it's not yet.
+unsafe fn get_or_alloc<'a, C, T: Default>(obj: *mut C) -> LinuxResult<&'a T> {
+    let slot = unsafe { slot::<C, T>(obj)? };
+    let mut ptr = slot.load(Ordering::Acquire);
+    if ptr.is_null() {
+        let new = Box::into_raw(Box::default());
+        match slot.compare_exchange(ptr, new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => ptr = new,
+            Err(other) => {
+                // allocated by someone else in the meantime
+                drop(unsafe { Box::from_raw(new) });
+                ptr = other;
+            }
+        }
+    }
+    Ok(unsafe { &*ptr })
+}
+
+/// Returns the Rust object of `obj`, which must have been initialized.
+unsafe fn get<'a, C, T>(obj: *mut C) -> LinuxResult<&'a T> {
+    let ptr = unsafe { slot::<C, T>(obj)? }.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(unsafe { &*ptr })
+}
+
+/// Sets the Rust object of `obj`, which must not be in use.
+unsafe fn init<C, T>(obj: *mut C, value: T) -> LinuxResult<c_int> {
+    let slot = unsafe { slot::<C, T>(obj)? };
+    slot.store(Box::into_raw(Box::new(value)), Ordering::Release);
+    Ok(0)
+}
+
+/// Frees the Rust object of `obj`, unless `busy` returns `true` for it.
+unsafe fn destroy<C, T>(obj: *mut C, busy: impl FnOnce(&T) -> bool) -> LinuxResult<c_int> {
+    let slot = unsafe { slot::<C, T>(obj)? };
+    let ptr = slot.load(Ordering::Acquire);
+    if !ptr.is_null() {
+        if busy(unsafe { &*ptr }) {
+            return Err(LinuxError::EBUSY);
+        }
+        slot.store(null_mut(), Ordering::Release);
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+    Ok(0)
+}
+
+/// The time left until the absolute time `abstime` of `CLOCK_REALTIME`,
+/// which is the time since boot as `CLOCK_MONOTONIC`.
+#[cfg(feature = "irq")]
+unsafe fn timeout_of(abstime: *const ctypes::timespec) -> LinuxResult<core::time::Duration> {
+    if abstime.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    let ts = unsafe { *abstime };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let deadline = core::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    Ok(deadline.saturating_sub(axhal::time::current_time()))
+}
+
+/// Initialize a mutex with the default attributes
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_mutex_init(mutex: *mut ctypes::pthread_mutex_t) -> c_int {
+    ax_call_body!(ax_pthread_mutex_init, {
+        unsafe { init(mutex, PthreadMutex::new(())) }
+    })
+}
+
+/// Destroy a mutex, which must be unlocked
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_mutex_destroy(mutex: *mut ctypes::pthread_mutex_t) -> c_int {
+    ax_call_body!(ax_pthread_mutex_destroy, {
+        unsafe { destroy(mutex, PthreadMutex::is_locked) }
+    })
+}
+
+/// Lock a mutex
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_mutex_lock(mutex: *mut ctypes::pthread_mutex_t) -> c_int {
+    ax_call_body!(ax_pthread_mutex_lock, {
+        let mutex: &PthreadMutex = unsafe { get_or_alloc(mutex)? };
+        forget(mutex.lock());
+        Ok(0)
+    })
+}
+
+/// Lock a mutex if it's unlocked, or fail with `EBUSY`
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_mutex_trylock(mutex: *mut ctypes::pthread_mutex_t) -> c_int {
+    ax_call_body!(ax_pthread_mutex_trylock, {
+        let mutex: &PthreadMutex = unsafe { get_or_alloc(mutex)? };
+        forget(mutex.try_lock().ok_or(LinuxError::EBUSY)?);
+        Ok(0)
+    })
+}
+
+/// Unlock a mutex locked by the current thread
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_mutex_unlock(mutex: *mut ctypes::pthread_mutex_t) -> c_int {
+    ax_call_body!(ax_pthread_mutex_unlock, {
+        let mutex: &PthreadMutex = unsafe { get_or_alloc(mutex)? };
+        if !mutex.is_owned_by_current() {
+            return Err(LinuxError::EPERM);
+        }
+        unsafe { mutex.force_unlock() };
+        Ok(0)
+    })
+}
+
+/// Initialize a condition variable with the default attributes
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_init(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    ax_call_body!(ax_pthread_cond_init, {
+        unsafe { init(cond, Condvar::new()) }
+    })
+}
+
+/// Destroy a condition variable
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_destroy(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    ax_call_body!(ax_pthread_cond_destroy, {
+        unsafe { destroy(cond, |_: &Condvar| false) }
+    })
+}
+
+/// Wait on a condition variable, with `mutex` locked by the current thread
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_wait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+) -> c_int {
+    ax_call_body!(ax_pthread_cond_wait, {
+        let cond: &Condvar = unsafe { get_or_alloc(cond)? };
+        let mutex: &PthreadMutex = unsafe { get_or_alloc(mutex)? };
+        if !mutex.is_owned_by_current() {
+            return Err(LinuxError::EPERM);
+        }
+        let guard = unsafe { mutex.make_guard_unchecked() };
+        forget(cond.wait(guard));
+        Ok(0)
+    })
+}
+
+/// Wait on a condition variable until the absolute time `abstime`, or fail
+/// with `ETIMEDOUT`
+#[cfg(feature = "irq")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_timedwait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    ax_call_body!(ax_pthread_cond_timedwait, {
+        let cond: &Condvar = unsafe { get_or_alloc(cond)? };
+        let mutex: &PthreadMutex = unsafe { get_or_alloc(mutex)? };
+        let timeout = unsafe { timeout_of(abstime)? };
+        if !mutex.is_owned_by_current() {
+            return Err(LinuxError::EPERM);
+        }
+        let guard = unsafe { mutex.make_guard_unchecked() };
+        let (guard, res) = cond.wait_timeout(guard, timeout);
+        forget(guard);
+        if res.timed_out() {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Wake up one thread waiting on a condition variable
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_signal(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    ax_call_body!(ax_pthread_cond_signal, {
+        let cond: &Condvar = unsafe { get_or_alloc(cond)? };
+        cond.notify_one();
+        Ok(0)
+    })
+}
+
+/// Wake up all threads waiting on a condition variable
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cond_broadcast(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    ax_call_body!(ax_pthread_cond_broadcast, {
+        let cond: &Condvar = unsafe { get_or_alloc(cond)? };
+        cond.notify_all();
+        Ok(0)
+    })
+}
+
+/// Initialize a readers-writer lock with the default attributes
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_init(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_init, {
+        unsafe { init(rwlock, PthreadRwLock::new(())) }
+    })
+}
+
+/// Destroy a readers-writer lock, which must be unlocked
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_destroy(
+    rwlock: *mut ctypes::pthread_rwlock_t,
+) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_destroy, {
+        unsafe { destroy(rwlock, |rwlock: &PthreadRwLock| rwlock.try_write().is_none()) }
+    })
+}
+
+/// Lock a readers-writer lock for reading
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_rdlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_rdlock, {
+        let rwlock: &PthreadRwLock = unsafe { get_or_alloc(rwlock)? };
+        forget(rwlock.read());
+        Ok(0)
+    })
+}
+
+/// Lock a readers-writer lock for reading if no writer holds it, or fail
+/// with `EBUSY`
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_tryrdlock(
+    rwlock: *mut ctypes::pthread_rwlock_t,
+) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_tryrdlock, {
+        let rwlock: &PthreadRwLock = unsafe { get_or_alloc(rwlock)? };
+        forget(rwlock.try_read().ok_or(LinuxError::EBUSY)?);
+        Ok(0)
+    })
+}
+
+/// Lock a readers-writer lock for writing
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_wrlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_wrlock, {
+        let rwlock: &PthreadRwLock = unsafe { get_or_alloc(rwlock)? };
+        forget(rwlock.write());
+        Ok(0)
+    })
+}
+
+/// Lock a readers-writer lock for writing if it's unlocked, or fail with
+/// `EBUSY`
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_trywrlock(
+    rwlock: *mut ctypes::pthread_rwlock_t,
+) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_trywrlock, {
+        let rwlock: &PthreadRwLock = unsafe { get_or_alloc(rwlock)? };
+        forget(rwlock.try_write().ok_or(LinuxError::EBUSY)?);
+        Ok(0)
+    })
+}
+
+/// Unlock a readers-writer lock held by the current thread
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_rwlock_unlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    ax_call_body!(ax_pthread_rwlock_unlock, {
+        let rwlock: &PthreadRwLock = unsafe { get_or_alloc(rwlock)? };
+        // a writer can't hold it while the current thread reads
+        if rwlock.is_locked_exclusive() {
+            unsafe { rwlock.force_write_unlock() };
+        } else {
+            unsafe { rwlock.force_read_unlock() };
+        }
+        Ok(0)
+    })
+}
+
+/// Initialize a semaphore with the count `value`
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_init(sem: *mut ctypes::sem_t, value: c_uint) -> c_int {
+    ax_call_body!(ax_sem_init, {
+        unsafe { init(sem, Semaphore::new(value as isize)) }
+    })
+}
+
+/// Destroy a semaphore
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_destroy(sem: *mut ctypes::sem_t) -> c_int {
+    ax_call_body!(ax_sem_destroy, {
+        unsafe { destroy(sem, |_: &Semaphore| false) }
+    })
+}
+
+/// Decrement a semaphore, waiting until its count is positive
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_wait(sem: *mut ctypes::sem_t) -> c_int {
+    ax_call_body!(ax_sem_wait, {
+        let sem: &Semaphore = unsafe { get(sem)? };
+        sem.acquire();
+        Ok(0)
+    })
+}
+
+/// Decrement a semaphore if its count is positive, or fail with `EAGAIN`
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_trywait(sem: *mut ctypes::sem_t) -> c_int {
+    ax_call_body!(ax_sem_trywait, {
+        let sem: &Semaphore = unsafe { get(sem)? };
+        if !sem.try_acquire() {
+            return Err(LinuxError::EAGAIN);
+        }
+        Ok(0)
+    })
+}
+
+/// Decrement a semaphore, waiting until its count is positive or until the
+/// absolute time `abstime`, then failing with `ETIMEDOUT`
+#[cfg(feature = "irq")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_timedwait(
+    sem: *mut ctypes::sem_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    ax_call_body!(ax_sem_timedwait, {
+        let sem: &Semaphore = unsafe { get(sem)? };
+        // POSIX doesn't check the time if it doesn't have to wait
+        if sem.try_acquire() {
+            return Ok(0);
+        }
+        if !sem.acquire_timeout(unsafe { timeout_of(abstime)? }) {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Increment a semaphore
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_post(sem: *mut ctypes::sem_t) -> c_int {
+    ax_call_body!(ax_sem_post, {
+        let sem: &Semaphore = unsafe { get(sem)? };
+        sem.release();
+        Ok(0)
+    })
+}
+
+/// Store the count of a semaphore to `sval`
+#[no_mangle]
+pub unsafe extern "C" fn ax_sem_getvalue(sem: *mut ctypes::sem_t, sval: *mut c_int) -> c_int {
+    ax_call_body!(ax_sem_getvalue, {
+        let sem: &Semaphore = unsafe { get(sem)? };
+        if sval.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        unsafe { *sval = sem.count().max(0) as c_int };
+        Ok(0)
+    })
+}
@@ -2,10 +2,41 @@ use crate::task::exit;
 use core::ffi::c_int;
 
 #[cfg(feature = "multitask")]
-use crate::task::current;
+use {
+    super::ctypes,
+    crate::sync::Mutex,
+    crate::task::signal::{self, SigAction},
+    crate::task::{current, Builder, JoinHandle},
+    alloc::{collections::BTreeMap, sync::Arc, vec::Vec},
+    axerrno::{LinuxError, LinuxResult},
+    core::cell::RefCell,
+    core::ffi::c_void,
+    core::sync::atomic::{AtomicPtr, AtomicU32, Ordering},
+};
 
+/// A thread created by `ax_pthread_create`, until it is joined, or until it
+/// is detached and has exited.
 #[cfg(feature = "multitask")]
-use axerrno::LinuxResult;
+struct Pthread {
+    handle: JoinHandle,
+    retval: Arc<AtomicPtr<c_void>>,
+    /// Detached by `ax_pthread_detach`, it can't be joined.
+    detached: bool,
+    /// The thread has returned or called `ax_pthread_exit`.
+    exited: bool,
+}
+
+/// The threads created by `ax_pthread_create`, by task ID.
+#[cfg(feature = "multitask")]
+static THREADS: Mutex<BTreeMap<ctypes::pthread_t, Pthread>> = Mutex::new(BTreeMap::new());
+
+#[cfg(feature = "multitask")]
+crate::task::task_local! {
+    /// Where the current thread stores its return value, if it's created by
+    /// `ax_pthread_create`. It's not looked up in `THREADS`, where the thread
+    /// is no longer once another thread starts to join it.
+    static RETVAL: RefCell<Option<Arc<AtomicPtr<c_void>>>> = RefCell::new(None);
+}
 
 /// Exit current task
 #[no_mangle]
@@ -22,3 +53,263 @@ pub unsafe extern "C" fn ax_getpid() -> c_int {
         Ok(pid)
     })
 }
+
+/// Create a thread running `start_routine(arg)`, with a stack of
+/// `stack_size` bytes, or of the default size if it is 0.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_create(
+    res: *mut ctypes::pthread_t,
+    stack_size: usize,
+    start_routine: extern "C" fn(arg: *mut c_void) -> *mut c_void,
+    arg: *mut c_void,
+) -> c_int {
+    ax_call_body!(ax_pthread_create, {
+        if res.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let retval = Arc::new(AtomicPtr::new(core::ptr::null_mut()));
+        let ret = retval.clone();
+        let arg = arg as usize;
+        let mut builder = Builder::new();
+        if stack_size != 0 {
+            builder = builder.stack_size(stack_size);
+        }
+        // held until the thread is in `THREADS`, which it leaves on exit
+        // if it is detached
+        let mut threads = THREADS.lock();
+        let handle = builder.spawn(move || {
+            RETVAL.with(|r| *r.borrow_mut() = Some(ret.clone()));
+            ret.store(start_routine(arg as *mut c_void), Ordering::Release);
+            thread_exited();
+        });
+        let id = handle.id().as_u64() as _;
+        threads.insert(
+            id,
+            Pthread {
+                handle,
+                retval,
+                detached: false,
+                exited: false,
+            },
+        );
+        drop(threads);
+        unsafe { *res = id };
+        Ok(0)
+    })
+}
+
+/// Wait for a thread created by `ax_pthread_create` to exit, and store the
+/// value it returned to `retval` if it is not NULL.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_join(
+    thread: ctypes::pthread_t,
+    retval: *mut *mut c_void,
+) -> c_int {
+    ax_call_body!(ax_pthread_join, {
+        if thread == unsafe { ax_pthread_self() } {
+            return Err(LinuxError::EDEADLK);
+        }
+        let thread = {
+            let mut threads = THREADS.lock();
+            match threads.get(&thread) {
+                None => return Err(LinuxError::ESRCH),
+                Some(t) if t.detached => return Err(LinuxError::EINVAL),
+                Some(_) => threads.remove(&thread).unwrap(),
+            }
+        };
+        thread.handle.join();
+        if !retval.is_null() {
+            unsafe { *retval = thread.retval.load(Ordering::Acquire) };
+        }
+        Ok(0)
+    })
+}
+
+/// Detach a thread created by `ax_pthread_create`: it can't be joined, and
+/// it is forgotten once it exits.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_detach(thread: ctypes::pthread_t) -> c_int {
+    ax_call_body!(ax_pthread_detach, {
+        let mut threads = THREADS.lock();
+        match threads.get_mut(&thread) {
+            None => return Err(LinuxError::ESRCH),
+            Some(t) if t.detached => return Err(LinuxError::EINVAL),
+            Some(t) if t.exited => drop(threads.remove(&thread)),
+            Some(t) => t.detached = true,
+        }
+        Ok(0)
+    })
+}
+
+/// Called by a thread created by `ax_pthread_create` when it exits: it is
+/// removed from `THREADS` if it is detached, or else marked so that
+/// `ax_pthread_detach` removes it.
+#[cfg(feature = "multitask")]
+fn thread_exited() {
+    let id = current().id().as_u64() as ctypes::pthread_t;
+    let mut threads = THREADS.lock();
+    match threads.get_mut(&id) {
+        Some(t) if t.detached => drop(threads.remove(&id)),
+        Some(t) => t.exited = true,
+        // being joined
+        None => {}
+    }
+}
+
+/// Exit the current thread, `retval` is returned to the thread joining it.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_exit(retval: *mut c_void) -> ! {
+    let created = RETVAL.with(|r| match &*r.borrow() {
+        Some(r) => {
+            r.store(retval, Ordering::Release);
+            true
+        }
+        None => false,
+    });
+    if created {
+        thread_exited();
+    }
+    exit(0)
+}
+
+/// The value returned to the thread joining a cancelled thread.
+#[cfg(feature = "multitask")]
+const PTHREAD_CANCELED: *mut c_void = -1isize as _;
+
+/// Cancel a thread. The thread exits with `PTHREAD_CANCELED` when it handles
+/// the cancellation, e.g., once it returns from `sleep`, or calls
+/// `pthread_testcancel`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    ax_call_body!(ax_pthread_cancel, {
+        fn cancel_handler(_sig: usize) {
+            unsafe { ax_pthread_exit(PTHREAD_CANCELED) }
+        }
+        signal::set_action(signal::SIGCANCEL, SigAction::Handler(cancel_handler))?;
+        super::signal::cancel(thread as _)?;
+        Ok(0)
+    })
+}
+
+/// Handle the cancellation of the current thread if it is requested.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_testcancel() {
+    signal::handle_pending();
+}
+
+/// Get the ID of the current thread
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_self() -> ctypes::pthread_t {
+    current().id().as_u64() as _
+}
+
+/// The destructor of a thread-specific data key.
+#[cfg(feature = "multitask")]
+type KeyDestructor = Option<unsafe extern "C" fn(*mut c_void)>;
+
+/// The maximum number of keys that can exist at the same time.
+#[cfg(feature = "multitask")]
+const PTHREAD_KEYS_MAX: usize = 128;
+
+/// The keys created by `ax_pthread_key_create` and not deleted, with their
+/// destructors. The keys are never reused, so that the values of a deleted key
+/// are not seen through a new one.
+#[cfg(feature = "multitask")]
+static KEYS: Mutex<BTreeMap<ctypes::pthread_key_t, KeyDestructor>> = Mutex::new(BTreeMap::new());
+
+/// The values of the keys in a thread, whose destructors are called when the
+/// thread exits.
+#[cfg(feature = "multitask")]
+#[derive(Default)]
+struct KeyValues(RefCell<BTreeMap<ctypes::pthread_key_t, *mut c_void>>);
+
+#[cfg(feature = "multitask")]
+crate::task::task_local! {
+    static KEY_VALUES: KeyValues = KeyValues::default();
+}
+
+#[cfg(feature = "multitask")]
+impl Drop for KeyValues {
+    fn drop(&mut self) {
+        let values = core::mem::take(self.0.get_mut());
+        let destructors = {
+            let keys = KEYS.lock();
+            values
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .filter_map(|(key, value)| Some((keys.get(&key).copied()??, value)))
+                .collect::<Vec<_>>()
+        };
+        // the values set by the destructors are dropped after this
+        for (destructor, value) in destructors {
+            unsafe { destructor(value) };
+        }
+    }
+}
+
+/// Create a thread-specific data key, whose `destructor` is called with the
+/// non-NULL value of the key when a thread exits.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_key_create(
+    key: *mut ctypes::pthread_key_t,
+    destructor: KeyDestructor,
+) -> c_int {
+    ax_call_body!(ax_pthread_key_create, {
+        static NEXT_KEY: AtomicU32 = AtomicU32::new(0);
+        if key.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let mut keys = KEYS.lock();
+        if keys.len() >= PTHREAD_KEYS_MAX {
+            return Err(LinuxError::EAGAIN);
+        }
+        let new_key = NEXT_KEY.fetch_add(1, Ordering::Relaxed) as _;
+        keys.insert(new_key, destructor);
+        unsafe { *key = new_key };
+        Ok(0)
+    })
+}
+
+/// Delete a thread-specific data key, the destructor is not called.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_key_delete(key: ctypes::pthread_key_t) -> c_int {
+    ax_call_body!(ax_pthread_key_delete, {
+        KEYS.lock().remove(&key).ok_or(LinuxError::EINVAL)?;
+        Ok(0)
+    })
+}
+
+/// Get the value of a key in the current thread, NULL if it's not set.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_getspecific(key: ctypes::pthread_key_t) -> *mut c_void {
+    KEY_VALUES.with(|values| {
+        let value = values.0.borrow().get(&key).copied();
+        value.unwrap_or(core::ptr::null_mut())
+    })
+}
+
+/// Set the value of a key in the current thread.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_pthread_setspecific(
+    key: ctypes::pthread_key_t,
+    value: *const c_void,
+) -> c_int {
+    ax_call_body!(ax_pthread_setspecific, {
+        if !KEYS.lock().contains_key(&key) {
+            return Err(LinuxError::EINVAL);
+        }
+        KEY_VALUES.with(|values| values.0.borrow_mut().insert(key, value as *mut c_void));
+        Ok(0)
+    })
+}
@@ -1,4 +1,4 @@
-use crate::{debug, task::sleep};
+use crate::debug;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::time::{current_time, NANOS_PER_SEC};
 use core::{
@@ -30,7 +30,10 @@ pub unsafe extern "C" fn ax_clock_gettime(ts: *mut ctypes::timespec) -> c_int {
 
 /// Sleep some nanoseconds
 ///
-/// TODO: should be woken by signals, and set errno
+/// It's interrupted by a signal that is handled when `multitask` and `irq`
+/// are enabled.
+///
+/// TODO: set errno
 #[no_mangle]
 pub unsafe extern "C" fn ax_nanosleep(
     req: *const ctypes::timespec,
@@ -45,7 +48,14 @@ pub unsafe extern "C" fn ax_nanosleep(
         let total_nano = (*req).tv_sec as u64 * NANOS_PER_SEC + (*req).tv_nsec as u64;
         let before = current_time().as_nanos() as u64;
 
-        sleep(Duration::from_nanos(total_nano));
+        let dur = Duration::from_nanos(total_nano);
+        #[cfg(all(feature = "multitask", feature = "irq"))]
+        if axtask::sleep_until_interruptible(current_time() + dur).is_err() {
+            // holds no locks here, the task may exit
+            crate::task::signal::handle_pending();
+        }
+        #[cfg(not(all(feature = "multitask", feature = "irq")))]
+        crate::task::sleep(dur);
 
         let after = current_time().as_nanos() as u64;
         let diff = after - before;
@@ -2,4 +2,9 @@
 
 pub use axfs::api::{canonicalize, metadata, read, read_to_string, remove_file, write};
 pub use axfs::api::{create_dir, create_dir_all, read_dir, remove_dir};
+pub use axfs::api::{fs_types, mount, mounts, umount, MountInfo};
+pub use axfs::api::{fstrim, mknod, resize_fs, set_write_back, sync};
+pub use axfs::api::{getxattr, listxattr, removexattr, setxattr};
+pub use axfs::api::{create_snapshot, delete_snapshot, snapshots};
 pub use axfs::api::{DirEntry, File, FileType, Metadata, OpenOptions, Permissions, ReadDir};
+pub use axfs::api::{FileLock, LockKind};
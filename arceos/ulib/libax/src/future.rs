@@ -0,0 +1,3 @@
+//! Async runtime, running futures on tasks.
+
+pub use axtask::future::{block_on, sleep, sleep_until, Executor, Sleep};
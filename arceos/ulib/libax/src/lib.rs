@@ -14,10 +14,18 @@
 //!     - `irq`: Enable interrupt handling support. This feature is required for
 //!       some multitask operations, such as [`sync::WaitQueue::wait_timeout`] and
 //!       non-spinning [`task::sleep`].
+//!     - `tickless`: Program the timer for the next tick or sleep only, and stop
+//!       ticking while idle, see [`task::set_timer_slack`]. It also enables
+//!       `multitask` and `irq`.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
+//!     - `tls`: Enable thread-local storage, for the `#[thread_local]` statics
+//!       in Rust and the `__thread` variables in C. The statics declared by
+//!       [`task::task_local!`] work without it.
+//!     - `async`: Enable the async runtime in [`future`], and the async methods
+//!       of the network sockets. It also enables `multitask` and `irq`.
 //! - Device and upperlayer stack
 //!     - `fs`: Enable file system support.
 //!     - `net`: Enable networking support.
@@ -57,6 +65,9 @@ pub mod sync;
 pub mod task;
 pub mod time;
 
+#[cfg(feature = "async")]
+pub mod future;
+
 #[cfg(feature = "fs")]
 pub mod fs;
 
@@ -34,6 +34,43 @@ impl TcpStream {
     pub fn shutdown(&self) -> io::Result {
         self.socket.shutdown()
     }
+
+    /// Opens a TCP connection to a remote host, without blocking the calling
+    /// task.
+    #[cfg(feature = "async")]
+    pub async fn connect_async(addr: SocketAddr) -> io::Result<Self> {
+        let mut socket = TcpSocket::new();
+        socket.connect_async(addr).await?;
+        Ok(Self { socket })
+    }
+
+    /// Reads some bytes into `buf`, like [`Read::read`] but without blocking
+    /// the calling task.
+    #[cfg(feature = "async")]
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.socket.recv_async(buf).await
+    }
+
+    /// Writes some bytes of `buf`, like [`Write::write`] but without blocking
+    /// the calling task.
+    #[cfg(feature = "async")]
+    pub async fn write_async(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send_async(buf).await
+    }
+
+    /// Writes the whole `buf`, like [`Write::write_all`] but without blocking
+    /// the calling task.
+    #[cfg(feature = "async")]
+    pub async fn write_all_async(&mut self, mut buf: &[u8]) -> io::Result {
+        while !buf.is_empty() {
+            match self.write_async(buf).await {
+                Ok(0) => return axerrno::ax_err!(WriteZero, "failed to write whole buffer"),
+                Ok(n) => buf = &buf[n..],
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Read for TcpStream {
@@ -77,4 +114,13 @@ impl TcpListener {
         let addr = socket.peer_addr()?;
         Ok((TcpStream { socket }, addr))
     }
+
+    /// Accept a new incoming connection from this listener, without blocking
+    /// the calling task.
+    #[cfg(feature = "async")]
+    pub async fn accept_async(&mut self) -> io::Result<(TcpStream, SocketAddr)> {
+        let socket = self.socket.accept_async().await?;
+        let addr = socket.peer_addr()?;
+        Ok((TcpStream { socket }, addr))
+    }
 }
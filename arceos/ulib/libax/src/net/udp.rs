@@ -29,4 +29,17 @@ impl UdpSocket {
     pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         self.socket.sendto(buf, addr)
     }
+
+    /// Receives a message on the socket, without blocking the calling task.
+    #[cfg(feature = "async")]
+    pub async fn recv_from_async(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.socket.recvfrom_async(buf).await
+    }
+
+    /// Sends data on the socket to the given address, without blocking the
+    /// calling task.
+    #[cfg(feature = "async")]
+    pub async fn send_to_async(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.socket.sendto_async(buf, addr).await
+    }
 }
@@ -1,7 +1,13 @@
 //! Useful synchronization primitives.
 
 #[cfg(feature = "multitask")]
-pub use axsync::{Mutex, MutexGuard};
+pub use axsync::{
+    Barrier, BarrierWaitResult, Condvar, Mutex, MutexGuard, Once, OnceCell, RwLock,
+    RwLockReadGuard, RwLockWriteGuard, Semaphore, SemaphoreGuard,
+};
+
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub use axsync::WaitTimeoutResult;
 
 #[cfg(feature = "multitask")]
 pub use axtask::WaitQueue;
@@ -1,7 +1,14 @@
 //! Native threads.
 
 #[cfg(feature = "multitask")]
-pub use axtask::{current, set_priority, spawn, TaskId};
+pub use axtask::{
+    current, set_priority, set_sched_policy, signal, spawn, task_local, tasks, Builder,
+    DeadlineParams, JoinHandle, LocalKey, SchedPolicy, TaskId, TaskInfo, TaskState, TaskStats,
+};
+#[cfg(feature = "tickless")]
+pub use axtask::{set_timer_slack, timer_slack};
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub use axtask::{timer_stats, TimerStats};
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
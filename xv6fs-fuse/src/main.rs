@@ -59,7 +59,7 @@ fn xv6fs_test_create() -> std::io::Result<()> {
     info!("block size:{}, disk inode size:{}, log header size:{}",BSIZE,size_of::<DiskInode>(),size_of::<LogHeader>());
     let mut xfs=Xv6FS::new();
     //xfs.create(block_file.clone());
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let root_inode=xfs.get_root_inode();
     info!("root inode is {:?}",root_inode);
     let mut root_data=root_inode.lock();
@@ -102,7 +102,7 @@ fn xv6fs_log_delete() -> std::io::Result<()> {
     info!("block size:{}, disk inode size:{}, log header size:{}",BSIZE,size_of::<DiskInode>(),size_of::<LogHeader>());
     let mut xfs=Xv6FS::new();
     //xfs.create(block_file.clone());
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let root_inode=xfs.get_root_inode();
     //info!("root inode is {:?}",root_inode);
     let mut root_data=root_inode.lock();
@@ -127,7 +127,7 @@ fn xv6fs_test_write() -> std::io::Result<()> {
         f
     })));
     let mut xfs=Xv6FS::new();
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let path:&[u8]=b"/test\0\0\0";
     let mut inode=ICACHE.create(path, xv6fs::disk_inode::InodeType::File, 2, 1).unwrap();
     let mut inode_data=inode.lock();
@@ -151,7 +151,7 @@ fn xv6fs_ls_root() -> std::io::Result<()> {
         f
     })));
     let mut xfs=Xv6FS::new();
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let root_inode=xfs.get_root_inode();
     //info!("root inode is {:?}",root_inode);
     let mut root_data=root_inode.lock();
@@ -175,7 +175,7 @@ fn xv6fs_test_read() -> std::io::Result<()> {
     info!("block size:{}, disk inode size:{}, log header size:{}",BSIZE,size_of::<DiskInode>(),size_of::<LogHeader>());
     let mut xfs=Xv6FS::new();
     //xfs.create(block_file.clone());
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let path:&[u8]=b"/test\0\0\0";
     let mut inode=ICACHE.create(path, xv6fs::disk_inode::InodeType::File, 2, 1).unwrap();
     let mut inode_data=inode.lock();
@@ -205,7 +205,7 @@ fn xv6fs_test_bdealloc() -> std::io::Result<()> {
         f
     })));
     let mut xfs=Xv6FS::new();
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     bfree(47);
     LOG_MANAGER.end_op();
     Ok(())
@@ -229,7 +229,7 @@ fn xv6fs_test_remove()->std::io::Result<()> {
         f
     })));
     let mut xfs=Xv6FS::new();
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let path:&[u8]=b"/test\0\0\0";
     let rinode=ICACHE.get_root_dir();
     ICACHE.remove(path);
@@ -256,7 +256,7 @@ fn xv6fs_test_remove_dir()->std::io::Result<()> {
         f
     })));
     let mut xfs=Xv6FS::new();
-    unsafe{xv6fs::init(block_file.clone(), 1);}
+    unsafe{xv6fs::init(block_file.clone(), 1).unwrap();}
     let path:&[u8]=b"/testdir\0\0\0";
     let rinode=ICACHE.get_root_dir();
     ICACHE.remove(path);
@@ -8,12 +8,24 @@ use std::{println as info}; // Workaround to use prinltn! for logs.
 use crate::{superblock::SUPER_BLOCK};
 use crate::log::LOG_MANAGER;
 use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::checksum;
+use crate::snapshot;
+use crate::discard;
 use super::{ InodeType, DiskInode };
 
 
 use crate::fs_const::{ BPB,IPB };
 
 use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// `balloc` only hands out blocks below this, see `resize`.
+static ALLOC_LIMIT: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Keep `balloc` below `limit`, or lift the limit with `u32::MAX`.
+pub(crate) fn set_alloc_limit(limit: u32) {
+    ALLOC_LIMIT.store(limit, Ordering::SeqCst);
+}
 
 // / Zero a block. 
 // pub fn bzero(dev: u32, bno: u32) {
@@ -49,21 +61,23 @@ fn locate_inode_offset(inum: u32) -> usize {
 /// Allocate a zeroed disk block 
 pub fn balloc(dev: u32) -> u32 {
     let mut b = 0;
-    let sb_size = unsafe{ SUPER_BLOCK.size() };
+    let sb_size = unsafe{ SUPER_BLOCK.size() }.min(ALLOC_LIMIT.load(Ordering::SeqCst));
     while b < sb_size {
         let bm_blockno = unsafe{ SUPER_BLOCK.bitmap_blockno(b) };
         let mut buf = BLOCK_CACHE_MANAGER.bread(dev, bm_blockno);
-        let mut bi = 0;
-        while bi < BPB && b + bi < sb_size {
-            bi += 1;
+        checksum::assert_valid(dev, &buf);
+        for bi in 0..BPB.min(sb_size - b) {
             let m = 1 << (bi % 8);
             let buf_ptr = unsafe{ (buf.raw_data_mut() as *mut u8).offset((bi / 8) as isize).as_mut().unwrap() };
             let buf_val = unsafe{ ptr::read(buf_ptr) };
             //info!("bval is {}",buf_val);
-            if (buf_val&m) == 0{ // Is block free?
+            // Is block free? Blocks of snapshots are not in this bitmap.
+            if (buf_val&m) == 0 && !snapshot::is_shared(b + bi) {
                 let new_val:u8=buf_val|m;
                 unsafe{ ptr::write(buf_ptr, new_val) };
+                discard::PENDING.lock().retain(|&freed| freed != b + bi);
                 debug!("[Xv6fs] balloc: inum is {}",bi);
+                checksum::update(dev, &buf);
                 LOG_MANAGER.write(buf);
                 // drop(buf);
                 // bzero(dev, b + bi);
@@ -77,13 +91,14 @@ pub fn balloc(dev: u32) -> u32 {
 }
 
 pub fn bisalloc(blockno:u32)->bool{
-    if blockno > 1000{
+    if blockno >= unsafe { SUPER_BLOCK.size() } {
         return false;
     }
     let bm_blockno=unsafe {SUPER_BLOCK.bitmap_blockno(blockno)};
     let mut buf=BLOCK_CACHE_MANAGER.bread(0, bm_blockno);
-    let bi=blockno%8;
-    let offset=blockno/8;
+    checksum::assert_valid(0, &buf);
+    let bi=blockno%BPB%8;
+    let offset=blockno%BPB/8;
     let buf_ptr=unsafe {(buf.raw_data_mut() as *mut u8).offset(offset as isize).as_mut().unwrap()};
     let buf_val=unsafe {ptr::read(buf_ptr)};
     //info!("buf val is {}",buf_val);
@@ -97,13 +112,14 @@ pub fn bisalloc(blockno:u32)->bool{
 
 pub fn bfree(blockno:u32)->Result<(),&'static str>{
     info!("[Xv6fs] bfree: free block no is {}",blockno);
-    if blockno > 1000{
+    if blockno >= unsafe { SUPER_BLOCK.size() } {
         return Ok(())
     }
     let bm_blockno=unsafe {SUPER_BLOCK.bitmap_blockno(blockno)};
     let mut buf=BLOCK_CACHE_MANAGER.bread(0, bm_blockno);
-    let bi=blockno%8;
-    let offset=blockno/8;
+    checksum::verify(0, &buf)?;
+    let bi=blockno%BPB%8;
+    let offset=blockno%BPB/8;
     let buf_ptr=unsafe {(buf.raw_data_mut() as *mut u8).offset(offset as isize).as_mut().unwrap()};
     let buf_val=unsafe {ptr::read(buf_ptr)};
     //info!("buf val is {}",buf_val);
@@ -114,7 +130,19 @@ pub fn bfree(blockno:u32)->Result<(),&'static str>{
     //info!("new val is {}",new_val);
     unsafe{ptr::write(buf_ptr, new_val)};
     //unsafe{info!("buf is {:?}",buf.raw_data().as_ref().unwrap())};
+    // a snapshot may still use the block
+    let shared = snapshot::is_shared(blockno);
+    if !shared {
+        // queued while the bit can't change, see `discard`
+        discard::PENDING.lock().push(blockno);
+    }
+    checksum::update(0, &buf);
     LOG_MANAGER.write(buf);
+    // the block may have been a directory block,
+    // unless a snapshot still uses it, which keeps its checksum
+    if !shared {
+        checksum::clear(0, blockno);
+    }
     Ok(())
 }
 
@@ -125,10 +153,12 @@ pub fn inode_alloc(dev: u32, itype: InodeType) -> u32 {
         let offset = locate_inode_offset(inum) as isize;
         debug!("inode alloc");
         let mut buf = BLOCK_CACHE_MANAGER.bread(dev, blockno);
+        checksum::assert_valid(dev, &buf);
         let dinode = unsafe { (buf.raw_data_mut() as *mut DiskInode).offset(offset) };
         let dinode = unsafe { &mut *dinode };
         if dinode.try_alloc(itype).is_ok() {
             info!("[Xv6fs] inode alloc: inum is {} and offset is {}",inum,offset);
+            checksum::update(dev, &buf);
             LOG_MANAGER.write(buf);
             return inum
         }
@@ -5,6 +5,10 @@ use core::any::Any;
 pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, _block_id: usize, _buf: &mut [u8]);
     fn write_block(&self, _block_id: usize, _buf: &[u8]);
+    /// Write back anything the device itself still buffers.
+    fn flush(&self) {}
+    /// Tell the device that `count` blocks from `block_id` are unused.
+    fn discard(&self, _block_id: usize, _count: usize) {}
 }
 
 pub struct BlockNone;
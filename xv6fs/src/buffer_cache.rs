@@ -20,7 +20,6 @@ use crate::block_dev::BlockNone;
 use super::{BlockDevice,NBUF, BSIZE};
 use alloc::sync::Arc;
 use lazy_static::*;
-use crate::sync::UPSafeCell;
 // lazy_static!{
 //     pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> = Mutex::new(
 //         BlockCacheManager::new());
@@ -33,7 +32,8 @@ lazy_static!{
 pub struct BlockCacheManager {
     ctrl: Mutex<BufLru>,
     bufs: [BufInner; NBUF],
-    inner: UPSafeCell<BlockCacheManagerInner>,
+    /// Only held to get the device, tasks use it concurrently.
+    inner: Mutex<BlockCacheManagerInner>,
 }
 
 pub struct BlockCacheManagerInner{
@@ -51,12 +51,27 @@ impl BlockCacheManager {
         Self {
             ctrl: Mutex::new(BufLru::new()),
             bufs: array![_ => BufInner::new(); NBUF],
-            inner:unsafe{UPSafeCell::new(BlockCacheManagerInner::new())},
+            inner:Mutex::new(BlockCacheManagerInner::new()),
         }
     }
 
     pub fn set_block_device(&self,block_device: Arc<dyn BlockDevice>){
-        self.inner.exclusive_access().block_device=Arc::clone(&block_device);
+        self.inner.lock().block_device=Arc::clone(&block_device);
+    }
+
+    /// Flush the underlying block device.
+    /// Buffers are written through by `bwrite`, so there is nothing to write back here.
+    pub fn flush(&self) {
+        self.block_device().flush();
+    }
+
+    /// Tell the underlying block device that the blocks are unused, see `discard`.
+    pub fn discard(&self, block_id: u32, count: u32) {
+        self.block_device().discard(block_id as usize, count as usize);
+    }
+
+    fn block_device(&self) -> Arc<dyn BlockDevice> {
+        Arc::clone(&self.inner.lock().block_device)
     }
 
     /// Init the bcache.
@@ -130,16 +145,17 @@ impl BlockCacheManager {
      /// Get the buf from the cache/disk(block device)
      pub fn bread<'a>(&'a self, dev: u32, block_id: u32) -> Buf<'a> {
         //info!("block id is {}",block_id);
-        let inner=self.inner.exclusive_access();
+        // bget may sleep, the device isn't locked meanwhile
+        let block_device=self.block_device();
         //debug!("bread block id is {}",block_id);
-        let mut b = self.bget(Arc::clone(&inner.block_device), dev, block_id);
+        let mut b = self.bget(Arc::clone(&block_device), dev, block_id);
         //info!("end bget");
         if !self.bufs[b.index].valid.load(Ordering::Relaxed) {
             info!("not find block {} in cache!",block_id);
-            inner.block_device.read_block(block_id as usize, b.data.as_mut().unwrap().0.as_mut());
+            block_device.read_block(block_id as usize, b.data.as_mut().unwrap().0.as_mut());
+            self.bufs[b.index].verified.store(false, Ordering::Relaxed);
             self.bufs[b.index].valid.store(true, Ordering::Relaxed);
         }
-        drop(inner);
         b
     }
 
@@ -183,6 +199,16 @@ impl<'a> Buf<'a> {
         guard.deref_mut()
     }
 
+    /// Whether the checksum of the buf data has been verified
+    /// since it was read from the disk.
+    pub fn verified(&self) -> bool {
+        BLOCK_CACHE_MANAGER.bufs[self.index].verified.load(Ordering::Relaxed)
+    }
+
+    pub fn set_verified(&self, verified: bool) {
+        BLOCK_CACHE_MANAGER.bufs[self.index].verified.store(verified, Ordering::Relaxed);
+    }
+
     /// Pin the buf.
     /// SAFETY: it should be definitly safe.
     ///     Because the current refcnt >= 1, so the rc_ptr is valid.
@@ -319,6 +345,8 @@ struct BufInner {
     // the bcache spinlock and the relevant buf sleeplock
     // holding either of which can get access to them
     valid: AtomicBool,
+    /// guarded by the buf sleeplock, see `Buf::verified`
+    verified: AtomicBool,
     data: SleepLock<BufData>,
 }
 
@@ -326,6 +354,7 @@ impl BufInner {
     fn new() -> Self {
         Self {
             valid: AtomicBool::new(false),
+            verified: AtomicBool::new(false),
             data: SleepLock::new(BufData::new(),init_lock()),
         }
     }
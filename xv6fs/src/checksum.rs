@@ -0,0 +1,192 @@
+//! Metadata checksums.
+//!
+//! When the super block has `FEATURE_METADATA_CSUM` set, xv6fs keeps CRC32C
+//! checksums of its metadata:
+//!
+//! - The super block and the log header carry their own checksum. The one
+//!   of the log header also covers the blocks in the log.
+//! - Inode, bitmap and directory blocks have theirs in the checksum table,
+//!   which starts at block `csumstart` and has one entry per block of the
+//!   file system. The last entry of each table block is the checksum of the
+//!   table block itself.
+//!
+//! A table entry of 0 means that the block has no checksum, like file data
+//! blocks, so a checksum of 0 is stored as `!0`.
+//!
+//! Checksums are verified when metadata is read, a mismatch is reported as
+//! the `CORRUPTED` error. Callers that have no way to return an error panic.
+
+use core::slice;
+
+use crate::buffer_cache::{Buf, BLOCK_CACHE_MANAGER};
+use crate::fs_const::BSIZE;
+use crate::log::LOG_MANAGER;
+use crate::superblock::{FEATURE_METADATA_CSUM, SUPER_BLOCK};
+
+/// Error of metadata whose checksum doesn't match.
+pub const CORRUPTED: &str = "Corrupted";
+
+/// Checksum entries in a table block, the last word is for the block itself.
+pub const CSUMS_PER_BLOCK: usize = BSIZE / 4 - 1;
+
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32C_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+/// CRC32C of `data`, continuing from `crc`, the CRC32C of the bytes before it
+/// (0 to start).
+pub fn crc32c(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &b| {
+        CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Whether the file system keeps metadata checksums.
+pub fn enabled() -> bool {
+    unsafe { SUPER_BLOCK.features() & FEATURE_METADATA_CSUM != 0 }
+}
+
+/// Number of table blocks that the checksums of `nblocks` blocks may be in,
+/// which are written to the log along with them.
+pub fn table_blocks(nblocks: usize) -> usize {
+    if !enabled() {
+        return 0;
+    }
+    let ntables = (unsafe { SUPER_BLOCK.size() } as usize + CSUMS_PER_BLOCK - 1) / CSUMS_PER_BLOCK;
+    nblocks.min(ntables)
+}
+
+/// The value stored in the table for a checksum.
+fn stored(crc: u32) -> u32 {
+    if crc == 0 { !0 } else { crc }
+}
+
+fn block_bytes<'a>(buf: &'a Buf) -> &'a [u8] {
+    unsafe { slice::from_raw_parts(buf.raw_data() as *const u8, BSIZE) }
+}
+
+fn entry(table: &Buf, i: usize) -> u32 {
+    let bytes = &block_bytes(table)[i * 4..i * 4 + 4];
+    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn set_entry(table: &mut Buf, i: usize, value: u32) {
+    let data = unsafe { slice::from_raw_parts_mut(table.raw_data_mut() as *mut u8, BSIZE) };
+    data[i * 4..i * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn table_csum(table: &Buf) -> u32 {
+    stored(crc32c(0, &block_bytes(table)[..CSUMS_PER_BLOCK * 4]))
+}
+
+/// Read the table block with the entry of `blockno`, and the entry index.
+fn read_table(dev: u32, blockno: u32) -> Result<(Buf<'static>, usize), &'static str> {
+    let csumstart = unsafe { SUPER_BLOCK.csumstart() };
+    let table_no = csumstart + blockno / CSUMS_PER_BLOCK as u32;
+    let table = BLOCK_CACHE_MANAGER.bread(dev, table_no);
+    if !table.verified() {
+        if entry(&table, CSUMS_PER_BLOCK) != table_csum(&table) {
+            return Err(CORRUPTED);
+        }
+        table.set_verified(true);
+    }
+    Ok((table, blockno as usize % CSUMS_PER_BLOCK))
+}
+
+/// Check the block in `buf` against its checksum.
+/// A block is only checked once after it is read from the disk, changes
+/// to it in the cache are followed by `update`.
+pub fn verify(dev: u32, buf: &Buf) -> Result<(), &'static str> {
+    if !enabled() || buf.verified() {
+        return Ok(());
+    }
+    let (table, i) = read_table(dev, buf.read_blockno())?;
+    let csum = entry(&table, i);
+    drop(table);
+    if csum != 0 && csum != stored(crc32c(0, block_bytes(buf))) {
+        return Err(CORRUPTED);
+    }
+    buf.set_verified(true);
+    Ok(())
+}
+
+/// Like `verify`, for callers that can't return an error.
+pub fn assert_valid(dev: u32, buf: &Buf) {
+    if let Err(e) = verify(dev, buf) {
+        panic!("xv6fs: {} metadata in block {}", e, buf.read_blockno());
+    }
+}
+
+fn set_csum(dev: u32, blockno: u32, csum: u32) {
+    let (mut table, i) = match read_table(dev, blockno) {
+        Ok(table) => table,
+        Err(e) => panic!("xv6fs: {} checksum table of block {}", e, blockno),
+    };
+    if entry(&table, i) == csum {
+        return;
+    }
+    set_entry(&mut table, i, csum);
+    let self_csum = table_csum(&table);
+    set_entry(&mut table, CSUMS_PER_BLOCK, self_csum);
+    LOG_MANAGER.write(table);
+}
+
+/// Update the checksum of the block in `buf` after it is modified,
+/// before it is written to the log.
+pub fn update(dev: u32, buf: &Buf) {
+    if enabled() {
+        set_csum(dev, buf.read_blockno(), stored(crc32c(0, block_bytes(buf))));
+        buf.set_verified(true);
+    }
+}
+
+/// Drop the checksum of a block that is freed.
+pub fn clear(dev: u32, blockno: u32) {
+    if enabled() {
+        set_csum(dev, blockno, 0);
+    }
+}
+
+/// The table entry of a block, see `resize`.
+pub(crate) fn entry_of(dev: u32, blockno: u32) -> Result<u32, &'static str> {
+    let (table, i) = read_table(dev, blockno)?;
+    Ok(entry(&table, i))
+}
+
+/// The value stored in the table for the block `data`, or the last word of
+/// a table block for its first `CSUMS_PER_BLOCK` entries. See `resize`.
+pub(crate) fn block_csum(data: &[u8]) -> u32 {
+    stored(crc32c(0, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32c() {
+        // the check value of CRC-32C
+        assert_eq!(crc32c(0, b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(0, b""), 0);
+        // a checksum can be continued over the following bytes
+        assert_eq!(crc32c(crc32c(0, b"1234"), b"56789"), 0xe306_9283);
+        // 32 bytes of zeros, from RFC 3720
+        assert_eq!(crc32c(0, &[0; 32]), 0x8a91_36aa);
+    }
+}
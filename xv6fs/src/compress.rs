@@ -0,0 +1,201 @@
+//! Transparent compression of file data.
+//!
+//! A file whose inode has `IFLAG_COMPRESSED` set keeps its data in clusters
+//! of `CLUSTER_BLOCKS` logical blocks: cluster `c` owns the logical blocks
+//! `c * CLUSTER_BLOCKS` up to `(c + 1) * CLUSTER_BLOCKS`, and is stored
+//!
+//! - not at all if it only holds zeros, its blocks are holes;
+//! - compressed if that saves at least a block: the first blocks hold the
+//!   length of the compressed data as a `u32`, then the data in the LZ4
+//!   block format (see `lz4`), and the last block of the cluster is a hole;
+//! - as is otherwise, with all its blocks allocated.
+//!
+//! A cluster is always read and written as a whole, and bytes past the end
+//! of the file are zeros. One transaction writes at most one cluster of a
+//! compressed file, see `write_limit`.
+//!
+//! A directory with the flag set passes it on to the files and directories
+//! created in it, its own entries are not compressed.
+
+use core::ptr;
+
+use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::disk_inode::{InodeType, IFLAG_COMPRESSED};
+use crate::fs_const::{BSIZE, MAXFILE, MAXOPBLOCKS};
+use crate::inode::InodeData;
+use crate::log::LOG_MANAGER;
+use crate::lz4;
+use crate::misc::min;
+
+/// Logical blocks in a cluster.
+pub const CLUSTER_BLOCKS: usize = 4;
+/// Bytes in a cluster.
+pub const CLUSTER_SIZE: usize = CLUSTER_BLOCKS * BSIZE;
+/// Error of a compressed cluster that can't be decompressed.
+pub const CORRUPTED_CLUSTER: &str = "compressed cluster is corrupted";
+
+const HEADER: usize = 4;
+/// Bytes a compressed cluster may hold, it must leave its last block free.
+const PACKED_SIZE: usize = (CLUSTER_BLOCKS - 1) * BSIZE;
+/// Bytes one transaction writes to a file that is not compressed: the log
+/// must have room for the inode, an indirect block, the bitmap, and 2 more
+/// blocks for writes that are not aligned.
+const MAX_WRITE: usize = ((MAXOPBLOCKS - 1 - 1 - 2) / 2) * BSIZE;
+
+type Cluster = [u8; CLUSTER_SIZE];
+
+impl InodeData {
+    /// Whether the data of this file is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.dinode.itype == InodeType::File && self.dinode.flags() & IFLAG_COMPRESSED != 0
+    }
+
+    /// Whether the files created in this directory are compressed.
+    pub fn compresses_new_files(&self) -> bool {
+        self.dinode.itype == InodeType::Directory && self.dinode.flags() & IFLAG_COMPRESSED != 0
+    }
+
+    /// Compress the data of this file, which must be empty, or the files
+    /// created in this directory from now on.
+    /// Caller must hold inode's sleeplock.
+    pub fn make_compressed(&mut self) -> Result<(), &'static str> {
+        match self.dinode.itype {
+            InodeType::File if self.dinode.size > 0 => return Err("file not empty"),
+            InodeType::File | InodeType::Directory => {}
+            _ => return Err("not a file or directory"),
+        }
+        let flags = self.dinode.flags();
+        if flags & IFLAG_COMPRESSED == 0 {
+            self.dinode.set_flags(flags | IFLAG_COMPRESSED);
+            self.update();
+        }
+        Ok(())
+    }
+
+    /// Most bytes one transaction may write from `offset` on,
+    /// so that the blocks it changes fit in the log.
+    pub fn write_limit(&self, offset: u32) -> usize {
+        if self.is_compressed() {
+            CLUSTER_SIZE - offset as usize % CLUSTER_SIZE
+        } else {
+            MAX_WRITE
+        }
+    }
+
+    fn read_cluster(&self, cluster: usize) -> Result<Cluster, &'static str> {
+        let mut data = [0u8; CLUSTER_SIZE];
+        let mut blocks = [None; CLUSTER_BLOCKS];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = self.block_at((cluster * CLUSTER_BLOCKS + i) as u32);
+        }
+        if blocks[CLUSTER_BLOCKS - 1].is_some() || blocks.iter().all(Option::is_none) {
+            // stored as is, or all zeros
+            read_blocks(self.dev, &blocks, &mut data);
+            return Ok(data);
+        }
+        let mut packed = [0u8; PACKED_SIZE];
+        read_blocks(self.dev, &blocks[..CLUSTER_BLOCKS - 1], &mut packed);
+        let len = u32::from_ne_bytes([packed[0], packed[1], packed[2], packed[3]]) as usize;
+        if len > PACKED_SIZE - HEADER {
+            return Err(CORRUPTED_CLUSTER);
+        }
+        match lz4::decompress(&packed[HEADER..HEADER + len], &mut data) {
+            Some(CLUSTER_SIZE) => Ok(data),
+            _ => Err(CORRUPTED_CLUSTER),
+        }
+    }
+
+    fn write_cluster(&mut self, cluster: usize, data: &Cluster) -> Result<(), &'static str> {
+        let mut packed = [0u8; PACKED_SIZE];
+        let nblocks = if data.iter().all(|&b| b == 0) {
+            0
+        } else {
+            match lz4::compress(data, &mut packed[HEADER..]) {
+                Some(len) => {
+                    packed[..HEADER].copy_from_slice(&(len as u32).to_ne_bytes());
+                    (HEADER + len + BSIZE - 1) / BSIZE
+                }
+                None => CLUSTER_BLOCKS,
+            }
+        };
+        let src: &[u8] = if nblocks == CLUSTER_BLOCKS { data } else { &packed };
+        let first = cluster * CLUSTER_BLOCKS;
+        for i in 0..nblocks {
+            let blockno = self.bmap((first + i) as u32, false)?;
+            let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, blockno);
+            unsafe {
+                ptr::copy_nonoverlapping(src[i * BSIZE..].as_ptr(), buf.raw_data_mut() as *mut u8, BSIZE)
+            };
+            LOG_MANAGER.write(buf);
+        }
+        for i in nblocks..CLUSTER_BLOCKS {
+            self.unmap((first + i) as u32);
+        }
+        Ok(())
+    }
+
+    /// `read` for a compressed file.
+    pub(crate) fn read_compressed(&self, dst: usize, offset: u32, count: u32) -> Result<usize, &'static str> {
+        let size = self.dinode.size as usize;
+        let offset = offset as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let end = min(offset + count as usize, size);
+        let mut pos = offset;
+        while pos < end {
+            let start = pos % CLUSTER_SIZE;
+            let len = min(end - pos, CLUSTER_SIZE - start);
+            let data = self.read_cluster(pos / CLUSTER_SIZE)?;
+            unsafe { ptr::copy_nonoverlapping(data[start..].as_ptr(), (dst + pos - offset) as *mut u8, len) };
+            pos += len;
+        }
+        Ok(end - offset)
+    }
+
+    /// `write` for a compressed file.
+    pub(crate) fn write_compressed(&mut self, src: usize, offset: u32, count: u32) -> Result<usize, &'static str> {
+        let offset = offset as usize;
+        let end = offset + count as usize;
+        if (end + CLUSTER_SIZE - 1) / CLUSTER_SIZE * CLUSTER_BLOCKS > MAXFILE {
+            return Err("file too large");
+        }
+        let mut pos = offset;
+        while pos < end {
+            let start = pos % CLUSTER_SIZE;
+            let len = min(end - pos, CLUSTER_SIZE - start);
+            let cluster = pos / CLUSTER_SIZE;
+            let mut data = if len == CLUSTER_SIZE { [0; CLUSTER_SIZE] } else { self.read_cluster(cluster)? };
+            unsafe { ptr::copy_nonoverlapping((src + pos - offset) as *const u8, data[start..].as_mut_ptr(), len) };
+            self.write_cluster(cluster, &data)?;
+            pos += len;
+        }
+        if self.dinode.size < end as u32 {
+            self.dinode.size = end as u32;
+        }
+        self.update();
+        Ok(count as usize)
+    }
+
+    /// Zero the bytes from `size` on in the cluster that holds them,
+    /// when a compressed file is cut to `size`.
+    pub(crate) fn zero_cluster_tail(&mut self, size: usize) -> Result<(), &'static str> {
+        if size % CLUSTER_SIZE == 0 {
+            return Ok(());
+        }
+        let cluster = size / CLUSTER_SIZE;
+        let mut data = self.read_cluster(cluster)?;
+        data[size % CLUSTER_SIZE..].fill(0);
+        self.write_cluster(cluster, &data)
+    }
+}
+
+/// Read the blocks into consecutive chunks of `dst`, holes are zeros.
+fn read_blocks(dev: u32, blocks: &[Option<u32>], dst: &mut [u8]) {
+    for (block, chunk) in blocks.iter().zip(dst.chunks_mut(BSIZE)) {
+        if let Some(blockno) = *block {
+            let buf = BLOCK_CACHE_MANAGER.bread(dev, blockno);
+            unsafe { ptr::copy_nonoverlapping(buf.raw_data() as *const u8, chunk.as_mut_ptr(), BSIZE) };
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Hashed directory index.
+//!
+//! A directory whose inode has `IFLAG_DIR_INDEX` set keeps its entries in
+//! buckets, found through an extendible hash table stored in the directory:
+//!
+//! - Block 0 holds "." and ".." in its first two slots, like any directory.
+//! - The other slots of block 0, and of block 1 once the table gets large,
+//!   hold the index: a magic number, the depth of the table and the table
+//!   itself, one logical block number per entry. The index only uses the
+//!   name bytes of slots whose inum is 0, so code that scans the directory
+//!   linearly sees empty slots there.
+//! - Each following block is a bucket of `DirEntry`s. An entry whose name
+//!   hashes to `h` lives in the bucket `table[h % 2^depth]`.
+//!
+//! Looking up, adding or removing an entry reads at most the two index blocks
+//! and one bucket. A full bucket is split in two on insert, doubling the table
+//! if needed, up to `MAX_DEPTH`.
+
+use core::mem::size_of;
+
+use array_macro::array;
+
+use crate::disk_inode::{DirEntry, InodeType, IFLAG_DIR_INDEX};
+use crate::fs_const::{BSIZE, DIRSIZ, MAXFILE};
+use crate::inode::InodeData;
+
+const DIRENT_SIZE: usize = size_of::<DirEntry>();
+const DIRENTS_PER_BLOCK: usize = BSIZE / DIRENT_SIZE;
+/// Index words kept in the name bytes of an empty slot.
+const WORDS_PER_SLOT: usize = (DIRENT_SIZE - 2) / 2;
+/// Index words that fit in block 0, after "." and "..".
+const BLOCK0_WORDS: usize = (DIRENTS_PER_BLOCK - 2) * WORDS_PER_SLOT;
+
+const INDEX_MAGIC: u16 = 0x4458;
+/// Maximum depth of the table, the table has up to `2^MAX_DEPTH` entries.
+pub const MAX_DEPTH: u32 = 8;
+const MAGIC_WORD: usize = 0;
+const DEPTH_WORD: usize = 1;
+const TABLE_WORD: usize = 2;
+/// Logical block of the first bucket, after the index blocks.
+const FIRST_BUCKET: u32 = 2;
+
+type DirBlock = [DirEntry; DIRENTS_PER_BLOCK];
+
+/// FNV-1a hash of an entry name.
+fn name_hash(name: &[u8]) -> u32 {
+    name_of(name).iter().fold(0x811c_9dc5, |h, &c| (h ^ c as u32).wrapping_mul(0x0100_0193))
+}
+
+/// The bytes of an entry name, up to its terminating NUL.
+fn name_of(name: &[u8]) -> &[u8] {
+    let name = &name[..name.len().min(DIRSIZ)];
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    &name[..len]
+}
+
+fn empty_block() -> DirBlock {
+    array![_ => DirEntry::new(); DIRENTS_PER_BLOCK]
+}
+
+/// In-memory copy of the index blocks.
+struct DirIndex {
+    raw: [u8; 2 * BSIZE],
+}
+
+impl DirIndex {
+    const fn new() -> Self {
+        Self { raw: [0; 2 * BSIZE] }
+    }
+
+    fn word_offset(word: usize) -> usize {
+        let slot = 2 + word / WORDS_PER_SLOT;
+        slot * DIRENT_SIZE + 2 + word % WORDS_PER_SLOT * 2
+    }
+
+    fn word(&self, word: usize) -> u16 {
+        let off = Self::word_offset(word);
+        u16::from_ne_bytes([self.raw[off], self.raw[off + 1]])
+    }
+
+    fn set_word(&mut self, word: usize, value: u16) {
+        let off = Self::word_offset(word);
+        self.raw[off..off + 2].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    fn depth(&self) -> u32 {
+        self.word(DEPTH_WORD) as u32
+    }
+
+    /// Number of index blocks a table of `depth` needs.
+    fn blocks(depth: u32) -> usize {
+        if TABLE_WORD + (1 << depth) <= BLOCK0_WORDS { 1 } else { 2 }
+    }
+
+    fn table(&self, i: usize) -> u32 {
+        self.word(TABLE_WORD + i) as u32
+    }
+
+    fn set_table(&mut self, i: usize, lb: u32) {
+        self.set_word(TABLE_WORD + i, lb as u16);
+    }
+
+    /// Table slot of a name hash.
+    fn slot(&self, hash: u32) -> usize {
+        (hash & ((1 << self.depth()) - 1)) as usize
+    }
+
+    /// Inode number of "." (slot 0) or ".." (slot 1).
+    fn dot_inum(&self, slot: usize) -> u32 {
+        let off = slot * DIRENT_SIZE;
+        u16::from_ne_bytes([self.raw[off], self.raw[off + 1]]) as u32
+    }
+
+    /// Number of bits of the hash that the bucket `lb` is split on.
+    fn local_depth(&self, lb: u32) -> u32 {
+        let depth = self.depth();
+        let refs = (0..1 << depth).filter(|&i| self.table(i) == lb).count();
+        depth - refs.trailing_zeros()
+    }
+
+    /// Double the table, each bucket is then referred to twice as often.
+    fn grow(&mut self) {
+        let depth = self.depth();
+        for i in 0..1 << depth {
+            self.set_table(i + (1 << depth), self.table(i));
+        }
+        self.set_word(DEPTH_WORD, depth as u16 + 1);
+    }
+}
+
+impl InodeData {
+    /// Whether this directory keeps a hashed index of its entries.
+    pub fn is_dir_indexed(&self) -> bool {
+        self.dinode.itype == InodeType::Directory && self.dinode.flags() & IFLAG_DIR_INDEX != 0
+    }
+
+    /// Turn on the hashed index of this directory.
+    /// Caller must hold inode's sleeplock, and the directory must not hold
+    /// anything but "." and "..".
+    pub fn dir_make_indexed(&mut self) -> Result<(), &'static str> {
+        if self.dinode.itype != InodeType::Directory {
+            return Err("not a directory");
+        }
+        if self.is_dir_indexed() {
+            return Ok(());
+        }
+        if !self.is_dir_empty() {
+            return Err("directory not empty");
+        }
+        let mut index = DirIndex::new();
+        // keep "." and ".."
+        self.read(index.raw.as_mut_ptr() as usize, 0, 2 * DIRENT_SIZE as u32)?;
+        index.set_word(MAGIC_WORD, INDEX_MAGIC);
+        index.set_word(DEPTH_WORD, 0);
+        index.set_table(0, FIRST_BUCKET);
+        if self.dinode.size as usize > BSIZE {
+            // block 1 may hold stale names of removed entries
+            self.write(index.raw[BSIZE..].as_ptr() as usize, BSIZE as u32, BSIZE as u32)?;
+        }
+        self.write(index.raw.as_ptr() as usize, 0, BSIZE as u32)?;
+        self.write_dir_block(FIRST_BUCKET, &empty_block())?;
+        let flags = self.dinode.flags();
+        self.dinode.set_flags(flags | IFLAG_DIR_INDEX);
+        self.update();
+        Ok(())
+    }
+
+    fn read_index(&self) -> Result<DirIndex, &'static str> {
+        let mut index = DirIndex::new();
+        self.read(index.raw.as_mut_ptr() as usize, 0, BSIZE as u32)?;
+        if index.word(MAGIC_WORD) != INDEX_MAGIC || index.depth() > MAX_DEPTH {
+            return Err("bad directory index");
+        }
+        if DirIndex::blocks(index.depth()) > 1 {
+            self.read(index.raw[BSIZE..].as_mut_ptr() as usize, BSIZE as u32, BSIZE as u32)?;
+        }
+        Ok(index)
+    }
+
+    fn write_index(&mut self, index: &DirIndex) -> Result<(), &'static str> {
+        let len = DirIndex::blocks(index.depth()) * BSIZE;
+        self.write(index.raw.as_ptr() as usize, 0, len as u32)?;
+        Ok(())
+    }
+
+    fn read_dir_block(&self, lb: u32) -> Result<DirBlock, &'static str> {
+        let mut block = empty_block();
+        self.read(block.as_mut_ptr() as usize, lb * BSIZE as u32, BSIZE as u32)?;
+        Ok(block)
+    }
+
+    fn write_dir_block(&mut self, lb: u32, block: &DirBlock) -> Result<(), &'static str> {
+        self.write(block.as_ptr() as usize, lb * BSIZE as u32, BSIZE as u32)?;
+        Ok(())
+    }
+
+    /// Look for `name` in an indexed directory, returns its inode number.
+    pub(crate) fn index_lookup(&self, name: &[u8]) -> Result<Option<u32>, &'static str> {
+        let index = self.read_index()?;
+        let name = name_of(name);
+        if name == b"." || name == b".." {
+            return Ok(Some(index.dot_inum(name.len() - 1)).filter(|&inum| inum != 0));
+        }
+        let block = self.read_dir_block(index.table(index.slot(name_hash(name))))?;
+        Ok(block
+            .iter()
+            .find(|de| de.inum != 0 && name_of(&de.name) == name)
+            .map(|de| de.inum as u32))
+    }
+
+    /// Add the entry (name, inum) to an indexed directory.
+    /// Caller must have checked that `name` is not in the directory yet.
+    pub(crate) fn index_link(&mut self, name: &[u8], inum: u32) -> Result<(), &'static str> {
+        let name = name_of(name);
+        if name == b"." || name == b".." {
+            return Err("cannot link dot entries into an indexed directory");
+        }
+        let hash = name_hash(name);
+        let mut index = self.read_index()?;
+        // One split is enough unless the names collide badly, and every split
+        // writes more blocks to this transaction.
+        for _ in 0..2 {
+            let lb = index.table(index.slot(hash));
+            let mut block = self.read_dir_block(lb)?;
+            if let Some(de) = block.iter_mut().find(|de| de.inum == 0) {
+                de.inum = inum as u16;
+                de.name = [0; DIRSIZ];
+                de.name[..name.len()].copy_from_slice(name);
+                return self.write_dir_block(lb, &block);
+            }
+            self.split_bucket(&mut index, hash, block)?;
+        }
+        Err("directory bucket is full")
+    }
+
+    /// Split the full bucket that `hash` maps to in two.
+    fn split_bucket(
+        &mut self,
+        index: &mut DirIndex,
+        hash: u32,
+        mut block: DirBlock,
+    ) -> Result<(), &'static str> {
+        let lb = index.table(index.slot(hash));
+        let depth = index.local_depth(lb);
+        if depth == index.depth() {
+            if depth == MAX_DEPTH {
+                return Err("directory index is full");
+            }
+            index.grow();
+        }
+        let new_lb = self.dinode.size / BSIZE as u32;
+        if new_lb as usize >= MAXFILE || new_lb > u16::MAX as u32 {
+            return Err("directory is too large");
+        }
+        // table slots of this bucket with the next bit of the hash set
+        // now refer to the new bucket, and so do their entries
+        for i in 0..1 << index.depth() {
+            if index.table(i) == lb && (i >> depth) & 1 == 1 {
+                index.set_table(i, new_lb);
+            }
+        }
+        let mut new_block = empty_block();
+        let mut moved = 0;
+        for de in block.iter_mut().filter(|de| de.inum != 0) {
+            if (name_hash(&de.name) >> depth) & 1 == 1 {
+                new_block[moved].inum = de.inum;
+                new_block[moved].name = de.name;
+                moved += 1;
+                *de = DirEntry::new();
+            }
+        }
+        self.write_dir_block(new_lb, &new_block)?;
+        self.write_dir_block(lb, &block)?;
+        self.write_index(index)
+    }
+
+    /// Remove the entry `name` from an indexed directory.
+    pub(crate) fn index_unlink(&mut self, name: &[u8]) -> Result<(), &'static str> {
+        let name = name_of(name);
+        let index = self.read_index()?;
+        let lb = index.table(index.slot(name_hash(name)));
+        let mut block = self.read_dir_block(lb)?;
+        match block.iter_mut().find(|de| de.inum != 0 && name_of(&de.name) == name) {
+            Some(de) => {
+                *de = DirEntry::new();
+                self.write_dir_block(lb, &block)
+            }
+            None => Err("not find this file in the directory"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_hash() {
+        // FNV-1a test vectors
+        assert_eq!(name_hash(b""), 0x811c_9dc5);
+        assert_eq!(name_hash(b"a"), 0xe40c_292c);
+        assert_eq!(name_hash(b"foobar"), 0xbf9c_f968);
+        // only the name up to its NUL or DIRSIZ bytes counts
+        assert_eq!(name_hash(b"foobar\0\0junk"), name_hash(b"foobar"));
+        assert_eq!(name_hash(b"abcdefghijklmnopq"), name_hash(&b"abcdefghijklmnopq"[..DIRSIZ]));
+    }
+
+    fn new_index() -> DirIndex {
+        let mut index = DirIndex::new();
+        index.set_word(MAGIC_WORD, INDEX_MAGIC);
+        index.set_table(0, FIRST_BUCKET);
+        index
+    }
+
+    #[test]
+    fn test_split_table() {
+        let mut index = new_index();
+        assert_eq!(index.local_depth(FIRST_BUCKET), 0);
+        // split the only bucket: the table doubles, the odd hashes move
+        index.grow();
+        index.set_table(1, FIRST_BUCKET + 1);
+        assert_eq!(index.depth(), 1);
+        assert_eq!(index.slot(0b110), 0);
+        assert_eq!(index.slot(0b111), 1);
+        assert_eq!(index.local_depth(FIRST_BUCKET), 1);
+        assert_eq!(index.local_depth(FIRST_BUCKET + 1), 1);
+        // a second split of the even bucket on the next bit
+        index.grow();
+        index.set_table(2, FIRST_BUCKET + 2);
+        assert_eq!((0..4).map(|i| index.table(i)).collect::<Vec<_>>(), [2, 3, 4, 3]);
+        assert_eq!(index.local_depth(FIRST_BUCKET), 2);
+        assert_eq!(index.local_depth(FIRST_BUCKET + 1), 1);
+        assert_eq!(index.local_depth(FIRST_BUCKET + 2), 2);
+        assert_eq!(index.slot(0b110), 2);
+        // the index words don't touch the "." and ".." slots
+        assert_eq!(index.dot_inum(0), 0);
+        assert_eq!(index.dot_inum(1), 0);
+    }
+
+    #[test]
+    fn test_table_fits_index_blocks() {
+        assert_eq!(DirIndex::blocks(0), 1);
+        assert_eq!(DirIndex::blocks(MAX_DEPTH), 2);
+        let last = DirIndex::word_offset(TABLE_WORD + (1 << MAX_DEPTH) - 1);
+        assert!(last + 2 <= 2 * BSIZE);
+        let mut index = new_index();
+        for _ in 0..MAX_DEPTH {
+            index.grow();
+        }
+        assert_eq!(index.depth(), MAX_DEPTH);
+        assert!((0..1 << MAX_DEPTH).all(|i| index.table(i) == FIRST_BUCKET));
+        assert_eq!(index.local_depth(FIRST_BUCKET), 0);
+    }
+}
@@ -0,0 +1,83 @@
+//! Telling the block device which blocks are unused.
+//!
+//! `bfree` clears the bit of a block, but the committed file system still
+//! uses the block until the transaction that freed it is committed. So the
+//! freed blocks are queued, and discarded right after that commit, see
+//! `committed`. A block that is allocated again before then is taken off the
+//! queue by `balloc`.
+//!
+//! `trim` discards all free blocks at once, like fstrim, which also covers
+//! blocks freed before the file system was mounted and those of deleted
+//! snapshots.
+//!
+//! Locking: a bitmap block is held before `PENDING`, and so is the log.
+//! Discarding under `PENDING` keeps `balloc` from handing out a block that is
+//! being discarded.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::checksum;
+use crate::fs_const::{BPB, ROOTDEV};
+use crate::log::LOG_MANAGER;
+use crate::snapshot;
+use crate::superblock::SUPER_BLOCK;
+
+/// Blocks freed by transactions that are not committed yet.
+pub(crate) static PENDING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Called by the log after a commit, discard the blocks it freed.
+pub(crate) fn committed() {
+    let mut pending = PENDING.lock();
+    if pending.is_empty() {
+        return;
+    }
+    pending.sort_unstable();
+    pending.dedup();
+    let mut start = 0;
+    for i in 1..=pending.len() {
+        if i == pending.len() || pending[i] != pending[i - 1] + 1 {
+            BLOCK_CACHE_MANAGER.discard(pending[start], (i - start) as u32);
+            start = i;
+        }
+    }
+    pending.clear();
+}
+
+/// Discard the free blocks that lie in runs of at least `min_len` blocks,
+/// return how many were discarded.
+pub fn trim(min_len: u32) -> Result<u32, &'static str> {
+    LOG_MANAGER.flush();
+    let size = unsafe { SUPER_BLOCK.size() };
+    let min_len = min_len.max(1);
+    let mut trimmed = 0;
+    let mut first = 0;
+    while first < size {
+        let end = size.min((first / BPB + 1) * BPB);
+        // no bit in this bitmap block changes while it is held
+        let buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, unsafe { SUPER_BLOCK.bitmap_blockno(first) });
+        checksum::verify(ROOTDEV, &buf)?;
+        let bytes = buf.raw_data() as *const u8;
+        let pending = PENDING.lock().clone();
+        let is_free = |b: u32| {
+            let bi = b % BPB;
+            let byte = unsafe { *bytes.offset((bi / 8) as isize) };
+            byte & (1 << (bi % 8)) == 0 && !pending.contains(&b) && !snapshot::is_shared(b)
+        };
+        let mut run = first;
+        for b in first..=end {
+            if b < end && is_free(b) {
+                continue;
+            }
+            if b - run >= min_len {
+                BLOCK_CACHE_MANAGER.discard(run, b - run);
+                trimmed += b - run;
+            }
+            run = b + 1;
+        }
+        drop(buf);
+        first = end;
+    }
+    Ok(trimmed)
+}
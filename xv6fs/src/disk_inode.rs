@@ -1,6 +1,7 @@
 use core::ptr;
 
-use crate::fs_const::{ NDIRECT, DIRSIZ };
+use crate::fs_const::{ NADDRS, NDIRECT, DIRSIZ };
+use crate::xattr;
 
 #[repr(u16)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -11,6 +12,14 @@ pub enum InodeType {
     Device = 3
 }
 
+/// Inode flags. Only device inodes use `major` for a device number,
+/// the other inodes keep their flags in its high bits.
+pub const IFLAG_MASK: u16 = 0xff00;
+/// The directory keeps a hashed index of its entries, see `dir_index`.
+pub const IFLAG_DIR_INDEX: u16 = 0x0100;
+/// The file data is compressed, see `compress`.
+pub const IFLAG_COMPRESSED: u16 = 0x0200;
+
 /// On-disk inode structure
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -20,7 +29,10 @@ pub struct DiskInode {
     pub minor: i16, // Minor device number (T_DEVICE only)
     pub nlink: i16, // Number of links to inode in file system
     pub size: u32, // Size of file (bytes)
-    pub addrs: [u32; NDIRECT+2] // Data block addresses
+    /// Data block addresses: `ndirect()` direct ones, the indirect and the
+    /// double indirect block, then with `FEATURE_XATTR` the block of
+    /// extended attributes, see `xattr`
+    pub addrs: [u32; NADDRS],
 }
 
 #[repr(C)]
@@ -38,7 +50,7 @@ impl DiskInode {
             minor: 0,
             nlink: 0,
             size: 0,
-            addrs: [0; NDIRECT+2]
+            addrs: [0; NADDRS],
         }
     }
 
@@ -51,6 +63,45 @@ impl DiskInode {
             Err(())
         }
     }
+
+    pub fn flags(&self) -> u16 {
+        if self.itype == InodeType::Device {
+            0
+        } else {
+            self.major as u16 & IFLAG_MASK
+        }
+    }
+
+    pub fn set_flags(&mut self, flags: u16) {
+        debug_assert!(self.itype != InodeType::Device, "device inodes have no flags");
+        self.major = ((self.major as u16 & !IFLAG_MASK) | (flags & IFLAG_MASK)) as i16;
+    }
+
+    /// The block of extended attributes, 0 if none. Always 0 in the old
+    /// layout, which has no room for it.
+    pub fn xattr(&self) -> u32 {
+        if xattr::enabled() {
+            self.addrs[NADDRS - 1]
+        } else {
+            0
+        }
+    }
+
+    /// Only called with `FEATURE_XATTR`.
+    pub fn set_xattr(&mut self, blockno: u32) {
+        debug_assert!(xattr::enabled());
+        self.addrs[NADDRS - 1] = blockno;
+    }
+}
+
+/// Number of direct addresses in a disk inode: `NDIRECT` with
+/// `FEATURE_XATTR`, one more in the old layout of images without it.
+pub fn ndirect() -> usize {
+    if xattr::enabled() {
+        NDIRECT
+    } else {
+        NDIRECT + 1
+    }
 }
 
 impl DirEntry {
@@ -1,13 +1,17 @@
 use crate::bitmap::inode_alloc;
 use crate::disk_inode::{InodeType,DirEntry};
-use crate::fs_const::{ BSIZE, MAXOPBLOCKS, DIRSIZ };
-use crate::inode::{ICACHE,Inode, InodeData};
+use crate::fs_const::{ BSIZE, DIRSIZ, ROOTDEV };
+use crate::snapshot;
+use crate::inode::{ICACHE,Inode, InodeData, read_disk_inode};
 use super::stat::Stat;
 use crate::log::{LOG_MANAGER};
+use crate::interface::INTERFACE_MANAGER;
 use alloc::vec::Vec;
 use alloc::string::String;
 use axlog::{info, debug};
 use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u16)]
@@ -19,9 +23,39 @@ pub enum FileType {
     Device = 4,
 }
 
-#[derive(Clone)]
+/// The device behind a device file, I/O on it is forwarded to the kernel
+/// through `FsInterface::dev_read`/`dev_write`.
+#[derive(Clone, Copy, Debug)]
 pub struct Device {
+    pub major: i16,
+    pub minor: i16,
+}
+
+impl Device {
+    /// Whether this is a block device rather than a character device,
+    /// as the kernel's device table says.
+    pub fn is_block(&self) -> bool {
+        INTERFACE_MANAGER.interface.dev_is_block(self.major, self.minor)
+    }
+}
 
+/// A directory entry read by `vfile_read_dir`.
+#[derive(Clone, Copy, Debug)]
+pub struct DirEnt {
+    pub inum: u32,
+    name: [u8; DIRSIZ],
+    /// Only filled in if the entries were read with `with_type`.
+    pub itype: Option<InodeType>,
+    /// The device behind a device entry, filled in with its type.
+    pub dev: Option<Device>,
+}
+
+impl DirEnt {
+    /// The name without the NUL padding.
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(DIRSIZ);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
 }
 
 #[derive(Clone)]
@@ -38,16 +72,29 @@ pub enum FileInner {
 
 /// Virtual File, which can abstract struct to dispatch 
 /// syscall to specific file.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct VFile {
     pub(crate) ftype: FileType,
     pub(crate) readable: bool,
     pub(crate) writeable: bool,
     pub(crate) inode: Option<Inode>,
-    pub(crate) offset: u32
+    /// advanced by reads, which hold the inode lock shared only
+    pub(crate) offset: AtomicU32
     // inner: FileInner
 }
 
+impl Clone for VFile {
+    fn clone(&self) -> Self {
+        Self {
+            ftype: self.ftype,
+            readable: self.readable,
+            writeable: self.writeable,
+            inode: self.inode.clone(),
+            offset: AtomicU32::new(self.offset.load(Ordering::Relaxed)),
+        }
+    }
+}
+
 impl VFile {
     pub const fn init() -> Self {
         Self{
@@ -55,13 +102,13 @@ impl VFile {
             readable: false,
             writeable: false,
             inode: None,
-            offset: 0,
+            offset: AtomicU32::new(0),
         }
     }
 
     pub fn get_size(&self)->usize{
         let node=self.inode.as_ref().unwrap();
-        let guard=node.lock();
+        let guard=node.lock_shared();
         let res=guard.dinode.size;
         drop(guard);
         res as usize
@@ -82,13 +129,13 @@ impl VFile {
         match self.ftype {
             FileType::File|FileType::Directory => {
                 let inode = self.inode.as_ref().unwrap();
-                let mut inode_guard = inode.lock();
+                // readers of the same inode can proceed in parallel
+                let inode_guard = inode.lock_shared();
                 debug!("offset is {}",offset);
                 match inode_guard.read( addr, offset as u32, len as u32) {
                     Ok(size) => {
                         ret = size;
-                        let offset = unsafe { &mut *(&self.offset as *const _ as *mut u32)};
-                        *offset += ret as u32;
+                        self.offset.fetch_add(ret as u32, Ordering::Relaxed);
                         drop(inode_guard);
                         Ok(ret)
                     },
@@ -98,6 +145,12 @@ impl VFile {
                 }
             },
 
+            FileType::Device => {
+                let dev = self.vfile_dev();
+                let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+                INTERFACE_MANAGER.interface.dev_read(dev.major, dev.minor, offset, buf)
+            },
+
             _ => {
                 panic!("Invalid file!")
             },
@@ -122,23 +175,16 @@ impl VFile {
         match self.ftype {
             FileType::File|FileType::Directory => {
                 // write a few blocks at a time to avoid exceeding 
-                // the maxinum log transaction size, including
-                // inode, indirect block, allocation blocks, 
-                // and 2 blocks of slop for non-aligned writes. 
-                // this really belongs lower down, since inode write
-                // might be writing a device like console. 
-                let max = ((MAXOPBLOCKS -1 -1 -2) / 2) * BSIZE;
+                // the maxinum log transaction size, see `write_limit`.
                 let mut count  = 0;
                 let mut offset =offset;
                 while count < len {
-                    let mut write_bytes = len - count;
-                    if write_bytes > max { write_bytes = max; }
-                    info!("[Xv6fs] vfile_write: write bytes is {}",write_bytes);
-
                     // start log
-                    //LOG.begin_op();
+                    let op = LOG_MANAGER.begin_op();
                     let inode = self.inode.as_ref().unwrap();
                     let mut inode_guard = inode.lock();
+                    let write_bytes = (len - count).min(inode_guard.write_limit(offset));
+                    info!("[Xv6fs] vfile_write: write bytes is {}",write_bytes);
 
                     // return err when failt to write
                     inode_guard.write(
@@ -149,9 +195,8 @@ impl VFile {
 
                     // release sleeplock
                     drop(inode_guard);
-                    LOG_MANAGER.end_op();
                     // end log
-                    //LOG.end_op();
+                    drop(op);
 
                     // update loop data
                     // self.offset += write_bytes as u32;
@@ -163,6 +208,12 @@ impl VFile {
                 Ok(ret)
             },
 
+            FileType::Device => {
+                let dev = self.vfile_dev();
+                let buf = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+                INTERFACE_MANAGER.interface.dev_write(dev.major, dev.minor, offset as usize, buf)
+            },
+
             _ => {
                 panic!("Invalid File Type!")
             }
@@ -183,22 +234,21 @@ impl VFile {
             FileType::File|FileType::Directory => {
                 let inode = self.inode.as_ref().unwrap();
                 let inode_guard = inode.lock();
-                let max = ((MAXOPBLOCKS -1 -1 -2) / 2) * BSIZE;
                 let mut count  = 0;
                 let mut offset=inode_guard.dinode.size;
                 drop(inode_guard);
                 while count < len {
-                    let mut write_bytes = len - count;
-                    if write_bytes > max { write_bytes = max; }
-                    info!("[Xv6fs] vfile_write: write bytes is {}",write_bytes);
+                    let op = LOG_MANAGER.begin_op();
                     let mut inode_guard = inode.lock();
+                    let write_bytes = (len - count).min(inode_guard.write_limit(offset));
+                    info!("[Xv6fs] vfile_write: write bytes is {}",write_bytes);
                     inode_guard.write(
                         addr + count, 
                         offset, 
                         write_bytes as u32
                     )?;
                     drop(inode_guard);
-                    LOG_MANAGER.end_op();
+                    drop(op);
                     offset+=write_bytes as u32;
                     count += write_bytes;
                 }
@@ -216,7 +266,7 @@ impl VFile {
         self.readable
     }
 
-    fn vfile_writeable(&self) -> bool {
+    pub fn vfile_writeable(&self) -> bool {
         self.writeable
     }
 
@@ -225,13 +275,13 @@ impl VFile {
     pub fn vfile_stat(&self) -> Result<Stat, &'static str> {
         let mut stat: Stat = Stat::new();
         match self.ftype {
-            FileType::File|FileType::Directory => {
+            FileType::File|FileType::Directory|FileType::Device => {
                 let inode = self.inode.as_ref().unwrap();
                 
                 #[cfg(feature = "debug")]
                 info!("[Kernel] stat: inode index: {}, dev: {}, inum: {}", inode.index, inode.dev, inode.inum);
 
-                let inode_guard = inode.lock();
+                let inode_guard = inode.lock_shared();
                 inode_guard.stat(&mut stat);
                 drop(inode_guard);
                 
@@ -262,34 +312,74 @@ impl VFile {
         false
     }
 
+    pub fn vfile_is_dev(&self)->bool{
+        self.ftype==FileType::Device
+    }
+
+    /// The device behind a device file.
+    pub fn vfile_dev(&self)->Device{
+        let idata=self.inode.as_ref().unwrap().lock_shared();
+        Device{major:idata.dinode.major,minor:idata.dinode.minor}
+    }
+
     pub fn vfile_create_file(path:&str,readable:bool,writeable:bool)->Option<Self>{
         info!("vfile create file: path is {}",path);
+        let op=LOG_MANAGER.begin_op();
         let inode=ICACHE.create(path.as_bytes(),crate::disk_inode::InodeType::File, 2, 1).unwrap();
-        LOG_MANAGER.end_op();
-        Some(Self { ftype: FileType::File, readable, writeable, inode:Some(inode), offset:0})
+        drop(op);
+        Some(Self { ftype: FileType::File, readable, writeable, inode:Some(inode), offset: AtomicU32::new(0)})
     }
 
     pub fn vfile_create_dir(path:&str,readable:bool,writeable:bool)->Option<Self>{
         info!("vfile create dir: path is {}",path);
+        let op=LOG_MANAGER.begin_op();
         let inode=ICACHE.create(path.as_bytes(),crate::disk_inode::InodeType::Directory, 2, 1).unwrap();
-        LOG_MANAGER.end_op();
-        Some(Self { ftype: FileType::Directory, readable, writeable, inode:Some(inode), offset:0})
+        drop(op);
+        Some(Self { ftype: FileType::Directory, readable, writeable, inode:Some(inode), offset: AtomicU32::new(0)})
+    }
+
+    /// Create a device file whose I/O is forwarded to the device (major, minor).
+    pub fn vfile_mknod(path:&str,major:i16,minor:i16)->Result<Self,&'static str>{
+        info!("vfile mknod: path is {}, dev is ({}, {})",path,major,minor);
+        let op=LOG_MANAGER.begin_op();
+        let mut name=[0u8;DIRSIZ];
+        if ICACHE.namei_parent(path.as_bytes(), &mut name).is_none(){
+            return Err("mknod: parent directory not found");
+        }
+        let inode=ICACHE.create(path.as_bytes(),InodeType::Device, major, minor)?;
+        drop(op);
+        Ok(Self { ftype: FileType::Device, readable:true, writeable:true, inode:Some(inode), offset: AtomicU32::new(0)})
     }
 
     pub fn vfile_lookup(path:&str)->Option<Self>{
+        Self::vfile_try_lookup(path).ok()
+    }
+
+    /// Like `vfile_lookup`, but tells why the path can't be found,
+    /// e.g. `checksum::CORRUPTED`.
+    pub fn vfile_try_lookup(path:&str)->Result<Self,&'static str>{
+        Self::lookup_in(ROOTDEV, path)
+    }
+
+    /// Like `vfile_try_lookup`, but an absolute path is looked up in the tree
+    /// this file belongs to, the live tree or a snapshot.
+    pub fn vfile_try_lookup_from(&self,path:&str)->Result<Self,&'static str>{
+        let dev=self.inode.as_ref().map_or(ROOTDEV, |inode| inode.dev);
+        Self::lookup_in(dev, path)
+    }
+
+    fn lookup_in(root_dev:u32,path:&str)->Result<Self,&'static str>{
         info!("vfile lookup: path is {}",path);
-        match ICACHE.look_up(path.as_bytes()){
-            Ok(node)=>{
-                let guard=node.lock();
-                let ty=match guard.dinode.itype{
-                    InodeType::Directory=>FileType::Directory,
-                    _=>FileType::File,
-                };
-                drop(guard);
-                Some(Self { ftype: ty, readable:true, writeable:true, inode:Some(node), offset:0})
-            },
-            Err(_)=>None,
-        }
+        let node=ICACHE.look_up_in(root_dev, path.as_bytes())?;
+        let guard=node.try_lock_shared()?;
+        let ty=match guard.dinode.itype{
+            InodeType::Directory=>FileType::Directory,
+            InodeType::Device=>FileType::Device,
+            _=>FileType::File,
+        };
+        drop(guard);
+        let writeable=!snapshot::is_snapshot_dev(node.dev);
+        Ok(Self { ftype: ty, readable:true, writeable, inode:Some(node), offset: AtomicU32::new(0)})
     }
 
     pub fn vfile_readdir(&self)->Option<Vec<String>>{
@@ -297,19 +387,20 @@ impl VFile {
         if self.ftype!=FileType::Directory{
             panic!("this is not a directory!");
         }
-        let mut inode_data=self.inode.as_ref().unwrap().lock();
+        let inode_data=self.inode.as_ref().unwrap().lock_shared();
         inode_data.ls()
     }
 
     pub fn vfile_remove(&self,path:&str){
         info!("vfile remove");
+        let _op=LOG_MANAGER.begin_op();
         let _=ICACHE.remove(path.as_bytes());
-        LOG_MANAGER.end_op();
     }
 
     pub fn vfile_create_under_dir(&self,file_name:&str,itype:InodeType)->Self{
         info!("vfile create: path is {}",file_name);
         let self_inode=self.inode.as_ref().unwrap();
+        let op=LOG_MANAGER.begin_op();
         let mut self_idata=self_inode.lock();
         let dev=self_inode.dev;
         let inum=inode_alloc(dev,itype);
@@ -327,22 +418,88 @@ impl VFile {
             idata.update();
             let _=idata.dir_link(".".as_bytes(), inum);
             let _=idata.dir_link("..".as_bytes(), self_inode.inum);
+            if self_idata.is_dir_indexed(){
+                let _=idata.dir_make_indexed();
+            }
+        }
+        if self_idata.compresses_new_files(){
+            let _=idata.make_compressed();
         }
         self_idata.dir_link(file_name.as_bytes(), inode.inum).expect("parent inode fail to link");
         drop(idata);
         drop(self_idata);
-        LOG_MANAGER.end_op();
-        VFile { ftype, readable:true, writeable:true, inode:Some(inode), offset:0}
+        drop(op);
+        VFile { ftype, readable:true, writeable:true, inode:Some(inode), offset: AtomicU32::new(0)}
         
     }
 
+    /// Keep a hashed index of the entries of this directory,
+    /// which must only hold "." and "..". Its new subdirectories are indexed too.
+    pub fn vfile_set_dir_indexed(&self)->Result<(),&'static str>{
+        if self.ftype!=FileType::Directory{
+            return Err("not a directory");
+        }
+        let op=LOG_MANAGER.begin_op();
+        let mut idata=self.inode.as_ref().unwrap().lock();
+        let res=idata.dir_make_indexed();
+        drop(idata);
+        drop(op);
+        res
+    }
+
+    /// Compress the data of this file, which must be empty. For a directory,
+    /// compress the files and directories created in it from now on.
+    pub fn vfile_set_compressed(&self)->Result<(),&'static str>{
+        let op=LOG_MANAGER.begin_op();
+        let mut idata=self.inode.as_ref().unwrap().lock();
+        let res=idata.make_compressed();
+        drop(idata);
+        drop(op);
+        res
+    }
+
+    /// The value of the extended attribute `name`.
+    pub fn vfile_getxattr(&self,name:&str)->Result<Vec<u8>,&'static str>{
+        let idata=self.inode.as_ref().unwrap().lock_shared();
+        idata.get_xattr(name.as_bytes())
+    }
+
+    /// Set the extended attribute `name` to `value`.
+    pub fn vfile_setxattr(&self,name:&str,value:&[u8])->Result<(),&'static str>{
+        let op=LOG_MANAGER.begin_op();
+        let mut idata=self.inode.as_ref().unwrap().lock();
+        let res=idata.set_xattr(name.as_bytes(),value);
+        drop(idata);
+        drop(op);
+        res
+    }
+
+    /// The names of the extended attributes. Names that are not UTF-8 are
+    /// left out.
+    pub fn vfile_listxattr(&self)->Result<Vec<String>,&'static str>{
+        let idata=self.inode.as_ref().unwrap().lock_shared();
+        let names=idata.list_xattrs()?;
+        Ok(names.into_iter().filter_map(|name| String::from_utf8(name).ok()).collect())
+    }
+
+    /// Remove the extended attribute `name`.
+    pub fn vfile_removexattr(&self,name:&str)->Result<(),&'static str>{
+        let op=LOG_MANAGER.begin_op();
+        let mut idata=self.inode.as_ref().unwrap().lock();
+        let res=idata.remove_xattr(name.as_bytes());
+        drop(idata);
+        drop(op);
+        res
+    }
+
     pub fn vfile_size(&self)->usize{
         let inode=self.inode.as_ref().unwrap();
-        let idata=inode.lock();
+        let idata=inode.lock_shared();
         idata.dinode.size as usize
     }
 
     pub fn vfile_link(&self,src_path:&str,dir_path:&str){
+        let op=LOG_MANAGER.begin_op();
         let inode=match ICACHE.namei(src_path.as_bytes()) {
             Some(cur)=>{
                 cur
@@ -371,11 +528,12 @@ impl VFile {
         parent_guard.update();
         drop(parent_guard);
         drop(inode_guard);
-        LOG_MANAGER.end_op();
+        drop(op);
     }
 
     pub fn vfile_unlink(&self,path:&str){//目录没有删掉dir entry
         info!("[Xv6fs] vfile unlink: unlink {}",path);
+        let op=LOG_MANAGER.begin_op();
         let mut name = [0u8; DIRSIZ];
         let parent=match ICACHE.namei_parent(&path.as_bytes(), &mut name) {
             Some(cur)=>cur,
@@ -409,7 +567,7 @@ impl VFile {
             let _=parent_guard.dir_unlink(&name);
             drop(parent_guard);
         }
-        LOG_MANAGER.end_op();
+        drop(op);
     }
 
     pub fn vfile_rename(&self,path:&str,new_name:&str){
@@ -417,34 +575,80 @@ impl VFile {
     }
 
     pub fn vfile_pass_dir(&self)->Option<Vec<(String,InodeType)>>{
-        let mut inode_guard=self.inode.as_ref().unwrap().lock();
-        let mut v=Vec::new();
-        let de_size = size_of::<DirEntry>();
-        let mut dir_entry = DirEntry::new();
-        let dir_entry_ptr = &mut dir_entry as *mut _ as *mut u8;
-        for offset in (0..inode_guard.dinode.size).step_by(de_size) {
-            inode_guard.read(
-                dir_entry_ptr as usize, 
-                offset, 
-                de_size as u32
-            ).expect("Cannot read entry in this dir");
-            if dir_entry.inum == 0 {
-                continue;
-            }
-            // info!("dir_entry_name: {}, name: {}", String::from_utf8(dir_entry.name.to_vec()).unwrap(), String::from_utf8(name.to_vec()).unwrap());
-            let name=String::from_utf8(dir_entry.name.to_vec()).unwrap();
-            let itype=ICACHE.get_inum_type(inode_guard.dev,dir_entry.inum as u32);
-            v.push((name,itype));
-
-        }
+        let (entries,_)=self.vfile_read_dir(0, usize::MAX, true).ok()?;
+        let v:Vec<(String,InodeType)>=entries.iter()
+            .map(|ent|(String::from(ent.name()),ent.itype.unwrap()))
+            .collect();
         info!("xv6fs: vfile pass dir is {:?}",v);
         Some(v)
     }
 
+    /// Read up to `max` entries of the directory, starting at the byte
+    /// `offset` into it, which is 0 or an offset returned by an earlier call.
+    /// Returns the entries, including "." and "..", and the offset to resume from.
+    /// Only the blocks that hold these entries are read. Their types are
+    /// read from the inode blocks too if `with_type` is set.
+    pub fn vfile_read_dir(&self,offset:u32,max:usize,with_type:bool)->Result<(Vec<DirEnt>,u32),&'static str>{
+        if self.ftype!=FileType::Directory{
+            return Err("not a directory");
+        }
+        let inode_guard=self.inode.as_ref().unwrap().try_lock_shared()?;
+        let de_size=size_of::<DirEntry>() as u32;
+        let size=inode_guard.dinode.size;
+        let mut offset=offset-offset%de_size;
+        let mut entries=Vec::new();
+        let mut block=[0u8;BSIZE];
+        while offset<size && entries.len()<max{
+            // read the rest of the block at once
+            let len=(BSIZE as u32-offset%BSIZE as u32).min(size-offset);
+            let len=inode_guard.read(block.as_mut_ptr() as usize, offset, len)? as u32;
+            if len==0{
+                break;
+            }
+            let mut pos=0;
+            while pos+de_size<=len && entries.len()<max{
+                let dir_entry=unsafe{ ptr::read_unaligned(block.as_ptr().add(pos as usize) as *const DirEntry) };
+                pos+=de_size;
+                if dir_entry.inum!=0{
+                    entries.push(DirEnt{inum:dir_entry.inum as u32,name:dir_entry.name,itype:None,dev:None});
+                }
+            }
+            offset+=pos;
+        }
+        let dev=inode_guard.dev;
+        drop(inode_guard);
+        if with_type{
+            for ent in entries.iter_mut(){
+                // the inodes aren't loaded into the inode cache, most of the
+                // entries share a few inode blocks
+                let dinode=read_disk_inode(dev,ent.inum)?;
+                ent.itype=Some(dinode.itype);
+                if dinode.itype==InodeType::Device{
+                    ent.dev=Some(Device{major:dinode.major,minor:dinode.minor});
+                }
+            }
+        }
+        Ok((entries,offset))
+    }
+
+    /// Make everything written through this file durable.
+    /// The log is shared by the whole fs, so this commits all pending operations.
+    pub fn vfile_fsync(&self){
+        LOG_MANAGER.flush();
+    }
+
     pub fn vfile_truncate(&self,size:u64)->usize{
+        if self.ftype==FileType::Device{
+            return 0;
+        }
+        if !self.vfile_writeable() {
+            panic!("file can't be written")
+        }
+        let op=LOG_MANAGER.begin_op();
         let mut inode_guard=self.inode.as_ref().unwrap().lock();
         let res=inode_guard.resize(self.inode.as_ref().unwrap(), size);
-        LOG_MANAGER.end_op();
+        drop(inode_guard);
+        drop(op);
         res
 
     }
@@ -492,7 +696,7 @@ pub fn test_link_unlink(){
         readable:true, 
         writeable:true, 
         inode:Some(inode), 
-        offset:0,
+        offset: AtomicU32::new(0),
     };
     root.vfile_readdir().map(|x| {
         for file_name in x {
@@ -6,10 +6,15 @@ pub const FSMAGIC: u32 = 0x10203040;
 pub const BSIZE: usize = 512;
 /// Maxinum of blocks an FS op can write
 pub const MAXOPBLOCKS: usize = 10;
-/// size of buffer cache for block
-pub const NBUF: usize = MAXOPBLOCKS * 3;
-/// size of log space in disk
-pub const LOGSIZE: usize = MAXOPBLOCKS * 3;
+/// Most blocks an FS op reserves in the log: its own blocks and,
+/// with metadata checksums, the checksum table blocks covering them
+pub const OPLOGBLOCKS: usize = MAXOPBLOCKS * 2;
+/// size of log space in disk, room for three ops in flight
+/// plus the header block and the two spare slots the log keeps
+pub const LOGSIZE: usize = OPLOGBLOCKS * 3 + 3;
+/// size of buffer cache for block,
+/// it must hold a full log of pinned blocks plus the blocks of a running op
+pub const NBUF: usize = LOGSIZE + MAXOPBLOCKS;
 
 /// open files per process
 pub const NOFILE: usize = 16;
@@ -28,9 +33,14 @@ pub const FSSIZE: usize = 1000;
 
 pub const ROOTINUM: u32 = 1;
 
-pub const NDIRECT: usize = 11;
+/// direct addresses with `FEATURE_XATTR`, one address went to the block of
+/// extended attributes. The old layout has one more, see `disk_inode::ndirect`.
+pub const NDIRECT: usize = 10;
+/// addresses in a disk inode, of both layouts
+pub const NADDRS: usize = NDIRECT + 3;
 pub const NINDIRECT: usize =  BSIZE / 8;
 pub const NININDIRECT: usize = BSIZE / 8 * NINDIRECT;
+/// the limit of both layouts
 pub const MAXFILE: usize = NDIRECT + NINDIRECT + NININDIRECT;
 
 /// Directory is a file containing a sequence of dirent structures
@@ -42,6 +52,9 @@ pub const IPB: usize = BSIZE / size_of::<DiskInode>();
 /// Bitmap bits per block
 pub const BPB: u32 = (BSIZE*8) as u32;
 
+/// maximum number of snapshots of the file system
+pub const NSNAPSHOT: usize = 4;
+
 #[inline]
 pub fn major(dev: usize) -> usize {
     (dev >> 16) & 0xFFFF
@@ -6,16 +6,20 @@ use axlog::{info};
 #[cfg(test)]
 use std::{println as info}; // Workaround to use prinltn! for logs.
 
-use crate::{SleepLock, init_lock, SleepLockGuard};
-use crate::fs_const::{BSIZE, DIRSIZ, IPB, NDIRECT, NINDIRECT, NINODE, ROOTDEV, ROOTINUM, NININDIRECT};
+use crate::{SleepLock, init_lock, SleepLockGuard, SleepLockSharedGuard};
+use crate::fs_const::{BSIZE, DIRSIZ, IPB, NINDIRECT, NINODE, ROOTDEV, ROOTINUM, NININDIRECT};
 use crate::log::LOG_MANAGER;
 use crate::bitmap::{inode_alloc, bisalloc};
 use crate::misc::{min, mem_set};
 use crate::interface::INTERFACE_MANAGER;
+use crate::checksum;
+use crate::snapshot;
+use crate::compress::CLUSTER_BLOCKS;
 
 use spin::{Mutex};
 
 use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::ptr::{self, read, write};
 use core::{str, usize};
 
@@ -24,7 +28,7 @@ use array_macro::array;
 use crate::buffer_cache::{BLOCK_CACHE_MANAGER};
 use crate::superblock::{SUPER_BLOCK};
 use super::stat::Stat;
-use crate::disk_inode::{ InodeType, DiskInode, DirEntry };
+use crate::disk_inode::{ InodeType, DiskInode, DirEntry, ndirect };
 use super::bitmap::{balloc, bfree};
 use alloc::{vec::Vec,string::String};
 use lazy_init::LazyInit;
@@ -71,16 +75,20 @@ impl InodeCache {
         if imeta.refs == 1 {
             // SAFETY: reference count is 1, so this lock will not block. 
             let mut idata = self.data[i].lock();
-            if !idata.valid || idata.dinode.nlink > 0 {
+            // a snapshot is read-only, even its unlinked inodes stay
+            if !idata.valid || idata.dinode.nlink > 0 || snapshot::is_snapshot_dev(inode.dev) {
                 idata.valid = false;
                 drop(idata);
                 imeta.refs -= 1;
                 drop(guard);
             } else {
+                // freeing it is an fs op, which begins before the inode is
+                // locked. This reference keeps it in the cache meanwhile.
+                drop(idata);
                 drop(guard);
-                idata.dinode.itype = InodeType::Empty;
-                idata.truncate(inode);
-                idata.valid = false;
+                let op = LOG_MANAGER.begin_op();
+                let mut idata = self.data[i].lock();
+                idata.free(inode);
                 drop(idata);
 
                 // recycle after this inode content in the cache is no longer valid. 
@@ -91,6 +99,7 @@ impl InodeCache {
                 guard[i].refs -= 1;
                 debug_assert_eq!(guard[i].refs, 0);
                 drop(guard);
+                drop(op);
             }
         } else {
             imeta.refs -= 1;
@@ -113,6 +122,7 @@ impl InodeCache {
             // read block into buffer by device and block_id
             debug!("alloc");
             let mut block = BLOCK_CACHE_MANAGER.bread(dev, block_id);
+            checksum::assert_valid(dev, &block);
         
             // Get inode offset in the block
             let offset = locate_inode_offset(inum) as isize;
@@ -120,6 +130,7 @@ impl InodeCache {
             let dinode = unsafe{ &mut *dinode };
             // Find a empty inode
             if dinode.try_alloc(itype).is_ok() {
+                checksum::update(dev, &block);
                 LOG_MANAGER.write(block);
                 return Some(self.get(dev, inum))
             }
@@ -170,25 +181,18 @@ impl InodeCache {
         }
     }
 
-    pub fn get_inum_type(&self,dev: u32,inum: u32)->InodeType{
-        let inode=self.get(dev, inum);
-        let inode_data=inode.lock();
-        let dinode=inode_data.dinode;
-        let itype=dinode.itype;
-        drop(inode_data);
-        itype 
-    }
-
     /// Helper function for 'namei' and 'namei_parent'
+    /// Absolute paths start at the root of `root_dev`, the live tree or a snapshot.
     fn namex(
         &self, 
+        root_dev: u32,
         path: &[u8], 
         name: &mut [u8;DIRSIZ], 
         is_parent: bool
-    ) -> Option<Inode> {
+    ) -> Result<Inode, &'static str> {
         let mut inode: Inode;
         if path[0] == b'/' {
-            inode = self.get(ROOTDEV, ROOTINUM);
+            inode = self.get(root_dev, ROOTINUM);
             //info!("path 0 is /");
         } else {
             //这里是要获取当前目录的名称
@@ -199,23 +203,23 @@ impl InodeCache {
             cur = skip_path(path, cur, name);//这里name获取了/后面的第一个路径名
             if cur == 0 { break; }
             //info!("cur is {:?}, and name is {:?}",cur,String::from_utf8(name.to_vec()).unwrap());
-            let mut data_guard = inode.lock();
+            let data_guard = inode.try_lock_shared()?;
             //info!("acquire lock");
             if data_guard.dinode.itype != InodeType::Directory {
                 drop(data_guard);
-                return None
+                return Err("not a directory")
             }
             if is_parent && path[cur] == 0 {
                 //info!("is is parent and path[cur]=0");
                 drop(data_guard);
-                return Some(inode)
+                return Ok(inode)
             }
 
-            match data_guard.dir_lookup(name) {
+            match data_guard.dir_try_lookup(name)? {
                 None => {
                     drop(data_guard);
                     // info!("[Kernel] name: {}", String::from_utf8(name.to_vec()).unwrap());
-                    return None
+                    return Err("not found")
                 },
                 Some(last_inode) => {
                     drop(data_guard);
@@ -227,33 +231,41 @@ impl InodeCache {
         if is_parent {
             // only when querying root inode's parent 
             //info!("[Kernel] Warning: namex querying root inode's parent");
-            None 
+            Err("not found")
         } else {
-            Some(inode)
+            Ok(inode)
         }
     }
 
     /// namei interprets the path argument as an pathname to Unix file. 
     /// It will return an [`inode`] if succeed, Err(()) if fail. 
-    /// It must be called inside a transaction(i.e.,'begin_op' and `end_op`) since it calls `put`.
+    /// It may free an inode through `put`, so it must be called before taking any inode lock.
     /// Note: the path should end with 0u8, otherwise it might panic due to out-of-bound. 
     pub fn namei(&self, path: &[u8]) -> Option<Inode> {
         let mut name: [u8;DIRSIZ] = [0;DIRSIZ];
-        self.namex(path, &mut name, false)
+        self.namex(ROOTDEV, path, &mut name, false).ok()
     }
 
     /// Same behavior as `namei`, but return the parent of the inode, 
     /// and copy the end path into name. 
     pub fn namei_parent(&self, path: &[u8], name: &mut [u8;DIRSIZ]) -> Option<Inode> {
-        self.namex(path, name, true)
+        self.namex(ROOTDEV, path, name, true).ok()
     }
 
+    /// Like `namei`, but tells why the path can't be resolved,
+    /// e.g. `checksum::CORRUPTED`.
     pub fn look_up(&self,path: &[u8])->Result<Inode, &'static str>{
+        self.look_up_in(ROOTDEV, path)
+    }
+
+    /// Like `look_up`, but absolute paths are looked up in the tree of
+    /// `root_dev`, which is `ROOTDEV` or the device of a snapshot.
+    pub fn look_up_in(&self,root_dev: u32,path: &[u8])->Result<Inode, &'static str>{
         info!("[Xv6fs] lookup file/dir: path: {}", String::from_utf8(path.to_vec()).unwrap());
         let mut name: [u8; DIRSIZ] = [0; DIRSIZ];
-        let dirinode = self.namei_parent(path, &mut name).unwrap();
-        let mut dirinode_guard = dirinode.lock();
-        match dirinode_guard.dir_lookup(&name) {
+        let dirinode = self.namex(root_dev, path, &mut name, true)?;
+        let dirinode_guard = dirinode.try_lock_shared()?;
+        match dirinode_guard.dir_try_lookup(&name)? {
             Some(node) => Ok(node), 
             None => Err("not found"),
         }
@@ -313,6 +325,13 @@ impl InodeCache {
             // No nlink++ for . to avoid recycle ref count. 
             inode_guard.dir_link(".".as_bytes(), inode.inum)?;
             inode_guard.dir_link("..".as_bytes(), dirinode_guard.inum)?;
+            // subdirectories of an indexed directory are indexed too
+            if dirinode_guard.is_dir_indexed() {
+                inode_guard.dir_make_indexed()?;
+            }
+        }
+        if dirinode_guard.compresses_new_files() && itype != InodeType::Device {
+            inode_guard.make_compressed()?;
         }
         dirinode_guard
             .dir_link(&name, inode_guard.inum)
@@ -327,6 +346,11 @@ impl InodeCache {
         self.get(ROOTDEV, ROOTINUM)
     }
 
+    /// Whether any inode of `dev` is referenced.
+    pub fn dev_in_use(&self, dev: u32) -> bool {
+        self.meta.lock().iter().any(|imeta| imeta.refs > 0 && imeta.dev == dev)
+    }
+
     pub fn remove(&self,path: &[u8])->Result<(),&'static str>{
         //info!("begin remove");
         info!("[Xv6fs] remove file/dir, path is {:?}",core::str::from_utf8(path));
@@ -342,19 +366,15 @@ impl InodeCache {
                 match idata.dinode.itype {
                     InodeType::Directory=> {
                         idata.clear_dir()?;
-                        idata.dinode.itype=InodeType::Empty;
-                        idata.truncate(&inode);
-                        idata.valid=false;
+                        idata.free(&inode);
                         drop(idata);
                         dirinode_guard.dir_unlink(&name)?;
                         dirinode_guard.update();
                         return Ok(());
                     },
 
-                    InodeType::File=>{
-                        idata.dinode.itype=InodeType::Empty;
-                        idata.truncate(&inode);
-                        idata.valid=false;
+                    InodeType::File|InodeType::Device=>{
+                        idata.free(&inode);
                         drop(idata);
                         dirinode_guard.dir_unlink(&name)?;
                         dirinode_guard.update();
@@ -440,16 +460,21 @@ pub struct InodeData {
     pub valid: bool,
     pub dev: u32,
     pub inum: u32,
-    pub dinode: DiskInode
+    pub dinode: DiskInode,
+    /// Count of `data_blocks`, `UNKNOWN_BLOCKS` until it is counted.
+    nblocks: AtomicUsize,
 }
 
+const UNKNOWN_BLOCKS: usize = !0;
+
 impl InodeData {
     const fn new() -> Self {
         Self {
             valid: false,
             dev: 0,
             inum: 0,
-            dinode: DiskInode::new()
+            dinode: DiskInode::new(),
+            nblocks: AtomicUsize::new(UNKNOWN_BLOCKS),
         }
     }
 
@@ -461,6 +486,7 @@ impl InodeData {
         stat.itype = self.dinode.itype;
         stat.nlink = self.dinode.nlink;
         stat.size = self.dinode.size as usize;
+        stat.phys_size = self.data_blocks() * BSIZE;
     }
 
     pub fn clear_block(dev:u32,block_id:u32){
@@ -472,10 +498,21 @@ impl InodeData {
         LOG_MANAGER.write(buf);
     }
 
-    /// Discard the inode data/content. 
+    /// Free the inode on disk with its data, and tell the kernel so that it
+    /// drops what it caches for the inode.
+    pub fn free(&mut self, inode: &Inode) {
+        self.dinode.itype = InodeType::Empty;
+        self.truncate(inode);
+        self.valid = false;
+        INTERFACE_MANAGER.interface.inode_freed(inode.dev, inode.inum);
+    }
+
+    /// Discard the inode data/content and its extended attributes,
+    /// when the inode is freed.
     pub fn truncate(&mut self, inode: &Inode) {
+        let ndirect = ndirect();
         // direct block
-        for i in 0..NDIRECT {
+        for i in 0..ndirect {
             if self.dinode.addrs[i] > 0 {
                 let _=bfree(self.dinode.addrs[i]);
                 self.dinode.addrs[i] = 0;
@@ -483,9 +520,9 @@ impl InodeData {
         }
 
         // indirect block
-        if self.dinode.addrs[NDIRECT] > 0 {
+        if self.dinode.addrs[ndirect] > 0 {
             //debug!("truncate bread indirect block ");
-            let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[NDIRECT]);
+            let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[ndirect]);
             let buf_ptr = buf.raw_data() as *const BlockNo;
             for i in 0..NINDIRECT {
                 let bn = unsafe{ read(buf_ptr.offset(i as isize)) };
@@ -494,13 +531,13 @@ impl InodeData {
                 }
             }
             drop(buf);
-            let _=bfree(self.dinode.addrs[NDIRECT]);//这里要清空这个页面才行
-            self.dinode.addrs[NDIRECT] = 0;
+            let _=bfree(self.dinode.addrs[ndirect]);//这里要清空这个页面才行
+            self.dinode.addrs[ndirect] = 0;
         }
 
-        if self.dinode.addrs[NDIRECT+1] > 0 {
+        if self.dinode.addrs[ndirect+1] > 0 {
             //debug!("truncate bread inindirect block");
-            let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[NDIRECT+1]);
+            let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[ndirect+1]);
             let buf_ptr=buf.raw_data() as *const BlockNo;
             for i in 0..NINDIRECT{
                 let ibn=unsafe { read(buf_ptr.offset(i as isize))};
@@ -521,154 +558,195 @@ impl InodeData {
                 }
             }
             drop(buf);
-            let _=bfree(self.dinode.addrs[NDIRECT+1]);
-            self.dinode.addrs[NDIRECT+1]=0;
+            let _=bfree(self.dinode.addrs[ndirect+1]);
+            self.dinode.addrs[ndirect+1]=0;
         }
 
+        self.free_xattrs();
         self.dinode.size = 0;
+        self.blocks_changed();
         self.update();
     }
 
-    pub fn resize(&mut self,inode: &Inode,size:u64)->usize{//todo! need verify！！！
-        let nblocks:usize=match size%BSIZE as u64{
-            0=>size as usize/BSIZE,
-            _=>size as usize/BSIZE+1,
-        };
-        let begin:usize=match self.dinode.size as usize%BSIZE{
-            0=>self.dinode.size as usize/BSIZE,
-            _=>self.dinode.size as usize/BSIZE+1,
-        };
+    /// Free the entries from `from` on in the indirect block `*block`.
+    /// Return true if no entry is left, i.e. the block itself can be freed,
+    /// it is left unchanged then. A block that is kept is copied first if a
+    /// snapshot shares it, `*block` is set to the copy.
+    fn free_indirect_from(dev: u32, block: &mut u32, from: usize) -> bool {
+        if from > 0 {
+            *block = Self::cow_block(dev, *block);
+        }
+        let mut buf = BLOCK_CACHE_MANAGER.bread(dev, *block);
+        let buf_ptr = buf.raw_data_mut() as *mut BlockNo;
+        for i in from..NINDIRECT {
+            let bn = unsafe{ read(buf_ptr.offset(i as isize)) };
+            if bn > 0 {
+                let _=bfree(bn);
+                if from > 0 {
+                    unsafe{ write(buf_ptr.offset(i as isize), 0) };
+                }
+            }
+        }
+        if from > 0 {
+            LOG_MANAGER.write(buf);
+        }
+        from == 0
+    }
+
+    /// Called before a block of the live tree is changed. If a snapshot
+    /// shares the block, copy it to a new block, which the live tree uses
+    /// from now on instead. Returns the block to change.
+    pub(crate) fn cow_block(dev: u32, blockno: u32) -> u32 {
+        if !snapshot::is_shared(blockno) {
+            return blockno;
+        }
+        let copy = balloc(dev);
+        let src = BLOCK_CACHE_MANAGER.bread(dev, blockno);
+        let mut dst = BLOCK_CACHE_MANAGER.bread(dev, copy);
+        unsafe{ ptr::copy_nonoverlapping(src.raw_data(), dst.raw_data_mut(), 1) };
+        drop(src);
+        LOG_MANAGER.write(dst);
+        // only the snapshots use it from now on
+        let _=bfree(blockno);
+        copy
+    }
+
+    /// Change the size of the file to `size`.
+    /// Blocks beyond the new end are freed, new blocks are zeroed.
+    pub fn resize(&mut self,inode: &Inode,size:u64)->usize{
+        let ndirect = ndirect();
+        let mut nblocks = (size as usize + BSIZE - 1) / BSIZE;
+        if self.is_compressed() {
+            // keep the cluster that holds the new end, its tail is zeroed below
+            nblocks = (nblocks + CLUSTER_BLOCKS - 1) / CLUSTER_BLOCKS * CLUSTER_BLOCKS;
+        }
+        let begin = (self.dinode.size as usize + BSIZE - 1) / BSIZE;
         if self.dinode.size == size as u32{
             return size as usize;
         }else if self.dinode.size > size as u32{
-            for i in nblocks..NDIRECT {
+            for i in nblocks.min(ndirect)..ndirect {
                 if self.dinode.addrs[i] > 0 {
                     let _=bfree(self.dinode.addrs[i]);
                     self.dinode.addrs[i] = 0;
                 }
             }
-    
+
             // indirect block
-            let mut _count=NDIRECT;
-            let indirect_index=(nblocks-NDIRECT).max(0);
-            if self.dinode.addrs[NDIRECT] > 0 {
-                //debug!("truncate bread indirect block ");
-                let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[NDIRECT]);
-                let buf_ptr = buf.raw_data() as *const BlockNo;
-                for i in indirect_index..NINDIRECT {
-                    let bn = unsafe{ read(buf_ptr.offset(i as isize)) };
-                    if bn > 0 {
-                        let _=bfree(bn);
-                    }
-                    _count += 1;
-                }
-                drop(buf);
-                if nblocks <= NDIRECT{
-                    let _=bfree(self.dinode.addrs[NDIRECT]);//这里要清空这个页面才行
-                    self.dinode.addrs[NDIRECT] = 0;
+            if self.dinode.addrs[ndirect] > 0 {
+                let from = nblocks.saturating_sub(ndirect).min(NINDIRECT);
+                if Self::free_indirect_from(inode.dev, &mut self.dinode.addrs[ndirect], from) {
+                    let _=bfree(self.dinode.addrs[ndirect]);
+                    self.dinode.addrs[ndirect] = 0;
                 }
             }
-            let left_blocks=(nblocks-NDIRECT-NINDIRECT).max(0);
-            let inindirect_index=left_blocks/NINDIRECT;
-            let indirect_index=left_blocks%NINDIRECT;
-            if self.dinode.addrs[NDIRECT+1] > 0 {//这个还没弄呢
-                //debug!("truncate bread inindirect block");
-                let buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[NDIRECT+1]);
-                let buf_ptr=buf.raw_data() as *const BlockNo;
-                let ibn=unsafe { read(buf_ptr.offset(inindirect_index as isize))};
-                if ibn > 0{
-                    //debug!("ibn is {}",ibn);
-                    let ibuf=BLOCK_CACHE_MANAGER.bread(inode.dev, ibn);
-                    let ibuf_ptr=ibuf.raw_data() as *const BlockNo;
-                    for j in indirect_index..NINDIRECT{
-                        let bn = unsafe{ read(ibuf_ptr.offset(j as isize)) };
-                        info!("[Xv6fs] inode truncate: direct block no is {}",bn);
-                        if bn > 0 {
-                            let _=bfree(bn);
-                        }
+
+            // double indirect block
+            if self.dinode.addrs[ndirect+1] > 0 {
+                let left = nblocks.saturating_sub(ndirect + NINDIRECT);
+                if left > 0 {
+                    self.dinode.addrs[ndirect+1] = Self::cow_block(inode.dev, self.dinode.addrs[ndirect+1]);
+                }
+                let mut buf = BLOCK_CACHE_MANAGER.bread(inode.dev, self.dinode.addrs[ndirect+1]);
+                let buf_ptr = buf.raw_data_mut() as *mut BlockNo;
+                for i in 0..NINDIRECT {
+                    let from = left.saturating_sub(i * NINDIRECT);
+                    let mut ibn = unsafe { read(buf_ptr.offset(i as isize)) };
+                    if ibn == 0 || from >= NINDIRECT {
+                        continue;
                     }
-                    drop(ibuf);
-                    if indirect_index==0{
+                    info!("[Xv6fs] inode resize: indirect block no is {}",ibn);
+                    if Self::free_indirect_from(inode.dev, &mut ibn, from) {
                         let _=bfree(ibn);
+                        ibn = 0;
                     }
-                }
-                for i in inindirect_index+1..NINDIRECT{
-                    let ibn=unsafe { read(buf_ptr.offset(i as isize))};
-                    info!("[Xv6fs] inode truncate: indirect block no is {}",ibn);
-                    if ibn > 0{
-                        //debug!("ibn is {}",ibn);
-                        let ibuf=BLOCK_CACHE_MANAGER.bread(inode.dev, ibn);
-                        let ibuf_ptr=ibuf.raw_data() as *const BlockNo;
-                        for j in 0..NINDIRECT{
-                            let bn = unsafe{ read(ibuf_ptr.offset(j as isize)) };
-                            info!("[Xv6fs] inode truncate: direct block no is {}",bn);
-                            if bn > 0 {
-                                let _=bfree(bn);
-                            }
-                        }
-                        drop(ibuf);
-                        let _=bfree(ibn);
+                    // the block goes as a whole if nothing is left
+                    if left > 0 {
+                        unsafe { write(buf_ptr.offset(i as isize), ibn) };
                     }
                 }
-                drop(buf);
-                if nblocks <= NDIRECT+NINDIRECT{
-                    let _=bfree(self.dinode.addrs[NDIRECT+1]);
-                    self.dinode.addrs[NDIRECT+1]=0;
+                if left > 0 {
+                    LOG_MANAGER.write(buf);
+                } else {
+                    drop(buf);
+                }
+                if left == 0 {
+                    let _=bfree(self.dinode.addrs[ndirect+1]);
+                    self.dinode.addrs[ndirect+1]=0;
                 }
             }
-        }else{
-            for i in begin..=nblocks{
-                let _=self.bmap(i as u32,true);
+            if self.is_compressed() {
+                if let Err(e) = self.zero_cluster_tail(size as usize) {
+                    info!("[Xv6fs] inode resize: {}", e);
+                }
+            }
+            self.blocks_changed();
+        }else if !self.is_compressed(){
+            // holes of a compressed file read as zeros already
+            for i in begin..nblocks{
+                match self.bmap(i as u32,false) {
+                    Ok(bn) => Self::clear_block(self.dev, bn),
+                    Err(_) => break,
+                }
             }
         }
+        self.dinode.size = size as u32;
         self.update();
-        0
+        size as usize
     }
 
     /// Update a modified in-memory inode to disk. 
     /// Typically called after changing the content of inode info. 
     pub fn update(&mut self) {
         //info!("update: begin update");
+        assert!(!snapshot::is_snapshot_dev(self.dev), "inode update: {}", snapshot::READ_ONLY);
         let mut buf = BLOCK_CACHE_MANAGER.bread(
             self.dev, 
             unsafe { SUPER_BLOCK.locate_inode(self.inum)}
         );
+        checksum::assert_valid(self.dev, &buf);
         let offset = locate_inode_offset(self.inum) as isize;
         let dinode = unsafe{ (buf.raw_data_mut() as *mut DiskInode).offset(offset) };
         unsafe{ write(dinode, self.dinode) };
         //info!("update: self.dindoe: {:?}", self.dinode);
+        checksum::update(self.dev, &buf);
         LOG_MANAGER.write(buf);
     }
 
     /// The content (data) associated with each inode is stored
-    /// in blocks on the disk. The first NDIRECT block numbers
+    /// in blocks on the disk. The first `ndirect()` block numbers
     /// are listed in self.dinode.addrs, The next NINDIRECT blocks are 
-    /// listed in block self.dinode.addrs[NDIRECT]. 
+    /// listed in block self.dinode.addrs[ndirect()]. 
     /// 
     /// Return the disk block address of the nth block in inode. 
     /// If there is no such block, bmap allocates one. 
+    /// The block is about to be written: blocks on the way to it that a
+    /// snapshot shares are replaced by copies.
     pub fn bmap(&mut self, offset_bn: u32, balloc_flag: bool) -> Result<u32, &'static str> {
+        let ndirect = ndirect();
         let mut addr;
         let mut _iaddr:u32;
         let offset_bn = offset_bn as usize;
-        if offset_bn < NDIRECT {
+        if offset_bn < ndirect {
             if self.dinode.addrs[offset_bn] == 0 {
                 addr = balloc(self.dev);
+                self.blocks_changed();
                 self.dinode.addrs[offset_bn] = addr;
                 return Ok(addr)
             } else {
+                self.dinode.addrs[offset_bn] = Self::cow_block(self.dev, self.dinode.addrs[offset_bn]);
                 return Ok(self.dinode.addrs[offset_bn])
             }
         }
-        if offset_bn < NINDIRECT + NDIRECT {
+        if offset_bn < NINDIRECT + ndirect {
             // Load indirect block, allocating if necessary. 
-            let count = offset_bn - NDIRECT;
-            if self.dinode.addrs[NDIRECT] == 0 {
+            let count = offset_bn - ndirect;
+            if self.dinode.addrs[ndirect] == 0 {
                 _iaddr = balloc(self.dev);
-                self.dinode.addrs[NDIRECT] = _iaddr;
+                self.dinode.addrs[ndirect] = _iaddr;
                 Self::clear_block(self.dev, _iaddr);
             } else {
-                _iaddr = self.dinode.addrs[NDIRECT]
+                _iaddr = Self::cow_block(self.dev, self.dinode.addrs[ndirect]);
+                self.dinode.addrs[ndirect] = _iaddr;
             }
             //debug!("bread iaddr {}",iaddr);
             let mut _buf = BLOCK_CACHE_MANAGER.bread(self.dev, _iaddr);
@@ -680,19 +758,25 @@ impl InodeData {
                     addr = balloc(self.dev);
                     write(_buf_data.offset(count as isize), addr);
                 }
+                self.blocks_changed();
+                LOG_MANAGER.write(_buf);
+            } else if snapshot::is_shared(addr) {
+                addr = Self::cow_block(self.dev, addr);
+                unsafe{ write(_buf_data.offset(count as isize), addr) };
                 LOG_MANAGER.write(_buf);
             }
             // drop(buf);
             return Ok(addr)
         }
-        if offset_bn < NINDIRECT+NDIRECT+NININDIRECT{
-            let count=offset_bn-NDIRECT-NINDIRECT;
-            if self.dinode.addrs[NDIRECT+1]==0{
+        if offset_bn < NINDIRECT+ndirect+NININDIRECT{
+            let count=offset_bn-ndirect-NINDIRECT;
+            if self.dinode.addrs[ndirect+1]==0{
                 addr=balloc(self.dev);
-                self.dinode.addrs[NDIRECT+1]=addr;
+                self.dinode.addrs[ndirect+1]=addr;
                 Self::clear_block(self.dev, addr);
             }else {
-                addr=self.dinode.addrs[NDIRECT+1];
+                addr=Self::cow_block(self.dev, self.dinode.addrs[ndirect+1]);
+                self.dinode.addrs[ndirect+1]=addr;
             }
             let indirect_count=count/64;
             let indirect_offset=count%64;
@@ -712,6 +796,10 @@ impl InodeData {
                 }
                 LOG_MANAGER.write(_buf);
                 drop(_buf_data);
+            } else if snapshot::is_shared(iaddr) {
+                iaddr=Self::cow_block(self.dev, iaddr);
+                unsafe{ write(_buf_data.offset(indirect_count as isize), iaddr) };
+                LOG_MANAGER.write(_buf);
             }
             //debug!("bread indirect iaddr {}",iaddr);
             let mut _ibuf=BLOCK_CACHE_MANAGER.bread(self.dev, iaddr);
@@ -723,6 +811,11 @@ impl InodeData {
                     addr=balloc(self.dev);
                     write(_ibuf_data.offset(indirect_offset as isize), addr);
                 }
+                self.blocks_changed();
+                LOG_MANAGER.write(_ibuf);
+            } else if snapshot::is_shared(addr) {
+                addr=Self::cow_block(self.dev, addr);
+                unsafe{ write(_ibuf_data.offset(indirect_offset as isize), addr) };
                 LOG_MANAGER.write(_ibuf);
             }
             return Ok(addr);
@@ -730,18 +823,131 @@ impl InodeData {
         panic!("inode bmap: out of range.");
     }
 
+    /// Free the nth block of the inode if it is allocated, leaving a hole.
+    pub(crate) fn unmap(&mut self, offset_bn: u32) {
+        let ndirect = ndirect();
+        let blockno = match self.block_at(offset_bn) {
+            Some(blockno) => blockno,
+            None => return,
+        };
+        let offset_bn = offset_bn as usize;
+        if offset_bn < ndirect {
+            self.dinode.addrs[offset_bn] = 0;
+        } else {
+            let (block, index) = self.pointer_block(offset_bn);
+            let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, block);
+            unsafe{ write((buf.raw_data_mut() as *mut u32).offset(index as isize), 0) };
+            LOG_MANAGER.write(buf);
+        }
+        let _=bfree(blockno);
+        self.blocks_changed();
+    }
+
+    /// The indirect block that holds the address of the nth block, which
+    /// must be allocated, and the index of the address in it.
+    /// The indirect blocks are about to be changed and are copied first if
+    /// a snapshot shares them, like in `bmap`.
+    fn pointer_block(&mut self, offset_bn: usize) -> (u32, usize) {
+        let ndirect = ndirect();
+        if offset_bn < ndirect + NINDIRECT {
+            self.dinode.addrs[ndirect] = Self::cow_block(self.dev, self.dinode.addrs[ndirect]);
+            return (self.dinode.addrs[ndirect], offset_bn - ndirect);
+        }
+        let count = offset_bn - ndirect - NINDIRECT;
+        self.dinode.addrs[ndirect+1] = Self::cow_block(self.dev, self.dinode.addrs[ndirect+1]);
+        let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.dinode.addrs[ndirect+1]);
+        let entry = unsafe{ (buf.raw_data_mut() as *mut u32).offset((count / NINDIRECT) as isize) };
+        let iaddr = unsafe{ read(entry) };
+        let copy = Self::cow_block(self.dev, iaddr);
+        if copy != iaddr {
+            unsafe{ write(entry, copy) };
+            LOG_MANAGER.write(buf);
+        }
+        (copy, count % NINDIRECT)
+    }
+
+    /// Number of data blocks allocated to the inode.
+    /// Freeing a block zeroes its pointer, so like `block_at` only the
+    /// pointers are counted. The count is kept until blocks are allocated
+    /// or freed, stat doesn't read the indirect blocks each time.
+    pub fn data_blocks(&self) -> usize {
+        let ndirect = ndirect();
+        let cached = self.nblocks.load(Ordering::Relaxed);
+        if cached != UNKNOWN_BLOCKS {
+            return cached;
+        }
+        let entries = |block: u32| -> [u32; NINDIRECT] {
+            let buf = BLOCK_CACHE_MANAGER.bread(self.dev, block);
+            unsafe { read(buf.raw_data() as *const [u32; NINDIRECT]) }
+        };
+        let used = |addrs: &[u32]| addrs.iter().filter(|&&addr| addr != 0).count();
+        let mut count = used(&self.dinode.addrs[..ndirect]);
+        if self.dinode.addrs[ndirect] != 0 {
+            count += used(&entries(self.dinode.addrs[ndirect]));
+        }
+        if self.dinode.addrs[ndirect+1] != 0 {
+            for iaddr in entries(self.dinode.addrs[ndirect+1]) {
+                if iaddr != 0 {
+                    count += used(&entries(iaddr));
+                }
+            }
+        }
+        self.nblocks.store(count, Ordering::Relaxed);
+        count
+    }
+
+    /// Blocks were allocated or freed, `data_blocks` counts them again.
+    fn blocks_changed(&self) {
+        self.nblocks.store(UNKNOWN_BLOCKS, Ordering::Relaxed);
+    }
+
+    /// Return the disk block address of the nth block in inode,
+    /// or None if it is not allocated yet, without allocating it like `bmap`.
+    /// Used by readers that only hold the inode's sleeplock shared.
+    /// Freeing a block zeroes its pointer, so a pointer that isn't 0 is
+    /// allocated and the bitmap isn't read.
+    pub fn block_at(&self, offset_bn: u32) -> Option<u32> {
+        let ndirect = ndirect();
+        let offset_bn = offset_bn as usize;
+        let present = |addr: u32| if addr == 0 { None } else { Some(addr) };
+        let entry = |block: u32, index: usize| -> Option<u32> {
+            let buf = BLOCK_CACHE_MANAGER.bread(self.dev, block);
+            let addr = unsafe { read((buf.raw_data() as *const u32).offset(index as isize)) };
+            drop(buf);
+            present(addr)
+        };
+        if offset_bn < ndirect {
+            return present(self.dinode.addrs[offset_bn]);
+        }
+        if offset_bn < NINDIRECT + ndirect {
+            let iaddr = present(self.dinode.addrs[ndirect])?;
+            return entry(iaddr, offset_bn - ndirect);
+        }
+        if offset_bn < NINDIRECT + ndirect + NININDIRECT {
+            let count = offset_bn - ndirect - NINDIRECT;
+            let iiaddr = present(self.dinode.addrs[ndirect + 1])?;
+            let iaddr = entry(iiaddr, count / NINDIRECT)?;
+            return entry(iaddr, count % NINDIRECT);
+        }
+        panic!("inode block_at: out of range.");
+    }
+
     /// Read data from inode. 
-    /// Caller must hold inode's sleeplock. 
+    /// Caller must hold inode's sleeplock, it may be shared. 
+    /// Blocks that are not allocated are read as zeros. 
     /// If is_user is true, then dst is a user virtual address;
     /// otherwise, dst is a kernel address. 
     /// is_user 为 true 表示 dst 为用户虚拟地址，否则表示内核虚拟地址
     /// 以上是曾经的注释，目前已经没有用户虚拟地址和内核虚拟地址的区别
     pub fn read(
-        &mut self,
+        &self,
         mut dst: usize, 
         offset: u32, 
         count: u32
     ) -> Result<usize, &'static str> { 
+        if self.is_compressed() {
+            return self.read_compressed(dst, offset, count);
+        }
         // Check the reading content is in range.
         let end = offset.checked_add(count).ok_or("Fail to add count.")?;
         if end > self.dinode.size {
@@ -763,10 +969,24 @@ impl InodeData {
         let mut block_offset = offset % BSIZE;
         while total < count as usize {
             let surplus_len = count - total;
-            let block_no = self.bmap(block_basic as u32, false)?;
+            let write_len = min(surplus_len, BSIZE - block_offset);
+            let block_no = match self.block_at(block_basic as u32) {
+                Some(block_no) => block_no,
+                None => {
+                    mem_set(dst as *mut u8, 0, write_len);
+                    total += write_len as usize;
+                    offset += write_len as usize;
+                    dst += write_len as usize;
+                    block_basic = offset / BSIZE;
+                    block_offset = offset % BSIZE;
+                    continue;
+                }
+            };
             //debug!("read block no is {},offset is {}",block_no,offset);
             let buf = BLOCK_CACHE_MANAGER.bread(self.dev, block_no);
-            let write_len = min(surplus_len, BSIZE - block_offset);
+            if self.dinode.itype == InodeType::Directory {
+                checksum::verify(self.dev, &buf)?;
+            }
             // if copy_from_kernel(
             //     is_user, 
             //     dst, 
@@ -809,6 +1029,12 @@ impl InodeData {
         //     return Err("inode write: end is more than diskinode's size.")
         // }
         info!("[Xv6fs] inode write file/dir: begin inode write");
+        if snapshot::is_snapshot_dev(self.dev) {
+            return Err(snapshot::READ_ONLY);
+        }
+        if self.is_compressed() {
+            return self.write_compressed(src, offset, count);
+        }
         let mut offset = offset as usize;
         info!("[Xv6fs] inode write file/dir: write block offset is {}",offset);
         let count = count as usize;
@@ -827,8 +1053,15 @@ impl InodeData {
             let block_no = self.bmap(block_basic as u32,_balloc_flag)?;
             info!("[Xv6fs] inode write file/dir: write block no is {}",block_no);
             let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, block_no);
+            let is_dir = self.dinode.itype == InodeType::Directory;
+            if is_dir {
+                checksum::verify(self.dev, &buf)?;
+            }
             let dst=unsafe{ (buf.raw_data_mut() as *mut u8).offset((offset % BSIZE) as isize ) };
             unsafe{ptr::copy(src as *const u8, dst, write_len);}
+            if is_dir {
+                checksum::update(self.dev, &buf);
+            }
             offset += write_len;
             src += write_len;
             total += write_len;
@@ -851,12 +1084,21 @@ impl InodeData {
 
     /// Look for an inode entry in this directory according the name. 
     /// Panics if this is not a directory. 
-    pub fn dir_lookup(&mut self, name: &[u8]) -> Option<Inode> {
+    pub fn dir_lookup(&self, name: &[u8]) -> Option<Inode> {
+        self.dir_try_lookup(name).expect("Cannot read entry in this dir")
+    }
+
+    /// Like `dir_lookup`, but returns an error if the directory can't be read.
+    pub fn dir_try_lookup(&self, name: &[u8]) -> Result<Option<Inode>, &'static str> {
         // assert!(name.len() == DIRSIZ);
         info!("[Xv6fs] dir lookup: name is {:?}",core::str::from_utf8(name));
         if self.dinode.itype != InodeType::Directory {
             panic!("inode type is not directory");
         }
+        if self.is_dir_indexed() {
+            let inum = self.index_lookup(name)?;
+            return Ok(inum.map(|inum| ICACHE.get(self.dev, inum)));
+        }
         let de_size = size_of::<DirEntry>();
         let mut dir_entry = DirEntry::new();
         let dir_entry_ptr = &mut dir_entry as *mut _ as *mut u8;
@@ -865,7 +1107,7 @@ impl InodeData {
                 dir_entry_ptr as usize, 
                 offset, 
                 de_size as u32
-            ).expect("Cannot read entry in this dir");
+            )?;
             if dir_entry.inum == 0 {
                 continue;
             }
@@ -876,11 +1118,11 @@ impl InodeData {
                 }
                 if dir_entry.name[i] == 0 {
                     //info!("find you!");
-                    return Some(ICACHE.get(self.dev, dir_entry.inum as u32))
+                    return Ok(Some(ICACHE.get(self.dev, dir_entry.inum as u32)))
                 }
             }
         }
-        None
+        Ok(None)
     }
 
     /// Write s new directory entry (name, inum) into the directory
@@ -889,6 +1131,9 @@ impl InodeData {
         if self.dir_lookup(name).is_some() {
             return Err("It's incorrect to find entry in disk")
         }
+        if self.is_dir_indexed() {
+            return self.index_link(name, inum);
+        }
         let mut dir_entry = DirEntry::new();
         // look for an empty dir_entry
         let mut entry_offset = 0;
@@ -919,7 +1164,7 @@ impl InodeData {
     }
 
     /// Is the directory empty execpt for "." and ".." ?
-    pub fn is_dir_empty(&mut self) -> bool {
+    pub fn is_dir_empty(&self) -> bool {
         let mut dir_entry = DirEntry::new();
         // "." and ".." size
         let init_size = 2 * size_of::<DirEntry>() as u32;
@@ -935,19 +1180,33 @@ impl InodeData {
             }
 
             if dir_entry.inum != 0 {
-                return true
+                return false
             }
         }
-        false
+        true
     }
 
     pub fn rename(path:&str,new_name:&str){
+        let _op=LOG_MANAGER.begin_op();
         let mut old_name = [0u8; DIRSIZ];
         let parent=match ICACHE.namei_parent(&path.as_bytes(), &mut old_name) {
             Some(cur)=>cur,
             None=>panic!("[Xv6fs] vfile_unlink: not find path")
         };
         let mut parent_guard=parent.lock();
+        if parent_guard.is_dir_indexed() {
+            // the entry moves to the bucket of its new name
+            let mut name = [0u8; DIRSIZ];
+            let len = new_name.len().min(DIRSIZ);
+            name[..len].copy_from_slice(&new_name.as_bytes()[..len]);
+            let inum = match parent_guard.index_lookup(&old_name) {
+                Ok(Some(inum)) => inum,
+                _ => panic!("[Xv6fs] inode rename: not find dir entry"),
+            };
+            parent_guard.index_unlink(&old_name).expect("[Xv6fs] inode rename: fail to unlink");
+            parent_guard.index_link(&name, inum).expect("[Xv6fs] inode rename: fail to link");
+            return;
+        }
         let de_size = size_of::<DirEntry>();
         let mut dir_entry = DirEntry::new();
         let dir_entry_ptr = &mut dir_entry as *mut _ as *mut u8;
@@ -974,7 +1233,6 @@ impl InodeData {
                         }
                     }
                     let _=parent_guard.write(dir_entry_ptr as usize, offset, de_size as u32);
-                    LOG_MANAGER.end_op();
                     return;
                 }
             }
@@ -982,7 +1240,7 @@ impl InodeData {
         panic!("[Xv6fs] inode rename: not find dir entry");
     }
 
-    pub fn ls(&mut self)->Option<Vec<String>>{
+    pub fn ls(&self)->Option<Vec<String>>{
         if self.dinode.itype!=InodeType::Directory{
             None
         }else{
@@ -1013,6 +1271,9 @@ impl InodeData {
         if self.dinode.itype != InodeType::Directory {
             panic!("inode type is not directory");
         }
+        if self.is_dir_indexed() {
+            return self.index_unlink(name);
+        }
         let de_size = size_of::<DirEntry>();
         let mut dir_entry = DirEntry::new();
         let dir_entry_ptr = &mut dir_entry as *mut _ as *mut u8;
@@ -1063,18 +1324,14 @@ impl InodeData {
             let child_inode=ICACHE.get(self.dev, dir_entry.inum as u32);
             let mut cdata=child_inode.lock();
             match cdata.dinode.itype {
-                InodeType::File=>{
-                    cdata.dinode.itype=InodeType::Empty;
-                    cdata.truncate(&child_inode);
-                    cdata.valid=false;
+                InodeType::File|InodeType::Device=>{
+                    cdata.free(&child_inode);
                     drop(cdata);
                     self.dir_unlink(&dir_entry.name)?;
                 },
                 InodeType::Directory=>{
                     cdata.clear_dir()?;
-                    cdata.dinode.itype=InodeType::Empty;
-                    cdata.truncate(&child_inode);
-                    cdata.valid=false;
+                    cdata.free(&child_inode);
                     drop(cdata);
                     self.dir_unlink(&dir_entry.name)?;
                 },
@@ -1108,36 +1365,21 @@ impl Inode {
     /// Lock the inode. 
     /// Load it from the disk if its content not cached yet. 
     pub fn lock<'a>(&'a self) -> SleepLockGuard<'a, InodeData> {
+        match self.try_lock() {
+            Ok(guard) => guard,
+            Err(e) => panic!("inode lock: {} inode {}", e, self.inum),
+        }
+    }
+
+    /// Like `lock`, but returns an error if the inode can't be loaded.
+    pub fn try_lock<'a>(&'a self) -> Result<SleepLockGuard<'a, InodeData>, &'static str> {
         assert!(self.index < NINODE, "index must less than NINODE");
         //info!("[Kernel] inode.lock(): inode index: {}, dev: {}, inum: {}", self.index, self.dev, self.inum);
         let mut guard = ICACHE.data[self.index].lock();
         
         if !guard.valid {
-            let blockno = unsafe{ SUPER_BLOCK.locate_inode(self.inum) };
-            //info!("lock blockno is {}",blockno);
-            let buf = BLOCK_CACHE_MANAGER.bread(self.dev, blockno);
-            let offset = locate_inode_offset(self.inum) as isize;
-            //info!("offset is {:?}",offset);
-            //let data=buf.raw_data() as *const RawSuperBlock;
-            //let data=buf.raw_data() as *const DiskInode;
-            // for i in 0..16{
-            //     let d=unsafe {
-            //         data.offset(i)
-            //     };
-            //     let dd=unsafe {
-            //         core::ptr::read(d)
-            //     };
-            //     info!("{} disk inode is {:?}",i,dd);
-            // }
-            //info!("data is {:?}",unsafe{core::ptr::read(data)});
-            //let dinode = unsafe{ (buf.raw_data() as *const RawSuperBlock).offset(offset) };
-            let dinode = unsafe{ (buf.raw_data() as *const DiskInode).offset(offset) };
-            guard.dinode = unsafe{ core::ptr::read(dinode) };
-            //info!("{:?}",guard.dinode);
-            // info!("dinode is {:?}",unsafe {
-            //     core::ptr::read(dinode)
-            // });
-            drop(buf);
+            guard.dinode = read_disk_inode(self.dev, self.inum)?;
+            guard.blocks_changed();
             guard.valid = true;
             guard.dev = self.dev;
             guard.inum = self.inum;
@@ -1145,7 +1387,30 @@ impl Inode {
                 panic!("inode lock: trying to lock an inode whose type is empty.")
             }
         }
-        guard
+        Ok(guard)
+    }
+
+    /// Lock the inode shared with other readers.
+    /// Load it from the disk if its content not cached yet.
+    pub fn lock_shared<'a>(&'a self) -> SleepLockSharedGuard<'a, InodeData> {
+        match self.try_lock_shared() {
+            Ok(guard) => guard,
+            Err(e) => panic!("inode lock: {} inode {}", e, self.inum),
+        }
+    }
+
+    /// Like `lock_shared`, but returns an error if the inode can't be loaded.
+    pub fn try_lock_shared<'a>(&'a self) -> Result<SleepLockSharedGuard<'a, InodeData>, &'static str> {
+        assert!(self.index < NINODE, "index must less than NINODE");
+        let guard = ICACHE.data[self.index].lock_shared();
+        if guard.valid {
+            return Ok(guard);
+        }
+        // Loading needs the exclusive lock. This handle holds a reference,
+        // so the content stays valid after it is loaded.
+        drop(guard);
+        drop(self.try_lock()?);
+        Ok(ICACHE.data[self.index].lock_shared())
     }
 }
 
@@ -1159,6 +1424,23 @@ impl Drop for Inode {
 }
 
 
+/// Read the on-disk inode `inum` of `dev` through the buffer cache.
+/// Its type and device are up to date there even if the inode cache holds
+/// the inode, they are written back as soon as they are set.
+pub(crate) fn read_disk_inode(dev: u32, inum: u32) -> Result<DiskInode, &'static str> {
+    let blockno = snapshot::locate_inode(dev, inum)?;
+    let buf = BLOCK_CACHE_MANAGER.bread(dev, blockno);
+    checksum::verify(dev, &buf)?;
+    let offset = locate_inode_offset(inum) as isize;
+    let dinode = unsafe{ (buf.raw_data() as *const DiskInode).offset(offset) };
+    // don't make up an InodeType out of a bad value
+    let raw_type = unsafe{ core::ptr::read(dinode as *const u16) };
+    if raw_type > InodeType::Device as u16 {
+        return Err(checksum::CORRUPTED);
+    }
+    Ok(unsafe{ core::ptr::read(dinode) })
+}
+
 /// Given an inode number. 
 /// Calculate the offset index of this inode inside the block. 
 #[inline]
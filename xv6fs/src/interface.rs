@@ -6,10 +6,30 @@ use crate::inode::Inode;
 
 pub trait FsInterface:Send + Sync + Any {
     fn get_cur_dir_inode(&self)->Option<Inode>;
-    fn sleep_cur_proc(&self,index:usize);
-    fn wake_up_next_proc(&self,index:usize);
+    /// Create a sleep lock and return its index.
     fn new_sleep_lock(&self)->usize;
-    fn get_flag(&self,index:usize)->bool;
+    /// Acquire the sleep lock `index` exclusively,
+    /// sleeping while any other task holds it.
+    fn sleep_lock(&self,index:usize);
+    /// Acquire the sleep lock `index` shared with other readers,
+    /// sleeping while a task holds it exclusively.
+    fn sleep_lock_shared(&self,index:usize);
+    /// Release the sleep lock `index` acquired by `sleep_lock`.
+    fn sleep_unlock(&self,index:usize);
+    /// Release the sleep lock `index` acquired by `sleep_lock_shared`.
+    fn sleep_unlock_shared(&self,index:usize);
+    /// Read from the device (major, minor) behind a device inode.
+    fn dev_read(&self,major:i16,minor:i16,offset:usize,buf:&mut [u8])->Result<usize,&'static str>;
+    /// Write to the device (major, minor) behind a device inode.
+    fn dev_write(&self,major:i16,minor:i16,offset:usize,buf:&[u8])->Result<usize,&'static str>;
+    /// Whether the device (major, minor) is a block device rather than a
+    /// character device, false if there is no such device.
+    fn dev_is_block(&self,major:i16,minor:i16)->bool;
+    /// An id of the current task, telling apart the fs operations of tasks.
+    fn cur_task_id(&self)->usize;
+    /// Called once the inode `inum` of `dev` is freed, e.g. to drop the data
+    /// cached for it before the inode number is reused.
+    fn inode_freed(&self,dev:u32,inum:u32);
 }
 
 pub struct InterfaceManager{
@@ -23,19 +43,38 @@ impl FsInterface for InterfaceNone {
         None
     }
 
-    fn sleep_cur_proc(&self,_index:usize){
+    fn new_sleep_lock(&self)->usize {
+        0
+    }
+
+    fn sleep_lock(&self,_index:usize){
         panic!("not set interface!");
     }
 
-    fn wake_up_next_proc(&self,_index:usize){
+    fn sleep_lock_shared(&self,_index:usize){
         panic!("not set interface!");
     }
-    fn new_sleep_lock(&self)->usize {
-        0
+
+    fn sleep_unlock(&self,_index:usize){
+        panic!("not set interface!");
     }
-    fn get_flag(&self,_index:usize)->bool {
-        true
+
+    fn sleep_unlock_shared(&self,_index:usize){
+        panic!("not set interface!");
+    }
+    fn dev_read(&self,_major:i16,_minor:i16,_offset:usize,_buf:&mut [u8])->Result<usize,&'static str> {
+        Err("not set interface!")
+    }
+    fn dev_write(&self,_major:i16,_minor:i16,_offset:usize,_buf:&[u8])->Result<usize,&'static str> {
+        Err("not set interface!")
+    }
+    fn dev_is_block(&self,_major:i16,_minor:i16)->bool {
+        false
+    }
+    fn cur_task_id(&self)->usize {
+        0
     }
+    fn inode_freed(&self,_dev:u32,_inum:u32) {}
 }
 pub static INTERFACE_MANAGER: LazyInit<InterfaceManager>=LazyInit::new();
 
@@ -17,7 +17,15 @@ pub mod superblock;
 pub mod stat;
 pub mod disk_inode;
 pub mod bitmap;
+pub mod checksum;
 pub mod inode;
+pub mod dir_index;
+pub mod compress;
+pub mod lz4;
+pub mod snapshot;
+pub mod resize;
+pub mod discard;
+pub mod xattr;
 pub mod misc;
 pub mod file;
 pub mod interface;
@@ -39,17 +47,19 @@ use core::mem::size_of;
 
 use crate::inode::{ICACHE, InodeCache};
 
-pub unsafe fn init(block_dev:Arc<dyn BlockDevice>,dev:u32) {
+pub unsafe fn init(block_dev:Arc<dyn BlockDevice>,dev:u32) -> Result<(), &'static str> {
     BLOCK_CACHE_MANAGER.set_block_device(Arc::clone(&block_dev));
     BLOCK_CACHE_MANAGER.binit();
     info!("init ICACHE");
     let icache=InodeCache::new();
     ICACHE.init_by(icache);
     info!("init SUPER BLOCK");
-    SUPER_BLOCK.init(dev);
+    SUPER_BLOCK.init(dev)?;
     info!("init LOG");
     let log=LOG_MANAGER.log.lock().deref_mut() as *mut Log;
-    log.as_mut().unwrap().init(dev);
+    log.as_mut().unwrap().init(dev)?;
+    SUPER_BLOCK.reload(dev)?;
     info!("block size:{}, disk inode size:{}, log header size:{}",BSIZE,size_of::<DiskInode>(),size_of::<LogHeader>());
     info!("file system: setup done!");
+    Ok(())
 }
\ No newline at end of file
@@ -4,15 +4,18 @@
 use core::{ panic, ptr};
 use core::mem;
 //use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 use lazy_static::*;
 
 //use crate::{fs_const::{MAXOPBLOCKS, LOGSIZE, BSIZE}, block_dev::BlockDevice};
-use crate::fs_const::{LOGSIZE, BSIZE};
+use crate::fs_const::{LOGSIZE, BSIZE, MAXOPBLOCKS};
 use crate::buffer_cache::{BLOCK_CACHE_MANAGER, Buf, BufData};
 use crate::interface::INTERFACE_MANAGER;
 //use crate::block_dev::BlockDevice;
 use crate::superblock::SUPER_BLOCK;
+use crate::checksum::{self, crc32c, CORRUPTED};
+use crate::discard;
 
 lazy_static!{
     pub static ref LOG_MANAGER: LogManager = LogManager::init();
@@ -22,6 +25,17 @@ pub struct LogManager{
     pub log: Mutex<Log>,
 }
 
+/// When the transactions in the log are committed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteBackMode {
+    /// Commit at the end of every fs operation.
+    Sync,
+    /// Let fs operations accumulate in the log. They are committed by `flush`
+    /// (e.g. from a periodic background task), or by the end of an operation
+    /// once the log has no room left for another one.
+    Delayed,
+}
+
 impl LogManager {
     pub fn init()->Self{
         LogManager { log: Mutex::new(Log::uninit()) }
@@ -30,7 +44,8 @@ impl LogManager {
 
 /// Log info about the file system.
 pub struct Log {
-    ///wait queue index
+    /// sleep lock held shared by the fs ops in flight,
+    /// and exclusively to commit once none is
     channel: usize,
     /// the starting block in the fs
     start: u32,
@@ -39,6 +54,12 @@ pub struct Log {
     dev: u32,
     /// not allow any fs op when the log is committing
     committing: bool,
+    mode: WriteBackMode,
+    /// tasks in the middle of an fs op and how deeply their ops nest,
+    /// each has `op_blocks()` blocks of the log reserved
+    ops: Vec<(usize, u32)>,
+    /// the task that froze the fs, see `LogManager::freeze`
+    frozen_by: Option<usize>,
     lh: LogHeader,
 }
 
@@ -50,7 +71,10 @@ impl Log {
             size: 0,
             dev: 0,
             committing: false,
-            lh: LogHeader { len: 0, blocknos: [0; LOGSIZE-1] },
+            mode: WriteBackMode::Sync,
+            ops: Vec::new(),
+            frozen_by: None,
+            lh: LogHeader { len: 0, blocknos: [0; LOGSIZE-1], checksum: 0 },
         }
     }
 
@@ -59,7 +83,7 @@ impl Log {
     /// SAFETY: It must be called without holding any locks,
     ///         because it will call disk rw, which might sleep.
     /// 这里的dev要再考虑一下
-    pub unsafe fn init(&mut self, dev: u32) {
+    pub unsafe fn init(&mut self, dev: u32) -> Result<(), &'static str> {
         debug_assert!(mem::size_of::<LogHeader>() < BSIZE);
         debug_assert_eq!(mem::align_of::<BufData>() % mem::align_of::<LogHeader>(), 0);
         let (start, size) = SUPER_BLOCK.read_log();
@@ -67,13 +91,13 @@ impl Log {
         self.start = start;
         self.size = size;
         self.dev = dev;
-        self.recover();
+        self.recover()
     }
 
     /// Recover the file system from log if necessary.
-    fn recover(&mut self) {
+    fn recover(&mut self) -> Result<(), &'static str> {
         //info!("file system: checking logs");
-        self.read_head();
+        self.read_head()?;
         if self.lh.len > 0 {
             //info!("file system: recovering from logs");
             self.install_trans(true);
@@ -81,10 +105,11 @@ impl Log {
         } else {
             //info!("file system: no need to recover");
         }
+        Ok(())
     }
 
     /// Read the log header from disk into the in-memory log header.
-    fn read_head(&mut self) {
+    fn read_head(&mut self) -> Result<(), &'static str> {
         let buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.start);
         unsafe {
             ptr::copy_nonoverlapping(
@@ -94,11 +119,35 @@ impl Log {
             );
         }
         drop(buf);
+        if self.lh.len as usize >= LOGSIZE {
+            return Err(CORRUPTED);
+        }
+        if checksum::enabled() && self.lh.checksum != self.head_checksum() {
+            return Err(CORRUPTED);
+        }
+        Ok(())
+    }
+
+    /// Checksum of the in-memory log header and the blocks in the log,
+    /// so that a log that is not completely written is not replayed.
+    fn head_checksum(&self) -> u32 {
+        let len = mem::size_of::<LogHeader>() - mem::size_of::<u32>();
+        let head = unsafe { core::slice::from_raw_parts(&self.lh as *const LogHeader as *const u8, len) };
+        let mut crc = crc32c(0, head);
+        for i in 0..self.lh.len {
+            let log_buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.start+1+i);
+            let data = unsafe { core::slice::from_raw_parts(log_buf.raw_data() as *const u8, BSIZE) };
+            crc = crc32c(crc, data);
+        }
+        crc
     }
 
     /// Write in-memory log header to disk.
     /// This is the true point at which the current transaction commits.
     fn write_head(&mut self) {
+        if checksum::enabled() {
+            self.lh.checksum = self.head_checksum();
+        }
         let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.start);
         unsafe {
             ptr::copy_nonoverlapping(
@@ -115,9 +164,14 @@ impl Log {
     /// setting the len of log(both in-memory and in-disk) to zero.
     fn empty_head(&mut self) {
         self.lh.len = 0;
+        let checksum = if checksum::enabled() { self.head_checksum() } else { 0 };
+        self.lh.checksum = checksum;
         let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.start);
         let raw_lh = buf.raw_data_mut() as *mut LogHeader;
-        unsafe { raw_lh.as_mut().unwrap().len = 0; }
+        unsafe {
+            raw_lh.as_mut().unwrap().len = 0;
+            raw_lh.as_mut().unwrap().checksum = checksum;
+        }
         buf.bwrite();
         drop(buf);
     }
@@ -135,6 +189,8 @@ impl Log {
                 );
             }
             disk_buf.bwrite();
+            // the home block now holds what was logged
+            disk_buf.set_verified(false);
             if !recovering {
                 //info!("unpin disk buf {}",self.lh.blocknos[i as usize]);
                 unsafe { disk_buf.unpin(); }
@@ -145,22 +201,30 @@ impl Log {
     }
 
     /// Commit the log.
-    /// SAFETY: It must be called while the committing field is set.
+    /// SAFETY: It must be called with the log locked and no fs op in flight,
+    /// so that no op is still writing the blocks to be logged.
     pub unsafe fn commit(&mut self) {
+        debug_assert!(self.ops.is_empty());
         self.committing=true;
-        if !self.committing {
-            panic!("log: committing while the committing flag is not set");
-        }
         // debug_assert!(self.lh.len > 0);     // it should have some log to commit
         if self.lh.len > 0 {
             self.write_log();
             self.write_head();
             self.install_trans(false);
             self.empty_head();
+            discard::committed();
         }
         self.committing=false;
     }
 
+    /// Whether the log has room for the blocks of one more op,
+    /// besides those reserved by the ops in flight.
+    fn has_room(&self) -> bool {
+        // `LogManager::write` needs two spare slots when adding a block
+        let reserved = (self.ops.len() + 1) * op_blocks();
+        self.lh.len as usize + reserved + 2 < LOGSIZE.min(self.size as usize)
+    }
+
     /// Copy the log content from buffer cache to disk.
     fn write_log(&mut self) {
         for i in 0..self.lh.len {
@@ -180,32 +244,115 @@ impl Log {
     }
 }
 
+/// Blocks of the log reserved for an fs op: up to `MAXOPBLOCKS` blocks, and
+/// the checksum table blocks with the checksums of these blocks.
+fn op_blocks() -> usize {
+    MAXOPBLOCKS + checksum::table_blocks(MAXOPBLOCKS)
+}
+
 impl LogManager {
-    // It should be called at the start of file system call.
-    // pub fn begin_op(&self) {
-    //     let mut guard  = self.log.lock();
-    //     loop {
-    //         if guard.committing ||
-    //             1 + guard.lh.len as usize + (guard.outstanding+1) as usize * MAXOPBLOCKS > LOGSIZE
-    //         {
-    //             // let channel = guard.deref() as *const Log as usize;
-    //             // unsafe { CPU_MANAGER.myproc().unwrap().sleep(channel, guard); }
-    //             // guard = self.acquire();
-    //             let channel=guard.channel;
-    //             drop(guard);
-    //             INTERFACE_MANAGER.interface.sleep_cur_proc(channel);
-    //             guard=self.log.lock();
-    //         } 
-    //         else 
-    //         {
-    //             guard.outstanding += 1;
-    //             drop(guard);
-    //             break;
-    //         }
-    //     }
-    // }
+    /// Start an fs operation, which may write up to `MAXOPBLOCKS` blocks to
+    /// the log, and their checksums. Waits while the log has no room for
+    /// them, and must be called before taking any inode lock. The ops of one
+    /// task may nest, the inner ones share the blocks of the outermost one.
+    /// The operation ends when the returned guard is dropped.
+    pub fn begin_op(&self) -> LogOp {
+        let task = INTERFACE_MANAGER.interface.cur_task_id();
+        let channel = {
+            let mut guard = self.log.lock();
+            if let Some(op) = guard.ops.iter_mut().find(|op| op.0 == task) {
+                op.1 += 1;
+                return LogOp(());
+            }
+            if guard.frozen_by == Some(task) {
+                // the channel is held already, and the log is empty
+                guard.ops.push((task, 1));
+                return LogOp(());
+            }
+            guard.channel
+        };
+        loop {
+            INTERFACE_MANAGER.interface.sleep_lock_shared(channel);
+            let mut guard = self.log.lock();
+            if guard.has_room() {
+                guard.ops.push((task, 1));
+                return LogOp(());
+            }
+            drop(guard);
+            INTERFACE_MANAGER.interface.sleep_unlock_shared(channel);
+            // make room once the ops in flight end
+            self.commit_all();
+        }
+    }
 
+    /// End the fs operation of the current task, see `begin_op`.
+    /// The last op in flight commits the log, unless the ops accumulate.
+    fn end_op(&self) {
+        let task = INTERFACE_MANAGER.interface.cur_task_id();
+        let mut guard = self.log.lock();
+        let i = guard.ops.iter().position(|op| op.0 == task)
+            .expect("log: end_op without begin_op");
+        guard.ops[i].1 -= 1;
+        if guard.ops[i].1 > 0 {
+            return;
+        }
+        guard.ops.swap_remove(i);
+        if guard.frozen_by == Some(task) {
+            // leave the log empty for the next op of the freezer
+            unsafe{guard.commit()};
+            return;
+        }
+        // the other ops in flight may have written half of their blocks
+        if guard.ops.is_empty() && (guard.mode == WriteBackMode::Sync || !guard.has_room()) {
+            unsafe{guard.commit()};
+        }
+        let channel = guard.channel;
+        drop(guard);
+        INTERFACE_MANAGER.interface.sleep_unlock_shared(channel);
+    }
 
+    /// Commit the log after the ops in flight end, which must not include
+    /// one of the current task.
+    fn commit_all(&self) {
+        let task = INTERFACE_MANAGER.interface.cur_task_id();
+        let channel = {
+            let guard = self.log.lock();
+            assert!(guard.ops.iter().all(|op| op.0 != task), "log: commit inside an fs op");
+            guard.channel
+        };
+        INTERFACE_MANAGER.interface.sleep_lock(channel);
+        unsafe{self.log.lock().commit()};
+        INTERFACE_MANAGER.interface.sleep_unlock(channel);
+    }
+
+    /// Stop the fs ops of the other tasks until the returned guard is
+    /// dropped, for changes of the whole file system. Waits for the ops in
+    /// flight to end and commits them, so it must not be called inside an op.
+    /// The current task may run ops meanwhile, each one is committed when it
+    /// ends. It must not call `flush`.
+    pub fn freeze(&self) -> LogFreeze {
+        let task = INTERFACE_MANAGER.interface.cur_task_id();
+        let channel = {
+            let guard = self.log.lock();
+            assert!(guard.ops.iter().all(|op| op.0 != task), "log: freeze inside an fs op");
+            guard.channel
+        };
+        INTERFACE_MANAGER.interface.sleep_lock(channel);
+        let mut guard = self.log.lock();
+        unsafe{guard.commit()};
+        guard.frozen_by = Some(task);
+        LogFreeze(())
+    }
+
+    fn thaw(&self) {
+        let channel = {
+            let mut guard = self.log.lock();
+            assert!(guard.ops.is_empty(), "log: thaw inside an fs op");
+            guard.frozen_by = None;
+            guard.channel
+        };
+        INTERFACE_MANAGER.interface.sleep_unlock(channel);
+    }
 
     /// Accept a buffer, write it into the log and then release the buffer.
     /// This function will pin this buf in the cache until the log commits.
@@ -240,13 +387,50 @@ impl LogManager {
         drop(buf);
     }
 
-    // It should be called at the end of file system call.
-    // It will commit the log if this is the last outstanding op.
-    // 这里要不要最后一个end op再commit还需要再斟酌一下的说
-    pub fn end_op(&self) {
-        let mut guard = self.log.lock();
-        unsafe{guard.commit()};
-        drop(guard);
+    /// Commit all pending operations and flush the block device.
+    /// Waits for the operations in flight to end, so it must not be called
+    /// inside one.
+    pub fn flush(&self) {
+        self.commit_all();
+        BLOCK_CACHE_MANAGER.flush();
+    }
+
+    /// Number of blocks written by operations that are not committed yet.
+    pub fn pending(&self) -> u32 {
+        self.log.lock().lh.len
+    }
+
+    pub fn mode(&self) -> WriteBackMode {
+        self.log.lock().mode
+    }
+
+    /// Switch the write-back mode.
+    /// Pending operations are committed when switching back to `Sync`.
+    pub fn set_mode(&self, mode: WriteBackMode) {
+        self.log.lock().mode = mode;
+        if mode == WriteBackMode::Sync {
+            self.flush();
+        }
+    }
+}
+
+/// An fs operation in flight, see `LogManager::begin_op`.
+#[must_use]
+pub struct LogOp(());
+
+impl Drop for LogOp {
+    fn drop(&mut self) {
+        LOG_MANAGER.end_op();
+    }
+}
+
+/// The fs frozen by the current task, see `LogManager::freeze`.
+#[must_use]
+pub struct LogFreeze(());
+
+impl Drop for LogFreeze {
+    fn drop(&mut self) {
+        LOG_MANAGER.thaw();
     }
 }
 
@@ -255,4 +439,24 @@ impl LogManager {
 pub struct LogHeader {
     len: u32,                       // current len of blocknos array
     blocknos: [u32; LOGSIZE-1],     // LOGSIZE-1: one block left for log info
+    checksum: u32,                  // CRC32C of the fields above and the logged blocks
+}
+
+impl LogHeader {
+    /// Number of blocks recorded in the log.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Checksum of the header and the logged blocks,
+    /// only kept with `FEATURE_METADATA_CSUM`.
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Home block numbers of the logged blocks.
+    pub fn blocknos(&self) -> &[u32] {
+        let len = (self.len as usize).min(self.blocknos.len());
+        &self.blocknos[..len]
+    }
 }
@@ -0,0 +1,208 @@
+//! LZ4 block format, without the frame around it.
+//!
+//! The compressor is the simple greedy one: a hash table of the positions of
+//! the last 4-byte sequences, no lazy matching. Inputs are at most 64 KiB.
+
+const MIN_MATCH: usize = 4;
+const HASH_LOG: u32 = 10;
+/// A match must start at least this far from the end of the input.
+const MFLIMIT: usize = 12;
+/// The last bytes of the input are always literals.
+const LAST_LITERALS: usize = 5;
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([src[pos], src[pos + 1], src[pos + 2], src[pos + 3]])
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+/// Append `n` in the LZ4 variable length encoding, after a 4-bit field of 15.
+fn push_len(dst: &mut [u8], out: &mut usize, mut n: usize) -> Option<()> {
+    while n >= 255 {
+        *dst.get_mut(*out)? = 255;
+        *out += 1;
+        n -= 255;
+    }
+    *dst.get_mut(*out)? = n as u8;
+    *out += 1;
+    Some(())
+}
+
+/// Append a sequence: the literals, then the match if `matched` is not 0.
+fn push_sequence(
+    dst: &mut [u8],
+    out: &mut usize,
+    literals: &[u8],
+    offset: usize,
+    matched: usize,
+) -> Option<()> {
+    let match_code = if matched == 0 { 0 } else { matched - MIN_MATCH };
+    let token = (literals.len().min(15) << 4 | match_code.min(15)) as u8;
+    *dst.get_mut(*out)? = token;
+    *out += 1;
+    if literals.len() >= 15 {
+        push_len(dst, out, literals.len() - 15)?;
+    }
+    dst.get_mut(*out..*out + literals.len())?.copy_from_slice(literals);
+    *out += literals.len();
+    if matched == 0 {
+        return Some(());
+    }
+    dst.get_mut(*out..*out + 2)?.copy_from_slice(&(offset as u16).to_le_bytes());
+    *out += 2;
+    if match_code >= 15 {
+        push_len(dst, out, match_code - 15)?;
+    }
+    Some(())
+}
+
+/// Compress `src` into `dst`, returns the compressed length,
+/// or None if it doesn't fit in `dst`.
+pub fn compress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    debug_assert!(src.len() <= u16::MAX as usize);
+    let mut table = [0u16; 1 << HASH_LOG]; // position + 1, 0 for none
+    let mut out = 0;
+    let mut anchor = 0;
+    let mut pos = 0;
+    if src.len() > MFLIMIT {
+        let match_end = src.len() - LAST_LITERALS;
+        while pos < src.len() - MFLIMIT {
+            let seq = read_u32(src, pos);
+            let h = hash(seq);
+            let candidate = table[h] as usize;
+            table[h] = pos as u16 + 1;
+            if candidate == 0 || pos - (candidate - 1) > MAX_OFFSET || read_u32(src, candidate - 1) != seq {
+                pos += 1;
+                continue;
+            }
+            let start = candidate - 1;
+            let mut len = MIN_MATCH;
+            while pos + len < match_end && src[start + len] == src[pos + len] {
+                len += 1;
+            }
+            push_sequence(dst, &mut out, &src[anchor..pos], pos - start, len)?;
+            pos += len;
+            anchor = pos;
+        }
+    }
+    push_sequence(dst, &mut out, &src[anchor..], 0, 0)?;
+    Some(out)
+}
+
+fn read_len(src: &[u8], i: &mut usize) -> Option<usize> {
+    let mut n = 0;
+    loop {
+        let b = *src.get(*i)?;
+        *i += 1;
+        n += b as usize;
+        if b != 255 {
+            return Some(n);
+        }
+    }
+}
+
+/// Decompress `src` into `dst`, returns the decompressed length,
+/// or None if `src` is not valid or doesn't fit in `dst`.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut out = 0;
+    loop {
+        let token = *src.get(i)?;
+        i += 1;
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_len(src, &mut i)?;
+        }
+        dst.get_mut(out..out + literals)?.copy_from_slice(src.get(i..i + literals)?);
+        i += literals;
+        out += literals;
+        if i == src.len() {
+            return Some(out);
+        }
+        let offset = u16::from_le_bytes([*src.get(i)?, *src.get(i + 1)?]) as usize;
+        i += 2;
+        let mut matched = (token & 15) as usize;
+        if matched == 15 {
+            matched += read_len(src, &mut i)?;
+        }
+        matched += MIN_MATCH;
+        if offset == 0 || offset > out || out + matched > dst.len() {
+            return None;
+        }
+        // the match may overlap what it copies, byte by byte repeats it
+        for k in out..out + matched {
+            dst[k] = dst[k - offset];
+        }
+        out += matched;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(src: &[u8]) {
+        let mut packed = vec![0; src.len() + src.len() / 255 + 16];
+        let len = compress(src, &mut packed).unwrap();
+        let mut out = vec![0; src.len()];
+        assert_eq!(decompress(&packed[..len], &mut out), Some(src.len()));
+        assert_eq!(out, src);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(b"");
+        round_trip(b"short");
+        round_trip(&[0; 4096]);
+        let text: Vec<u8> = b"the quick brown fox jumps over the lazy dog. ".iter().cycle().take(3000).copied().collect();
+        round_trip(&text);
+        // xorshift, nothing to match
+        let mut x = 0x2545_f491u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        round_trip(&noise);
+    }
+
+    #[test]
+    fn test_known_blocks() {
+        let mut out = [0; 64];
+        // literals only
+        assert_eq!(decompress(b"\x50hello", &mut out), Some(5));
+        assert_eq!(&out[..5], b"hello");
+        // 15 + 0 literals, the length continues in the next byte
+        assert_eq!(decompress(b"\xf0\x00abcdefghijklmno", &mut out), Some(15));
+        assert_eq!(&out[..15], b"abcdefghijklmno");
+        // "ab" repeated by an overlapping match of 4 + 2, then "!"
+        assert_eq!(decompress(b"\x22ab\x02\x00\x10!", &mut out), Some(9));
+        assert_eq!(&out[..9], b"abababab!");
+
+        // one literal, a match of 4 + 15 + 7 at offset 1, the last 5 literals
+        let mut packed = [0; 64];
+        let len = compress(&[b'a'; 32], &mut packed).unwrap();
+        assert_eq!(&packed[..len], b"\x1fa\x01\x00\x07\x50aaaaa");
+        assert_eq!(compress(b"", &mut packed), Some(1));
+        assert_eq!(packed[0], 0);
+    }
+
+    #[test]
+    fn test_bad_blocks() {
+        let mut out = [0; 8];
+        // offset 0, and an offset before the start of the output
+        assert_eq!(decompress(b"\x10a\x00\x00\x00", &mut out), None);
+        assert_eq!(decompress(b"\x10a\x02\x00\x00", &mut out), None);
+        // literals cut short
+        assert_eq!(decompress(b"\x50hel", &mut out), None);
+        // doesn't fit in the output
+        assert_eq!(decompress(b"\x10a\x01\x00\x50aaaaa", &mut out), None);
+        assert_eq!(compress(&[b'a'; 32], &mut out[..4]), None);
+    }
+}
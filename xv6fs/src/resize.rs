@@ -0,0 +1,352 @@
+//! Growing and shrinking the file system while it is mounted.
+//!
+//! The file system uses the blocks below the `size` of the super block.
+//! Growing clears the bits and checksums of the new blocks and writes the
+//! super block. Shrinking first moves the blocks of files and directories
+//! that lie past the new end to free blocks before it, a few blocks per
+//! transaction, while `balloc` is kept below the new end.
+//!
+//! The bitmap has `size / BPB + 1` blocks and the checksum table one entry
+//! per block. When they need more blocks, or would lie past the new end, a
+//! new bitmap and table are built in a run of free blocks and written there
+//! directly, as nothing refers to those blocks yet. The super block then
+//! switches to them in one transaction, which frees the old ones.
+//!
+//! The fs ops of other tasks wait while it is resized, see
+//! `LogManager::freeze`, and it must not have snapshots. Every inode is read
+//! before any block is moved. A shrink that stops halfway anyway leaves the
+//! moved blocks where they are, the file system keeps its old size and a
+//! later resize goes on from there.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::ptr::{self, read, write};
+
+use crate::bitmap::{balloc, bfree, bisalloc, set_alloc_limit};
+use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::checksum::{self, CSUMS_PER_BLOCK};
+use crate::disk_inode::{ndirect, InodeType};
+use crate::fs_const::{BPB, BSIZE, IPB, MAXOPBLOCKS, NINDIRECT, NSNAPSHOT, ROOTDEV};
+use crate::inode::{read_disk_inode, InodeData, ICACHE};
+use crate::log::LOG_MANAGER;
+use crate::superblock::SUPER_BLOCK;
+
+/// Error of a file system with snapshots, which can't be resized.
+pub const HAS_SNAPSHOTS: &str = "file system has snapshots";
+/// Error of a size that leaves no room for the inode blocks.
+pub const TOO_SMALL: &str = "new size too small";
+/// Error of a size that leaves no room for the blocks in use.
+pub const NO_SPACE: &str = "not enough free blocks";
+
+/// Blocks moved in one transaction: each changes the new block, the
+/// bitmap, a table block and the block that points to it.
+const MOVES_PER_OP: usize = 2;
+
+/// New blocks whose bits and checksums are cleared in one transaction,
+/// taking at most half of its blocks for the table or the bitmap.
+fn clears_per_op() -> u32 {
+    let per_block = if checksum::enabled() { CSUMS_PER_BLOCK as u32 } else { BPB };
+    per_block * (MAXOPBLOCKS / 2) as u32
+}
+
+fn bitmap_blocks(size: u32) -> u32 {
+    size / BPB + 1
+}
+
+fn table_blocks(size: u32) -> u32 {
+    if checksum::enabled() {
+        (size + CSUMS_PER_BLOCK as u32 - 1) / CSUMS_PER_BLOCK as u32
+    } else {
+        0
+    }
+}
+
+/// The bitmap and checksum table blocks for a file system of `size` blocks.
+fn metadata(size: u32) -> (Range<u32>, Range<u32>) {
+    let (bmapstart, csumstart) = unsafe { (SUPER_BLOCK.bmapstart(), SUPER_BLOCK.csumstart()) };
+    (
+        bmapstart..bmapstart + bitmap_blocks(size),
+        csumstart..csumstart + table_blocks(size),
+    )
+}
+
+/// Change the size of the file system to `new_size` blocks.
+/// The block device must have that many blocks.
+pub fn resize(new_size: u32) -> Result<(), &'static str> {
+    let (inodestart, ninodes) = unsafe { (SUPER_BLOCK.inodestart(), SUPER_BLOCK.ninodes()) };
+    if new_size <= inodestart + ninodes / IPB as u32 + 1 {
+        return Err(TOO_SMALL);
+    }
+    let freeze = LOG_MANAGER.freeze();
+    let res = resize_frozen(new_size);
+    drop(freeze);
+    LOG_MANAGER.flush();
+    res
+}
+
+fn resize_frozen(new_size: u32) -> Result<(), &'static str> {
+    let old_size = unsafe { SUPER_BLOCK.size() };
+    if (0..NSNAPSHOT).any(|slot| unsafe { SUPER_BLOCK.snapshot(slot) } != 0) {
+        return Err(HAS_SNAPSHOTS);
+    }
+    if new_size == old_size {
+        return Ok(());
+    }
+    let used = (0..old_size).filter(|&b| bisalloc(b)).count() as u32;
+    let old_meta = bitmap_blocks(old_size) + table_blocks(old_size);
+    if used - old_meta + bitmap_blocks(new_size) + table_blocks(new_size) > new_size {
+        return Err(NO_SPACE);
+    }
+
+    let (bitmap, table) = metadata(new_size);
+    let relocate = bitmap_blocks(new_size) > bitmap_blocks(old_size)
+        || table_blocks(new_size) > table_blocks(old_size)
+        || bitmap.end > new_size
+        || table.end > new_size;
+    let inums = if new_size < old_size { inodes_in_use()? } else { Vec::new() };
+    set_alloc_limit(new_size);
+    let res = move_files(&inums, new_size).and_then(|_| {
+        if relocate { relocate_metadata(old_size, new_size) } else { resize_in_place(old_size, new_size) }
+    });
+    set_alloc_limit(u32::MAX);
+    res
+}
+
+/// Switch to the new size, the bitmap and the table stay where they are.
+fn resize_in_place(old_size: u32, new_size: u32) -> Result<(), &'static str> {
+    // the bits may still be set from before the file system shrank,
+    // balloc doesn't hand out the blocks until the super block is written
+    let mut start = old_size;
+    while start < new_size {
+        let end = new_size.min((start / clears_per_op() + 1) * clears_per_op());
+        let op = LOG_MANAGER.begin_op();
+        clear_bits(start..end)?;
+        for blockno in start..end {
+            checksum::clear(ROOTDEV, blockno);
+        }
+        drop(op);
+        start = end;
+    }
+    let op = LOG_MANAGER.begin_op();
+    if new_size < old_size {
+        // blocks of the bitmap and the table that are not needed anymore
+        let (bitmap, table) = metadata(old_size);
+        let (new_bitmap, new_table) = metadata(new_size);
+        for blockno in (new_bitmap.end..bitmap.end).chain(new_table.end..table.end) {
+            if blockno < new_size {
+                bfree(blockno)?;
+            }
+        }
+    }
+    let (bmapstart, csumstart) = unsafe { (SUPER_BLOCK.bmapstart(), SUPER_BLOCK.csumstart()) };
+    unsafe { SUPER_BLOCK.set_layout(ROOTDEV, new_size, bmapstart, csumstart) };
+    drop(op);
+    Ok(())
+}
+
+/// Clear the bits of the blocks in `blocks` in the bitmap.
+fn clear_bits(blocks: Range<u32>) -> Result<(), &'static str> {
+    let mut blockno = blocks.start;
+    while blockno < blocks.end {
+        let mut buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, unsafe { SUPER_BLOCK.bitmap_blockno(blockno) });
+        checksum::verify(ROOTDEV, &buf)?;
+        let bytes = buf.raw_data_mut() as *mut u8;
+        let end = blocks.end.min((blockno / BPB + 1) * BPB);
+        for b in blockno..end {
+            let bi = b % BPB;
+            unsafe { *bytes.offset((bi / 8) as isize) &= !(1 << (bi % 8)) };
+        }
+        checksum::update(ROOTDEV, &buf);
+        LOG_MANAGER.write(buf);
+        blockno = end;
+    }
+    Ok(())
+}
+
+/// Build the bitmap and the table for `new_size` in a run of free blocks
+/// and switch to them.
+fn relocate_metadata(old_size: u32, new_size: u32) -> Result<(), &'static str> {
+    let (old_bitmap, old_table) = metadata(old_size);
+    let nmap = bitmap_blocks(new_size);
+    let ntable = table_blocks(new_size);
+    let start = find_run(new_size, nmap + ntable).ok_or(NO_SPACE)?;
+    let new_blocks = start..start + nmap + ntable;
+
+    let mut map = vec![0u8; nmap as usize * BSIZE];
+    let mut set_bit = |b: u32| map[b as usize / 8] |= 1 << (b % 8);
+    // one more word per table block for its own checksum
+    let mut entries = vec![0u32; ntable as usize * (CSUMS_PER_BLOCK + 1)];
+    let entry_index = |b: u32| b as usize / CSUMS_PER_BLOCK * (CSUMS_PER_BLOCK + 1) + b as usize % CSUMS_PER_BLOCK;
+    for b in 0..old_size.min(new_size) {
+        if old_bitmap.contains(&b) || old_table.contains(&b) {
+            continue;
+        }
+        if bisalloc(b) {
+            set_bit(b);
+        }
+        if ntable > 0 {
+            entries[entry_index(b)] = checksum::entry_of(ROOTDEV, b)?;
+        }
+    }
+    new_blocks.clone().for_each(&mut set_bit);
+    if ntable > 0 {
+        for (i, block) in map.chunks(BSIZE).enumerate() {
+            entries[entry_index(start + i as u32)] = checksum::block_csum(block);
+        }
+        for block in entries.chunks_mut(CSUMS_PER_BLOCK + 1) {
+            let bytes: Vec<u8> = block[..CSUMS_PER_BLOCK].iter().flat_map(|e| e.to_ne_bytes()).collect();
+            block[CSUMS_PER_BLOCK] = checksum::block_csum(&bytes);
+        }
+    }
+
+    // nothing refers to the blocks until the super block is committed
+    let table_bytes: Vec<u8> = entries.iter().flat_map(|e| e.to_ne_bytes()).collect();
+    let data = map.chunks(BSIZE).chain(table_bytes.chunks(BSIZE));
+    for (blockno, block) in new_blocks.zip(data) {
+        let mut buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, blockno);
+        unsafe { ptr::copy_nonoverlapping(block.as_ptr(), buf.raw_data_mut() as *mut u8, BSIZE) };
+        buf.bwrite();
+        buf.set_verified(true);
+    }
+    let csumstart = if ntable > 0 { start + nmap } else { unsafe { SUPER_BLOCK.csumstart() } };
+    let op = LOG_MANAGER.begin_op();
+    unsafe { SUPER_BLOCK.set_layout(ROOTDEV, new_size, start, csumstart) };
+    drop(op);
+    Ok(())
+}
+
+/// The first of `len` free blocks in a row below `limit`.
+/// The blocks past the current size are free.
+fn find_run(limit: u32, len: u32) -> Option<u32> {
+    let mut run = 0;
+    for b in 0..limit {
+        if bisalloc(b) {
+            run = 0;
+        } else {
+            run += 1;
+            if run == len {
+                return Some(b + 1 - len);
+            }
+        }
+    }
+    None
+}
+
+/// The allocated inodes, read from the disk without caching them.
+/// Fails if one of them can't be read, before anything is moved.
+fn inodes_in_use() -> Result<Vec<u32>, &'static str> {
+    let mut inums = Vec::new();
+    for inum in 1..unsafe { SUPER_BLOCK.ninodes() } {
+        if read_disk_inode(ROOTDEV, inum)?.itype != InodeType::Empty {
+            inums.push(inum);
+        }
+    }
+    Ok(inums)
+}
+
+/// Move the blocks of the inodes `inums` below `limit`.
+fn move_files(inums: &[u32], limit: u32) -> Result<(), &'static str> {
+    for &inum in inums {
+        let inode = ICACHE.get(ROOTDEV, inum);
+        loop {
+            let op = LOG_MANAGER.begin_op();
+            let mut idata = inode.try_lock()?;
+            let moved = idata.move_blocks_below(limit, MOVES_PER_OP);
+            drop(idata);
+            drop(op);
+            if moved < MOVES_PER_OP {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the block `from` to a new block and free it, return the new block.
+/// `csum` tells whether the block has a checksum, like directory blocks.
+fn move_block(dev: u32, from: u32, csum: bool) -> u32 {
+    let to = balloc(dev);
+    let src = BLOCK_CACHE_MANAGER.bread(dev, from);
+    let mut dst = BLOCK_CACHE_MANAGER.bread(dev, to);
+    unsafe { ptr::copy_nonoverlapping(src.raw_data(), dst.raw_data_mut(), 1) };
+    drop(src);
+    if csum {
+        checksum::update(dev, &dst);
+    }
+    LOG_MANAGER.write(dst);
+    let _ = bfree(from);
+    to
+}
+
+impl InodeData {
+    /// Move up to `max` blocks of the inode that lie at or past `limit`,
+    /// return how many were moved. Caller must hold inode's sleeplock.
+    fn move_blocks_below(&mut self, limit: u32, max: usize) -> usize {
+        let csum = self.dinode.itype == InodeType::Directory;
+        let mut moved = 0;
+        let mut changed = false;
+        let ndirect = ndirect();
+        for i in 0..ndirect + 2 {
+            let addr = self.dinode.addrs[i];
+            if addr >= limit && moved < max {
+                self.dinode.addrs[i] = move_block(self.dev, addr, csum && i < ndirect);
+                moved += 1;
+                changed = true;
+            }
+        }
+        if self.dinode.addrs[ndirect] != 0 {
+            move_entries(self.dev, self.dinode.addrs[ndirect], limit, 1, csum, &mut moved, max);
+        }
+        if self.dinode.addrs[ndirect + 1] != 0 {
+            move_entries(self.dev, self.dinode.addrs[ndirect + 1], limit, 2, csum, &mut moved, max);
+        }
+        if self.dinode.xattr() >= limit && moved < max {
+            self.dinode.set_xattr(move_block(self.dev, self.dinode.xattr(), true));
+            moved += 1;
+            changed = true;
+        }
+        if changed {
+            self.update();
+        }
+        moved
+    }
+}
+
+/// Move the blocks the indirect block `block` points to, and with a `depth`
+/// of 2 the blocks those point to, see `move_blocks_below`.
+fn move_entries(dev: u32, block: u32, limit: u32, depth: usize, csum: bool, moved: &mut usize, max: usize) {
+    let mut buf = BLOCK_CACHE_MANAGER.bread(dev, block);
+    let entries = buf.raw_data_mut() as *mut u32;
+    let mut changed = false;
+    for i in 0..NINDIRECT {
+        if *moved == max {
+            break;
+        }
+        let entry = unsafe { entries.offset(i as isize) };
+        let mut addr = unsafe { read(entry) };
+        if addr == 0 {
+            continue;
+        }
+        if !bisalloc(addr) {
+            // left behind when the block was freed, it must not point past the end
+            if addr >= limit {
+                unsafe { write(entry, 0) };
+                changed = true;
+            }
+            continue;
+        }
+        if addr >= limit {
+            addr = move_block(dev, addr, csum && depth == 1);
+            unsafe { write(entry, addr) };
+            changed = true;
+            *moved += 1;
+        }
+        if depth == 2 {
+            move_entries(dev, addr, limit, 1, csum, moved, max);
+        }
+    }
+    if changed {
+        LOG_MANAGER.write(buf);
+    }
+}
@@ -0,0 +1,344 @@
+//! Copy-on-write snapshots.
+//!
+//! A snapshot is a read-only view of the whole file system as it was when the
+//! snapshot was taken. The super block refers to up to `NSNAPSHOT` snapshot
+//! blocks, each of which holds the name of the snapshot and the block numbers
+//! of:
+//!
+//! - a copy of the inode blocks, so that changes to the live inodes don't show
+//!   in the snapshot;
+//! - a copy of the bitmap, the allocation map of the snapshot. It has every
+//!   block the snapshot uses set, including the snapshot block and the copies.
+//!
+//! Data, indirect and directory blocks are shared with the live tree. A block
+//! that is set in the map of any snapshot is never written in place nor
+//! reused: the live tree copies it before changing it (`InodeData::bmap`),
+//! `bfree` only clears it in the live bitmap and `balloc` skips it. Deleting a
+//! snapshot drops its map, which frees the blocks that only it still used.
+//!
+//! The inodes of a snapshot are cached under a device number of their own,
+//! see `snapshot_dev`, and are loaded from the copied inode blocks.
+//!
+//! The snapshot blocks and maps are kept in memory after they are first read,
+//! `is_shared` is called for every block that is allocated or written.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::bitmap::bisalloc;
+use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::checksum::{self, CORRUPTED};
+use crate::fs_const::{BPB, BSIZE, IPB, MAXOPBLOCKS, NSNAPSHOT, ROOTDEV};
+use crate::inode::ICACHE;
+use crate::log::LOG_MANAGER;
+use crate::superblock::SUPER_BLOCK;
+
+/// Error of a snapshot name that is not in use.
+pub const NOT_FOUND: &str = "no such snapshot";
+/// Error of a snapshot name that is already in use.
+pub const EXISTS: &str = "snapshot already exists";
+/// Error of a snapshot whose inodes are still referenced.
+pub const BUSY: &str = "snapshot is in use";
+/// Error of a snapshot name that is empty, too long or has a '/'.
+pub const INVALID_NAME: &str = "invalid snapshot name";
+/// Error of a write to a snapshot.
+pub const READ_ONLY: &str = "snapshot is read-only";
+
+/// Longest name of a snapshot.
+pub const SNAPNAME: usize = 15;
+
+const SNAPSHOT_MAGIC: u32 = 0x534e_4150;
+/// Block numbers that fit in a snapshot block.
+const SNAPSHOT_BLOCKS: usize = (BSIZE - 28) / 4;
+
+/// On-disk snapshot block.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawSnapshot {
+    magic: u32,
+    name: [u8; SNAPNAME + 1], // NUL terminated
+    ninodeblocks: u32,
+    nmapblocks: u32,
+    // the copies of the inode blocks, then those of the bitmap blocks
+    blocks: [u32; SNAPSHOT_BLOCKS],
+}
+
+impl RawSnapshot {
+    fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(SNAPNAME);
+        &self.name[..len]
+    }
+}
+
+/// The device number the inodes of the snapshot in `slot` are cached under.
+pub fn snapshot_dev(slot: usize) -> u32 {
+    ROOTDEV | ((slot as u32 + 1) << 16)
+}
+
+fn slot_of(dev: u32) -> Option<usize> {
+    (dev >> 16).checked_sub(1).map(|slot| slot as usize)
+}
+
+/// Whether `dev` is the device number of a snapshot.
+pub fn is_snapshot_dev(dev: u32) -> bool {
+    slot_of(dev).is_some()
+}
+
+fn read_snapshot(blockno: u32) -> Result<RawSnapshot, &'static str> {
+    debug_assert_eq!(size_of::<RawSnapshot>(), BSIZE);
+    let buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, blockno);
+    checksum::verify(ROOTDEV, &buf)?;
+    let snap = unsafe { ptr::read(buf.raw_data() as *const RawSnapshot) };
+    drop(buf);
+    if snap.magic != SNAPSHOT_MAGIC {
+        return Err(CORRUPTED);
+    }
+    Ok(snap)
+}
+
+/// Whether the bit of `blockno` is set in the bitmap `map`.
+fn bit(map: &[u8], blockno: u32) -> bool {
+    map.get(blockno as usize / 8).map_or(false, |byte| byte & (1 << (blockno % 8)) != 0)
+}
+
+/// The snapshots in memory.
+struct Snapshots {
+    slots: [Option<RawSnapshot>; NSNAPSHOT],
+    /// The map of each snapshot, one bit per block of the file system.
+    maps: [Vec<u8>; NSNAPSHOT],
+    /// The union of the maps.
+    shared: Vec<u8>,
+}
+
+impl Snapshots {
+    fn load() -> Result<Self, &'static str> {
+        let mut snapshots = Self {
+            slots: [None; NSNAPSHOT],
+            maps: Default::default(),
+            shared: Vec::new(),
+        };
+        let size = unsafe { SUPER_BLOCK.size() } as usize;
+        snapshots.shared = vec![0; (size + 7) / 8];
+        for slot in 0..NSNAPSHOT {
+            let snap = match unsafe { SUPER_BLOCK.snapshot(slot) } {
+                0 => continue,
+                blockno => read_snapshot(blockno)?,
+            };
+            let mut map = vec![0; snapshots.shared.len()];
+            for (i, chunk) in map.chunks_mut(BSIZE).enumerate().take(snap.nmapblocks as usize) {
+                let buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, snap.blocks[snap.ninodeblocks as usize + i]);
+                checksum::verify(ROOTDEV, &buf)?;
+                let data = unsafe { core::slice::from_raw_parts(buf.raw_data() as *const u8, BSIZE) };
+                chunk.copy_from_slice(&data[..chunk.len()]);
+            }
+            for (shared, byte) in snapshots.shared.iter_mut().zip(map.iter()) {
+                *shared |= byte;
+            }
+            snapshots.slots[slot] = Some(snap);
+            snapshots.maps[slot] = map;
+        }
+        Ok(snapshots)
+    }
+}
+
+static SNAPSHOTS: Mutex<Option<Arc<Snapshots>>> = Mutex::new(None);
+/// Bumped whenever the snapshots on disk change.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// The snapshots, read from the disk if they are not in memory.
+fn snapshots() -> Result<Arc<Snapshots>, &'static str> {
+    if let Some(snapshots) = SNAPSHOTS.lock().clone() {
+        return Ok(snapshots);
+    }
+    let generation = GENERATION.load(Ordering::Acquire);
+    let snapshots = Arc::new(Snapshots::load()?);
+    let mut cached = SNAPSHOTS.lock();
+    // don't keep what was read while the snapshots changed
+    if GENERATION.load(Ordering::Acquire) == generation {
+        *cached = Some(snapshots.clone());
+    }
+    Ok(snapshots)
+}
+
+/// Drop the snapshots in memory after they are changed on disk.
+fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    *SNAPSHOTS.lock() = None;
+}
+
+/// The snapshot in `slot`, if the slot is used.
+fn snapshot_at(slot: usize) -> Result<Option<RawSnapshot>, &'static str> {
+    Ok(snapshots()?.slots[slot])
+}
+
+fn find(name: &str) -> Result<(usize, RawSnapshot), &'static str> {
+    for slot in 0..NSNAPSHOT {
+        if let Some(snap) = snapshot_at(slot)? {
+            if snap.name() == name.as_bytes() {
+                return Ok((slot, snap));
+            }
+        }
+    }
+    Err(NOT_FOUND)
+}
+
+/// Whether a snapshot uses the block, so that it must not be changed.
+pub fn is_shared(blockno: u32) -> bool {
+    match snapshots() {
+        Ok(snapshots) => bit(&snapshots.shared, blockno),
+        Err(e) => panic!("xv6fs: {} snapshot", e),
+    }
+}
+
+/// The block that holds inode `inum` of the live tree or of a snapshot.
+pub fn locate_inode(dev: u32, inum: u32) -> Result<u32, &'static str> {
+    let slot = match slot_of(dev) {
+        None => return Ok(unsafe { SUPER_BLOCK.locate_inode(inum) }),
+        Some(slot) => slot,
+    };
+    let snap = snapshot_at(slot)?.ok_or(NOT_FOUND)?;
+    if inum >= unsafe { SUPER_BLOCK.ninodes() } {
+        return Err("inode number out of range");
+    }
+    Ok(snap.blocks[inum as usize / IPB])
+}
+
+/// Copy the block `from` to the block `to`, letting `edit` change the copy,
+/// and write it to the log.
+fn copy_block(from: u32, to: u32, edit: impl FnOnce(&mut [u8])) {
+    let src = BLOCK_CACHE_MANAGER.bread(ROOTDEV, from);
+    let mut dst = BLOCK_CACHE_MANAGER.bread(ROOTDEV, to);
+    unsafe { ptr::copy_nonoverlapping(src.raw_data(), dst.raw_data_mut(), 1) };
+    drop(src);
+    edit(unsafe { core::slice::from_raw_parts_mut(dst.raw_data_mut() as *mut u8, BSIZE) });
+    checksum::update(ROOTDEV, &dst);
+    LOG_MANAGER.write(dst);
+}
+
+/// Take a snapshot of the file system named `name`.
+/// It must not be called inside an fs op.
+pub fn create(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > SNAPNAME || name.bytes().any(|c| c == 0 || c == b'/') {
+        return Err(INVALID_NAME);
+    }
+    // the snapshot holds everything done before it, and nothing changes
+    // the file system while it is copied
+    let _frozen = LOG_MANAGER.freeze();
+    let snapshots = snapshots()?;
+    let mut free_slot = None;
+    for (slot, snap) in snapshots.slots.iter().enumerate() {
+        match snap {
+            Some(snap) if snap.name() == name.as_bytes() => return Err(EXISTS),
+            None if free_slot.is_none() => free_slot = Some(slot),
+            _ => {}
+        }
+    }
+    let slot = free_slot.ok_or("too many snapshots")?;
+    let (inodestart, bmapstart, size) =
+        unsafe { (SUPER_BLOCK.inodestart(), SUPER_BLOCK.bmapstart(), SUPER_BLOCK.size()) };
+    // the bitmap doesn't follow the inode blocks after a resize moved it
+    let ninodeblocks = unsafe { SUPER_BLOCK.ninodes() } as usize / IPB + 1;
+    let nmapblocks = (size / BPB + 1) as usize;
+    let ncopies = ninodeblocks + nmapblocks;
+    if ncopies > SNAPSHOT_BLOCKS {
+        return Err("file system too large for snapshots");
+    }
+    // check what is copied before anything is written
+    let inode_blocks = inodestart..inodestart + ninodeblocks as u32;
+    for blockno in inode_blocks.chain(bmapstart..bmapstart + nmapblocks as u32) {
+        let buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, blockno);
+        checksum::verify(ROOTDEV, &buf)?;
+    }
+    // blocks free in the live tree and in the other snapshots, they are
+    // never set in the live bitmap
+    let own: Vec<u32> = (0..size)
+        .filter(|&b| !bisalloc(b) && !bit(&snapshots.shared, b))
+        .take(ncopies + 1)
+        .collect();
+    if own.len() < ncopies + 1 {
+        return Err("not enough free blocks for a snapshot");
+    }
+
+    let mut snap = RawSnapshot {
+        magic: SNAPSHOT_MAGIC,
+        name: [0; SNAPNAME + 1],
+        ninodeblocks: ninodeblocks as u32,
+        nmapblocks: nmapblocks as u32,
+        blocks: [0; SNAPSHOT_BLOCKS],
+    };
+    snap.name[..name.len()].copy_from_slice(name.as_bytes());
+    let snap_block = own[0];
+    snap.blocks[..ncopies].copy_from_slice(&own[1..]);
+    // Nothing refers to the copies until the snapshot block is in the super
+    // block, so they may be written by several ops.
+    for first in (0..ncopies).step_by(MAXOPBLOCKS) {
+        let _op = LOG_MANAGER.begin_op();
+        for (i, &to) in snap.blocks[..ncopies].iter().enumerate().skip(first).take(MAXOPBLOCKS) {
+            if i < ninodeblocks {
+                copy_block(inodestart + i as u32, to, |_| {});
+                continue;
+            }
+            // the map also has the snapshot's own blocks set
+            let map = (i - ninodeblocks) as u32;
+            copy_block(bmapstart + map, to, |data| {
+                for &b in own.iter().filter(|&&b| b / BPB == map) {
+                    let bi = (b % BPB) as usize;
+                    data[bi / 8] |= 1 << (bi % 8);
+                }
+            });
+        }
+    }
+    let _op = LOG_MANAGER.begin_op();
+    let mut buf = BLOCK_CACHE_MANAGER.bread(ROOTDEV, snap_block);
+    unsafe { ptr::write(buf.raw_data_mut() as *mut RawSnapshot, snap) };
+    checksum::update(ROOTDEV, &buf);
+    LOG_MANAGER.write(buf);
+    unsafe { SUPER_BLOCK.set_snapshot(ROOTDEV, slot, snap_block) };
+    invalidate();
+    Ok(())
+}
+
+/// Delete the snapshot `name`, it must not be in use.
+pub fn delete(name: &str) -> Result<(), &'static str> {
+    let (slot, _) = find(name)?;
+    if ICACHE.dev_in_use(snapshot_dev(slot)) {
+        return Err(BUSY);
+    }
+    let snapshots = snapshots()?;
+    let size = unsafe { SUPER_BLOCK.size() };
+    let used: Vec<u32> = (0..size).filter(|&b| bit(&snapshots.maps[slot], b)).collect();
+    let _op = LOG_MANAGER.begin_op();
+    unsafe { SUPER_BLOCK.set_snapshot(ROOTDEV, slot, 0) };
+    invalidate();
+    // blocks only this snapshot used are free now,
+    // a directory block may be reused as another directory block
+    for blockno in used {
+        if !bisalloc(blockno) && !is_shared(blockno) {
+            checksum::clear(ROOTDEV, blockno);
+        }
+    }
+    Ok(())
+}
+
+/// Names of all snapshots.
+pub fn list() -> Result<Vec<String>, &'static str> {
+    let mut names = Vec::new();
+    for slot in 0..NSNAPSHOT {
+        if let Some(snap) = snapshot_at(slot)? {
+            names.push(String::from_utf8_lossy(snap.name()).into_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// The device number the inodes of the snapshot `name` are cached under.
+pub fn dev_of(name: &str) -> Result<u32, &'static str> {
+    find(name).map(|(slot, _)| snapshot_dev(slot))
+}
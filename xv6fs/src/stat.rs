@@ -8,6 +8,7 @@ pub struct Stat {
     pub itype: InodeType, // Type of file
     pub nlink: i16, // Number of links to link
     pub size: usize, // Size of file bytes 
+    pub phys_size: usize, // Bytes of the data blocks, less than size if compressed or sparse
 }
 
 impl Stat {
@@ -17,7 +18,8 @@ impl Stat {
             inum: 0,
             itype: InodeType::Empty,
             nlink: 0,
-            size: 0
+            size: 0,
+            phys_size: 0
         }
     }
 }
\ No newline at end of file
@@ -3,8 +3,30 @@ use core::ptr;
 use core::mem::{self, MaybeUninit};
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::fs_const::{ FSMAGIC, IPB, BPB };
+use crate::fs_const::{ FSMAGIC, IPB, BPB, NSNAPSHOT };
 use crate::buffer_cache::{ BLOCK_CACHE_MANAGER, BufData };
+use crate::checksum::{crc32c, CORRUPTED};
+use crate::log::LOG_MANAGER;
+
+/// The file system keeps checksums of its metadata, see `checksum`.
+pub const FEATURE_METADATA_CSUM: u32 = 0x1;
+/// The file system has snapshots, see `snapshot`.
+/// Implementations that don't know about them would overwrite their blocks.
+pub const FEATURE_SNAPSHOTS: u32 = 0x2;
+/// Disk inodes have `NDIRECT` (10) direct addresses and the block of
+/// extended attributes, see `xattr`. Images without it have the old layout,
+/// 11 direct addresses and no attributes, see `disk_inode::ndirect`.
+pub const FEATURE_XATTR: u32 = 0x4;
+/// Features this implementation knows about.
+pub const FEATURES_SUPPORTED: u32 = FEATURE_METADATA_CSUM | FEATURE_SNAPSHOTS | FEATURE_XATTR;
+
+/// Check that this implementation can mount a file system with `features`.
+pub fn check_features(features: u32) -> Result<(), &'static str> {
+    if features & !FEATURES_SUPPORTED != 0 {
+        return Err("unsupported file system features");
+    }
+    Ok(())
+}
 
 pub static mut SUPER_BLOCK: SuperBlock = SuperBlock::uninit();
 
@@ -27,10 +49,10 @@ impl SuperBlock {
 
     /// Read and init the super block from disk into memory.
     /// SAFETY: it should only be called by the first regular process alone.
-    pub unsafe fn init(&mut self, dev: u32) {
+    pub unsafe fn init(&mut self, dev: u32) -> Result<(), &'static str> {
         debug_assert_eq!(mem::align_of::<BufData>() % mem::align_of::<RawSuperBlock>(), 0);
         if self.initialized.load(Ordering::Relaxed) {
-            return
+            return Ok(())
         }
         let buf = BLOCK_CACHE_MANAGER.bread(dev, 1);
         //info!("super block init end bread");
@@ -39,16 +61,22 @@ impl SuperBlock {
             self.data.as_mut_ptr(),
             1,
         );
+        drop(buf);
         //info!("check magic number");
-        if self.data.as_ptr().as_ref().unwrap().magic != FSMAGIC {
-            panic!("invalid file system magic num");
+        let sb = self.data.as_ptr().as_ref().unwrap();
+        if sb.magic != FSMAGIC {
+            return Err("invalid file system magic num");
+        }
+        check_features(sb.features)?;
+        if sb.features & FEATURE_METADATA_CSUM != 0 && sb.checksum != sb.calc_checksum() {
+            return Err(CORRUPTED);
         }
         //info!("superblock init data {:?}",self.data.as_ptr().as_ref().unwrap());
         self.initialized.store(true, Ordering::SeqCst);
-        drop(buf);
 
         #[cfg(feature = "verbose_init_info")]
         info!("super block data: {:?}", self.data.as_ptr().as_ref().unwrap());
+        Ok(())
     }
 
     /// Read the info of super block.
@@ -89,6 +117,63 @@ impl SuperBlock {
         self.read().ninodes
     }
 
+    /// Feature bits, `FEATURE_*`
+    pub fn features(&self) -> u32 {
+        self.read().features
+    }
+
+    /// The first block of the checksum table
+    pub fn csumstart(&self) -> u32 {
+        self.read().csumstart
+    }
+
+    /// The snapshot block of the snapshot `slot`, 0 if the slot is free.
+    pub fn snapshot(&self, slot: usize) -> u32 {
+        self.read().snapshots[slot]
+    }
+
+    /// Set the snapshot block of the snapshot `slot`, 0 to free it,
+    /// and write the super block to the log.
+    pub fn set_snapshot(&mut self, dev: u32, slot: usize, blockno: u32) {
+        self.modify(dev, |sb| {
+            sb.snapshots[slot] = blockno;
+            if sb.snapshots.iter().any(|&b| b != 0) {
+                sb.features |= FEATURE_SNAPSHOTS;
+            } else {
+                sb.features &= !FEATURE_SNAPSHOTS;
+            }
+        });
+    }
+
+    /// Set the size of the file system and where its bitmap and checksum
+    /// table start, and write the super block to the log. See `resize`.
+    pub fn set_layout(&mut self, dev: u32, size: u32, bmapstart: u32, csumstart: u32) {
+        self.modify(dev, |sb| {
+            sb.nblocks = sb.nblocks + size - sb.size;
+            sb.size = size;
+            sb.bmapstart = bmapstart;
+            sb.csumstart = csumstart;
+        });
+    }
+
+    /// Change the in-memory copy with `f` and write it to the log.
+    fn modify(&mut self, dev: u32, f: impl FnOnce(&mut RawSuperBlock)) {
+        let sb = unsafe { self.data.assume_init_mut() };
+        f(sb);
+        sb.checksum = sb.calc_checksum();
+        let mut buf = BLOCK_CACHE_MANAGER.bread(dev, 1);
+        unsafe { ptr::copy_nonoverlapping(sb as *const RawSuperBlock, buf.raw_data_mut() as *mut RawSuperBlock, 1) };
+        LOG_MANAGER.write(buf);
+    }
+
+    /// Read the super block again, after the log is recovered,
+    /// which may have changed it.
+    /// SAFETY: same as `init`.
+    pub unsafe fn reload(&mut self, dev: u32) -> Result<(), &'static str> {
+        self.initialized.store(false, Ordering::SeqCst);
+        self.init(dev)
+    }
+
     /// Given an inode number. 
     /// Return the blockno of the block this inode resides. 
     /// Panic if the queryed inode out of range. 
@@ -125,11 +210,38 @@ pub struct RawSuperBlock {
     pub logstart: u32,   // Block number of first log block
     pub inodestart: u32, // Block number of first inode block
     pub bmapstart: u32,  // Block number of first free map block
+    pub features: u32,   // Feature bits, FEATURE_*
+    pub csumstart: u32,  // Block number of first checksum table block
+    pub snapshots: [u32; NSNAPSHOT], // Snapshot blocks, 0 for a free slot
+    pub checksum: u32,   // CRC32C of the fields above
 }
 
 impl RawSuperBlock {
     pub fn new()->Self{
         RawSuperBlock { magic: 0, size: 0, nblocks: 0, ninodes: 0, 
-            nlog: 0, logstart: 0, inodestart: 0, bmapstart: 0 }
+            nlog: 0, logstart: 0, inodestart: 0, bmapstart: 0,
+            features: 0, csumstart: 0, snapshots: [0; NSNAPSHOT], checksum: 0 }
+    }
+
+    /// CRC32C of all fields but `checksum`.
+    pub fn calc_checksum(&self) -> u32 {
+        let len = mem::size_of::<Self>() - mem::size_of::<u32>();
+        let bytes = unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, len) };
+        crc32c(0, bytes)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_features() {
+        assert!(check_features(FEATURE_XATTR).is_ok());
+        assert!(check_features(FEATURES_SUPPORTED).is_ok());
+        // the layout before extended attributes
+        assert!(check_features(0).is_ok());
+        assert!(check_features(FEATURE_METADATA_CSUM | FEATURE_SNAPSHOTS).is_ok());
+        // a feature from the future
+        assert!(check_features(FEATURE_XATTR | 0x8000).is_err());
+    }
+}
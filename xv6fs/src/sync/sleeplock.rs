@@ -1,33 +1,32 @@
 //! sleeplock
+//!
+//! The lock itself lives in the kernel and is reached through `FsInterface`
+//! by its index, so a task that cannot get the lock sleeps on the kernel's
+//! wait queue instead of spinning.
+//! It can be held exclusively by one task, or shared by several readers.
+//! The kernel lends the priority of the waiting tasks to the exclusive holder.
 
 use core::ops::{Deref, DerefMut, Drop};
 use core::cell::{UnsafeCell};
-//use spin::{Mutex};
 
 use crate::interface::INTERFACE_MANAGER;
 
 
 pub struct SleepLock<T: ?Sized> {
-    //lock: Mutex<()>,
-    index:usize,  //use for index the wait queue in the kernel, 
-                //we use the wait queue in the kernel to guarantee the sleep lock
+    index:usize,  //use for index the lock in the kernel
     data: UnsafeCell<T>,
-    
 }
 
-unsafe impl<T: ?Sized + Sync> Sync for SleepLock<T> {}
-// not needed
-// unsafe impl<T: ?Sized + Sync> Send for SleepLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for SleepLock<T> {}
+unsafe impl<T: ?Sized + Send> Send for SleepLock<T> {}
 
 impl<T> SleepLock<T> {
     pub const fn new(data: T, index:usize) -> Self {
         Self {
-            //lock: Mutex::new(()),
             data: UnsafeCell::new(data),
             index,
         }
     }
-    
 }
 
 pub fn init_lock()->usize{
@@ -35,28 +34,33 @@ pub fn init_lock()->usize{
 }
 
 impl<T: ?Sized> SleepLock<T> {
-    /// non-blocking, but might sleep if other p lock this sleeplock
+    /// Lock exclusively, might sleep if another task holds this sleeplock
     pub fn lock(&self) -> SleepLockGuard<T> {
-        //let mut guard = self.lock.lock();
-        //info!("xv6 sleep lock: lock!");
-        //info!("1flag is {}",INTERFACE_MANAGER.interface.get_flag(self.index));
-        INTERFACE_MANAGER.interface.sleep_cur_proc(self.index);
-        //info!("2flag is {}",INTERFACE_MANAGER.interface.get_flag(self.index));
-        //drop(guard);
+        INTERFACE_MANAGER.interface.sleep_lock(self.index);
         SleepLockGuard {
             lock: &self,
             data: unsafe { &mut *self.data.get() }
         }
     }
 
+    /// Lock shared with other readers,
+    /// might sleep if another task holds this sleeplock exclusively
+    pub fn lock_shared(&self) -> SleepLockSharedGuard<T> {
+        INTERFACE_MANAGER.interface.sleep_lock_shared(self.index);
+        SleepLockSharedGuard {
+            lock: &self,
+            data: unsafe { &*self.data.get() }
+        }
+    }
+
     /// Called by its guard when dropped
     pub fn unlock(&self) {
-        //info!("unlock!");
-        self.wake_up();//我感觉这里还是得搞个队列吧
+        INTERFACE_MANAGER.interface.sleep_unlock(self.index);
     }
 
-    fn wake_up(&self) {
-        INTERFACE_MANAGER.interface.wake_up_next_proc(self.index);
+    /// Called by its shared guard when dropped
+    pub fn unlock_shared(&self) {
+        INTERFACE_MANAGER.interface.sleep_unlock_shared(self.index);
     }
 }
 
@@ -80,9 +84,28 @@ impl<'a, T: ?Sized> DerefMut for SleepLockGuard<'a, T> {
 }
 
 impl<'a, T: ?Sized> Drop for SleepLockGuard<'a, T> {
-    /// The dropping of the SpinLockGuard will call spinlock's release_lock(),
-    /// through its reference to its original spinlock.
+    /// The dropping of the SleepLockGuard will call sleeplock's unlock(),
+    /// through its reference to its original sleeplock.
     fn drop(&mut self) {
         self.lock.unlock();
     }
 }
+
+
+pub struct SleepLockSharedGuard<'a, T: ?Sized + 'a> {
+    lock: &'a SleepLock<T>,
+    data: &'a T,
+}
+
+impl<'a, T: ?Sized> Deref for SleepLockSharedGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SleepLockSharedGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock_shared();
+    }
+}
@@ -0,0 +1,185 @@
+//! Extended attributes.
+//!
+//! The extended attributes of an inode live in a single block, `xattr` in
+//! the disk inode, 0 if the inode has none. The block starts with
+//! `XATTR_MAGIC`, then come the entries, ended by a zero byte. An entry is
+//! the length of the name as a byte, the length of the value as a `u16`,
+//! the name and the value.
+//!
+//! The block is metadata like a directory block: it is changed through the
+//! log, has a checksum, and is copied before a change if a snapshot shares
+//! it. It is freed with the inode.
+//!
+//! Only images with `FEATURE_XATTR` have room for the block. Inodes of older
+//! images have no attributes, and can't be given any.
+
+use alloc::vec::Vec;
+use core::{ptr, slice};
+
+use crate::bitmap::{balloc, bfree};
+use crate::buffer_cache::BLOCK_CACHE_MANAGER;
+use crate::checksum::{self, CORRUPTED};
+use crate::fs_const::BSIZE;
+use crate::inode::InodeData;
+use crate::log::LOG_MANAGER;
+use crate::snapshot;
+use crate::superblock::{FEATURE_XATTR, SUPER_BLOCK};
+
+/// Error of an attribute that the inode doesn't have.
+pub const NO_ATTR: &str = "No such attribute";
+/// Error of attributes that don't fit in their block.
+pub const NO_SPACE: &str = "No space for attributes";
+/// Error of an empty name, or one that is too long or holds a zero byte.
+pub const BAD_NAME: &str = "Invalid attribute name";
+/// Error of setting attributes on an image without `FEATURE_XATTR`.
+pub const NOT_SUPPORTED: &str = "Attributes not supported by the disk inode layout";
+
+/// Longest name of an attribute.
+pub const XATTR_NAME_MAX: usize = 255;
+
+const XATTR_MAGIC: u32 = 0x7861_7474;
+const HEADER: usize = 4;
+const ENTRY_HEADER: usize = 3;
+
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Whether the disk inodes have the block of extended attributes.
+pub fn enabled() -> bool {
+    unsafe { SUPER_BLOCK.features() & FEATURE_XATTR != 0 }
+}
+
+fn check_name(name: &[u8]) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX || name.contains(&0) {
+        return Err(BAD_NAME);
+    }
+    Ok(())
+}
+
+fn parse(data: &[u8]) -> Result<Entries, &'static str> {
+    if u32::from_ne_bytes([data[0], data[1], data[2], data[3]]) != XATTR_MAGIC {
+        return Err(CORRUPTED);
+    }
+    let mut entries = Vec::new();
+    let mut pos = HEADER;
+    while pos < BSIZE && data[pos] != 0 {
+        if pos + ENTRY_HEADER > BSIZE {
+            return Err(CORRUPTED);
+        }
+        let name_len = data[pos] as usize;
+        let value_len = u16::from_ne_bytes([data[pos + 1], data[pos + 2]]) as usize;
+        let name = pos + ENTRY_HEADER;
+        let end = name + name_len + value_len;
+        if end > BSIZE {
+            return Err(CORRUPTED);
+        }
+        entries.push((data[name..name + name_len].to_vec(), data[name + name_len..end].to_vec()));
+        pos = end;
+    }
+    Ok(entries)
+}
+
+fn encode(entries: &Entries, data: &mut [u8; BSIZE]) -> Result<(), &'static str> {
+    data[..HEADER].copy_from_slice(&XATTR_MAGIC.to_ne_bytes());
+    let mut pos = HEADER;
+    for (name, value) in entries {
+        let end = pos + ENTRY_HEADER + name.len() + value.len();
+        // leave room for the end marker
+        if end >= BSIZE {
+            return Err(NO_SPACE);
+        }
+        data[pos] = name.len() as u8;
+        data[pos + 1..pos + ENTRY_HEADER].copy_from_slice(&(value.len() as u16).to_ne_bytes());
+        data[pos + ENTRY_HEADER..pos + ENTRY_HEADER + name.len()].copy_from_slice(name);
+        data[pos + ENTRY_HEADER + name.len()..end].copy_from_slice(value);
+        pos = end;
+    }
+    Ok(())
+}
+
+impl InodeData {
+    fn read_xattrs(&self) -> Result<Entries, &'static str> {
+        if self.dinode.xattr() == 0 {
+            return Ok(Vec::new());
+        }
+        let buf = BLOCK_CACHE_MANAGER.bread(self.dev, self.dinode.xattr());
+        checksum::verify(self.dev, &buf)?;
+        let data = unsafe { slice::from_raw_parts(buf.raw_data() as *const u8, BSIZE) };
+        parse(data)
+    }
+
+    fn write_xattrs(&mut self, entries: &Entries) -> Result<(), &'static str> {
+        if snapshot::is_snapshot_dev(self.dev) {
+            return Err(snapshot::READ_ONLY);
+        }
+        if !enabled() {
+            return Err(NOT_SUPPORTED);
+        }
+        if entries.is_empty() {
+            if self.dinode.xattr() != 0 {
+                bfree(self.dinode.xattr())?;
+                self.dinode.set_xattr(0);
+                self.update();
+            }
+            return Ok(());
+        }
+        let mut data = [0u8; BSIZE];
+        encode(entries, &mut data)?;
+        let blockno = match self.dinode.xattr() {
+            0 => balloc(self.dev),
+            blockno => Self::cow_block(self.dev, blockno),
+        };
+        let mut buf = BLOCK_CACHE_MANAGER.bread(self.dev, blockno);
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buf.raw_data_mut() as *mut u8, BSIZE) };
+        checksum::update(self.dev, &buf);
+        LOG_MANAGER.write(buf);
+        if blockno != self.dinode.xattr() {
+            self.dinode.set_xattr(blockno);
+            self.update();
+        }
+        Ok(())
+    }
+
+    /// The value of the attribute `name`.
+    /// Caller must hold inode's sleeplock, shared or not.
+    pub fn get_xattr(&self, name: &[u8]) -> Result<Vec<u8>, &'static str> {
+        self.read_xattrs()?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+            .ok_or(NO_ATTR)
+    }
+
+    /// Set the attribute `name` to `value`, adding it if the inode doesn't
+    /// have it. Caller must hold inode's sleeplock.
+    pub fn set_xattr(&mut self, name: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        check_name(name)?;
+        let mut entries = self.read_xattrs()?;
+        match entries.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_vec(),
+            None => entries.push((name.to_vec(), value.to_vec())),
+        }
+        self.write_xattrs(&entries)
+    }
+
+    /// The names of all attributes.
+    /// Caller must hold inode's sleeplock, shared or not.
+    pub fn list_xattrs(&self) -> Result<Vec<Vec<u8>>, &'static str> {
+        Ok(self.read_xattrs()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Remove the attribute `name`. Caller must hold inode's sleeplock.
+    pub fn remove_xattr(&mut self, name: &[u8]) -> Result<(), &'static str> {
+        let mut entries = self.read_xattrs()?;
+        let i = entries.iter().position(|(n, _)| n == name).ok_or(NO_ATTR)?;
+        entries.remove(i);
+        self.write_xattrs(&entries)
+    }
+
+    /// Free the attribute block, when the inode is freed.
+    pub(crate) fn free_xattrs(&mut self) {
+        if self.dinode.xattr() != 0 {
+            let _ = bfree(self.dinode.xattr());
+            self.dinode.set_xattr(0);
+        }
+    }
+}
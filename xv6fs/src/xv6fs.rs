@@ -1,12 +1,18 @@
 use core::ptr::copy_nonoverlapping;
+use core::sync::atomic::AtomicU32;
 use alloc::sync::Arc;
 
 use crate::BlockDevice;
 use crate::disk_inode::{DirEntry,DiskInode, InodeType};
 use crate::file::{VFile,FileType};
 use crate::inode::{ICACHE,Inode};
-use crate::superblock::RawSuperBlock;
-use crate::fs_const::{FSMAGIC,BSIZE,IPB,FSSIZE,NDINODES, LOGSIZE};
+use crate::log::LOG_MANAGER;
+use crate::superblock::{RawSuperBlock, FEATURE_XATTR};
+use crate::fs_const::{FSMAGIC,BSIZE,IPB,FSSIZE,NDINODES, LOGSIZE, ROOTINUM};
+use crate::checksum::CORRUPTED;
+use crate::snapshot;
+use crate::resize;
+use crate::discard;
 
 
 static mut FREEBLOCK:usize=0;
@@ -50,6 +56,7 @@ impl Xv6FS {
         raw_superblock.logstart=2;
         raw_superblock.inodestart=2+self.nlog as u32;
         raw_superblock.bmapstart=(2+self.nlog+self.ninodeblocks) as u32;
+        raw_superblock.features=FEATURE_XATTR;
         let mut buf=[0 as u8;BSIZE];
         for i in 0..FSSIZE{
             block_device.write_block(i, &buf);
@@ -82,6 +89,11 @@ impl Xv6FS {
 
     }
 
+    /// Commit all pending operations in the log and flush the disk.
+    pub fn sync(&self){
+        LOG_MANAGER.flush();
+    }
+
     pub fn get_root_inode(&mut self)->Inode{
         ICACHE.get_root_dir()
     }
@@ -96,10 +108,36 @@ impl Xv6FS {
             readable:true, 
             writeable:true, 
             inode:Some(inode), 
-            offset:0,
+            offset:AtomicU32::new(0),
+        }
+    }
+
+    /// The root directory of the snapshot `name`, see `snapshot`.
+    /// Files in a snapshot can't be written.
+    pub fn get_snapshot_vfile(&self, name: &str) -> Result<VFile, &'static str> {
+        let inode = ICACHE.get(snapshot::dev_of(name)?, ROOTINUM);
+        if inode.try_lock_shared()?.dinode.itype != InodeType::Directory {
+            return Err(CORRUPTED);
         }
+        Ok(VFile {
+            ftype: FileType::Directory,
+            readable: true,
+            writeable: false,
+            inode: Some(inode),
+            offset: AtomicU32::new(0),
+        })
+    }
+
+    /// Grow or shrink the file system to `new_blocks` blocks, see `resize`.
+    /// The block device must have that many blocks.
+    pub fn resize(&self, new_blocks: u32) -> Result<(), &'static str> {
+        resize::resize(new_blocks)
     }
-    
 
+    /// Discard the free blocks on the device that lie in runs of at least
+    /// `min_blocks` blocks, like fstrim. Returns how many were discarded.
+    pub fn trim(&self, min_blocks: u32) -> Result<u32, &'static str> {
+        discard::trim(min_blocks)
+    }
 }
 
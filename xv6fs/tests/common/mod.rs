@@ -0,0 +1,160 @@
+//! A file system in memory for the tests. xv6fs keeps its state in globals,
+//! so the tests of one file share a single file system.
+
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Once, RwLock};
+
+use xv6fs::file::VFile;
+use xv6fs::fs_const::{BSIZE, ROOTDEV};
+use xv6fs::inode::Inode;
+use xv6fs::interface::{FsInterface, InterfaceManager, INTERFACE_MANAGER};
+use xv6fs::BlockDevice;
+
+/// A disk image in memory.
+pub struct MemDisk(Mutex<Vec<u8>>);
+
+impl MemDisk {
+    pub fn new(image: Vec<u8>) -> Self {
+        Self(Mutex::new(image))
+    }
+}
+
+impl BlockDevice for MemDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let image = self.0.lock().unwrap();
+        buf[..BSIZE].copy_from_slice(&image[block_id * BSIZE..(block_id + 1) * BSIZE]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut image = self.0.lock().unwrap();
+        image[block_id * BSIZE..(block_id + 1) * BSIZE].copy_from_slice(&buf[..BSIZE]);
+    }
+}
+
+/// A sleep lock of threads, shared or exclusive.
+#[derive(Default)]
+struct SleepLock {
+    /// Number of readers, or `usize::MAX` while a writer holds it.
+    holders: Mutex<usize>,
+    released: Condvar,
+}
+
+impl SleepLock {
+    fn acquire(&self, shared: bool) {
+        let mut holders = self.holders.lock().unwrap();
+        while *holders == usize::MAX || (!shared && *holders > 0) {
+            holders = self.released.wait(holders).unwrap();
+        }
+        *holders = if shared { *holders + 1 } else { usize::MAX };
+    }
+
+    fn release(&self, shared: bool) {
+        let mut holders = self.holders.lock().unwrap();
+        *holders = if shared { *holders - 1 } else { 0 };
+        self.released.notify_all();
+    }
+}
+
+/// The interface for threads of the test harness, each one is a task.
+#[derive(Default)]
+struct TestInterface {
+    locks: RwLock<Vec<Arc<SleepLock>>>,
+}
+
+impl TestInterface {
+    fn get(&self, index: usize) -> Arc<SleepLock> {
+        self.locks.read().unwrap()[index].clone()
+    }
+}
+
+impl FsInterface for TestInterface {
+    fn get_cur_dir_inode(&self) -> Option<Inode> {
+        None
+    }
+    fn new_sleep_lock(&self) -> usize {
+        let mut locks = self.locks.write().unwrap();
+        locks.push(Arc::default());
+        locks.len() - 1
+    }
+    fn sleep_lock(&self, index: usize) {
+        self.get(index).acquire(false)
+    }
+    fn sleep_lock_shared(&self, index: usize) {
+        self.get(index).acquire(true)
+    }
+    fn sleep_unlock(&self, index: usize) {
+        self.get(index).release(false)
+    }
+    fn sleep_unlock_shared(&self, index: usize) {
+        self.get(index).release(true)
+    }
+    fn dev_read(&self, _major: i16, _minor: i16, _offset: usize, _buf: &mut [u8]) -> Result<usize, &'static str> {
+        Err("no devices in tests")
+    }
+    fn dev_write(&self, _major: i16, _minor: i16, _offset: usize, _buf: &[u8]) -> Result<usize, &'static str> {
+        Err("no devices in tests")
+    }
+    fn dev_is_block(&self, _major: i16, _minor: i16) -> bool {
+        false
+    }
+    fn cur_task_id(&self) -> usize {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        thread_local! {
+            static ID: usize = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        }
+        ID.with(|id| *id)
+    }
+    fn inode_freed(&self, _dev: u32, _inum: u32) {}
+}
+
+/// Mount a file system built by xv6mkfs with `options`,
+/// the first call of a test file mounts it and later calls do nothing.
+pub fn mount(options: xv6mkfs::Options) {
+    mount_with_room(options, 0);
+}
+
+/// Like `mount`, with a disk that has `extra` blocks past the file system.
+pub fn mount_with_room(options: xv6mkfs::Options, extra: usize) {
+    mount_image(|| {
+        let mut image = xv6mkfs::mkfs(&options);
+        image.resize(image.len() + extra * BSIZE, 0);
+        image
+    });
+}
+
+/// Like `mount`, with the disk image that `image` returns.
+pub fn mount_image(image: impl FnOnce() -> Vec<u8>) {
+    static MOUNT: Once = Once::new();
+    MOUNT.call_once(|| {
+        INTERFACE_MANAGER.init_by(InterfaceManager { interface: Arc::new(TestInterface::default()) });
+        let disk = MemDisk::new(image());
+        unsafe { xv6fs::init(Arc::new(disk), ROOTDEV) }.expect("failed to mount the test image");
+    });
+}
+
+/// Run the tests of a file one at a time, they share the file system.
+pub fn serial() -> MutexGuard<'static, ()> {
+    static SERIAL: Mutex<()> = Mutex::new(());
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Turn `path` into the NUL-terminated form xv6fs expects.
+pub fn fs_path(path: &str) -> String {
+    format!("{}\0", path)
+}
+
+/// Create the file `path` holding `data`.
+pub fn write_file(path: &str, data: &[u8]) -> VFile {
+    let file = VFile::vfile_create_file(&fs_path(path), true, true).unwrap();
+    assert_eq!(file.vfile_write(0, data.as_ptr() as usize, data.len()), Ok(data.len()));
+    file
+}
+
+/// The contents of the file `path`.
+pub fn read_file(path: &str) -> Vec<u8> {
+    let file = VFile::vfile_lookup(&fs_path(path)).unwrap();
+    let mut data = vec![0; file.vfile_size()];
+    assert_eq!(file.vfile_read(data.as_mut_ptr() as usize, 0, data.len()), Ok(data.len()));
+    data
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use xv6fs::file::VFile;
+use xv6fs::fs_const::BSIZE;
+
+use common::{fs_path, mount, serial, write_file};
+
+const NR_ENTRIES: usize = 120;
+
+#[test]
+fn test_bucket_split() {
+    let _serial = serial();
+    mount(Default::default());
+    let dir = VFile::vfile_create_dir(&fs_path("/hashed"), true, true).unwrap();
+    dir.vfile_set_dir_indexed().unwrap();
+    for i in 0..NR_ENTRIES {
+        write_file(&format!("/hashed/e{}", i), format!("entry {}", i).as_bytes());
+    }
+    // two index blocks and more than one bucket
+    let buckets = dir.vfile_size() / BSIZE - 2;
+    assert!(buckets > 1, "no bucket was split");
+
+    for i in 0..NR_ENTRIES {
+        let file = VFile::vfile_lookup(&fs_path(&format!("/hashed/e{}", i))).expect("entry lost by a split");
+        let mut data = vec![0; file.vfile_size()];
+        file.vfile_read(data.as_mut_ptr() as usize, 0, data.len()).unwrap();
+        assert_eq!(data, format!("entry {}", i).as_bytes());
+    }
+    // a linear scan sees every entry once and skips the index
+    let (entries, _) = dir.vfile_read_dir(0, usize::MAX, false).unwrap();
+    let mut names: Vec<_> = entries.iter().map(|ent| ent.name().to_string()).collect();
+    names.sort();
+    let mut expect: Vec<_> = (0..NR_ENTRIES).map(|i| format!("e{}", i)).collect();
+    expect.extend([".".to_string(), "..".to_string()]);
+    expect.sort();
+    assert_eq!(names, expect);
+
+    for i in (0..NR_ENTRIES).step_by(2) {
+        dir.vfile_remove(&fs_path(&format!("/hashed/e{}", i)));
+    }
+    for i in 0..NR_ENTRIES {
+        let found = VFile::vfile_lookup(&fs_path(&format!("/hashed/e{}", i))).is_some();
+        assert_eq!(found, i % 2 == 1, "e{}", i);
+    }
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use xv6fs::log::{WriteBackMode, LOG_MANAGER};
+
+use common::{mount, read_file, serial, write_file};
+
+#[test]
+fn test_delayed_concurrent_writes() {
+    let _serial = serial();
+    mount(Default::default());
+    LOG_MANAGER.set_mode(WriteBackMode::Delayed);
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            thread::spawn(move || {
+                for i in 0..8 {
+                    let data = vec![(t * 16 + i) as u8; 700 * (i + 1)];
+                    write_file(&format!("/delayed-{}-{}", t, i), &data);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    LOG_MANAGER.flush();
+    assert_eq!(LOG_MANAGER.pending(), 0);
+    for t in 0..4 {
+        for i in 0..8 {
+            let data = read_file(&format!("/delayed-{}-{}", t, i));
+            assert_eq!(data, vec![(t * 16 + i) as u8; 700 * (i + 1)]);
+        }
+    }
+    LOG_MANAGER.set_mode(WriteBackMode::Sync);
+}
+
+#[test]
+fn test_flush_waits_for_ops() {
+    let _serial = serial();
+    mount(Default::default());
+    LOG_MANAGER.set_mode(WriteBackMode::Delayed);
+    write_file("/flush-wait", b"pending");
+    let op = LOG_MANAGER.begin_op();
+    let flushed = Arc::new(AtomicBool::new(false));
+    let flusher = {
+        let flushed = flushed.clone();
+        thread::spawn(move || {
+            LOG_MANAGER.flush();
+            flushed.store(true, Ordering::Release);
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert!(!flushed.load(Ordering::Acquire), "flush committed an op in flight");
+    assert!(LOG_MANAGER.pending() > 0);
+    drop(op);
+    flusher.join().unwrap();
+    assert!(flushed.load(Ordering::Acquire));
+    assert_eq!(LOG_MANAGER.pending(), 0);
+    LOG_MANAGER.set_mode(WriteBackMode::Sync);
+}
+
+#[test]
+fn test_nested_ops() {
+    let _serial = serial();
+    mount(Default::default());
+    let outer = LOG_MANAGER.begin_op();
+    // the inner ops of the same task share the reservation of the outer one
+    write_file("/nested", b"inner op");
+    drop(outer);
+    assert_eq!(LOG_MANAGER.pending(), 0);
+    assert_eq!(read_file("/nested"), b"inner op");
+}
+
+#[test]
+fn test_freeze_stops_other_ops() {
+    let _serial = serial();
+    mount(Default::default());
+    LOG_MANAGER.set_mode(WriteBackMode::Delayed);
+    write_file("/freeze-before", b"committed by freeze");
+    let frozen = LOG_MANAGER.freeze();
+    assert_eq!(LOG_MANAGER.pending(), 0);
+    let written = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let written = written.clone();
+        thread::spawn(move || {
+            write_file("/freeze-other", b"after thaw");
+            written.store(true, Ordering::Release);
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert!(!written.load(Ordering::Acquire), "an op ran while the fs was frozen");
+    // the freezer's own ops run, and each one commits
+    write_file("/freeze-own", b"while frozen");
+    assert_eq!(LOG_MANAGER.pending(), 0);
+    drop(frozen);
+    writer.join().unwrap();
+    assert!(written.load(Ordering::Acquire));
+    assert_eq!(read_file("/freeze-other"), b"after thaw");
+    assert_eq!(read_file("/freeze-own"), b"while frozen");
+    LOG_MANAGER.set_mode(WriteBackMode::Sync);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::ptr;
+
+use xv6fs::disk_inode::ndirect;
+use xv6fs::fs_const::{BSIZE, NDIRECT, NINDIRECT};
+use xv6fs::superblock::{RawSuperBlock, FEATURE_XATTR};
+use xv6fs::xattr::{NOT_SUPPORTED, NO_ATTR};
+
+use common::{mount_image, read_file, serial, write_file};
+
+/// Mount an image of the disk inode layout before extended attributes,
+/// 11 direct addresses and no attribute block.
+fn mount_old() {
+    mount_image(|| {
+        let mut image = xv6mkfs::mkfs(&Default::default());
+        let block = image[BSIZE..2 * BSIZE].as_mut_ptr() as *mut RawSuperBlock;
+        unsafe {
+            let mut sb = ptr::read_unaligned(block);
+            sb.features &= !FEATURE_XATTR;
+            ptr::write_unaligned(block, sb);
+        }
+        image
+    });
+}
+
+#[test]
+fn test_old_layout_round_trip() {
+    let _serial = serial();
+    mount_old();
+    assert_eq!(ndirect(), NDIRECT + 1);
+    // every direct address, the indirect block and the double indirect one
+    let nblocks = NDIRECT + 1 + NINDIRECT + 2;
+    let data: Vec<u8> = (0..nblocks * BSIZE).map(|i| (i / BSIZE) as u8).collect();
+    let file = write_file("/old-full", &data);
+    assert_eq!(read_file("/old-full"), data);
+    assert_eq!(file.vfile_stat().unwrap().phys_size, nblocks * BSIZE);
+
+    file.vfile_truncate((NDIRECT + 1) as u64 * BSIZE as u64);
+    assert_eq!(read_file("/old-full"), &data[..(NDIRECT + 1) * BSIZE]);
+    assert_eq!(file.vfile_stat().unwrap().phys_size, (NDIRECT + 1) * BSIZE);
+}
+
+#[test]
+fn test_old_layout_has_no_xattrs() {
+    let _serial = serial();
+    mount_old();
+    let file = write_file("/old-xattr", &vec![1; (NDIRECT + 1 + NINDIRECT + 2) * BSIZE]);
+    assert_eq!(file.vfile_getxattr("user.a"), Err(NO_ATTR));
+    assert_eq!(file.vfile_listxattr(), Ok(vec![]));
+    assert_eq!(file.vfile_setxattr("user.a", b"value"), Err(NOT_SUPPORTED));
+    assert_eq!(file.vfile_removexattr("user.a"), Err(NO_ATTR));
+}
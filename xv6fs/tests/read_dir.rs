@@ -0,0 +1,53 @@
+mod common;
+
+use xv6fs::disk_inode::InodeType;
+use xv6fs::file::VFile;
+use xv6fs::fs_const::{NINODE, ROOTDEV};
+use xv6fs::inode::ICACHE;
+
+use common::{fs_path, mount, serial, write_file};
+
+#[test]
+fn test_read_dir_types_without_inode_cache() {
+    let _serial = serial();
+    mount(Default::default());
+    VFile::vfile_create_dir(&fs_path("/list"), true, true).unwrap();
+    for i in 0..12 {
+        write_file(&format!("/list/f{}", i), b"entry");
+    }
+    VFile::vfile_create_dir(&fs_path("/list/sub"), true, true).unwrap();
+    VFile::vfile_mknod(&fs_path("/list/tty"), 1, 0).unwrap();
+    let dir = VFile::vfile_lookup(&fs_path("/list")).unwrap();
+
+    // fill the inode cache but for the slot of the directory
+    let held: Vec<_> = (0..NINODE as u32 - 1).map(|i| ICACHE.get(ROOTDEV, 199 - i)).collect();
+    let (entries, end) = dir.vfile_read_dir(0, usize::MAX, true).unwrap();
+    drop(held);
+
+    let names: Vec<_> = entries.iter().map(|ent| ent.name()).collect();
+    assert_eq!(names.len(), 2 + 12 + 2);
+    assert_eq!(&names[..2], [".", ".."]);
+    for ent in &entries {
+        let itype = match ent.name() {
+            "." | ".." | "sub" => InodeType::Directory,
+            "tty" => InodeType::Device,
+            _ => InodeType::File,
+        };
+        assert_eq!(ent.itype, Some(itype), "type of {}", ent.name());
+        assert_eq!(ent.dev.map(|dev| (dev.major, dev.minor)), (itype == InodeType::Device).then_some((1, 0)));
+    }
+
+    // resuming from the returned offsets lists the same entries
+    let (mut resumed, mut offset) = (Vec::new(), 0);
+    loop {
+        let (part, next) = dir.vfile_read_dir(offset, 3, false).unwrap();
+        if part.is_empty() {
+            break;
+        }
+        assert!(part.iter().all(|ent| ent.itype.is_none()));
+        resumed.extend(part.iter().map(|ent| ent.name().to_string()));
+        offset = next;
+    }
+    assert_eq!(resumed, names);
+    assert_eq!(offset, end);
+}
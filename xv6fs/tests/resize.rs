@@ -0,0 +1,100 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use xv6fs::fs_const::{BSIZE, FSSIZE};
+use xv6fs::resize::{NO_SPACE, TOO_SMALL};
+use xv6fs::superblock::SUPER_BLOCK;
+use xv6fs::xv6fs::Xv6FS;
+
+use common::{fs_path, mount_with_room, read_file, serial, write_file};
+
+const ROOM: usize = 2000;
+
+fn mount() {
+    mount_with_room(xv6mkfs::Options { metadata_csum: true }, ROOM);
+}
+
+fn size() -> u32 {
+    unsafe { SUPER_BLOCK.size() }
+}
+
+fn pattern(seed: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + seed) as u8).collect()
+}
+
+#[test]
+fn test_grow_and_shrink() {
+    let _serial = serial();
+    mount();
+    let fs = Xv6FS::new();
+    let small = pattern(1, 300);
+    let large = pattern(2, 90 * BSIZE);
+    write_file("/rs-small", &small);
+    write_file("/rs-large", &large);
+
+    // the checksum table needs more blocks, bitmap and table move
+    fs.resize((FSSIZE + ROOM) as u32).unwrap();
+    assert_eq!(size() as usize, FSSIZE + ROOM);
+    // more than fits in the old size
+    let big = pattern(3, 1500 * BSIZE);
+    let file = write_file("/rs-big", &big);
+    assert_eq!(read_file("/rs-big"), big);
+    assert_eq!(fs.resize(FSSIZE as u32), Err(NO_SPACE));
+    assert_eq!(size() as usize, FSSIZE + ROOM);
+    file.vfile_remove(&fs_path("/rs-big"));
+    drop(file);
+
+    // the files move below the new end
+    fs.resize(FSSIZE as u32 / 2).unwrap();
+    assert_eq!(size() as usize, FSSIZE / 2);
+    assert_eq!(read_file("/rs-small"), small);
+    assert_eq!(read_file("/rs-large"), large);
+    assert_eq!(fs.resize(10), Err(TOO_SMALL));
+
+    // back to the old size in place, the bits of the new blocks are cleared
+    fs.resize(FSSIZE as u32).unwrap();
+    let refill = pattern(4, 600 * BSIZE);
+    let file = write_file("/rs-refill", &refill);
+    assert_eq!(read_file("/rs-refill"), refill);
+    assert_eq!(read_file("/rs-large"), large);
+    file.vfile_remove(&fs_path("/rs-refill"));
+}
+
+#[test]
+fn test_resize_during_writes() {
+    let _serial = serial();
+    mount();
+    let fs = Xv6FS::new();
+    let stop = Arc::new(AtomicBool::new(false));
+    let writers: Vec<_> = (0..3)
+        .map(|t| {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut round = 0;
+                while !stop.load(Ordering::Acquire) {
+                    // new blocks each round
+                    round += 1;
+                    let file = write_file(&format!("/rs-w{}-{}", t, round), &pattern(t + round, 20 * BSIZE));
+                    if round > 1 {
+                        file.vfile_remove(&fs_path(&format!("/rs-w{}-{}", t, round - 1)));
+                    }
+                }
+                round
+            })
+        })
+        .collect();
+    for _ in 0..3 {
+        fs.resize(FSSIZE as u32 * 2 / 3).unwrap();
+        fs.resize((FSSIZE + ROOM) as u32).unwrap();
+    }
+    fs.resize(FSSIZE as u32 * 2 / 3).unwrap();
+    stop.store(true, Ordering::Release);
+    for (t, writer) in writers.into_iter().enumerate() {
+        let round = writer.join().unwrap();
+        assert_eq!(read_file(&format!("/rs-w{}-{}", t, round)), pattern(t + round, 20 * BSIZE));
+    }
+    fs.resize(FSSIZE as u32).unwrap();
+}
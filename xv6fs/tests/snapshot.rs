@@ -0,0 +1,72 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use xv6fs::file::VFile;
+use xv6fs::snapshot;
+use xv6fs::xv6fs::Xv6FS;
+
+use common::{fs_path, mount, read_file, serial, write_file};
+
+const NR_WRITERS: usize = 3;
+const LEN: usize = 1500;
+
+fn read_from(root: &VFile, path: &str) -> Vec<u8> {
+    let file = root.vfile_try_lookup_from(&fs_path(path)).unwrap();
+    let mut data = vec![0; file.vfile_size()];
+    assert_eq!(file.vfile_read(data.as_mut_ptr() as usize, 0, data.len()), Ok(data.len()));
+    data
+}
+
+#[test]
+fn test_snapshot_during_writes() {
+    let _serial = serial();
+    mount(Default::default());
+    for t in 0..NR_WRITERS {
+        write_file(&format!("/snap-w{}", t), &[0; LEN]);
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let writers: Vec<_> = (0..NR_WRITERS)
+        .map(|t| {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut round = 1u8;
+                while !stop.load(Ordering::Acquire) {
+                    let file = VFile::vfile_lookup(&fs_path(&format!("/snap-w{}", t))).unwrap();
+                    let data = vec![round; LEN];
+                    assert_eq!(file.vfile_write(0, data.as_ptr() as usize, LEN), Ok(LEN));
+                    round = round.wrapping_add(1);
+                }
+            })
+        })
+        .collect();
+    snapshot::create("during").unwrap();
+    assert_eq!(snapshot::create("during"), Err(snapshot::EXISTS));
+
+    let root = Xv6FS::new().get_snapshot_vfile("during").unwrap();
+    let taken: Vec<_> = (0..NR_WRITERS).map(|t| read_from(&root, &format!("/snap-w{}", t))).collect();
+    for data in &taken {
+        // each write of a file is a single op, the snapshot has it whole or not
+        assert_eq!(data.len(), LEN);
+        assert!(data.iter().all(|&b| b == data[0]), "snapshot caught a write half done");
+    }
+    // the live tree keeps changing, the snapshot doesn't
+    thread::sleep(std::time::Duration::from_millis(50));
+    stop.store(true, Ordering::Release);
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    for (t, data) in taken.iter().enumerate() {
+        assert_eq!(&read_from(&root, &format!("/snap-w{}", t)), data);
+    }
+
+    assert_eq!(snapshot::delete("during"), Err(snapshot::BUSY));
+    drop(root);
+    snapshot::delete("during").unwrap();
+    assert_eq!(snapshot::list(), Ok(Vec::new()));
+    // the blocks only the snapshot kept are reused
+    write_file("/snap-after", &[7; 4 * LEN]);
+    assert_eq!(read_file("/snap-after"), vec![7; 4 * LEN]);
+}
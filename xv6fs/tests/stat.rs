@@ -0,0 +1,32 @@
+mod common;
+
+use xv6fs::fs_const::{BSIZE, NDIRECT, NINDIRECT};
+
+use common::{mount, serial, write_file};
+
+#[test]
+fn test_phys_size_follows_blocks() {
+    let _serial = serial();
+    mount(Default::default());
+    let file = write_file("/stat-blocks", &[1; 3 * BSIZE]);
+    let phys_size = || file.vfile_stat().unwrap().phys_size;
+    assert_eq!(phys_size(), 3 * BSIZE);
+    // counted once, the same until blocks change
+    assert_eq!(phys_size(), 3 * BSIZE);
+
+    // into the double indirect block
+    let nblocks = NDIRECT + NINDIRECT + 2;
+    let data = vec![2; (nblocks - 3) * BSIZE];
+    assert_eq!(file.vfile_write((3 * BSIZE) as u32, data.as_ptr() as usize, data.len()), Ok(data.len()));
+    assert_eq!(phys_size(), nblocks * BSIZE);
+
+    // rewriting allocates nothing
+    assert_eq!(file.vfile_write(0, data.as_ptr() as usize, BSIZE), Ok(BSIZE));
+    assert_eq!(phys_size(), nblocks * BSIZE);
+
+    assert_eq!(file.vfile_truncate((NDIRECT + 1) as u64 * BSIZE as u64), (NDIRECT + 1) * BSIZE);
+    assert_eq!(phys_size(), (NDIRECT + 1) * BSIZE);
+    file.vfile_truncate(BSIZE as u64 / 2);
+    assert_eq!(phys_size(), BSIZE);
+    assert_eq!(file.vfile_stat().unwrap().size, BSIZE / 2);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use xv6fs::file::VFile;
+use xv6fs::fs_const::{BSIZE, NDIRECT};
+use xv6fs::xattr::NO_ATTR;
+
+use common::{fs_path, mount, read_file, serial, write_file};
+
+fn mount_csum() {
+    mount(xv6mkfs::Options { metadata_csum: true });
+}
+
+#[test]
+fn test_xattr_round_trip() {
+    let _serial = serial();
+    mount_csum();
+    let file = write_file("/xattr-trip", b"data");
+    assert_eq!(file.vfile_getxattr("user.a"), Err(NO_ATTR));
+    assert_eq!(file.vfile_listxattr(), Ok(vec![]));
+
+    file.vfile_setxattr("user.a", b"first").unwrap();
+    file.vfile_setxattr("user.b", &[0, 1, 2, 255]).unwrap();
+    file.vfile_setxattr("user.empty", b"").unwrap();
+    assert_eq!(file.vfile_getxattr("user.a"), Ok(b"first".to_vec()));
+    assert_eq!(file.vfile_getxattr("user.b"), Ok(vec![0, 1, 2, 255]));
+    assert_eq!(file.vfile_getxattr("user.empty"), Ok(vec![]));
+    assert_eq!(file.vfile_listxattr(), Ok(vec!["user.a".into(), "user.b".into(), "user.empty".into()]));
+
+    file.vfile_setxattr("user.a", b"second, longer").unwrap();
+    assert_eq!(file.vfile_getxattr("user.a"), Ok(b"second, longer".to_vec()));
+    file.vfile_removexattr("user.b").unwrap();
+    assert_eq!(file.vfile_removexattr("user.b"), Err(NO_ATTR));
+    assert_eq!(file.vfile_listxattr(), Ok(vec!["user.a".into(), "user.empty".into()]));
+
+    // seen through another lookup of the same file
+    let again = VFile::vfile_lookup(&fs_path("/xattr-trip")).unwrap();
+    assert_eq!(again.vfile_getxattr("user.a"), Ok(b"second, longer".to_vec()));
+    assert_eq!(read_file("/xattr-trip"), b"data");
+}
+
+#[test]
+fn test_xattr_beside_every_direct_block() {
+    let _serial = serial();
+    mount_csum();
+    // all the direct addresses and the indirect block are in use
+    let data: Vec<u8> = (0..(NDIRECT + 2) * BSIZE).map(|i| (i / BSIZE) as u8).collect();
+    let file = write_file("/xattr-full", &data);
+    let value = vec![7; BSIZE / 2];
+    file.vfile_setxattr("user.big", &value).unwrap();
+    assert_eq!(read_file("/xattr-full"), data);
+    assert_eq!(file.vfile_getxattr("user.big"), Ok(value));
+
+    // rewriting the data leaves the attributes alone
+    let rewrite = vec![9; (NDIRECT + 2) * BSIZE];
+    assert_eq!(file.vfile_write(0, rewrite.as_ptr() as usize, rewrite.len()), Ok(rewrite.len()));
+    assert_eq!(file.vfile_getxattr("user.big"), Ok(vec![7; BSIZE / 2]));
+    assert_eq!(read_file("/xattr-full"), rewrite);
+}
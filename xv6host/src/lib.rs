@@ -0,0 +1,136 @@
+//! Access to an xv6 disk image stored in a host file,
+//! shared by the host tools xv6img and xv6resize.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use xv6fs::disk_inode::{DiskInode, InodeType};
+use xv6fs::fs_const::{BSIZE, FSMAGIC, IPB, ROOTDEV};
+use xv6fs::interface::{FsInterface, InterfaceManager, INTERFACE_MANAGER};
+use xv6fs::log::LogHeader;
+use xv6fs::superblock::{self, RawSuperBlock};
+use xv6fs::BlockDevice;
+
+/// Block device backed by an image file on the host.
+pub struct BlockFile(Mutex<File>);
+
+impl BlockFile {
+    pub fn open(path: &str, writable: bool) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self(Mutex::new(file)))
+    }
+
+    /// Size of the image in blocks.
+    pub fn blocks(&self) -> Result<u64, String> {
+        let len = self.0.lock().unwrap().metadata().map_err(|e| e.to_string())?.len();
+        Ok(len / BSIZE as u64)
+    }
+
+    /// Make the image `blocks` blocks long.
+    pub fn set_blocks(&self, blocks: u64) -> Result<(), String> {
+        self.0.lock().unwrap().set_len(blocks * BSIZE as u64).map_err(|e| e.to_string())
+    }
+}
+
+impl BlockDevice for BlockFile {
+    /// Read a block from file
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BSIZE) as u64))
+            .expect("Error when seeking!");
+        file.read_exact(&mut buf[..BSIZE]).expect("Not a complete block!");
+    }
+    /// Write a block into file
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BSIZE) as u64))
+            .expect("Error when seeking!");
+        file.write_all(&buf[..BSIZE]).expect("Not a complete block!");
+    }
+}
+
+/// The tools are single-threaded, so sleep locks never contend
+/// and there is no current directory.
+pub struct HostInterface;
+
+impl FsInterface for HostInterface {
+    fn get_cur_dir_inode(&self) -> Option<xv6fs::inode::Inode> {
+        None
+    }
+    fn new_sleep_lock(&self) -> usize {
+        0
+    }
+    fn sleep_lock(&self, _index: usize) {}
+    fn sleep_lock_shared(&self, _index: usize) {}
+    fn sleep_unlock(&self, _index: usize) {}
+    fn sleep_unlock_shared(&self, _index: usize) {}
+    fn dev_read(&self, _major: i16, _minor: i16, _offset: usize, _buf: &mut [u8]) -> Result<usize, &'static str> {
+        Err("device I/O is not available on the host")
+    }
+    fn dev_write(&self, _major: i16, _minor: i16, _offset: usize, _buf: &[u8]) -> Result<usize, &'static str> {
+        Err("device I/O is not available on the host")
+    }
+    fn dev_is_block(&self, _major: i16, _minor: i16) -> bool {
+        false
+    }
+    fn cur_task_id(&self) -> usize {
+        0
+    }
+    fn inode_freed(&self, _dev: u32, _inum: u32) {}
+}
+
+/// Open the image and initialize xv6fs on it.
+/// This replays a pending log, so the image is always opened writable.
+pub fn mount(path: &str) -> Result<(), String> {
+    let dev = BlockFile::open(path, true)?;
+    check_superblock(path, &dev)?;
+    INTERFACE_MANAGER.init_by(InterfaceManager { interface: Arc::new(HostInterface) });
+    unsafe { xv6fs::init(Arc::new(dev), ROOTDEV) }.map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Read the raw super block without mounting.
+pub fn read_superblock(dev: &dyn BlockDevice) -> RawSuperBlock {
+    let mut buf = [0u8; BSIZE];
+    dev.read_block(1, &mut buf);
+    unsafe { ptr::read_unaligned(buf.as_ptr() as *const RawSuperBlock) }
+}
+
+/// Read the super block and make sure the image holds an xv6 file system
+/// with a layout these tools know.
+pub fn check_superblock(path: &str, dev: &dyn BlockDevice) -> Result<RawSuperBlock, String> {
+    let sb = read_superblock(dev);
+    if sb.magic != FSMAGIC {
+        return Err(format!("{}: bad magic {:#x}, not an xv6 image", path, sb.magic));
+    }
+    superblock::check_features(sb.features).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(sb)
+}
+
+/// Read the on-disk log header without mounting.
+pub fn read_log_header(dev: &dyn BlockDevice, sb: &RawSuperBlock) -> LogHeader {
+    let mut buf = [0u8; BSIZE];
+    dev.read_block(sb.logstart as usize, &mut buf);
+    unsafe { ptr::read_unaligned(buf.as_ptr() as *const LogHeader) }
+}
+
+/// Read an on-disk inode without mounting.
+/// Returns the raw type field instead if it is not a valid `InodeType`.
+pub fn read_dinode(dev: &dyn BlockDevice, sb: &RawSuperBlock, inum: u32) -> Result<Result<DiskInode, u16>, String> {
+    if inum >= sb.ninodes {
+        return Err(format!("inode {} out of range (ninodes is {})", inum, sb.ninodes));
+    }
+    let mut buf = [0u8; BSIZE];
+    dev.read_block((sb.inodestart + inum / IPB as u32) as usize, &mut buf);
+    let offset = (inum as usize % IPB) * core::mem::size_of::<DiskInode>();
+    let raw_type = u16::from_ne_bytes([buf[offset], buf[offset + 1]]);
+    if raw_type > InodeType::Device as u16 {
+        return Ok(Err(raw_type));
+    }
+    Ok(Ok(unsafe { ptr::read_unaligned(buf[offset..].as_ptr() as *const DiskInode) }))
+}
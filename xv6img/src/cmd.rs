@@ -0,0 +1,333 @@
+//! Subcommand implementations.
+
+use std::io::Write;
+use std::mem::size_of;
+
+use xv6fs::disk_inode::{DirEntry, InodeType};
+use xv6fs::fs_const::{BSIZE, DIRSIZ, NADDRS};
+use xv6fs::inode::{Inode, InodeData, ICACHE};
+use xv6fs::log::LOG_MANAGER;
+use xv6fs::stat::Stat;
+use xv6fs::snapshot;
+use xv6fs::superblock::{FEATURE_METADATA_CSUM, FEATURE_SNAPSHOTS, FEATURE_XATTR};
+
+use xv6host::BlockFile;
+
+type CmdResult = Result<(), String>;
+
+/// Turn a user path into the NUL-terminated absolute path xv6fs expects.
+fn fs_path(path: &str) -> Result<Vec<u8>, String> {
+    let mut abs = String::new();
+    for name in path.split('/').filter(|s| !s.is_empty()) {
+        if name.len() >= DIRSIZ {
+            return Err(format!("{}: name longer than {} bytes", name, DIRSIZ - 1));
+        }
+        abs.push('/');
+        abs.push_str(name);
+    }
+    if abs.is_empty() {
+        abs.push('/');
+    }
+    let mut bytes = abs.into_bytes();
+    bytes.push(0);
+    Ok(bytes)
+}
+
+fn lookup(path: &str) -> Result<Inode, String> {
+    ICACHE
+        .namei(&fs_path(path)?)
+        .ok_or_else(|| format!("{}: no such file or directory", path))
+}
+
+/// Make sure the parent directory of `path` exists.
+fn check_parent(path: &str) -> Result<Vec<u8>, String> {
+    let fpath = fs_path(path)?;
+    let mut name = [0u8; DIRSIZ];
+    match ICACHE.namei_parent(&fpath, &mut name) {
+        Some(_) => Ok(fpath),
+        None => Err(format!("{}: parent directory does not exist", path)),
+    }
+}
+
+fn type_char(itype: InodeType) -> char {
+    match itype {
+        InodeType::Directory => 'd',
+        InodeType::File => '-',
+        InodeType::Device => 'c',
+        InodeType::Empty => '?',
+    }
+}
+
+fn stat_of(inode: &Inode) -> Result<Stat, String> {
+    let mut stat = Stat::new();
+    inode.try_lock()?.stat(&mut stat);
+    Ok(stat)
+}
+
+/// Read all live entries of a directory as (name, inum).
+fn dir_entries(idata: &mut InodeData) -> Result<Vec<(String, u32)>, String> {
+    let de_size = size_of::<DirEntry>();
+    let mut entries = Vec::new();
+    let mut dir_entry = DirEntry::new();
+    for offset in (0..idata.dinode.size).step_by(de_size) {
+        idata.read(&mut dir_entry as *mut DirEntry as usize, offset, de_size as u32)?;
+        if dir_entry.inum == 0 {
+            continue;
+        }
+        let len = dir_entry.name.iter().position(|&c| c == 0).unwrap_or(DIRSIZ);
+        let name = String::from_utf8_lossy(&dir_entry.name[..len]).into_owned();
+        entries.push((name, dir_entry.inum as u32));
+    }
+    Ok(entries)
+}
+
+fn read_all(inode: &Inode) -> Result<Vec<u8>, String> {
+    let mut idata = inode.try_lock()?;
+    let mut data = vec![0u8; idata.dinode.size as usize];
+    let mut offset = 0;
+    while offset < data.len() {
+        let n = idata.read(data[offset..].as_mut_ptr() as usize, offset as u32, (data.len() - offset) as u32)?;
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+    data.truncate(offset);
+    Ok(data)
+}
+
+pub fn ls(img: &str, path: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    let stat = stat_of(&inode)?;
+    if stat.itype != InodeType::Directory {
+        println!("{} {:>5} {:>8} {}", type_char(stat.itype), stat.inum, stat.size, path);
+        return Ok(());
+    }
+    let entries = dir_entries(&mut *inode.try_lock()?)?;
+    for (name, inum) in entries {
+        let stat = stat_of(&ICACHE.get(inode.dev, inum))?;
+        println!("{} {:>5} {:>8} {}", type_char(stat.itype), inum, stat.size, name);
+    }
+    Ok(())
+}
+
+pub fn cat(img: &str, path: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    if stat_of(&inode)?.itype == InodeType::Directory {
+        return Err(format!("{}: is a directory", path));
+    }
+    let data = read_all(&inode)?;
+    std::io::stdout().write_all(&data).map_err(|e| e.to_string())
+}
+
+pub fn get(img: &str, path: &str, host_path: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    if stat_of(&inode)?.itype == InodeType::Directory {
+        return Err(format!("{}: is a directory", path));
+    }
+    let data = read_all(&inode)?;
+    std::fs::write(host_path, data).map_err(|e| format!("{}: {}", host_path, e))
+}
+
+pub fn put(img: &str, host_path: &str, path: &str, compress: bool) -> CmdResult {
+    let data = std::fs::read(host_path).map_err(|e| format!("{}: {}", host_path, e))?;
+    xv6host::mount(img)?;
+    if let Ok(inode) = lookup(path) {
+        if stat_of(&inode)?.itype != InodeType::File {
+            return Err(format!("{}: not a regular file", path));
+        }
+    }
+    let fpath = check_parent(path)?;
+    let op = LOG_MANAGER.begin_op();
+    let inode = ICACHE.create(&fpath, InodeType::File, 2, 1)?;
+    let mut idata = inode.lock();
+    idata.truncate(&inode);
+    let res = if compress { idata.make_compressed() } else { Ok(()) };
+    drop(idata);
+    drop(op);
+    res?;
+
+    // keep every transaction within the log, as VFile::vfile_write does
+    let mut offset = 0;
+    while offset < data.len() {
+        let op = LOG_MANAGER.begin_op();
+        let mut idata = inode.lock();
+        let len = idata.write_limit(offset as u32).min(data.len() - offset);
+        idata.write(data[offset..].as_ptr() as usize, offset as u32, len as u32)?;
+        drop(idata);
+        drop(op);
+        offset += len;
+    }
+    Ok(())
+}
+
+pub fn rm(img: &str, path: &str, recursive: bool) -> CmdResult {
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    if inode.inum == lookup("/")?.inum {
+        return Err("cannot remove the root directory".into());
+    }
+    if stat_of(&inode)?.itype == InodeType::Directory && !recursive {
+        return Err(format!("{}: is a directory (use -r)", path));
+    }
+    drop(inode);
+    let _op = LOG_MANAGER.begin_op();
+    ICACHE.remove(&fs_path(path)?)?;
+    Ok(())
+}
+
+pub fn mkdir(img: &str, path: &str, index: bool, compress: bool) -> CmdResult {
+    xv6host::mount(img)?;
+    if lookup(path).is_ok() {
+        return Err(format!("{}: already exists", path));
+    }
+    let fpath = check_parent(path)?;
+    let op = LOG_MANAGER.begin_op();
+    let inode = ICACHE.create(&fpath, InodeType::Directory, 2, 1)?;
+    let mut idata = inode.lock();
+    let res = if index { idata.dir_make_indexed() } else { Ok(()) };
+    let res = res.and_then(|_| if compress { idata.make_compressed() } else { Ok(()) });
+    drop(idata);
+    drop(op);
+    Ok(res?)
+}
+
+pub fn stat(img: &str, path: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    let idata = inode.try_lock()?;
+    println!("  File: {}", path);
+    println!("  Type: {:?}", idata.dinode.itype);
+    println!(" Inode: {}", idata.inum);
+    println!(" Links: {}", idata.dinode.nlink);
+    println!("  Size: {}", idata.dinode.size);
+    println!("  Disk: {}", idata.data_blocks() * BSIZE);
+    println!("Device: {},{}", idata.dinode.major, idata.dinode.minor);
+    let mut flags = Vec::new();
+    if idata.is_dir_indexed() {
+        flags.push("indexed");
+    }
+    if idata.is_compressed() || idata.compresses_new_files() {
+        flags.push("compressed");
+    }
+    if !flags.is_empty() {
+        println!(" Flags: {}", flags.join(" "));
+    }
+    println!(" Addrs: {:?}", idata.dinode.addrs);
+    for name in idata.list_xattrs()? {
+        let value = idata.get_xattr(&name)?;
+        println!(" Xattr: {}={}", String::from_utf8_lossy(&name), String::from_utf8_lossy(&value));
+    }
+    Ok(())
+}
+
+pub fn tree(img: &str, path: &str) -> CmdResult {
+    fn walk(inode: &Inode, prefix: &str) -> CmdResult {
+        let entries = dir_entries(&mut *inode.try_lock()?)?;
+        let entries: Vec<_> = entries.into_iter().filter(|(name, _)| name != "." && name != "..").collect();
+        for (i, (name, inum)) in entries.iter().enumerate() {
+            let last = i + 1 == entries.len();
+            println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, name);
+            let child = ICACHE.get(inode.dev, *inum);
+            if stat_of(&child)?.itype == InodeType::Directory {
+                walk(&child, &format!("{}{}", prefix, if last { "    " } else { "│   " }))?;
+            }
+        }
+        Ok(())
+    }
+
+    xv6host::mount(img)?;
+    let inode = lookup(path)?;
+    println!("{}", path);
+    if stat_of(&inode)?.itype == InodeType::Directory {
+        walk(&inode, "")?;
+    }
+    Ok(())
+}
+
+pub fn snapshot_create(img: &str, name: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    snapshot::create(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+pub fn snapshot_delete(img: &str, name: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    snapshot::delete(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+pub fn snapshot_list(img: &str) -> CmdResult {
+    xv6host::mount(img)?;
+    for name in snapshot::list()? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+pub fn dump_superblock(img: &str) -> CmdResult {
+    let dev = BlockFile::open(img, false)?;
+    let sb = xv6host::read_superblock(&dev);
+    println!("magic:      {:#x}", sb.magic);
+    println!("size:       {}", sb.size);
+    println!("nblocks:    {}", sb.nblocks);
+    println!("ninodes:    {}", sb.ninodes);
+    println!("nlog:       {}", sb.nlog);
+    println!("logstart:   {}", sb.logstart);
+    println!("inodestart: {}", sb.inodestart);
+    println!("bmapstart:  {}", sb.bmapstart);
+    println!("features:   {:#x}", sb.features);
+    if sb.features & FEATURE_METADATA_CSUM != 0 {
+        let ok = if sb.checksum == sb.calc_checksum() { "ok" } else { "MISMATCH" };
+        println!("csumstart:  {}", sb.csumstart);
+        println!("checksum:   {:#010x} ({})", sb.checksum, ok);
+    }
+    if sb.features & FEATURE_SNAPSHOTS != 0 {
+        println!("snapshots:  {:?}", sb.snapshots);
+    }
+    Ok(())
+}
+
+pub fn dump_inode(img: &str, inum: u32) -> CmdResult {
+    let dev = BlockFile::open(img, false)?;
+    // the disk inodes of other layouts would be misread
+    let sb = xv6host::check_superblock(img, &dev)?;
+    match xv6host::read_dinode(&dev, &sb, inum)? {
+        Ok(dinode) => {
+            println!("inum:  {}", inum);
+            println!("type:  {:?}", dinode.itype);
+            println!("major: {}", dinode.major);
+            println!("minor: {}", dinode.minor);
+            println!("nlink: {}", dinode.nlink);
+            println!("size:  {}", dinode.size);
+            if sb.features & FEATURE_XATTR != 0 {
+                println!("addrs: {:?}", &dinode.addrs[..NADDRS - 1]);
+                println!("xattr: {}", dinode.addrs[NADDRS - 1]);
+            } else {
+                println!("addrs: {:?}", dinode.addrs);
+            }
+        }
+        Err(raw_type) => println!("inum:  {}\ntype:  invalid ({})", inum, raw_type),
+    }
+    Ok(())
+}
+
+pub fn dump_log(img: &str) -> CmdResult {
+    let dev = BlockFile::open(img, false)?;
+    let sb = xv6host::read_superblock(&dev);
+    let lh = xv6host::read_log_header(&dev, &sb);
+    println!("logstart: {}", sb.logstart);
+    println!("nlog:     {}", sb.nlog);
+    println!("len:      {}", lh.len());
+    if sb.features & FEATURE_METADATA_CSUM != 0 {
+        println!("checksum: {:#010x}", lh.checksum());
+    }
+    for (i, blockno) in lh.blocknos().iter().enumerate() {
+        println!("  log block {} -> block {}", sb.logstart + 1 + i as u32, blockno);
+    }
+    if lh.len() > 0 {
+        println!("the log holds a committed transaction, it is replayed on the next mount");
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! Inspect and modify xv6 disk images from the host, without FUSE.
+
+use clap::{App, AppSettings, Arg, SubCommand};
+
+mod cmd;
+
+fn main() {
+    let path_arg = |help| Arg::with_name("path").help(help).default_value("/");
+    let matches = App::new("xv6img")
+        .about("Inspect and modify xv6fs disk images")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("image")
+                .short("i")
+                .long("image")
+                .takes_value(true)
+                .default_value("disk.img")
+                .help("Disk image to operate on"),
+        )
+        .subcommand(SubCommand::with_name("ls").about("List a directory").arg(path_arg("Directory to list")))
+        .subcommand(SubCommand::with_name("tree").about("Show a directory tree").arg(path_arg("Directory to walk")))
+        .subcommand(SubCommand::with_name("stat").about("Show inode information").arg(Arg::with_name("path").required(true)))
+        .subcommand(SubCommand::with_name("cat").about("Print a file to stdout").arg(Arg::with_name("path").required(true)))
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Copy a file out of the image")
+                .arg(Arg::with_name("path").required(true))
+                .arg(Arg::with_name("host_path").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("put")
+                .about("Copy a host file into the image")
+                .arg(Arg::with_name("compress").short("z").long("compress").help("Compress the file data"))
+                .arg(Arg::with_name("host_path").required(true))
+                .arg(Arg::with_name("path").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Remove a file or directory")
+                .arg(Arg::with_name("recursive").short("r").help("Remove directories and their contents"))
+                .arg(Arg::with_name("path").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("mkdir")
+                .about("Create a directory")
+                .arg(Arg::with_name("index").short("x").long("index").help("Keep a hashed index of its entries"))
+                .arg(Arg::with_name("compress").short("z").long("compress").help("Compress the files created in it"))
+                .arg(Arg::with_name("path").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Manage read-only snapshots of the file system")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("create").about("Take a snapshot").arg(Arg::with_name("name").required(true)))
+                .subcommand(SubCommand::with_name("delete").about("Delete a snapshot").arg(Arg::with_name("name").required(true)))
+                .subcommand(SubCommand::with_name("list").about("List the snapshots")),
+        )
+        .subcommand(SubCommand::with_name("dump-superblock").about("Print the raw super block"))
+        .subcommand(
+            SubCommand::with_name("dump-inode")
+                .about("Print a raw on-disk inode")
+                .arg(Arg::with_name("inum").required(true)),
+        )
+        .subcommand(SubCommand::with_name("dump-log").about("Print the on-disk log header"))
+        .get_matches();
+
+    let img = matches.value_of("image").unwrap();
+    let res = match matches.subcommand() {
+        ("ls", Some(m)) => cmd::ls(img, m.value_of("path").unwrap()),
+        ("tree", Some(m)) => cmd::tree(img, m.value_of("path").unwrap()),
+        ("stat", Some(m)) => cmd::stat(img, m.value_of("path").unwrap()),
+        ("cat", Some(m)) => cmd::cat(img, m.value_of("path").unwrap()),
+        ("get", Some(m)) => cmd::get(img, m.value_of("path").unwrap(), m.value_of("host_path").unwrap()),
+        ("put", Some(m)) => {
+            cmd::put(img, m.value_of("host_path").unwrap(), m.value_of("path").unwrap(), m.is_present("compress"))
+        }
+        ("rm", Some(m)) => cmd::rm(img, m.value_of("path").unwrap(), m.is_present("recursive")),
+        ("mkdir", Some(m)) => {
+            cmd::mkdir(img, m.value_of("path").unwrap(), m.is_present("index"), m.is_present("compress"))
+        }
+        ("snapshot", Some(m)) => match m.subcommand() {
+            ("create", Some(m)) => cmd::snapshot_create(img, m.value_of("name").unwrap()),
+            ("delete", Some(m)) => cmd::snapshot_delete(img, m.value_of("name").unwrap()),
+            ("list", Some(_)) => cmd::snapshot_list(img),
+            _ => unreachable!(),
+        },
+        ("dump-superblock", Some(_)) => cmd::dump_superblock(img),
+        ("dump-inode", Some(m)) => match m.value_of("inum").unwrap().parse() {
+            Ok(inum) => cmd::dump_inode(img, inum),
+            Err(_) => Err("dump-inode: inum must be a number".into()),
+        },
+        ("dump-log", Some(_)) => cmd::dump_log(img),
+        _ => unreachable!(),
+    };
+    if let Err(e) = res {
+        eprintln!("xv6img: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,28 @@
+//! CRC32C checksums of the metadata, the same as `xv6fs::checksum`.
+
+use crate::fs_const::BSIZE;
+
+/// Checksum entries in a table block, the last word is for the block itself.
+pub const CSUMS_PER_BLOCK: usize = BSIZE / 4 - 1;
+
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+/// CRC32C of `data`, continuing from `crc` (0 to start).
+pub fn crc32c(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |mut crc, &b| {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32C_POLY } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// The value stored in the checksum table for a block,
+/// 0 is kept for blocks without a checksum.
+pub fn block_csum(block: &[u8]) -> u32 {
+    match crc32c(0, block) {
+        0 => !0,
+        crc => crc,
+    }
+}
@@ -2,19 +2,31 @@ use core::mem::size_of;
 use crate::structs::DiskInode;
 /// magic number indentifying this specific file system
 pub const FSMAGIC: u32 = 0x10203040;
+/// the file system keeps checksums of its metadata
+pub const FEATURE_METADATA_CSUM: u32 = 0x1;
+/// disk inodes have NDIRECT direct addresses and the block of extended attributes
+pub const FEATURE_XATTR: u32 = 0x4;
 /// size of disk block
 pub const BSIZE: usize = 512;
 /// Maxinum of blocks an FS op can write
 pub const MAXOPBLOCKS: usize = 10;
-/// size of log space in disk
-pub const LOGSIZE: usize = MAXOPBLOCKS * 3;
+/// Most blocks an FS op reserves in the log: its own blocks and,
+/// with metadata checksums, the checksum table blocks covering them
+pub const OPLOGBLOCKS: usize = MAXOPBLOCKS * 2;
+/// size of log space in disk, room for three ops in flight
+/// plus the header block and the two spare slots the log keeps
+pub const LOGSIZE: usize = OPLOGBLOCKS * 3 + 3;
 
 /// maximum number of disk inodes
 pub const NDINODES: usize = 200;
 /// size of file system in blocks
 pub const FSSIZE: usize = 1000; 
 
-pub const NDIRECT: usize = 11;
+/// one address went to the block of extended attributes
+pub const NDIRECT: usize = 10;
+
+/// maximum number of snapshots of the file system
+pub const NSNAPSHOT: usize = 4;
 
 /// Directory is a file containing a sequence of dirent structures
 pub const DIRSIZ: usize = 14;
@@ -0,0 +1,177 @@
+//! Build an empty xv6 file system, used by the `xv6mkfs` tool
+//! and by tests that need a fresh image.
+
+use crate::structs::*;
+use crate::fs_const::*;
+
+mod structs;
+pub mod fs_const;
+mod checksum;
+
+use checksum::{block_csum, crc32c, CSUMS_PER_BLOCK};
+
+use std::mem::size_of;
+use std::sync::Mutex;
+
+use std::ptr::copy_nonoverlapping;
+
+/// Features of the new file system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Keep CRC32C checksums of the metadata.
+    pub metadata_csum: bool,
+}
+
+/// An image being built in memory.
+struct Image(Mutex<Vec<u8>>);
+
+impl BlockDevice for Image {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let image = self.0.lock().unwrap();
+        buf[..BSIZE].copy_from_slice(&image[block_id * BSIZE..(block_id + 1) * BSIZE]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut image = self.0.lock().unwrap();
+        image[block_id * BSIZE..(block_id + 1) * BSIZE].copy_from_slice(&buf[..BSIZE]);
+    }
+}
+
+fn iblock(inum:usize,rsb_inodestart:usize)->usize{
+    inum/IPB+rsb_inodestart
+}
+
+/// A new inode of one block, `block`.
+fn ialloc(itype:InodeType,block:usize)->DiskInode{
+    let mut dinode=DiskInode::new();
+    dinode.itype=itype;
+    dinode.nlink=1;
+    dinode.major=0;
+    dinode.minor=0;
+    dinode.size=512;
+    dinode.addrs[0]=block as u32;
+    dinode
+}
+
+/// Write the checksum table of the metadata written so far,
+/// and the checksums of the log header and the super block.
+fn write_checksums(image: &Image, sb: &mut RawSuperBlock, csummed: &[usize]) {
+    let mut buf=[0 as u8;BSIZE];
+    let ncsum=(FSSIZE+CSUMS_PER_BLOCK-1)/CSUMS_PER_BLOCK;
+    for t in 0..ncsum{
+        let mut table=[0 as u8;BSIZE];
+        for &blockno in csummed.iter().filter(|&&b| b/CSUMS_PER_BLOCK==t){
+            image.read_block(blockno, &mut buf);
+            let i=blockno%CSUMS_PER_BLOCK;
+            table[i*4..i*4+4].copy_from_slice(&block_csum(&buf).to_ne_bytes());
+        }
+        let self_csum=block_csum(&table[..CSUMS_PER_BLOCK*4]);
+        table[CSUMS_PER_BLOCK*4..].copy_from_slice(&self_csum.to_ne_bytes());
+        image.write_block(sb.csumstart as usize+t, &table);
+    }
+
+    // the log header is all zeros but its checksum, the last word
+    let lh_len=(LOGSIZE+1)*4;
+    image.read_block(sb.logstart as usize, &mut buf);
+    let lh_csum=crc32c(0, &buf[..lh_len-4]);
+    buf[lh_len-4..lh_len].copy_from_slice(&lh_csum.to_ne_bytes());
+    image.write_block(sb.logstart as usize, &buf);
+
+    let len=size_of::<RawSuperBlock>()-size_of::<u32>();
+    let raw=unsafe{ std::slice::from_raw_parts(sb as *const RawSuperBlock as *const u8, len) };
+    sb.checksum=crc32c(0, raw);
+    buf=[0 as u8;BSIZE];
+    unsafe{copy_nonoverlapping(sb as *const RawSuperBlock, buf.as_mut_ptr() as *mut RawSuperBlock, 1);}
+    image.write_block(1, &buf);
+}
+
+/// Build an empty file system of `FSSIZE` blocks and return its image.
+pub fn mkfs(options: &Options) -> Vec<u8> {
+    //let nbitmap= FSSIZE/(BSIZE*8) + 1;
+    let ninodeblocks= NDINODES/IPB + 1;
+    let nlog=LOGSIZE;
+    let ncsum=if options.metadata_csum { (FSSIZE+CSUMS_PER_BLOCK-1)/CSUMS_PER_BLOCK } else { 0 };
+    let nmeta=2 + LOGSIZE + NDINODES/IPB + 1 + FSSIZE/(BSIZE*8) + 1 + ncsum;
+    let nblocks= FSSIZE-nmeta;
+    //init superblock
+    let mut raw_superblock=RawSuperBlock::new();
+    raw_superblock.magic=FSMAGIC;
+    raw_superblock.size=FSSIZE as u32;
+    raw_superblock.nblocks=nblocks as u32;
+    raw_superblock.ninodes=NDINODES as u32;
+    raw_superblock.nlog=nlog as u32;
+    raw_superblock.logstart=2;
+    raw_superblock.inodestart=2+nlog as u32;
+    raw_superblock.bmapstart=(2+nlog+ninodeblocks) as u32;
+    raw_superblock.features=FEATURE_XATTR;
+    if options.metadata_csum{
+        raw_superblock.features|=FEATURE_METADATA_CSUM;
+        // right after the bitmap
+        raw_superblock.csumstart=raw_superblock.bmapstart+(FSSIZE/(BSIZE*8)+1) as u32;
+    }
+
+    let image=Image(Mutex::new(vec![0; FSSIZE*BSIZE]));
+    let mut buf=[0 as u8;BSIZE];
+
+    //write superblock
+    unsafe{copy_nonoverlapping(&raw_superblock as *const RawSuperBlock, buf.as_mut_ptr() as *mut RawSuperBlock, 1);}
+    image.write_block(1, &buf);
+
+    //set root inode
+    let rinum:usize=1;
+    let dinode=ialloc(InodeType::Directory, nmeta);
+    let used=nmeta+1;
+    let block_id=iblock(rinum, raw_superblock.inodestart as usize);
+    image.read_block(block_id, &mut buf);
+    unsafe{
+        copy_nonoverlapping(
+            &dinode as *const DiskInode,
+            (buf.as_mut_ptr() as usize + (rinum%IPB)*core::mem::size_of::<DiskInode>()) as *mut DiskInode,
+            1
+        );
+    }
+    image.write_block(block_id, &buf);
+
+    //write direct entry
+    let mut dir_entry=DirEntry::new();
+    dir_entry.name[0]=".".as_bytes()[0];
+    dir_entry.inum=1;
+    let block_id=dinode.addrs[0];
+    image.read_block(block_id as usize, &mut buf);
+    unsafe{
+        copy_nonoverlapping(
+            &dir_entry as *const DirEntry,
+            buf.as_mut_ptr() as usize as *mut DirEntry,
+            1
+        );
+    }
+    image.write_block(block_id as usize, &buf);
+    let mut dir_entry=DirEntry::new();
+    dir_entry.name[0]="..".as_bytes()[0];
+    dir_entry.name[1]="..".as_bytes()[1];
+    dir_entry.inum=1;
+    let block_id=dinode.addrs[0];
+    image.read_block(block_id as usize, &mut buf);
+    unsafe{
+        copy_nonoverlapping(
+            &dir_entry as *const DirEntry,
+            (buf.as_mut_ptr() as usize + size_of::<DirEntry>()) as *mut DirEntry,
+            1
+        );
+    }
+    image.write_block(block_id as usize, &buf);
+
+    //write bitmap
+    let bblock_id=raw_superblock.bmapstart;
+    image.read_block(bblock_id as usize, &mut buf);
+    for i in 0..used{
+        buf[i/8]=buf[i/8]|(0x1 << (i%8));
+    }
+    image.write_block(bblock_id as usize, &buf);
+
+    if options.metadata_csum{
+        let root_iblock=iblock(rinum, raw_superblock.inodestart as usize);
+        let csummed=[root_iblock, bblock_id as usize, dinode.addrs[0] as usize];
+        write_checksums(&image, &mut raw_superblock, &csummed);
+    }
+    image.0.into_inner().unwrap()
+}
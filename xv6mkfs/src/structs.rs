@@ -1,5 +1,6 @@
 use crate::fs_const::*;
 use core::any::Any;
+#[repr(C)]
 pub struct RawSuperBlock {
     pub magic: u32,      // Must be FSMAGIC
     pub size: u32,       // Size of file system image (blocks)
@@ -9,15 +10,22 @@ pub struct RawSuperBlock {
     pub logstart: u32,   // Block number of first log block
     pub inodestart: u32, // Block number of first inode block
     pub bmapstart: u32,  // Block number of first free map block
+    pub features: u32,   // Feature bits, FEATURE_*
+    pub csumstart: u32,  // Block number of first checksum table block
+    pub snapshots: [u32; NSNAPSHOT], // Snapshot blocks, 0 for a free slot
+    pub checksum: u32,   // CRC32C of the fields above
 }
 
 impl RawSuperBlock {
     pub fn new()->Self{
         RawSuperBlock { magic: 0, size: 0, nblocks: 0, ninodes: 0, 
-            nlog: 0, logstart: 0, inodestart: 0, bmapstart: 0 }
+            nlog: 0, logstart: 0, inodestart: 0, bmapstart: 0,
+            features: 0, csumstart: 0, snapshots: [0; NSNAPSHOT], checksum: 0 }
     }
 }
 
+/// Same layout as `itype` in xv6fs, a u16.
+#[repr(u16)]
 #[derive(Clone, Copy, Debug)]
 pub enum InodeType {
     Empty = 0,
@@ -33,7 +41,8 @@ pub struct DiskInode {
     pub minor: i16, // Minor device number (T_DEVICE only)
     pub nlink: i16, // Number of links to inode in file system
     pub size: u32, // Size of file (bytes)
-    pub addrs: [u32; NDIRECT+2] // Data block addresses
+    pub addrs: [u32; NDIRECT+2], // Data block addresses
+    pub xattr: u32 // Block of extended attributes, 0 if none
 }
 
 impl DiskInode {
@@ -44,7 +53,8 @@ impl DiskInode {
             minor: 0,
             nlink: 0,
             size: 0,
-            addrs: [0; NDIRECT+2]
+            addrs: [0; NDIRECT+2],
+            xattr: 0
         }
     }
 }
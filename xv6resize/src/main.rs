@@ -0,0 +1,40 @@
+//! Grow or shrink the xv6 file system in a disk image from the host.
+
+use clap::{App, Arg};
+use xv6fs::xv6fs::Xv6FS;
+use xv6host::BlockFile;
+
+fn resize(img: &str, blocks: u32, truncate: bool) -> Result<(), String> {
+    let file = BlockFile::open(img, true)?;
+    let old = xv6host::check_superblock(img, &file)?.size;
+    // the new blocks must exist before the file system uses them
+    if file.blocks()? < blocks as u64 {
+        file.set_blocks(blocks as u64)?;
+    }
+    xv6host::mount(img)?;
+    Xv6FS::new().resize(blocks).map_err(|e| format!("{}: {}", img, e))?;
+    if truncate {
+        file.set_blocks(blocks as u64)?;
+    }
+    println!("{}: {} -> {} blocks", img, old, blocks);
+    Ok(())
+}
+
+fn main() {
+    let matches = App::new("xv6resize")
+        .about("Grow or shrink the xv6fs file system in a disk image")
+        .arg(Arg::with_name("truncate").short("t").long("truncate").help("Cut the image file to the new size"))
+        .arg(Arg::with_name("image").required(true).help("Disk image to resize"))
+        .arg(Arg::with_name("blocks").required(true).help("New size in blocks of 512 bytes"))
+        .get_matches();
+
+    let img = matches.value_of("image").unwrap();
+    let res = match matches.value_of("blocks").unwrap().parse() {
+        Ok(blocks) => resize(img, blocks, matches.is_present("truncate")),
+        Err(_) => Err("blocks must be a number".into()),
+    };
+    if let Err(e) = res {
+        eprintln!("xv6resize: {}", e);
+        std::process::exit(1);
+    }
+}